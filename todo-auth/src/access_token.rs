@@ -0,0 +1,328 @@
+/* ACCESS_TOKEN.rs
+ *   by Lut99
+ *
+ * Created:
+ *   18 Oct 2026, 05:27:38
+ * Last edited:
+ *   18 Oct 2026, 07:09:24
+ * Auto updated?
+ *   Yes
+ *
+ * Description:
+ *   Handles personal access tokens, which let scripts and other
+ *   automation authenticate without a password.
+ *
+ *   A token looks like `todo_pat_<id>_<secret>`. Only the hash of the
+ *   secret is stored, so the token is shown exactly once: when it is
+ *   created. Tokens carry a set of scopes that limit the routes they
+ *   are accepted on, and may expire.
+ *
+ *   Only this service can check the tokens, since it has the store.
+ *   Other services accept them only if they use the filters in
+ *   `todo_spec::jwt` with an `Introspector` that resolves them.
+**/
+
+use std::sync::Arc;
+
+use log::{debug, error, info, warn};
+use time::{Duration, OffsetDateTime};
+use warp::{Rejection, Reply};
+use warp::http::StatusCode;
+use warp::reply::Response;
+
+use todo_spec::api::{AccessTokenJson, AuthEventKind, CreatedAccessTokenJson, ErrorCode, NewAccessTokenJson, Scope};
+use todo_spec::jwt::{Claims, Unauthorized};
+
+pub use crate::errors::AccessTokenError as Error;
+pub use crate::spec::{AccessToken, Account, Context};
+use crate::audit::{self, Client};
+use crate::reply;
+use crate::spec::{ACCESS_TOKEN_MAX_COUNT, ACCESS_TOKEN_NAME_MAX_LENGTH, ACCESS_TOKEN_PREFIX};
+use crate::store::Error as StoreError;
+use crate::token::random_token;
+
+
+/***** UNIT TESTS *****/
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_token() {
+        // The secret may contain underscores itself
+        assert_eq!(parse_token("todo_pat_42_abc_def"), Some((42, "abc_def")));
+        assert_eq!(parse_token("todo_pat_1_secret"), Some((1, "secret")));
+
+        // Anything else is not a personal access token
+        assert_eq!(parse_token("todo_pat_42"), None);
+        assert_eq!(parse_token("todo_pat_42_"), None);
+        assert_eq!(parse_token("todo_pat_x_secret"), None);
+        assert_eq!(parse_token("eyJhbGciOiJIUzI1NiJ9.e30.sig"), None);
+    }
+}
+
+
+
+
+
+/***** HELPER MACROS *****/
+/// Writes the given error both to stderr via error!() and returns it as a custom reject
+macro_rules! throw {
+    ($err:expr) => {
+        let err = $err;
+        error!("{}", &err);
+        return Err(warp::reject::custom(err));
+    };
+}
+
+
+
+
+
+/***** HELPER FUNCTIONS *****/
+/// Splits the given personal access token into the identifier and the secret.
+/// 
+/// **Arguments**
+///  * `token`: The token to split, including its prefix.
+/// 
+/// **Returns**  
+/// The identifier and the secret of the token, or None if it is not a well-formed personal access token.
+fn parse_token(token: &str) -> Option<(u32, &str)> {
+    let (id, secret): (&str, &str) = token.strip_prefix(ACCESS_TOKEN_PREFIX)?.split_once('_')?;
+    if secret.is_empty() { return None; }
+    Some((id.parse().ok()?, secret))
+}
+
+/// Generates the rejection for a personal access token that we do not accept.
+#[inline]
+fn unauthorized() -> Rejection {
+    warp::reject::custom(Unauthorized{ reason: String::from("Invalid personal access token") })
+}
+
+/// Generates the reply for a token creation request that we refuse.
+#[inline]
+fn invalid<S: Into<String>>(message: S) -> Response {
+    reply::error(StatusCode::BAD_REQUEST, ErrorCode::InvalidRequest, message)
+}
+
+/// Converts the given AccessToken into its JSON representation, which never includes the secret.
+fn to_json(token: &AccessToken) -> AccessTokenJson {
+    AccessTokenJson {
+        name      : token.name.clone(),
+        scopes    : token.scopes.clone(),
+        created   : token.created.unix_timestamp(),
+        expires   : token.expires.map(|expires| expires.unix_timestamp()),
+        last_used : token.last_used.map(|last_used| last_used.unix_timestamp()),
+    }
+}
+
+
+
+
+
+/***** LIBRARY FUNCTIONS *****/
+/// Authenticates a request with a personal access token, for routes that need the given scope.
+/// 
+/// This hashes the secret of the token, so it should be run with `workers::verifying()`.
+/// 
+/// **Arguments**
+///  * `ctx`: The Context of the service.
+///  * `token`: The bearer token of the request, which starts with `ACCESS_TOKEN_PREFIX`.
+///  * `scope`: The scope that the token needs to have.
+/// 
+/// **Returns**  
+/// Claims for the owner of the token (as if they had logged in) on success, or a Warp rejection if the token is not accepted.
+pub fn authenticate(ctx: &Context, token: &str, scope: Scope) -> Result<Claims, Rejection> {
+    // Find the token by its identifier
+    let (id, secret): (u32, &str) = match parse_token(token) {
+        Some(parts) => parts,
+        None        => {
+            debug!("Personal access token authentication failure: malformed token");
+            return Err(unauthorized());
+        },
+    };
+    let token: AccessToken = match ctx.store.get_access_token(id) {
+        Ok(Some(token)) => token,
+        Ok(None)        => {
+            debug!("Personal access token authentication failure: unknown token {}", id);
+            return Err(unauthorized());
+        },
+        Err(err) => { throw!(Error::StoreError{ err }); }
+    };
+
+    // Check the secret before revealing anything else about the token
+    match ctx.metrics.time_password_verify(|| ctx.hashing.verify(&token.hash, secret)) {
+        Ok(true)  => {},
+        Ok(false) => {
+            debug!("Personal access token authentication failure: wrong secret for token {}", id);
+            return Err(unauthorized());
+        },
+        Err(err) => { throw!(Error::CredentialError{ err }); }
+    }
+    let now: OffsetDateTime = OffsetDateTime::now_utc();
+    if token.expires.map(|expires| expires <= now).unwrap_or(false) {
+        debug!("Personal access token authentication failure: token {} has expired", id);
+        return Err(unauthorized());
+    }
+    if !token.scopes.contains(&scope) {
+        debug!("{}", Error::MissingScope{ id, scope });
+        return Err(warp::reject::custom(Error::MissingScope{ id, scope }));
+    }
+
+    // The owner must still be allowed in
+    let user: Account = match ctx.store.get_by_id(token.user_id) {
+        Ok(Some(user)) => user,
+        Ok(None)       => {
+            debug!("Personal access token authentication failure: owner of token {} does not exist", id);
+            return Err(unauthorized());
+        },
+        Err(err) => { throw!(Error::StoreError{ err }); }
+    };
+    if user.disabled {
+        debug!("Personal access token authentication failure: owner of token {} is disabled", id);
+        return Err(unauthorized());
+    }
+
+    // Remember that it was used; this is informational, so it's not worth failing the request over
+    if let Err(err) = ctx.store.touch_access_token(id, now) { warn!("Could not update when personal access token {} was last used: {}", id, err); }
+    debug!("User {} authenticated with personal access token {}", user.id, id);
+    let claims = Claims::new(user.id, ctx.tokens.access_lifetime).with_role(user.role);
+    Ok(match &user.display_name { Some(display_name) => claims.with_name(display_name), None => claims })
+}
+
+
+
+/// Handles creating a new personal access token for the authenticated user.
+/// 
+/// **Arguments**
+///  * `ctx`: The Context of the service.
+///  * `client`: The Client that sent the request.
+///  * `user_id`: The identifier of the user, as taken from their access token.
+///  * `body`: The message body we got with the request.
+/// 
+/// **Returns**  
+/// The Warp reply on success, or a Warp rejection on failure.
+pub fn handle_create(ctx: Arc<Context>, client: Client, user_id: u32, body: NewAccessTokenJson) -> Result<Response, Rejection> {
+    info!("Handling personal access token creation (v1/tokens)");
+
+    // Check the request
+    let name: &str = body.name.trim();
+    if name.is_empty() || name.len() > ACCESS_TOKEN_NAME_MAX_LENGTH {
+        return Ok(invalid(format!("Token name must be between 1 and {} characters", ACCESS_TOKEN_NAME_MAX_LENGTH)));
+    }
+    // The name appears in the path when revoking the token, so keep it to characters that need no escaping there
+    if !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.') {
+        return Ok(invalid("Token name may only contain letters, digits, '-', '_' and '.'"));
+    }
+    if body.scopes.is_empty() { return Ok(invalid("Token must have at least one scope")); }
+    let mut scopes: Vec<Scope> = body.scopes;
+    scopes.sort();
+    scopes.dedup();
+    let created: OffsetDateTime = OffsetDateTime::now_utc();
+    let expires: Option<OffsetDateTime> = match body.expires_in {
+        Some(0)          => { return Ok(invalid("Token lifetime must be positive")); },
+        Some(expires_in) => match i64::try_from(expires_in).ok().and_then(|expires_in| created.checked_add(Duration::seconds(expires_in))) {
+            Some(expires) => Some(expires),
+            None          => { return Ok(invalid("Token lifetime is out of range")); },
+        },
+        None => None,
+    };
+
+    // Users have only so many tokens. Concurrent requests of the same user may all pass this check before any of them inserts, which lets them get a few more; that is not worth locking the store for, since they need to be logged in as that user anyway.
+    match ctx.store.list_access_tokens(user_id) {
+        Ok(tokens) => if tokens.len() >= ACCESS_TOKEN_MAX_COUNT {
+            debug!("User {} personal access token creation failure: too many tokens", user_id);
+            return Ok(reply::error(StatusCode::CONFLICT, ErrorCode::Conflict, format!("Cannot have more than {} personal access tokens", ACCESS_TOKEN_MAX_COUNT)));
+        },
+        Err(err) => { throw!(Error::StoreError{ err }); }
+    }
+
+    // Generate the secret, and store only its hash
+    let secret: String = random_token();
+    let hash: String = match ctx.hashing.hash(&secret) {
+        Ok(hash) => hash,
+        Err(err) => { throw!(Error::CredentialError{ err }); }
+    };
+    let mut token: AccessToken = AccessToken {
+        id        : 0,
+        user_id,
+        name      : name.to_string(),
+        hash,
+        scopes,
+        created,
+        expires,
+        last_used : None,
+    };
+    token.id = match ctx.store.insert_access_token(&token) {
+        Ok(id) => id,
+        Err(StoreError::DuplicateAccessToken{ name }) => {
+            debug!("User {} personal access token creation failure: token '{}' already exists", user_id, name);
+            return Ok(reply::error(StatusCode::CONFLICT, ErrorCode::Conflict, format!("A personal access token named '{}' already exists", name)));
+        },
+        Err(err) => { throw!(Error::StoreError{ err }); }
+    };
+
+    // Done; this is the only time the client ever sees the secret
+    info!("User {} created personal access token '{}'", user_id, token.name);
+    audit::record(&ctx, &client, AuthEventKind::AccessTokenCreate, Some(user_id), None, Some(&token.name));
+    Ok(warp::reply::with_status(
+        warp::reply::json(&CreatedAccessTokenJson{ token: format!("{}{}_{}", ACCESS_TOKEN_PREFIX, token.id, secret), info: to_json(&token) }),
+        StatusCode::CREATED,
+    ).into_response())
+}
+
+
+
+/// Handles listing the personal access tokens of the authenticated user.
+/// 
+/// **Arguments**
+///  * `ctx`: The Context of the service.
+///  * `user_id`: The identifier of the user, as taken from their access token.
+/// 
+/// **Returns**  
+/// The Warp reply on success, or a Warp rejection on failure.
+pub fn handle_list(ctx: Arc<Context>, user_id: u32) -> Result<Response, Rejection> {
+    info!("Handling personal access token listing (v1/tokens)");
+
+    let tokens: Vec<AccessToken> = match ctx.store.list_access_tokens(user_id) {
+        Ok(tokens) => tokens,
+        Err(err)   => { throw!(Error::StoreError{ err }); }
+    };
+    Ok(warp::reply::with_status(
+        warp::reply::json(&tokens.iter().map(to_json).collect::<Vec<AccessTokenJson>>()),
+        StatusCode::OK,
+    ).into_response())
+}
+
+
+
+/// Handles revoking one of the personal access tokens of the authenticated user.
+/// 
+/// **Arguments**
+///  * `ctx`: The Context of the service.
+///  * `client`: The Client that sent the request.
+///  * `user_id`: The identifier of the user, as taken from their access token.
+///  * `name`: The name of the token to revoke.
+/// 
+/// **Returns**  
+/// The Warp reply on success, or a Warp rejection on failure.
+pub fn handle_delete(ctx: Arc<Context>, client: Client, user_id: u32, name: String) -> Result<Response, Rejection> {
+    info!("Handling personal access token revocation (v1/tokens/{})", name);
+
+    match ctx.store.delete_access_token(user_id, &name) {
+        Ok(true)  => {},
+        Ok(false) => {
+            debug!("User {} personal access token revocation failure: no token named '{}'", user_id, name);
+            return Ok(reply::error(StatusCode::NOT_FOUND, ErrorCode::NotFound, format!("No personal access token named '{}'", name)));
+        },
+        Err(err) => { throw!(Error::StoreError{ err }); }
+    }
+
+    // Done
+    info!("User {} revoked personal access token '{}'", user_id, name);
+    audit::record(&ctx, &client, AuthEventKind::AccessTokenRevoke, Some(user_id), None, Some(&name));
+    Ok(warp::reply::with_status(
+        String::from("success"),
+        StatusCode::OK,
+    ).into_response())
+}
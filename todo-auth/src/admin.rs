@@ -0,0 +1,509 @@
+/* ADMIN.rs
+ *   by Lut99
+ *
+ * Created:
+ *   18 Oct 2026, 03:48:12
+ * Last edited:
+ *   18 Oct 2026, 07:32:14
+ * Auto updated?
+ *   Yes
+ *
+ * Description:
+ *   Handles the administrative actions of the authorization service.
+ *   These may only be performed by users with the admin role.
+**/
+
+use std::sync::Arc;
+
+use log::{debug, error, info};
+use time::OffsetDateTime;
+use warp::{Rejection, Reply};
+use warp::http::StatusCode;
+use warp::reply::Response;
+
+use todo_spec::api::{AuthEventJson, AuthEventKind, AuthEventPageJson, AuthEventQuery, ErrorCode, NewUserJson, ResetPasswordJson, ResetTokenJson, UserJson};
+use todo_spec::credentials::{verify_display_name, Credential};
+use todo_spec::jwt::{Claims, Role};
+use todo_spec::password::Error as PasswordPolicyError;
+
+pub use crate::errors::AdminError as Error;
+pub use crate::spec::{Account, Context};
+use crate::audit::{self, Client};
+use crate::reply;
+use crate::reset;
+use crate::spec::{AuthEvent, EventFilter, AUDIT_MAX_PAGE_SIZE, AUDIT_PAGE_SIZE};
+use crate::store::Error as StoreError;
+use crate::throttle::ThrottleKey;
+use crate::token;
+
+
+/***** HELPER MACROS *****/
+/// Writes the given error both to stderr via error!() and returns it as a custom reject
+macro_rules! throw {
+    ($err:expr) => {
+        let err = $err;
+        error!("{}", &err);
+        return Err(warp::reject::custom(err));
+    };
+}
+
+
+
+
+
+/***** HELPER FUNCTIONS *****/
+/// Checks whether the given user may perform administrative actions.
+/// 
+/// The role in the access token may be outdated (e.g., if the user was demoted after logging in), so the account is looked up again as well.
+/// 
+/// **Arguments**
+///  * `ctx`: The Context of the service.
+///  * `claims`: The claims of the user, as taken from their access token.
+/// 
+/// **Returns**  
+/// None if the user is an administrator, the reply to send them if they aren't, or an Error if we failed to find out.
+fn require_admin(ctx: &Context, claims: &Claims) -> Result<Option<Response>, Error> {
+    if claims.role != Role::Admin {
+        debug!("User {} is not an administrator", claims.id);
+        return Ok(Some(reply::error(StatusCode::FORBIDDEN, ErrorCode::Forbidden, "Not an administrator")));
+    }
+    match ctx.store.get_by_id(claims.id) {
+        Ok(Some(user)) if user.role == Role::Admin && !user.disabled => Ok(None),
+        Ok(_)                                                        => {
+            debug!("User {} is no longer an administrator", claims.id);
+            Ok(Some(reply::error(StatusCode::FORBIDDEN, ErrorCode::Forbidden, "Not an administrator")))
+        },
+        Err(err) => Err(Error::StoreError{ err }),
+    }
+}
+
+/// Generates the reply for a username that does not exist.
+#[inline]
+fn unknown_user(username: &str) -> Response {
+    reply::error(StatusCode::NOT_FOUND, ErrorCode::UnknownUser, format!("Unknown username '{}'", username))
+}
+
+/// Generates the reply for an administrator who tries to lock themselves out.
+#[inline]
+fn refuse_self() -> Response {
+    reply::error(StatusCode::CONFLICT, ErrorCode::Conflict, "Administrators cannot disable or delete their own account")
+}
+
+/// Converts the given UNIX timestamp from a query into an OffsetDateTime.
+/// 
+/// **Arguments**
+///  * `name`: The name of the query parameter, for the error message.
+///  * `timestamp`: The timestamp to convert, if any.
+/// 
+/// **Returns**  
+/// The moment on success (or None if there was no timestamp), or the reply to send the client if the timestamp is out of range.
+fn from_timestamp(name: &str, timestamp: Option<i64>) -> Result<Option<OffsetDateTime>, Response> {
+    match timestamp.map(OffsetDateTime::from_unix_timestamp) {
+        Some(Ok(time)) => Ok(Some(time)),
+        Some(Err(_))   => Err(reply::error(StatusCode::BAD_REQUEST, ErrorCode::InvalidRequest, format!("Timestamp '{}' is out of range", name))),
+        None => Ok(None),
+    }
+}
+
+/// Converts the given AuthEvent into its JSON representation.
+fn event_to_json(event: AuthEvent) -> AuthEventJson {
+    AuthEventJson {
+        id         : event.id,
+        time       : event.time.unix_timestamp(),
+        kind       : event.kind,
+        user_id    : event.user_id,
+        username   : event.username,
+        ip         : event.ip,
+        user_agent : event.user_agent,
+        detail     : event.detail,
+    }
+}
+
+/// Converts the given Account into its JSON representation.
+fn to_json(user: &Account) -> UserJson {
+    UserJson {
+        id           : user.id,
+        username     : user.credential.user().to_string(),
+        display_name : user.display_name.clone(),
+        role         : user.role,
+        disabled     : user.disabled,
+        locked       : user.locked_until.map(|until| until > OffsetDateTime::now_utc()).unwrap_or(false),
+    }
+}
+
+
+
+
+
+/***** LIBRARY FUNCTIONS *****/
+/// Handles listing all users.
+/// 
+/// **Arguments**
+///  * `ctx`: The Context of the service.
+///  * `claims`: The claims of the administrator, as taken from their access token.
+/// 
+/// **Returns**  
+/// The Warp reply on success, or a Warp rejection on failure.
+pub fn handle_list(ctx: Arc<Context>, claims: Claims) -> Result<Response, Rejection> {
+    info!("Handling user listing (v1/admin/users)");
+    match require_admin(&ctx, &claims) {
+        Ok(None)      => {},
+        Ok(Some(res)) => { return Ok(res); },
+        Err(err)      => { throw!(err); }
+    }
+
+    // Simply return all users
+    let users: Vec<Account> = match ctx.store.list() {
+        Ok(users) => users,
+        Err(err)  => { throw!(Error::StoreError{ err }); }
+    };
+    Ok(warp::reply::with_status(
+        warp::reply::json(&users.iter().map(to_json).collect::<Vec<UserJson>>()),
+        StatusCode::OK,
+    ).into_response())
+}
+
+
+
+/// Handles creating a new user, regardless of the registration policy.
+/// 
+/// **Arguments**
+///  * `ctx`: The Context of the service.
+///  * `client`: The Client that sent the request.
+///  * `claims`: The claims of the administrator, as taken from their access token.
+///  * `body`: The message body we got with the request.
+/// 
+/// **Returns**  
+/// The Warp reply on success, or a Warp rejection on failure.
+pub fn handle_create(ctx: Arc<Context>, client: Client, claims: Claims, body: NewUserJson) -> Result<Response, Rejection> {
+    info!("Handling user creation (v1/admin/users)");
+    match require_admin(&ctx, &claims) {
+        Ok(None)      => {},
+        Ok(Some(res)) => { return Ok(res); },
+        Err(err)      => { throw!(err); }
+    }
+
+    // Check the display name, if any
+    if let Some(display_name) = &body.display_name {
+        if let Err(err) = verify_display_name(display_name) {
+            debug!("User creation failure: {}", err);
+            return Ok(reply::error(StatusCode::BAD_REQUEST, ErrorCode::InvalidRequest, err.to_string()));
+        }
+    }
+
+    // Administrators are held to the password policy too
+    match ctx.passwords.check(&body.username, &body.password) {
+        Ok(()) => {},
+        Err(PasswordPolicyError::Rejected{ violations }) => {
+            debug!("User creation failure: password for '{}' does not meet the password policy", body.username);
+            return Ok(reply::weak_password(violations));
+        },
+        Err(err) => { throw!(Error::PasswordPolicyError{ err }); }
+    }
+
+    // Hash the password
+    let cred: Credential = match Credential::from_plain_with(&body.username, &body.password, &ctx.hashing) {
+        Ok(cred) => cred,
+        Err(err) => { throw!(Error::CredentialError{ err }); }
+    };
+
+    // Store the new user
+    let id: u32 = match ctx.store.insert(&cred, body.role) {
+        Ok(id) => id,
+        Err(StoreError::DuplicateUser{ .. }) => {
+            debug!("User creation failure: user '{}' already exists", body.username);
+            return Ok(reply::error(StatusCode::CONFLICT, ErrorCode::Conflict, format!("User '{}' already exists", body.username)));
+        },
+        Err(err) => { throw!(Error::StoreError{ err }); }
+    };
+    if let Some(display_name) = &body.display_name {
+        if let Err(err) = ctx.store.set_display_name(id, Some(display_name)) { throw!(Error::StoreError{ err }); }
+    }
+
+    // Done
+    info!("User {} created user '{}' with role {}", claims.id, cred.user(), body.role);
+    audit::record(&ctx, &client, AuthEventKind::AdminAction, Some(id), Some(cred.user()), Some(&format!("created with role {} by user {}", body.role, claims.id)));
+    Ok(warp::reply::with_status(
+        warp::reply::json(&UserJson{ id, username: cred.user().to_string(), display_name: body.display_name, role: body.role, disabled: false, locked: false }),
+        StatusCode::CREATED,
+    ).into_response())
+}
+
+
+
+/// Handles disabling or re-enabling the given user. Disabling a user also logs out all of their sessions.
+/// 
+/// **Arguments**
+///  * `ctx`: The Context of the service.
+///  * `client`: The Client that sent the request.
+///  * `claims`: The claims of the administrator, as taken from their access token.
+///  * `username`: The name of the user to disable or enable.
+///  * `disabled`: Whether to disable (true) or re-enable (false) the user.
+/// 
+/// **Returns**  
+/// The Warp reply on success, or a Warp rejection on failure.
+pub fn handle_set_disabled(ctx: Arc<Context>, client: Client, claims: Claims, username: String, disabled: bool) -> Result<Response, Rejection> {
+    info!("Handling user {} (v1/admin/users/{{username}}/{})", if disabled { "disabling" } else { "enabling" }, if disabled { "disable" } else { "enable" });
+    match require_admin(&ctx, &claims) {
+        Ok(None)      => {},
+        Ok(Some(res)) => { return Ok(res); },
+        Err(err)      => { throw!(err); }
+    }
+
+    // Query the store for the user to update
+    debug!("Searching for user '{}'...", username);
+    let user: Account = match ctx.store.get_by_name(&username) {
+        Ok(Some(user)) => user,
+        Ok(None)       => { return Ok(unknown_user(&username)); },
+        Err(err)       => { throw!(Error::StoreError{ err }); }
+    };
+    if disabled && user.id == claims.id { return Ok(refuse_self()); }
+
+    // Update it, and log it out if it is disabled
+    if let Err(err) = ctx.store.set_disabled(user.id, disabled) { throw!(Error::StoreError{ err }); }
+    if disabled {
+        if let Err(err) = token::end_all_sessions(&*ctx.store, user.id) { throw!(Error::StoreError{ err }); }
+    }
+
+    // Done
+    info!("User {} {} user '{}'", claims.id, if disabled { "disabled" } else { "re-enabled" }, user.credential.user());
+    audit::record(&ctx, &client, AuthEventKind::AdminAction, Some(user.id), Some(user.credential.user()), Some(&format!("{} by user {}", if disabled { "disabled" } else { "re-enabled" }, claims.id)));
+    Ok(warp::reply::with_status(
+        String::from("success"),
+        StatusCode::OK,
+    ).into_response())
+}
+
+
+
+/// Handles deleting the given user.
+/// 
+/// **Arguments**
+///  * `ctx`: The Context of the service.
+///  * `client`: The Client that sent the request.
+///  * `claims`: The claims of the administrator, as taken from their access token.
+///  * `username`: The name of the user to delete.
+/// 
+/// **Returns**  
+/// The Warp reply on success, or a Warp rejection on failure.
+pub fn handle_delete(ctx: Arc<Context>, client: Client, claims: Claims, username: String) -> Result<Response, Rejection> {
+    info!("Handling user deletion (v1/admin/users/{{username}})");
+    match require_admin(&ctx, &claims) {
+        Ok(None)      => {},
+        Ok(Some(res)) => { return Ok(res); },
+        Err(err)      => { throw!(err); }
+    }
+
+    // Query the store for the user to delete
+    debug!("Searching for user '{}'...", username);
+    let user: Account = match ctx.store.get_by_name(&username) {
+        Ok(Some(user)) => user,
+        Ok(None)       => { return Ok(unknown_user(&username)); },
+        Err(err)       => { throw!(Error::StoreError{ err }); }
+    };
+    if user.id == claims.id { return Ok(refuse_self()); }
+
+    // Delete it (which takes its tokens and second factor with it)
+    if let Err(err) = ctx.store.delete(user.id) { throw!(Error::StoreError{ err }); }
+    ctx.throttle.clear(&ThrottleKey::user(user.credential.user()));
+
+    // Done
+    info!("User {} deleted user '{}'", claims.id, user.credential.user());
+    audit::record(&ctx, &client, AuthEventKind::AdminAction, Some(user.id), Some(user.credential.user()), Some(&format!("deleted by user {}", claims.id)));
+    Ok(warp::reply::with_status(
+        String::from("success"),
+        StatusCode::OK,
+    ).into_response())
+}
+
+
+
+/// Handles setting a new password for the given user, e.g., because they forgot theirs. This logs out all of their sessions.
+/// 
+/// **Arguments**
+///  * `ctx`: The Context of the service.
+///  * `client`: The Client that sent the request.
+///  * `claims`: The claims of the administrator, as taken from their access token.
+///  * `username`: The name of the user whose password to reset.
+///  * `body`: The message body we got with the request.
+/// 
+/// **Returns**  
+/// The Warp reply on success, or a Warp rejection on failure.
+pub fn handle_reset_password(ctx: Arc<Context>, client: Client, claims: Claims, username: String, body: ResetPasswordJson) -> Result<Response, Rejection> {
+    info!("Handling password reset (v1/admin/users/{{username}}/password)");
+    match require_admin(&ctx, &claims) {
+        Ok(None)      => {},
+        Ok(Some(res)) => { return Ok(res); },
+        Err(err)      => { throw!(err); }
+    }
+
+    // Query the store for the user to update
+    debug!("Searching for user '{}'...", username);
+    let user: Account = match ctx.store.get_by_name(&username) {
+        Ok(Some(user)) => user,
+        Ok(None)       => { return Ok(unknown_user(&username)); },
+        Err(err)       => { throw!(Error::StoreError{ err }); }
+    };
+
+    // Check the new password against the policy
+    match ctx.passwords.check(user.credential.user(), &body.password) {
+        Ok(()) => {},
+        Err(PasswordPolicyError::Rejected{ violations }) => {
+            debug!("Password reset failure: new password for '{}' does not meet the password policy", username);
+            return Ok(reply::weak_password(violations));
+        },
+        Err(err) => { throw!(Error::PasswordPolicyError{ err }); }
+    }
+
+    // Hash the new password and store it
+    let cred: Credential = match Credential::from_plain_with(user.credential.user(), &body.password, &ctx.hashing) {
+        Ok(cred) => cred,
+        Err(err) => { throw!(Error::CredentialError{ err }); }
+    };
+    if let Err(err) = ctx.store.update_password(user.id, cred.pass()) { throw!(Error::StoreError{ err }); }
+
+    // Log out every session of the user
+    if let Err(err) = token::end_all_sessions(&*ctx.store, user.id) { throw!(Error::StoreError{ err }); }
+
+    // Done
+    info!("User {} reset the password of user '{}'", claims.id, user.credential.user());
+    audit::record(&ctx, &client, AuthEventKind::AdminAction, Some(user.id), Some(user.credential.user()), Some(&format!("password reset by user {}", claims.id)));
+    Ok(warp::reply::with_status(
+        String::from("success"),
+        StatusCode::OK,
+    ).into_response())
+}
+
+
+
+/// Handles issuing a one-time password reset token for the given user, which they can use to set a new password themselves (see `reset::handle()`).
+/// 
+/// **Arguments**
+///  * `ctx`: The Context of the service.
+///  * `client`: The Client that sent the request.
+///  * `claims`: The claims of the administrator, as taken from their access token.
+///  * `username`: The name of the user to issue the token for.
+/// 
+/// **Returns**  
+/// The Warp reply on success, or a Warp rejection on failure.
+pub fn handle_reset_token(ctx: Arc<Context>, client: Client, claims: Claims, username: String) -> Result<Response, Rejection> {
+    info!("Handling reset token issuing (v1/admin/users/{{username}}/reset-token)");
+    match require_admin(&ctx, &claims) {
+        Ok(None)      => {},
+        Ok(Some(res)) => { return Ok(res); },
+        Err(err)      => { throw!(err); }
+    }
+
+    // Query the store for the user to issue the token for
+    debug!("Searching for user '{}'...", username);
+    let user: Account = match ctx.store.get_by_name(&username) {
+        Ok(Some(user)) => user,
+        Ok(None)       => { return Ok(unknown_user(&username)); },
+        Err(err)       => { throw!(Error::StoreError{ err }); }
+    };
+
+    // Issue the token, which replaces any older one
+    let reset_token: String = match reset::issue(&*ctx.store, user.id, ctx.tokens.reset_lifetime) {
+        Ok(reset_token) => reset_token,
+        Err(err)        => { throw!(Error::StoreError{ err }); }
+    };
+
+    // Done
+    info!("User {} issued a reset token for user '{}'", claims.id, user.credential.user());
+    audit::record(&ctx, &client, AuthEventKind::AdminAction, Some(user.id), Some(user.credential.user()), Some(&format!("reset token issued by user {}", claims.id)));
+    Ok(warp::reply::with_status(
+        warp::reply::json(&ResetTokenJson{ reset_token, expires_in: ctx.tokens.reset_lifetime.as_secs() }),
+        StatusCode::CREATED,
+    ).into_response())
+}
+
+
+
+/// Handles lifting the lockout of the given user, which also forgets about any of their failed logins.
+/// 
+/// **Arguments**
+///  * `ctx`: The Context of the service.
+///  * `client`: The Client that sent the request.
+///  * `claims`: The claims of the administrator, as taken from their access token.
+///  * `username`: The name of the user to unlock.
+/// 
+/// **Returns**  
+/// The Warp reply on success, or a Warp rejection on failure.
+pub fn handle_unlock(ctx: Arc<Context>, client: Client, claims: Claims, username: String) -> Result<Response, Rejection> {
+    info!("Handling lockout removal (v1/admin/lockouts)");
+    match require_admin(&ctx, &claims) {
+        Ok(None)      => {},
+        Ok(Some(res)) => { return Ok(res); },
+        Err(err)      => { throw!(err); }
+    }
+
+    // Query the store for the user to unlock
+    debug!("Searching for user '{}'...", username);
+    let user: Account = match ctx.store.get_by_name(&username) {
+        Ok(Some(user)) => user,
+        Ok(None)       => { return Ok(unknown_user(&username)); },
+        Err(err)       => { throw!(Error::StoreError{ err }); }
+    };
+
+    // Reset both the persistent lockout and the throttling of the username
+    if let Err(err) = ctx.store.reset_login_failures(user.id) { throw!(Error::StoreError{ err }); }
+    ctx.throttle.clear(&ThrottleKey::user(user.credential.user()));
+
+    // Done
+    info!("User {} lifted the lockout of user '{}'", claims.id, user.credential.user());
+    audit::record(&ctx, &client, AuthEventKind::AdminAction, Some(user.id), Some(user.credential.user()), Some(&format!("lockout lifted by user {}", claims.id)));
+    Ok(warp::reply::with_status(
+        String::from("success"),
+        StatusCode::OK,
+    ).into_response())
+}
+
+
+
+/// Handles searching the audit log, newest events first.
+/// 
+/// **Arguments**
+///  * `ctx`: The Context of the service.
+///  * `claims`: The claims of the administrator, as taken from their access token.
+///  * `query`: The query parameters that determine which events to return.
+/// 
+/// **Returns**  
+/// The Warp reply on success, or a Warp rejection on failure.
+pub fn handle_events(ctx: Arc<Context>, claims: Claims, query: AuthEventQuery) -> Result<Response, Rejection> {
+    info!("Handling audit log query (v1/admin/events)");
+    match require_admin(&ctx, &claims) {
+        Ok(None)      => {},
+        Ok(Some(res)) => { return Ok(res); },
+        Err(err)      => { throw!(err); }
+    }
+
+    // Build the filter from the query
+    let since: Option<OffsetDateTime> = match from_timestamp("since", query.since) {
+        Ok(since) => since,
+        Err(res)  => { return Ok(res); }
+    };
+    let until: Option<OffsetDateTime> = match from_timestamp("until", query.until) {
+        Ok(until) => until,
+        Err(res)  => { return Ok(res); }
+    };
+    let offset: u64 = query.offset.unwrap_or(0);
+    let limit: u64  = query.limit.unwrap_or(AUDIT_PAGE_SIZE).clamp(1, AUDIT_MAX_PAGE_SIZE);
+
+    // Ask for one event more than we return, so we know if there is a next page
+    let mut events: Vec<AuthEvent> = match ctx.store.query_events(&EventFilter{ username: query.user, kind: query.kind, since, until, offset, limit: limit + 1 }) {
+        Ok(events) => events,
+        Err(err)   => { throw!(Error::StoreError{ err }); }
+    };
+    let next_offset: Option<u64> = if events.len() as u64 > limit {
+        events.truncate(limit as usize);
+        Some(offset + limit)
+    } else {
+        None
+    };
+
+    // Done
+    Ok(warp::reply::with_status(
+        warp::reply::json(&AuthEventPageJson{ events: events.into_iter().map(event_to_json).collect(), next_offset }),
+        StatusCode::OK,
+    ).into_response())
+}
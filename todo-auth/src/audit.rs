@@ -0,0 +1,154 @@
+/* AUDIT.rs
+ *   by Lut99
+ *
+ * Created:
+ *   18 Oct 2026, 04:10:50
+ * Last edited:
+ *   18 Oct 2026, 05:44:48
+ * Auto updated?
+ *   Yes
+ *
+ * Description:
+ *   Implements the audit log of the authorization service, which records
+ *   every authentication event in the store (together with where it came
+ *   from) so that it survives restarts.
+**/
+
+use std::net::SocketAddr;
+
+use log::error;
+use time::OffsetDateTime;
+use warp::{Filter, Rejection};
+
+use todo_spec::api::AuthEventKind;
+
+use crate::spec::{AuthEvent, Context, AUDIT_FIELD_MAX_LENGTH};
+use crate::store::Store;
+use crate::tls::PeerAddr;
+
+
+/***** UNIT TESTS *****/
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_truncate() {
+        assert_eq!(truncate("john", 8), "john");
+        assert_eq!(truncate("johnjohnjohn", 8), "johnjohn");
+        // Multi-byte characters are never split
+        assert_eq!(truncate("ééééé", 3), "ééé");
+    }
+}
+
+
+
+
+
+/***** HELPER FUNCTIONS *****/
+/// Cuts off the given string after the given number of characters, so that clients cannot fill the audit log (or other parts of the store) with arbitrarily long values.
+/// 
+/// **Arguments**
+///  * `value`: The string to cut off.
+///  * `max`: The maximum number of characters to keep.
+/// 
+/// **Returns**  
+/// The (possibly) shortened string.
+pub(crate) fn truncate(value: &str, max: usize) -> String {
+    value.chars().take(max).collect()
+}
+
+
+
+/// Writes an event to the audit log of the given store, logging (but otherwise ignoring) any failure.
+/// 
+/// **Arguments**
+///  * `store`: The Store with the audit log.
+///  * `client`: The Client that caused the event.
+///  * `kind`: The kind of event.
+///  * `user_id`: The identifier of the user the event is about, if they exist.
+///  * `username`: The name of the user the event is about, if known.
+///  * `detail`: Further details about the event, if any.
+fn insert(store: &dyn Store, client: &Client, kind: AuthEventKind, user_id: Option<u32>, username: Option<&str>, detail: Option<&str>) {
+    let event = AuthEvent {
+        id         : 0,
+        time       : OffsetDateTime::now_utc(),
+        kind,
+        user_id,
+        username   : username.map(|username| truncate(username, AUDIT_FIELD_MAX_LENGTH)),
+        ip         : client.addr.map(|addr| addr.ip().to_string()),
+        user_agent : client.user_agent.as_deref().map(|user_agent| truncate(user_agent, AUDIT_FIELD_MAX_LENGTH)),
+        detail     : detail.map(|detail| truncate(detail, AUDIT_FIELD_MAX_LENGTH)),
+    };
+    if let Err(err) = store.insert_event(&event) {
+        error!("Could not record {} event in the audit log: {}", kind, err);
+    }
+}
+
+
+
+
+
+
+/***** LIBRARY FUNCTIONS *****/
+/// Builds a warp filter that extracts where a request comes from, for recording in the audit log.
+/// 
+/// Works both when warp accepts the connections itself and when we terminate TLS (see `tls::serve()`).
+/// 
+/// **Returns**  
+/// A warp filter that extracts a Client.
+pub fn client() -> impl Filter<Extract = (Client,), Error = Rejection> + Clone {
+    warp::addr::remote()
+        .and(warp::ext::optional::<PeerAddr>())
+        .and(warp::header::optional::<String>("user-agent"))
+        .map(|addr: Option<SocketAddr>, peer: Option<PeerAddr>, user_agent: Option<String>| Client{ addr: addr.or(peer.map(|peer| peer.0)), user_agent })
+}
+
+
+
+/// Records an event in the audit log. Logins are counted in the metrics as well.
+/// 
+/// Failing to do so is logged, but otherwise ignored; we don't refuse logins just because the audit log is unavailable.
+/// 
+/// **Arguments**
+///  * `ctx`: The Context of the service.
+///  * `client`: The Client that caused the event.
+///  * `kind`: The kind of event.
+///  * `user_id`: The identifier of the user the event is about, if they exist.
+///  * `username`: The name of the user the event is about, if known.
+///  * `detail`: Further details about the event, if any.
+pub fn record(ctx: &Context, client: &Client, kind: AuthEventKind, user_id: Option<u32>, username: Option<&str>, detail: Option<&str>) {
+    match kind {
+        AuthEventKind::LoginSuccess => ctx.metrics.record_login(true),
+        AuthEventKind::LoginFailure => ctx.metrics.record_login(false),
+        _                           => {},
+    }
+    insert(ctx.store.as_ref(), client, kind, user_id, username, detail);
+}
+
+/// Records an administrative action that was taken on the command line (see `manage`) instead of through the API.
+/// 
+/// Like `record()`, failing to do so is logged, but otherwise ignored.
+/// 
+/// **Arguments**
+///  * `store`: The Store with the audit log.
+///  * `user_id`: The identifier of the user the action is about.
+///  * `username`: The name of the user the action is about.
+///  * `detail`: What was done to the user.
+pub fn record_local(store: &dyn Store, user_id: u32, username: &str, detail: &str) {
+    insert(store, &Client::default(), AuthEventKind::AdminAction, Some(user_id), Some(username), Some(detail));
+}
+
+
+
+
+
+/***** LIBRARY STRUCTS *****/
+/// Describes where a request comes from.
+#[derive(Clone, Debug, Default)]
+pub struct Client {
+    /// The address of the client, if known.
+    pub addr       : Option<SocketAddr>,
+    /// The User-Agent that the client sent, if any.
+    pub user_agent : Option<String>,
+}
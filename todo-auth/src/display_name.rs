@@ -0,0 +1,93 @@
+/* DISPLAY_NAME.rs
+ *   by Lut99
+ *
+ * Created:
+ *   18 Oct 2026, 06:15:20
+ * Last edited:
+ *   18 Oct 2026, 06:24:13
+ * Auto updated?
+ *   Yes
+ *
+ * Description:
+ *   Handles logged-in users changing their display name.
+**/
+
+use std::sync::Arc;
+
+use log::{debug, error, info};
+use warp::{Rejection, Reply};
+use warp::http::StatusCode;
+use warp::reply::Response;
+
+use todo_spec::api::{AuthEventKind, DisplayNameJson, ErrorCode};
+use todo_spec::credentials::verify_display_name;
+
+pub use crate::errors::DisplayNameError as Error;
+pub use crate::spec::{Account, Context};
+use crate::audit::{self, Client};
+use crate::reply;
+
+
+/***** HELPER MACROS *****/
+/// Writes the given error both to stderr via error!() and returns it as a custom reject
+macro_rules! throw {
+    ($err:expr) => {
+        let err = $err;
+        error!("{}", &err);
+        return Err(warp::reject::custom(err));
+    };
+}
+
+
+
+
+
+/***** LIBRARY FUNCTIONS *****/
+/// Handles changing (or removing) the display name of the authenticated user.
+/// 
+/// Note that access tokens that have already been handed out keep carrying the old name until they are refreshed.
+/// 
+/// **Arguments**
+///  * `ctx`: The Context of the service.
+///  * `client`: The Client that sent the request.
+///  * `user_id`: The identifier of the user, as taken from their access token.
+///  * `body`: The message body we got with the request.
+/// 
+/// **Returns**  
+/// The Warp reply on success, or a Warp rejection on failure.
+pub fn handle(ctx: Arc<Context>, client: Client, user_id: u32, body: DisplayNameJson) -> Result<Response, Rejection> {
+    info!("Handling display name change (v1/display-name)");
+
+    // Query the store for this user
+    debug!("Searching for user {}...", user_id);
+    let user: Account = match ctx.store.get_by_id(user_id) {
+        Ok(Some(user)) => user,
+        Ok(None)       => {
+            debug!("User {} display name change failure: unknown user", user_id);
+            return Ok(reply::error(StatusCode::UNAUTHORIZED, ErrorCode::Unauthorized, "Unknown user"));
+        },
+        Err(err) => { throw!(Error::StoreError{ err }); }
+    };
+
+    // Check the new name
+    if let Some(display_name) = &body.display_name {
+        if let Err(err) = verify_display_name(display_name) {
+            debug!("User '{}' display name change failure: {}", user.credential.user(), err);
+            return Ok(reply::error(StatusCode::BAD_REQUEST, ErrorCode::InvalidRequest, err.to_string()));
+        }
+    }
+
+    // Store it
+    if let Err(err) = ctx.store.set_display_name(user.id, body.display_name.as_deref()) { throw!(Error::StoreError{ err }); }
+
+    // Done
+    info!("User '{}' changed their display name", user.credential.user());
+    audit::record(&ctx, &client, AuthEventKind::DisplayNameChange, Some(user.id), Some(user.credential.user()), Some(&match &body.display_name {
+        Some(display_name) => format!("display name set to '{}'", display_name),
+        None               => String::from("display name removed"),
+    }));
+    Ok(warp::reply::with_status(
+        String::from("success"),
+        StatusCode::OK,
+    ).into_response())
+}
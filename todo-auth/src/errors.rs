@@ -4,7 +4,7 @@
  * Created:
  *   19 Mar 2022, 11:48:04
  * Last edited:
- *   19 Mar 2022, 21:07:54
+ *   18 Oct 2026, 06:42:40
  * Auto updated?
  *   Yes
  *
@@ -14,6 +14,8 @@
 
 use std::error::Error;
 use std::fmt::{Display, Formatter, Result as FResult};
+use std::net::SocketAddr;
+use std::path::PathBuf;
 
 use url::Url;
 use warp::reject::Reject;
@@ -23,46 +25,256 @@ use warp::reject::Reject;
 /// Defines top-level errors.
 #[derive(Debug)]
 pub enum AuthError {
+    /// Could not load the configuration
+    ConfigError{ err: ConfigError },
+
     /// Something went wrong with a credential
     CredentialError{ err: todo_spec::credentials::Error },
     /// Could not match the given two credentials
     CredentialVerifyError{ err: todo_spec::credentials::Error },
     /// The given root credentials are outdated
     RootCredentialsOutdated,
+    /// Could not read the passphrase of the credential files from the given file descriptor
+    PassphraseReadError{ fd: i32, err: std::io::Error },
+    /// The environment variable with the passphrase of the credential files is not valid
+    PassphraseEnvError{ name: &'static str, err: std::env::VarError },
+
+    /// The given password hashing parameters are invalid
+    HashPolicyError{ err: todo_spec::credentials::Error },
+    /// Could not read the file with the password pepper
+    PepperReadError{ path: PathBuf, err: std::io::Error },
+    /// Could not create the policy that new passwords have to meet
+    PasswordPolicyError{ err: todo_spec::password::Error },
+
+    /// Could not read the file with invite codes
+    InviteCodesReadError{ path: PathBuf, err: std::io::Error },
+    /// The registration mode requires invite codes, but none were given
+    MissingInviteCodes,
+
+    /// Could not load a key to sign or verify JWT's with
+    JwtKeyError{ path: PathBuf, err: todo_spec::jwt::Error },
+    /// The key to sign JWT's with is not for the configured algorithm
+    JwtAlgorithmMismatch{ path: PathBuf, expected: todo_spec::jwt::Algorithm, got: todo_spec::jwt::Algorithm },
+    /// The key to sign JWT's with is only a public key
+    JwtKeyNotPrivate{ path: PathBuf },
+
+    /// Something went wrong in the account store
+    StoreError{ err: StoreError },
+    /// Could not migrate the schema of the account store
+    MigrationError{ err: MigrationError },
+
+    /// Could not listen for SIGHUP
+    SignalError{ err: std::io::Error },
+
+    /// Could not run an account management subcommand
+    ManageError{ err: ManageError },
+}
+
+impl Display for AuthError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FResult {
+        match self {
+            AuthError::ConfigError{ err } => write!(f, "{}", err),
+
+            AuthError::CredentialError{ err }          => write!(f, "{}", err),
+            AuthError::CredentialVerifyError{ err }    => write!(f, "Could not verify credentials: {}", err),
+            AuthError::RootCredentialsOutdated         => write!(f, "The provided root credentials are outdated; update the file or re-generate the database"),
+            AuthError::PassphraseReadError{ fd, err }  => write!(f, "Could not read credential passphrase from file descriptor {}: {}", fd, err),
+            AuthError::PassphraseEnvError{ name, err } => write!(f, "Could not read credential passphrase from environment variable '{}': {}", name, err),
+
+            AuthError::HashPolicyError{ err }       => write!(f, "Could not create password hashing policy: {}", err),
+            AuthError::PepperReadError{ path, err } => write!(f, "Could not read password pepper file '{}': {}", path.display(), err),
+            AuthError::PasswordPolicyError{ err }   => write!(f, "Could not create password policy: {}", err),
+
+            AuthError::InviteCodesReadError{ path, err } => write!(f, "Could not read invite codes file '{}': {}", path.display(), err),
+            AuthError::MissingInviteCodes                => write!(f, "Registration mode 'invite' requires an invite codes file (see '--invite-codes')"),
+
+            AuthError::JwtKeyError{ path, err }                    => write!(f, "Could not load JWT key '{}': {}", path.display(), err),
+            AuthError::JwtAlgorithmMismatch{ path, expected, got } => write!(f, "JWT key '{}' is an {} key, but the configured algorithm is {}", path.display(), got, expected),
+            AuthError::JwtKeyNotPrivate{ path }                    => write!(f, "JWT key '{}' is a public key; a private key is needed to sign JWT's", path.display()),
 
+            AuthError::StoreError{ err }     => write!(f, "{}", err),
+            AuthError::MigrationError{ err } => write!(f, "Could not migrate database: {}", err),
+
+            AuthError::SignalError{ err } => write!(f, "Could not listen for SIGHUP: {}", err),
+
+            AuthError::ManageError{ err } => write!(f, "{}", err),
+        }
+    }
+}
+
+impl Error for AuthError {}
+
+
+
+/// Defines the errors that may occur while loading the configuration
+#[derive(Debug)]
+pub enum ConfigError {
+    /// Could not read the configuration file
+    ReadError{ path: PathBuf, err: std::io::Error },
+    /// Could not parse the configuration file
+    ParseError{ path: PathBuf, err: toml::de::Error },
+    /// Could not serialize the configuration
+    SerializeError{ err: toml::ser::Error },
+
+    /// The MySQL store is used, but no URL is given
+    MissingMySqlUrl,
+    /// Only one of the TLS certificate and key is given
+    IncompleteTls,
+    /// The minimum length of passwords is larger than the maximum length
+    IllegalPasswordLength{ min: usize, max: usize },
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FResult {
+        match self {
+            ConfigError::ReadError{ path, err }  => write!(f, "Could not read configuration file '{}': {}", path.display(), err),
+            ConfigError::ParseError{ path, err } => write!(f, "Could not parse configuration file '{}': {}", path.display(), err),
+            ConfigError::SerializeError{ err }   => write!(f, "Could not serialize configuration: {}", err),
+
+            ConfigError::MissingMySqlUrl                 => write!(f, "Store 'mysql' requires a MySQL URL (see '--mysql-url' or 'store.mysql_url')"),
+            ConfigError::IncompleteTls                   => write!(f, "A TLS certificate and private key must be given together (see '--tls-cert' and '--tls-key', or 'server.tls_cert' and 'server.tls_key')"),
+            ConfigError::IllegalPasswordLength{ min, max } => write!(f, "Minimum password length {} is larger than the maximum password length {} (see 'password.min_length' and 'password.max_length')", min, max),
+        }
+    }
+}
+
+impl Error for ConfigError {}
+
+
+
+/// Defines the errors that may occur while serving over TLS
+#[derive(Debug)]
+pub enum TlsError {
+    /// Could not read the certificate file
+    CertReadError{ path: PathBuf, err: std::io::Error },
+    /// The certificate file does not contain any certificates
+    MissingCertificates{ path: PathBuf },
+    /// Could not read the private key file
+    KeyReadError{ path: PathBuf, err: std::io::Error },
+    /// The private key file does not contain a private key
+    MissingKey{ path: PathBuf },
+    /// The certificate and key do not make a valid configuration (e.g., because they don't match)
+    ConfigError{ err: tokio_rustls::rustls::Error },
+
+    /// Could not bind the server to the given address
+    BindError{ addr: SocketAddr, err: std::io::Error },
+    /// Could not listen for SIGHUP
+    SignalError{ err: std::io::Error },
+}
+
+impl Display for TlsError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FResult {
+        match self {
+            TlsError::CertReadError{ path, err }  => write!(f, "Could not read TLS certificate file '{}': {}", path.display(), err),
+            TlsError::MissingCertificates{ path } => write!(f, "TLS certificate file '{}' does not contain any PEM-encoded certificates", path.display()),
+            TlsError::KeyReadError{ path, err }   => write!(f, "Could not read TLS private key file '{}': {}", path.display(), err),
+            TlsError::MissingKey{ path }          => write!(f, "TLS private key file '{}' does not contain a PEM-encoded (PKCS#8, PKCS#1 or SEC1) private key", path.display()),
+            TlsError::ConfigError{ err }          => write!(f, "Invalid TLS certificate or private key: {}", err),
+
+            TlsError::BindError{ addr, err } => write!(f, "Could not bind to '{}': {}", addr, err),
+            TlsError::SignalError{ err }     => write!(f, "Could not listen for SIGHUP: {}", err),
+        }
+    }
+}
+
+impl Error for TlsError {}
+
+
+
+/// Defines the errors that may occur while working with an account store
+#[derive(Debug)]
+pub enum StoreError {
     /// Could not create the connection pool
     MySqlPoolCreateError{ url: Url, err: mysql::Error },
     /// Could not connect to the local MySQL database
     MySqlConnectError{ err: mysql::Error },
     /// Could not execute the given query
     MySqlQueryError{ query: String, err: mysql::Error },
+
+    /// Could not open the given SQLite database file
+    SqliteOpenError{ path: PathBuf, err: rusqlite::Error },
+    /// Could not execute the given query
+    SqliteQueryError{ query: String, err: rusqlite::Error },
+
+    /// The store contains an account that is not a valid Credential
+    IllegalAccount{ id: u32, err: todo_spec::credentials::Error },
+    /// The store contains an account with a role we do not know
+    IllegalRole{ id: u32, err: todo_spec::jwt::Error },
+    /// The store contains an audit event of a kind we do not know
+    IllegalEvent{ id: u64, err: todo_spec::api::Error },
+    /// The store contains a personal access token with a scope we do not know
+    IllegalAccessToken{ id: u32, err: todo_spec::api::Error },
+    /// A user with the given name already exists
+    DuplicateUser{ username: String },
+    /// The user with the given identifier does not exist
+    UnknownUser{ id: u32 },
+    /// The user already has a personal access token with the given name
+    DuplicateAccessToken{ name: String },
 }
 
-impl Display for AuthError {
+impl Display for StoreError {
     fn fmt(&self, f: &mut Formatter<'_>) -> FResult {
         match self {
-            AuthError::CredentialError{ err }       => write!(f, "{}", err),
-            AuthError::CredentialVerifyError{ err } => write!(f, "Could not verify credentials: {}", err),
-            AuthError::RootCredentialsOutdated      => write!(f, "The provided root credentials are outdated; update the file or re-generate the database"),
+            StoreError::MySqlPoolCreateError{ url, err } => write!(f, "Could not create a MySQL connection pool to database @ {}: {}", url, err),
+            StoreError::MySqlConnectError{ err }         => write!(f, "Could not connect to MySQL database: {}", err),
+            StoreError::MySqlQueryError{ query, err }    => write!(f, "Could not execute query '{}': {}", query, err),
+
+            StoreError::SqliteOpenError{ path, err }   => write!(f, "Could not open SQLite database '{}': {}", path.display(), err),
+            StoreError::SqliteQueryError{ query, err } => write!(f, "Could not execute query '{}': {}", query, err),
 
-            AuthError::MySqlPoolCreateError{ url, err } => write!(f, "Could not create a MySQL connection pool to database @ {}: {}", url, err),
-            AuthError::MySqlConnectError{ err }         => write!(f, "Could not connect to MySQL database: {}", err),
-            AuthError::MySqlQueryError{ query, err }    => write!(f, "Could not execute query '{}': {}", query, err),
+            StoreError::IllegalAccount{ id, err } => write!(f, "Account with ID {} in the store is invalid: {}", id, err),
+            StoreError::IllegalRole{ id, err }    => write!(f, "Account with ID {} in the store has an invalid role: {}", id, err),
+            StoreError::IllegalEvent{ id, err }       => write!(f, "Audit event with ID {} in the store is invalid: {}", id, err),
+            StoreError::IllegalAccessToken{ id, err } => write!(f, "Personal access token with ID {} in the store is invalid: {}", id, err),
+            StoreError::DuplicateUser{ username }     => write!(f, "User '{}' already exists", username),
+            StoreError::UnknownUser{ id }             => write!(f, "Unknown user with ID {}", id),
+            StoreError::DuplicateAccessToken{ name }  => write!(f, "A personal access token named '{}' already exists", name),
         }
     }
 }
 
-impl Error for AuthError {}
+impl Error for StoreError {}
+
+
+
+/// Defines the errors that may occur while migrating the schema of a store
+#[derive(Debug)]
+pub enum MigrationError {
+    /// Something went wrong in the store itself
+    StoreError{ err: StoreError },
+
+    /// The store has migrations applied that this binary does not know, so it was probably upgraded by a newer version
+    DatabaseTooNew{ database: u32, binary: u32 },
+    /// The store has not been migrated to the latest version, and we were told not to do so ourselves
+    PendingMigrations{ database: u32, binary: u32 },
+    /// The store has a migration applied that is not known to this binary, so we cannot revert it
+    UnknownMigration{ version: u32 },
+    /// The requested target version does not exist
+    UnknownTarget{ version: u32 },
+}
+
+impl Display for MigrationError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FResult {
+        match self {
+            MigrationError::StoreError{ err } => write!(f, "{}", err),
+
+            MigrationError::DatabaseTooNew{ database, binary }    => write!(f, "Database schema is at version {}, but this binary only knows up to version {}; upgrade todo-auth (or revert the database with a newer binary)", database, binary),
+            MigrationError::PendingMigrations{ database, binary } => write!(f, "Database schema is at version {}, but this binary needs version {}; run 'todo-auth migrate up' first", database, binary),
+            MigrationError::UnknownMigration{ version }           => write!(f, "Database has migration {} applied, which this binary does not know", version),
+            MigrationError::UnknownTarget{ version }              => write!(f, "There is no migration with version {}", version),
+        }
+    }
+}
+
+impl Error for MigrationError {}
 
 
 
 /// Defines the errors that may occur during login
 #[derive(Debug)]
 pub enum LoginError {
-    /// Could not connect to the local MySQL database
-    MySqlConnectError{ err: mysql::Error },
-    /// Could not execute the given query
-    MySqlQueryError{ query: String, err: mysql::Error },
+    /// Something went wrong in the account store
+    StoreError{ err: StoreError },
 
     /// Something went wrong with a credential
     CredentialError{ err: todo_spec::credentials::Error },
@@ -71,24 +283,20 @@ pub enum LoginError {
     /// The given user was not known to the system
     UnknownUser{ username: String },
 
-    /// Could not create the Hmac key for the JWT's
-    HmacKeyError{ err: hmac::digest::InvalidLength },
-    /// Could not create the given JWT
-    JwtCreateError{ err: jwt::Error },
+    /// Could not issue new tokens
+    TokenError{ err: TokenError },
 }
 
 impl Display for LoginError {
     fn fmt(&self, f: &mut Formatter<'_>) -> FResult {
         match self {
-            LoginError::MySqlConnectError{ err }      => write!(f, "Could not connect to MySQL database: {}", err),
-            LoginError::MySqlQueryError{ query, err } => write!(f, "Could not execute query '{}': {}", query, err),
+            LoginError::StoreError{ err } => write!(f, "{}", err),
 
             LoginError::CredentialError{ err }       => write!(f, "{}", err),
             LoginError::CredentialVerifyError{ err } => write!(f, "Could not verify credentials: {}", err),
             LoginError::UnknownUser{ username }      => write!(f, "Unknown user '{}'", username),
 
-            LoginError::HmacKeyError{ err }   => write!(f, "Could not create Hmac key: {}", err),
-            LoginError::JwtCreateError{ err } => write!(f, "Could not create JWT: {}", err),
+            LoginError::TokenError{ err } => write!(f, "{}", err),
         }
     }
 }
@@ -96,3 +304,292 @@ impl Display for LoginError {
 impl Error for LoginError {}
 
 impl Reject for LoginError {}
+
+
+
+/// Defines the errors that may occur during registration
+#[derive(Debug)]
+pub enum RegisterError {
+    /// Something went wrong in the account store
+    StoreError{ err: StoreError },
+
+    /// Something went wrong with a credential
+    CredentialError{ err: todo_spec::credentials::Error },
+    /// Could not check the password against the password policy
+    PasswordPolicyError{ err: todo_spec::password::Error },
+}
+
+impl Display for RegisterError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FResult {
+        match self {
+            RegisterError::StoreError{ err } => write!(f, "{}", err),
+
+            RegisterError::CredentialError{ err }     => write!(f, "{}", err),
+            RegisterError::PasswordPolicyError{ err } => write!(f, "{}", err),
+        }
+    }
+}
+
+impl Error for RegisterError {}
+
+impl Reject for RegisterError {}
+
+
+
+/// Defines the errors that may occur while changing a password
+#[derive(Debug)]
+pub enum PasswordError {
+    /// Something went wrong in the account store
+    StoreError{ err: StoreError },
+
+    /// Something went wrong with a credential
+    CredentialError{ err: todo_spec::credentials::Error },
+    /// Could not match the given two credentials
+    CredentialVerifyError{ err: todo_spec::credentials::Error },
+    /// Could not check the password against the password policy
+    PasswordPolicyError{ err: todo_spec::password::Error },
+}
+
+impl Display for PasswordError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FResult {
+        match self {
+            PasswordError::StoreError{ err } => write!(f, "{}", err),
+
+            PasswordError::CredentialError{ err }       => write!(f, "{}", err),
+            PasswordError::CredentialVerifyError{ err } => write!(f, "Could not verify credentials: {}", err),
+            PasswordError::PasswordPolicyError{ err }   => write!(f, "{}", err),
+        }
+    }
+}
+
+impl Error for PasswordError {}
+
+impl Reject for PasswordError {}
+
+
+
+/// Defines the errors that may occur while handling administrative actions
+#[derive(Debug)]
+pub enum AdminError {
+    /// Something went wrong in the account store
+    StoreError{ err: StoreError },
+
+    /// Something went wrong with a credential
+    CredentialError{ err: todo_spec::credentials::Error },
+    /// Could not check the password against the password policy
+    PasswordPolicyError{ err: todo_spec::password::Error },
+}
+
+impl Display for AdminError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FResult {
+        match self {
+            AdminError::StoreError{ err } => write!(f, "{}", err),
+
+            AdminError::CredentialError{ err }     => write!(f, "{}", err),
+            AdminError::PasswordPolicyError{ err } => write!(f, "{}", err),
+        }
+    }
+}
+
+impl Error for AdminError {}
+
+impl Reject for AdminError {}
+
+
+
+/// Defines the errors that may occur while managing accounts from the command line
+#[derive(Debug)]
+pub enum ManageError {
+    /// Something went wrong in the account store
+    StoreError{ err: StoreError },
+    /// The given user does not exist
+    UnknownUser{ username: String },
+
+    /// Something went wrong with a credential
+    CredentialError{ err: todo_spec::credentials::Error },
+    /// Could not prompt for a password
+    PasswordPromptError{ err: std::io::Error },
+    /// Could not read a password from stdin
+    PasswordReadError{ err: std::io::Error },
+    /// The two passwords that were entered did not match
+    PasswordMismatch,
+
+    /// Could not move the new root credentials file in place
+    RootCredentialsRenameError{ from: PathBuf, to: PathBuf, err: std::io::Error },
+}
+
+impl Display for ManageError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FResult {
+        match self {
+            ManageError::StoreError{ err }          => write!(f, "{}", err),
+            ManageError::UnknownUser{ username }    => write!(f, "User '{}' does not exist", username),
+
+            ManageError::CredentialError{ err }     => write!(f, "{}", err),
+            ManageError::PasswordPromptError{ err } => write!(f, "Could not prompt for a password: {}", err),
+            ManageError::PasswordReadError{ err }   => write!(f, "Could not read password from stdin: {}", err),
+            ManageError::PasswordMismatch           => write!(f, "Passwords do not match"),
+
+            ManageError::RootCredentialsRenameError{ from, to, err } => write!(f, "Could not move new root credentials '{}' to '{}': {}", from.display(), to.display(), err),
+        }
+    }
+}
+
+impl Error for ManageError {}
+
+
+
+/// Defines the errors that may occur while handling two-factor authentication
+#[derive(Debug)]
+pub enum TotpError {
+    /// Something went wrong in the account store
+    StoreError{ err: StoreError },
+
+    /// The store contains a TOTP secret that is not valid
+    IllegalSecret{ user_id: u32, err: todo_spec::totp::Error },
+    /// Could not hash or verify a recovery code
+    CredentialError{ err: todo_spec::credentials::Error },
+
+    /// Could not issue new tokens
+    TokenError{ err: TokenError },
+}
+
+impl Display for TotpError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FResult {
+        match self {
+            TotpError::StoreError{ err } => write!(f, "{}", err),
+
+            TotpError::IllegalSecret{ user_id, err } => write!(f, "TOTP secret of user {} in the store is invalid: {}", user_id, err),
+            TotpError::CredentialError{ err }        => write!(f, "{}", err),
+
+            TotpError::TokenError{ err } => write!(f, "{}", err),
+        }
+    }
+}
+
+impl Error for TotpError {}
+
+impl Reject for TotpError {}
+
+
+
+/// Defines the errors that may occur while resetting a forgotten password
+#[derive(Debug)]
+pub enum ResetError {
+    /// Something went wrong in the account store
+    StoreError{ err: StoreError },
+
+    /// Something went wrong with a credential
+    CredentialError{ err: todo_spec::credentials::Error },
+    /// Could not check the password against the password policy
+    PasswordPolicyError{ err: todo_spec::password::Error },
+}
+
+impl Display for ResetError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FResult {
+        match self {
+            ResetError::StoreError{ err } => write!(f, "{}", err),
+
+            ResetError::CredentialError{ err }     => write!(f, "{}", err),
+            ResetError::PasswordPolicyError{ err } => write!(f, "{}", err),
+        }
+    }
+}
+
+impl Error for ResetError {}
+
+impl Reject for ResetError {}
+
+
+
+/// Defines the errors that may occur while changing display names
+#[derive(Debug)]
+pub enum DisplayNameError {
+    /// Something went wrong in the account store
+    StoreError{ err: StoreError },
+}
+
+impl Display for DisplayNameError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FResult {
+        match self {
+            DisplayNameError::StoreError{ err } => write!(f, "{}", err),
+        }
+    }
+}
+
+impl Error for DisplayNameError {}
+
+impl Reject for DisplayNameError {}
+
+
+
+/// Defines the errors that may occur while handling sessions
+#[derive(Debug)]
+pub enum SessionError {
+    /// Something went wrong in the account store
+    StoreError{ err: StoreError },
+}
+
+impl Display for SessionError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FResult {
+        match self {
+            SessionError::StoreError{ err } => write!(f, "{}", err),
+        }
+    }
+}
+
+impl Error for SessionError {}
+
+impl Reject for SessionError {}
+
+
+
+/// Defines the errors that may occur while handling personal access tokens
+#[derive(Debug)]
+pub enum AccessTokenError {
+    /// Something went wrong in the account store
+    StoreError{ err: StoreError },
+    /// Could not hash or verify the secret of a token
+    CredentialError{ err: todo_spec::credentials::Error },
+
+    /// The token used for a request does not grant the scope that the route needs
+    MissingScope{ id: u32, scope: todo_spec::api::Scope },
+}
+
+impl Display for AccessTokenError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FResult {
+        match self {
+            AccessTokenError::StoreError{ err }      => write!(f, "{}", err),
+            AccessTokenError::CredentialError{ err } => write!(f, "{}", err),
+
+            AccessTokenError::MissingScope{ id, scope } => write!(f, "Personal access token {} does not have the '{}' scope", id, scope),
+        }
+    }
+}
+
+impl Error for AccessTokenError {}
+
+impl Reject for AccessTokenError {}
+
+
+
+/// Defines the errors that may occur while issuing, refreshing or revoking tokens
+#[derive(Debug)]
+pub enum TokenError {
+    /// Something went wrong in the account store
+    StoreError{ err: StoreError },
+    /// Could not create the given JWT
+    JwtError{ err: todo_spec::jwt::Error },
+}
+
+impl Display for TokenError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FResult {
+        match self {
+            TokenError::StoreError{ err } => write!(f, "{}", err),
+            TokenError::JwtError{ err }   => write!(f, "{}", err),
+        }
+    }
+}
+
+impl Error for TokenError {}
+
+impl Reject for TokenError {}
@@ -0,0 +1,76 @@
+/* HEALTH.rs
+ *   by Lut99
+ *
+ * Created:
+ *   18 Oct 2026, 04:44:02
+ * Last edited:
+ *   18 Oct 2026, 05:02:14
+ * Auto updated?
+ *   Yes
+ *
+ * Description:
+ *   Handles the routes with which orchestrators and monitoring check on
+ *   the authorization service: whether it is alive, whether it is ready
+ *   to serve requests, and its Prometheus metrics.
+**/
+
+use std::sync::Arc;
+
+use log::{debug, warn};
+use warp::{Rejection, Reply};
+use warp::http::StatusCode;
+use warp::reply::Response;
+
+use todo_spec::api::{ErrorCode, HealthJson};
+
+use crate::reply;
+use crate::spec::Context;
+
+
+/***** LIBRARY FUNCTIONS *****/
+/// Handles checking whether the service is alive. If it can answer at all, it is.
+/// 
+/// **Returns**  
+/// The Warp reply on success, or a Warp rejection on failure.
+pub async fn handle_health() -> Result<impl Reply, Rejection> {
+    debug!("Handling health check (healthz)");
+    Ok(warp::reply::json(&HealthJson{ status: String::from("ok") }))
+}
+
+
+
+/// Handles checking whether the service is ready to serve requests, i.e., whether the store can reach its backend.
+/// 
+/// **Arguments**
+///  * `ctx`: The Context of the service.
+/// 
+/// **Returns**  
+/// The Warp reply on success, or a Warp rejection on failure.
+pub fn handle_ready(ctx: Arc<Context>) -> Result<Response, Rejection> {
+    debug!("Handling readiness check (readyz)");
+
+    // Only our own logs learn why
+    if let Err(err) = ctx.store.check_ready() {
+        warn!("Not ready: {}", err);
+        return Ok(reply::error(StatusCode::SERVICE_UNAVAILABLE, ErrorCode::ServiceUnavailable, "The account store is not ready"));
+    }
+    Ok(warp::reply::json(&HealthJson{ status: String::from("ready") }).into_response())
+}
+
+
+
+/// Handles publishing the metrics of the service in the Prometheus text format.
+/// 
+/// **Arguments**
+///  * `ctx`: The Context of the service.
+/// 
+/// **Returns**  
+/// The Warp reply on success, or a Warp rejection on failure.
+pub async fn handle_metrics(ctx: Arc<Context>) -> Result<impl Reply, Rejection> {
+    debug!("Handling metrics request (metrics)");
+    Ok(warp::reply::with_header(
+        ctx.metrics.render(ctx.store.pool_stats(), ctx.hash_pool.stats()),
+        "Content-Type",
+        "text/plain; version=0.0.4",
+    ))
+}
@@ -4,7 +4,7 @@
  * Created:
  *   19 Mar 2022, 11:47:45
  * Last edited:
- *   19 Mar 2022, 15:36:42
+ *   18 Oct 2026, 06:24:13
  * Auto updated?
  *   Yes
  *
@@ -12,6 +12,9 @@
  *   Contains the library entrypoint for the todo-auth application.
 **/
 
+// We keep unit tests at the top of each file
+#![allow(clippy::items_after_test_module)]
+
 /// Allows the use of the log crate macros
 extern crate log;
 
@@ -19,5 +22,45 @@ extern crate log;
 pub mod errors;
 /// Contains specifications for the todo-auth service
 pub mod spec;
+/// Defines the configuration file of the service
+pub mod config;
+/// Defines the backends that store the user accounts
+pub mod store;
 /// Handles the logging in part of the service
 pub mod login;
+/// Handles the registration of new users
+pub mod register;
+/// Handles changing the password of logged-in users
+pub mod password;
+/// Handles resetting forgotten passwords with one-time tokens
+pub mod reset;
+/// Handles logged-in users changing their display name
+pub mod display_name;
+/// Handles two-factor authentication with authenticator apps
+pub mod totp;
+/// Defines how errors are reported to clients
+pub mod reply;
+/// Records authentication events in the audit log
+pub mod audit;
+/// Handles administrative actions, such as lifting lockouts
+pub mod admin;
+/// Manages accounts from the command line, without the HTTP server
+pub mod manage;
+/// Implements the brute-force protection of the login routes
+pub mod throttle;
+/// Handles issuing, refreshing and revoking tokens
+pub mod token;
+/// Handles the sessions in which users are logged in
+pub mod session;
+/// Handles the personal access tokens that scripts authenticate with
+pub mod access_token;
+/// Collects the metrics of the service for Prometheus
+pub mod metrics;
+/// Handles the health, readiness and metrics routes
+pub mod health;
+/// Runs the blocking work of the handlers off the async runtime
+pub mod workers;
+/// Implements TLS termination with reloadable certificates
+pub mod tls;
+/// Defines the warp filters for the service
+pub mod routes;
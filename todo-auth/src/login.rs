@@ -4,7 +4,7 @@
  * Created:
  *   19 Mar 2022, 12:05:59
  * Last edited:
- *   19 Mar 2022, 22:01:51
+ *   18 Oct 2026, 07:03:49
  * Auto updated?
  *   Yes
  *
@@ -13,26 +13,26 @@
 **/
 
 use std::ops::Add;
-use std::collections::BTreeMap;
 use std::sync::Arc;
 use std::time::Duration;
 
-use hmac::{Hmac, Mac};
-use jwt::SignWithKey;
-use log::{debug, error, info};
-use mysql::Pool;
-use mysql::prelude::Queryable;
-use sha2::Sha256;
+use log::{debug, error, info, warn};
 use time::OffsetDateTime;
-use time::format_description::well_known::Rfc3339;
 use warp::{Rejection, Reply};
 use warp::http::StatusCode;
+use warp::reply::Response;
 
-use todo_spec::api::LoginJson;
+use todo_spec::api::{AuthEventKind, ErrorCode, LoginJson, SecondFactorJson};
 use todo_spec::credentials::Credential;
 
 pub use crate::errors::LoginError as Error;
-pub use crate::spec::{Account, JWT_EXPIRATION_TIME};
+pub use crate::spec::{Account, Context};
+use crate::audit::{self, Client};
+use crate::reply;
+use crate::spec::CHALLENGE_EXPIRATION_TIME;
+use crate::store::Error as StoreError;
+use crate::throttle::{self, ThrottleKey};
+use crate::token;
 
 
 /***** HELPER MACROS *****/
@@ -49,159 +49,249 @@ macro_rules! throw {
 
 
 
-/***** LIBRARY FUNCTIONS *****/
-/// Handles the logging-in part of the authorization service, except that it doesn't actually login but just check the credentials.
+/***** HELPER ENUMS *****/
+/// Defines the possible outcomes of checking a user's credentials.
+enum Outcome {
+    /// The credentials are valid for the given Account. If the boolean is true, the user still has to provide their second factor.
+    Accepted(Account, bool),
+    /// The credentials were refused (or not checked at all), and the client should be sent the given reply.
+    Refused(Response),
+}
+
+
+
+
+
+/***** HELPER FUNCTIONS *****/
+/// Checks the credentials in the given login request, while protecting against brute-forcing.
+/// 
+/// Attempts are refused without checking anything if the client's IP address or the username failed too often recently, or if the account is locked. Every refusal is recorded in the audit log.
 /// 
 /// **Arguments**
-///  * `pool`: The MySQL pool of connections to use for this service.
+///  * `ctx`: The Context of the service.
+///  * `client`: The Client that is trying to login.
 ///  * `body`: The message body we got with the request.
 /// 
 /// **Returns**  
-/// The Warp reply on success, or a Warp rejection on failure.
-pub async fn handle_test(pool: Arc<Pool>, body: LoginJson) -> Result<impl Reply, Rejection> {
-    info!("Handling login test (v1/login/test)");
-
-    // Try to connect to the MySQL database
-    let mut conn = match pool.get_conn() {
-        Ok(conn) => conn,
-        Err(err) => { throw!(Error::MySqlConnectError{ err }); }
+/// Whether the credentials are valid on success, or an Error if we failed to check them.
+fn authenticate(ctx: &Context, client: &Client, body: &LoginJson) -> Result<Outcome, Error> {
+    let user_key = ThrottleKey::user(&body.username);
+    let keys: Vec<ThrottleKey> = match client.addr {
+        Some(addr) => vec![ ThrottleKey::Ip(addr.ip()), user_key.clone() ],
+        None       => vec![ user_key.clone() ],
     };
 
-    // Select the appropriate database
-    debug!("Selecting database...");
-    let query = String::from("USE todo;");
-    if let Err(err) = conn.query_drop(&query) { throw!(Error::MySqlQueryError{ query, err }); };
+    // Don't even look at the request if its source has failed too often
+    if let Some(wait) = ctx.throttle.check(&keys) {
+        debug!("User '{}' login failure: throttled for another {:.1} seconds", body.username, wait.as_secs_f64());
+        audit::record(ctx, client, AuthEventKind::LoginFailure, None, Some(&body.username), Some("throttled"));
+        return Ok(Outcome::Refused(throttle::too_many_requests(ErrorCode::TooManyRequests, wait)));
+    }
 
-    // Query the database for this username
+    // Query the store for this username
     debug!("Searching for user '{}'...", &body.username);
-    let query = format!("SELECT id, name, pass FROM users WHERE name = '{}';", &body.username);
-    let users: Vec<Account> = match conn.query_map(
-        &query,
-        |(id, name, pass)| { Account{ id, credential : Credential::new::<String, String>(name, pass).expect("Invalid username made its way into the MySQL database; this should never happen!") } }
-    ) {
-        Ok(res)  => res,
-        Err(err) => { throw!(Error::MySqlQueryError{ query, err }); }
+    let user: Account = match ctx.store.get_by_name(&body.username) {
+        Ok(Some(user)) => user,
+        Ok(None)       => {
+            debug!("User '{}' login failure: unknown user", body.username);
+            ctx.throttle.failure(&keys);
+            audit::record(ctx, client, AuthEventKind::LoginFailure, None, Some(&body.username), Some("unknown user"));
+            return Ok(Outcome::Refused(reply::error(StatusCode::NOT_FOUND, ErrorCode::UnknownUser, format!("Unknown username '{}'", body.username))));
+        },
+        Err(err) => { return Err(Error::StoreError{ err }); }
     };
 
-    // Check if there are any
-    if users.len() == 0 {
-        debug!("User '{}' login failure: unknown user", body.username);
-        return Ok(warp::reply::with_status(
-            format!("Unknown username '{}'", body.username),
-            StatusCode::NOT_FOUND,
-        ));
+    // Refuse locked accounts without checking the password
+    if let Some(wait) = locked_for(&user) {
+        debug!("User '{}' login failure: account is locked for another {} seconds", user.credential.user(), wait.as_secs());
+        audit::record(ctx, client, AuthEventKind::LoginFailure, Some(user.id), Some(user.credential.user()), Some("account is locked"));
+        return Ok(Outcome::Refused(throttle::too_many_requests(ErrorCode::AccountLocked, wait)));
     }
-    let user: &Account = &users[0];
 
     // Verify the password
-    match user.credential.verify(&body.username, &body.password) {
-        Ok(is_valid) => {
-            if !is_valid {
-                debug!("User '{}' login failure: invalid credentials", user.credential.user());
-                return Ok(warp::reply::with_status(
-                    "Invalid password".to_string(),
-                    StatusCode::FORBIDDEN),
-                );
+    match ctx.metrics.time_password_verify(|| user.credential.verify_with(&body.username, &body.password, &ctx.hashing)) {
+        Ok(true)  => {},
+        Ok(false) => {
+            debug!("User '{}' login failure: invalid credentials", user.credential.user());
+            match record_failure(ctx, &keys, &user) {
+                Ok(Some(failures)) => { audit::record(ctx, client, AuthEventKind::LoginFailure, Some(user.id), Some(user.credential.user()), Some(&format!("invalid password; account locked after {} consecutive failures", failures))); },
+                Ok(None)           => { audit::record(ctx, client, AuthEventKind::LoginFailure, Some(user.id), Some(user.credential.user()), Some("invalid password")); },
+                Err(err)           => { return Err(Error::StoreError{ err }); }
             }
+
+            return Ok(Outcome::Refused(reply::error(StatusCode::FORBIDDEN, ErrorCode::InvalidCredentials, "Invalid password")));
         },
-        Err(err) => { throw!(Error::CredentialVerifyError{ err }); }
+        Err(err) => { return Err(Error::CredentialVerifyError{ err }); }
+    }
+
+    // Disabled accounts may not login at all (but we only tell those who know the password)
+    if user.disabled {
+        debug!("User '{}' login failure: account is disabled", user.credential.user());
+        audit::record(ctx, client, AuthEventKind::LoginFailure, Some(user.id), Some(user.credential.user()), Some("account is disabled"));
+        return Ok(Outcome::Refused(reply::error(StatusCode::FORBIDDEN, ErrorCode::AccountDisabled, "Account is disabled")));
     }
 
-    // Success! Generate a JWT for this user.
+    // Users with a second factor have not proven who they are yet, so their earlier failures count until they complete it (see `totp::handle_login()`)
+    let second_factor: bool = match ctx.store.get_totp(user.id) {
+        Ok(Some(totp)) => totp.confirmed,
+        Ok(None)       => false,
+        Err(err)       => { return Err(Error::StoreError{ err }); }
+    };
+    if !second_factor {
+        if let Err(err) = clear_failures(ctx, &user) { return Err(Error::StoreError{ err }); }
+    }
+    Ok(Outcome::Accepted(user, second_factor))
+}
+
+
+
+
+
+/***** LIBRARY FUNCTIONS *****/
+/// Returns how long the given account is still locked after too many failed logins.
+/// 
+/// **Arguments**
+///  * `user`: The Account to check.
+/// 
+/// **Returns**  
+/// The time left until the lock expires, or None if the account isn't locked.
+pub fn locked_for(user: &Account) -> Option<Duration> {
+    let now = OffsetDateTime::now_utc();
+    match user.locked_until {
+        Some(until) if until > now => Some(Duration::try_from(until - now).unwrap_or_default()),
+        _                          => None,
+    }
+}
+
+/// Registers that somebody failed to prove that they are the given user (e.g., with a wrong password or second factor), and locks the account if that happens too often in a row.
+/// 
+/// **Arguments**
+///  * `ctx`: The Context of the service.
+///  * `keys`: The keys to throttle the attempt under, which should include the user's `ThrottleKey::user()`.
+///  * `user`: The Account that somebody failed to prove they are.
+/// 
+/// **Returns**  
+/// The number of consecutive failures if the account was locked because of this one, None if it wasn't, or an Error if we failed to update the store.
+pub fn record_failure(ctx: &Context, keys: &[ThrottleKey], user: &Account) -> Result<Option<u32>, StoreError> {
+    ctx.throttle.failure(keys);
+
+    // Lock the account if this happens too often in a row
+    let failures: u32 = ctx.store.record_login_failure(user.id)?;
+    let policy = ctx.throttle.policy();
+    if !policy.should_lock(failures) { return Ok(None); }
+    warn!("Locking user '{}' for {} seconds after {} consecutive failed logins", user.credential.user(), policy.lockout_duration.as_secs(), failures);
+    ctx.store.lock_account(user.id, OffsetDateTime::now_utc().add(policy.lockout_duration))?;
+    Ok(Some(failures))
+}
+
+/// Forgets about the failed logins of the given user, once they proved who they are.
+/// 
+/// This only clears the user's `ThrottleKey`, not that of their IP address, or one valid account would be enough to keep guessing others.
+/// 
+/// **Arguments**
+///  * `ctx`: The Context of the service.
+///  * `user`: The Account that successfully logged in.
+/// 
+/// **Returns**  
+/// Nothing on success, or an Error if we failed to update the store.
+pub fn clear_failures(ctx: &Context, user: &Account) -> Result<(), StoreError> {
+    ctx.throttle.clear(&ThrottleKey::user(user.credential.user()));
+    if user.failed_logins > 0 || user.locked_until.is_some() { ctx.store.reset_login_failures(user.id)?; }
+    Ok(())
+}
+
+
+
+/// Handles the logging-in part of the authorization service, except that it doesn't actually login but just check the credentials.
+/// 
+/// Note that this only checks the password, even if the user has enabled two-factor authentication. For such users, a correct password does not clear earlier failed attempts either.
+/// 
+/// **Arguments**
+///  * `ctx`: The Context of the service.
+///  * `client`: The Client that is trying to login.
+///  * `body`: The message body we got with the request.
+/// 
+/// **Returns**  
+/// The Warp reply on success, or a Warp rejection on failure.
+pub fn handle_test(ctx: Arc<Context>, client: Client, body: LoginJson) -> Result<Response, Rejection> {
+    info!("Handling login test (v1/login/test)");
+
+    // Check the credentials
+    let user: Account = match authenticate(&ctx, &client, &body) {
+        Ok(Outcome::Accepted(user, _)) => user,
+        Ok(Outcome::Refused(res))      => { return Ok(res); },
+        Err(err)                       => { throw!(err); }
+    };
+
+    // Success! (But nothing else happens.)
     debug!("User '{}' login success", user.credential.user());
+    audit::record(&ctx, &client, AuthEventKind::LoginSuccess, Some(user.id), Some(user.credential.user()), Some("credentials test only"));
     Ok(warp::reply::with_status(
         String::from("success"),
         StatusCode::OK,
-    ))
+    ).into_response())
 }
 
 
 
 /// Handles the logging-in part of the authorization service.
 /// 
+/// If the user has enabled two-factor authentication, this does not hand out any tokens yet but a challenge that has to be completed at `v1/login/totp` (see `totp::handle_login()`).
+/// 
 /// **Arguments**
-///  * `pool`: The MySQL pool of connections to use for this service.
-///  * `secret`: The server-wide shared secret that is used to sign the JWT's.
+///  * `ctx`: The Context of the service.
+///  * `client`: The Client that is trying to login.
 ///  * `body`: The message body we got with the request.
 /// 
 /// **Returns**  
 /// The Warp reply on success, or a Warp rejection on failure.
-pub async fn handle(pool: Arc<Pool>, secret: Arc<String>, body: LoginJson) -> Result<impl Reply, Rejection> {
+pub fn handle(ctx: Arc<Context>, client: Client, body: LoginJson) -> Result<Response, Rejection> {
     info!("Handling login (v1/login)");
 
-    // Try to connect to the MySQL database
-    let mut conn = match pool.get_conn() {
-        Ok(conn) => conn,
-        Err(err) => { throw!(Error::MySqlConnectError{ err }); }
+    // Check the credentials
+    let (user, second_factor): (Account, bool) = match authenticate(&ctx, &client, &body) {
+        Ok(Outcome::Accepted(user, second_factor)) => (user, second_factor),
+        Ok(Outcome::Refused(res))                  => { return Ok(res); },
+        Err(err)                                   => { throw!(err); }
     };
 
-    // Select the appropriate database
-    debug!("Selecting database...");
-    let query = String::from("USE todo;");
-    if let Err(err) = conn.query_drop(&query) { throw!(Error::MySqlQueryError{ query, err }); };
-
-    // Query the database for this username
-    debug!("Searching for user '{}'...", &body.username);
-    let query = format!("SELECT id, name, pass FROM users WHERE name = '{}';", &body.username);
-    let users: Vec<Account> = match conn.query_map(
-        &query,
-        |(id, name, pass)| { Account{ id, credential : Credential::new::<String, String>(name, pass).expect("Invalid username made its way into the MySQL database; this should never happen!") } }
-    ) {
-        Ok(res)  => res,
-        Err(err) => { throw!(Error::MySqlQueryError{ query, err }); }
-    };
-
-    // Check if there are any
-    if users.len() == 0 {
-        debug!("User '{}' login failure: unknown user", body.username);
-        return Ok(warp::reply::with_status(
-            format!("Unknown username '{}'", body.username),
-            StatusCode::NOT_FOUND,
-        ));
-    }
-    let user: &Account = &users[0];
-
-    // Verify the password
-    match user.credential.verify(&body.username, &body.password) {
-        Ok(is_valid) => {
-            if !is_valid {
-                debug!("User '{}' login failure: invalid credentials", user.credential.user());
-                return Ok(warp::reply::with_status(
-                    "Invalid password".to_string(),
-                    StatusCode::FORBIDDEN),
-                );
+    // Upgrade the hash if it's weaker than the current policy; failing to do so is not worth refusing the login over
+    match user.credential.needs_rehash(&ctx.hashing) {
+        Ok(true) => {
+            debug!("User '{}' password hash is outdated; rehashing", user.credential.user());
+            match Credential::from_plain_with(user.credential.user(), &body.password, &ctx.hashing) {
+                Ok(cred) => {
+                    if let Err(err) = ctx.store.update_password(user.id, cred.pass()) { warn!("Could not update password hash of user '{}': {}", user.credential.user(), err); }
+                },
+                Err(err) => { warn!("Could not rehash password of user '{}': {}", user.credential.user(), err); },
             }
         },
-        Err(err) => { throw!(Error::CredentialVerifyError{ err }); }
+        Ok(false) => {},
+        Err(err)  => { warn!("Could not check password hash of user '{}': {}", user.credential.user(), err); },
     }
 
-    // Success! Generate a JWT for this user.
-    debug!("User '{}' login success; generating JWT", user.credential.user());
-
-    // Define when the JWT will expire
-    let expiration_date: OffsetDateTime = OffsetDateTime::now_utc().add(Duration::from_secs(JWT_EXPIRATION_TIME));
-
-    // Create the Hmac key first
-    let key: Hmac<Sha256> = match Hmac::new_from_slice(secret.as_bytes()) {
-        Ok(key)  => key,
-        Err(err) => { throw!(Error::HmacKeyError{ err }); }
-    };
-
-    // Define the claims (i.e., content) that we'll carry in the JWT
-    let mut claims = BTreeMap::new();
-    claims.insert("id", format!("{}", user.id));
-    claims.insert("exp", expiration_date.format(&Rfc3339).expect("Could not format JWT expiration date to ISO/RFC3339; this should never happen!"));
+    // Ask for the second factor first if the user has one
+    if second_factor {
+        debug!("User '{}' password accepted; awaiting second factor", user.credential.user());
+        return Ok(warp::reply::with_status(
+            warp::reply::json(&SecondFactorJson{ challenge: ctx.challenges.create(user.id, body.device.clone()), expires_in: CHALLENGE_EXPIRATION_TIME }),
+            StatusCode::ACCEPTED,
+        ).into_response());
+    }
 
-    // We generate a JWT
-    let token = match claims.sign_with_key(&key) {
-        Ok(token) => token,
-        Err(err)  => { throw!(Error::JwtCreateError{ err }); }
+    // Success! Generate the tokens for this user.
+    debug!("User '{}' login success; generating tokens", user.credential.user());
+    let tokens = match token::start_session(&ctx, &client, &user, body.device.as_deref()) {
+        Ok(tokens) => tokens,
+        Err(err)   => { throw!(Error::TokenError{ err }); }
     };
+    audit::record(&ctx, &client, AuthEventKind::LoginSuccess, Some(user.id), Some(user.credential.user()), None);
+    audit::record(&ctx, &client, AuthEventKind::TokenIssue, Some(user.id), Some(user.credential.user()), None);
 
-    // Return the token!
-    info!("User '{}' authorized with JWT until {}", user.credential.user(), expiration_date.format(&Rfc3339).expect("Could not format JWT expiration date to ISO/RFC3339; this should never happen!"));
+    // Return the tokens!
+    info!("User '{}' authorized with JWT for {} seconds", user.credential.user(), tokens.expires_in);
     Ok(warp::reply::with_status(
-        token,
+        warp::reply::json(&tokens),
         StatusCode::OK,
-    ))
+    ).into_response())
 }
@@ -4,7 +4,7 @@
  * Created:
  *   19 Mar 2022, 11:45:08
  * Last edited:
- *   19 Mar 2022, 21:59:27
+ *   18 Oct 2026, 06:42:40
  * Auto updated?
  *   Yes
  *
@@ -18,54 +18,272 @@
  *   This server uses a backend MySQL server to store the relevant user data.
 **/
 
+use std::collections::HashSet;
 use std::fs;
-use std::net::Ipv4Addr;
-use std::path::PathBuf;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::Duration;
 
-use clap::Parser;
-use log::{info, debug, error, LevelFilter};
-use mysql::{Opts, Pool, PooledConn};
-use mysql::prelude::Queryable;
+use clap::{ArgGroup, Parser};
+use time::OffsetDateTime;
+use time::format_description::well_known::Rfc3339;
+use log::{info, debug, error, warn};
 use simplelog::{ColorChoice, TerminalMode, TermLogger};
+use tokio::signal::unix::{signal, SignalKind};
 use url::Url;
-use warp::Filter;
 
-use todo_spec::credentials::Credential;
+use todo_spec::credentials::{Credential, HashPolicy};
+use todo_spec::jwt::{self, Algorithm, Key, Role, Verifier};
+use todo_spec::password::{BreachedPasswords, PasswordPolicy};
 
-use todo_auth::login;
+use todo_auth::config::{Config, HashingConfig, PasswordConfig};
+use todo_auth::manage::{self, UserChanges};
+use todo_auth::metrics::Metrics;
+use todo_auth::routes;
 use todo_auth::errors::AuthError as Error;
-use todo_auth::spec::Account;
+use todo_auth::errors::{ConfigError, ManageError, MigrationError};
+use todo_auth::spec::{Account, Context, JwtAlgorithm, Keys, LogLevel, PasswordAlgorithm, RegistrationMode, RegistrationPolicy, TokenPolicy};
+use todo_auth::store::{MemoryStore, MigrationStore, MySqlStore, SqliteStore, Store, StoreKind};
+use todo_auth::store::migrations::{self, MigrationStatus};
+use todo_auth::throttle::{Throttle, ThrottlePolicy};
+use todo_auth::tls::{self, TlsState};
+use todo_auth::token;
+use todo_auth::totp::Challenges;
+use todo_auth::workers::HashPool;
+
+
+/***** HELPER MACROS *****/
+/// Overrides the given option of the configuration with the given argument, if that is set.
+macro_rules! set {
+    ($option:expr, $arg:expr) => {
+        if let Some(value) = $arg { $option = value; }
+    };
+}
+
+
+
+
+
+/***** CONSTANTS *****/
+/// The environment variable that may hold the passphrase of encrypted credential files.
+const CRED_PASSPHRASE_ENV: &str = "TODO_AUTH_CRED_PASSPHRASE";
+
+
+
 
 
 /***** ARGUMENTS *****/
 /// Defines the command-line arguments available for the auth service.
+/// 
+/// Every option may also be given in the configuration file (see '--config') or as an environment variable; the arguments override the environment, which overrides the file.
 #[derive(Parser)]
 #[clap(author, version, about, long_about = None)]
 struct Arguments {
+    /// The location of the configuration file
+    #[clap(short, long, help = "The location of a TOML configuration file. Any option that it leaves out keeps its default; environment variables and arguments override it.", env = "TODO_AUTH_CONFIG")]
+    config       : Option<PathBuf>,
+    /// If given, prints the configuration and exits
+    #[clap(long, help = "If given, prints the effective configuration (with secrets redacted) and exits.")]
+    print_config : bool,
+
     /// If given, shows debug prints as well
-    #[clap(short, long, help = "If given, also logs debug prints.")]
-    debug : bool,
+    #[clap(short, long, help = "If given, also logs debug prints. Shorthand for '--log-level debug'.")]
+    debug     : bool,
+    /// How much to log
+    #[clap(long, arg_enum, help = "The most detailed level to log. [default: info]", env = "TODO_AUTH_LOG_LEVEL")]
+    log_level : Option<LogLevel>,
 
     /// The hostname to use for setting up the server
-    #[clap(short, long, help = "The hostname to bind the server to. Use '0.0.0.0' to accept anything.", default_value = "0.0.0.0")]
-    host : Ipv4Addr,
+    #[clap(short, long, help = "The hostname to bind the server to. Use '0.0.0.0' to accept anything. [default: 0.0.0.0]", env = "TODO_AUTH_HOST")]
+    host : Option<Ipv4Addr>,
     /// The port to bind the server to
-    #[clap(short, long, help = "The port to bind the server to.", default_value = "4242")]
-    port : u16,
-
+    #[clap(short, long, help = "The port to bind the server to. [default: 4242]", env = "TODO_AUTH_PORT")]
+    port : Option<u16>,
+    /// The location of the TLS certificate
+    #[clap(long, help = "The location of a PEM-encoded certificate (chain) to serve HTTPS with. If omitted, serves plain HTTP. The certificate and key are reloaded when the service receives SIGHUP.", env = "TODO_AUTH_TLS_CERT")]
+    tls_cert : Option<PathBuf>,
+    /// The location of the TLS private key
+    #[clap(long, help = "The location of the PEM-encoded private key that belongs to '--tls-cert'.", env = "TODO_AUTH_TLS_KEY")]
+    tls_key  : Option<PathBuf>,
+
+    /// The backend to store the accounts in
+    #[clap(long, arg_enum, help = "The backend to store the user accounts in. Note that 'memory' does not persist anything across restarts. [default: mysql]", env = "TODO_AUTH_STORE")]
+    store : Option<StoreKind>,
     /// The mysql server to connect to
-    #[clap(short, long, help = "The address:port of the MySQL server to connect to.", env = "MYSQL_URL")]
-    mysql_url : Url,
+    #[clap(short, long, help = "The address:port of the MySQL server to connect to. Required if '--store' is 'mysql'.", env = "MYSQL_URL")]
+    mysql_url : Option<Url>,
     /// The location of the root MySQL database password file
-    #[clap(long, help = "The location of the MySQL database's root password.", default_value = "./mysql_root.cred")]
-    mysql_root_cred : PathBuf,
+    #[clap(long, help = "The location of the MySQL database's root password. [default: ./mysql_root.cred]", env = "TODO_AUTH_MYSQL_ROOT_CRED")]
+    mysql_root_cred : Option<PathBuf>,
+    /// The location of the SQLite database file
+    #[clap(long, help = "The location of the SQLite database file. Only used if '--store' is 'sqlite'. [default: ./todo.db]", env = "TODO_AUTH_SQLITE_PATH")]
+    sqlite_path : Option<PathBuf>,
     /// The location of the root user file
-    #[clap(short, long, help = "The location of the root user's credentials in the Todo server.", default_value = "./root.cred")]
-    root_cred : PathBuf,
-    /// The location of the JWT secret file
-    #[clap(short, long, help = "The location of the JWT secret file.", default_value = "./jwt.secret")]
-    secret: PathBuf,
+    #[clap(short, long, help = "The location of the root user's credentials in the Todo server. Reloaded when the service receives SIGHUP. [default: ./root.cred]", env = "TODO_AUTH_ROOT_CRED")]
+    root_cred : Option<PathBuf>,
+    /// The algorithm to sign JWT's with
+    #[clap(long, arg_enum, help = "The algorithm to sign JWT's with. Use 'eddsa' or 'rs256' to let other services verify tokens with only the public key (see 'v1/.well-known/jwks.json'). [default: hs256]", env = "TODO_AUTH_JWT_ALGORITHM")]
+    jwt_algorithm : Option<JwtAlgorithm>,
+    /// The location of the JWT signing key
+    #[clap(short = 's', long, alias = "secret", help = "The location of the key to sign JWT's with. For 'hs256', this is a file with the shared secret; otherwise, it is a PEM-encoded private key (e.g., generated with 'openssl genpkey -algorithm ed25519'). Reloaded when the service receives SIGHUP. [default: ./jwt.secret]", env = "TODO_AUTH_JWT_KEY")]
+    jwt_key : Option<PathBuf>,
+    /// The locations of any older keys that we still accept
+    #[clap(long = "jwt-verify-key", help = "The location of an additional key (a shared secret, or a PEM-encoded public or private key) that JWT's may be signed with. It is never used to sign new tokens, which allows tokens signed with an old key to remain valid after rotating keys. May be given multiple times.")]
+    jwt_verify_keys : Vec<PathBuf>,
+    /// The lifetime of access tokens
+    #[clap(long, help = "The time (in seconds) that access tokens are valid. [default: 3600]", env = "TODO_AUTH_ACCESS_TOKEN_LIFETIME")]
+    access_token_lifetime  : Option<u64>,
+    /// The lifetime of refresh tokens
+    #[clap(long, help = "The time (in seconds) that refresh tokens are valid. [default: 2592000]", env = "TODO_AUTH_REFRESH_TOKEN_LIFETIME")]
+    refresh_token_lifetime : Option<u64>,
+    /// The lifetime of password reset tokens
+    #[clap(long, help = "The time (in seconds) that password reset tokens are valid. [default: 3600]", env = "TODO_AUTH_RESET_TOKEN_LIFETIME")]
+    reset_token_lifetime   : Option<u64>,
+
+    /// The variant of Argon2 to hash passwords with
+    #[clap(long, arg_enum, help = "The variant of Argon2 to hash passwords with. [default: argon2id]", env = "TODO_AUTH_HASH_ALGORITHM")]
+    hash_algorithm   : Option<PasswordAlgorithm>,
+    /// The memory cost of hashing a password
+    #[clap(long, help = "The amount of memory (in KiB) to use when hashing a password. [default: 4096]", env = "TODO_AUTH_HASH_MEMORY")]
+    hash_memory      : Option<u32>,
+    /// The time cost of hashing a password
+    #[clap(long, help = "The number of iterations to perform when hashing a password. [default: 3]", env = "TODO_AUTH_HASH_ITERATIONS")]
+    hash_iterations  : Option<u32>,
+    /// The parallelism of hashing a password
+    #[clap(long, help = "The number of lanes to use when hashing a password. [default: 1]", env = "TODO_AUTH_HASH_PARALLELISM")]
+    hash_parallelism : Option<u32>,
+    /// The location of the password pepper
+    #[clap(long, help = "The location of a file with a secret that is mixed into every password hash but not stored in the database. Note that hashes made with a pepper can only be verified with that same pepper.", env = "TODO_AUTH_HASH_PEPPER")]
+    hash_pepper      : Option<PathBuf>,
+    /// The number of requests that may hash passwords at the same time
+    #[clap(long, help = "The number of requests that may hash (or verify) passwords at the same time. Further requests that need to are refused with '503 Service Unavailable' until one finishes. Defaults to the number of CPU cores.", env = "TODO_AUTH_HASH_CONCURRENCY")]
+    hash_concurrency : Option<NonZeroUsize>,
+
+    /// The number of failed logins before we start delaying attempts
+    #[clap(long, help = "The number of failed logins per IP address or username that are allowed before further attempts have to wait. [default: 3]", env = "TODO_AUTH_THROTTLE_FREE_ATTEMPTS")]
+    throttle_free_attempts : Option<u32>,
+    /// The delay after the first failed login that isn't free
+    #[clap(long, help = "The time (in seconds) that attempts have to wait after the first failed login that isn't free. Doubles with every further failure. [default: 1]", env = "TODO_AUTH_THROTTLE_BASE_DELAY")]
+    throttle_base_delay    : Option<u64>,
+    /// The maximum delay between attempts
+    #[clap(long, help = "The maximum time (in seconds) that attempts have to wait. [default: 300]", env = "TODO_AUTH_THROTTLE_MAX_DELAY")]
+    throttle_max_delay     : Option<u64>,
+    /// The number of consecutive failures after which an account is locked
+    #[clap(long, help = "The number of consecutive failed logins after which an account is locked. Use '0' to never lock accounts. [default: 10]", env = "TODO_AUTH_LOCKOUT_THRESHOLD")]
+    lockout_threshold      : Option<u32>,
+    /// The time that an account stays locked
+    #[clap(long, help = "The time (in seconds) that an account stays locked. The root user can lift it earlier with 'DELETE v1/admin/lockouts/<username>'. [default: 900]", env = "TODO_AUTH_LOCKOUT_DURATION")]
+    lockout_duration       : Option<u64>,
+
+    /// Determines if and how new users may register
+    #[clap(long, arg_enum, help = "Whether anyone may register ('open'), only users with an invite code ('invite') or nobody ('disabled'). [default: open]", env = "TODO_AUTH_REGISTRATION")]
+    registration : Option<RegistrationMode>,
+    /// The location of the invite codes file
+    #[clap(long, help = "The location of a file with one accepted invite code per line. Required if '--registration' is 'invite'.", env = "TODO_AUTH_INVITE_CODES")]
+    invite_codes : Option<PathBuf>,
+
+    /// Whether to leave migrating the database to the 'migrate' subcommand
+    #[clap(long, help = "If given, does not migrate the database at startup, but refuses to start if it is not up-to-date. Use 'todo-auth migrate up' to migrate it instead.")]
+    no_migrate : bool,
+
+    /// The file descriptor to read the passphrase of encrypted credential files from
+    #[clap(long, help = "A file descriptor to read the passphrase of encrypted credential files (e.g., '--root-cred') from. Alternatively, put the passphrase itself in the TODO_AUTH_CRED_PASSPHRASE environment variable.", env = "TODO_AUTH_CRED_PASSPHRASE_FD")]
+    cred_passphrase_fd : Option<i32>,
+
+    /// The subcommand to run instead of the service, if any
+    #[clap(subcommand)]
+    command : Option<Command>,
+}
+
+
+
+/// Defines the subcommands that do something else than running the service.
+#[derive(Parser)]
+enum Command {
+    /// Manages the schema of the database
+    #[clap(name = "migrate", about = "Inspect or change the version of the database schema, then exit.")]
+    Migrate {
+        #[clap(subcommand)]
+        action : MigrateAction,
+    },
+
+    /// Adds a user
+    #[clap(name = "useradd", about = "Add a new user to the database, then exit. Prompts for their password.")]
+    UserAdd {
+        #[clap(help = "The name of the new user.")]
+        username       : String,
+        #[clap(short, long, default_value = "user", help = "The role of the new user ('admin' or 'user').")]
+        role           : Role,
+        #[clap(long, help = "If given, reads the password from a single line on stdin instead of prompting for it.")]
+        password_stdin : bool,
+    },
+    /// Removes a user
+    #[clap(name = "userdel", about = "Remove a user (and their sessions) from the database, then exit.")]
+    UserDel {
+        #[clap(help = "The name of the user to remove.")]
+        username : String,
+    },
+    /// Changes a user
+    #[clap(name = "usermod", about = "Rename, change the role of, or disable a user in the database, then exit. Note that the root user is restored to an enabled administrator whenever the service starts.")]
+    #[clap(group(ArgGroup::new("changes").required(true).multiple(true).args(&[ "rename", "display-name", "clear-display-name", "role", "disable", "enable" ])))]
+    UserMod {
+        #[clap(help = "The name of the user to change.")]
+        username : String,
+        #[clap(long, help = "The new name of the user.")]
+        rename             : Option<String>,
+        #[clap(long, help = "The new display name of the user.")]
+        display_name       : Option<String>,
+        #[clap(long, conflicts_with = "display-name", help = "Removes the display name of the user.")]
+        clear_display_name : bool,
+        #[clap(short, long, help = "The new role of the user ('admin' or 'user').")]
+        role               : Option<Role>,
+        #[clap(long, conflicts_with = "enable", help = "Disables the user, which also logs out all of their sessions.")]
+        disable            : bool,
+        #[clap(long, help = "Re-enables the user.")]
+        enable             : bool,
+    },
+    /// Sets the password of a user
+    #[clap(name = "passwd", about = "Set a new password for a user in the database, then exit. Prompts for the password, and logs out all of the user's sessions.")]
+    Passwd {
+        #[clap(help = "The name of the user whose password to set.")]
+        username       : String,
+        #[clap(long, help = "If given, reads the password from a single line on stdin instead of prompting for it.")]
+        password_stdin : bool,
+    },
+    /// Issues a password reset token
+    #[clap(name = "reset-token", about = "Issue a one-time password reset token for a user and print it, then exit. The user can trade it for a new password with `todoctl reset-password`.")]
+    ResetToken {
+        #[clap(help = "The name of the user to issue the token for.")]
+        username : String,
+    },
+    /// Lists the users
+    #[clap(name = "list", about = "List the users in the database, then exit.")]
+    List,
+    /// Rotates the root password
+    #[clap(name = "rotate-root", about = "Give the root user a new, random password and write it to the root credentials file, then exit. If a credential passphrase is given, the new file is encrypted with it. Send SIGHUP to a running service to make it pick up the new file.")]
+    RotateRoot,
+}
+
+/// Defines what to do with the schema of the database.
+#[derive(Parser)]
+enum MigrateAction {
+    /// Shows the migrations
+    #[clap(name = "status", about = "Show which migrations have been applied.")]
+    Status,
+    /// Applies migrations
+    #[clap(name = "up", about = "Apply the migrations that have not been applied yet.")]
+    Up {
+        #[clap(long, help = "The version to migrate up to. If omitted, migrates to the latest version.")]
+        to : Option<u32>,
+    },
+    /// Reverts migrations
+    #[clap(name = "down", about = "Revert applied migrations. Note that this may delete data.")]
+    Down {
+        #[clap(long, help = "The version to migrate down to ('0' reverts everything). If omitted, only reverts the latest migration.")]
+        to : Option<u32>,
+    },
 }
 
 
@@ -73,93 +291,496 @@ struct Arguments {
 
 
 /***** HELPER FUNCTIONS *****/
-/// Ensure the desired database structure is present in the MySQL database.
+/// Builds the effective configuration: the defaults, overridden by the configuration file (if any), overridden by the environment and the command-line arguments.
+/// 
+/// Note that the result is not validated yet (see `Config::validate()`).
 /// 
 /// **Arguments**
-///  * `pool`: The MySQL pool to allocate a new connection from.
-///  * `root_cred`: The credentials for the root account. Will be used to initiate it if the root does not exist, or checked to see if the root authentication is still valid.
+///  * `args`: The Arguments to build the configuration from. Clap has already put the environment variables in there, unless the argument itself is given.
 /// 
 /// **Returns**  
-/// Nothing on success, or else an Error.
-fn ensure_database(pool: Arc<Pool>, root_cred: &Credential) -> Result<(), Error> {
-    // Connect to the database to setup tables
-    info!("Preparig database...");
-    debug!("Connecting to MySQL database...");
-    let mut conn: PooledConn = match pool.get_conn() {
-        Ok(conn) => conn,
-        Err(err) => { return Err(Error::MySqlConnectError{ err }); }
+/// The effective Config on success, or else an Error.
+fn load_config(args: &Arguments) -> Result<Config, Error> {
+    let mut config: Config = match &args.config {
+        Some(path) => match Config::from_file(path) {
+            Ok(config) => config,
+            Err(err)   => { return Err(Error::ConfigError{ err }); }
+        },
+        None => Config::default(),
     };
 
-    // Create the database if it does not yet exist
-    debug!("Creating 'todo' database if necessary...");
-    let query = String::from("CREATE DATABASE IF NOT EXISTS todo; USE DATABASE todo;");
-    if let Err(err) = conn.query_drop(&query) {
-        return Err(Error::MySqlQueryError{ query, err });
+    set!(config.logging.level, args.log_level);
+    if args.debug { config.logging.level = LogLevel::Debug; }
+
+    set!(config.server.host, args.host);
+    set!(config.server.port, args.port);
+    set!(config.server.tls_cert, args.tls_cert.clone().map(Some));
+    set!(config.server.tls_key, args.tls_key.clone().map(Some));
+
+    set!(config.store.kind, args.store);
+    set!(config.store.mysql_url, args.mysql_url.clone().map(Some));
+    set!(config.store.sqlite_path, args.sqlite_path.clone());
+    if args.no_migrate { config.store.migrate = false; }
+
+    set!(config.credentials.root, args.root_cred.clone());
+    set!(config.credentials.mysql_root, args.mysql_root_cred.clone());
+
+    set!(config.jwt.algorithm, args.jwt_algorithm);
+    set!(config.jwt.key, args.jwt_key.clone());
+    if !args.jwt_verify_keys.is_empty() { config.jwt.verify_keys = args.jwt_verify_keys.clone(); }
+    set!(config.jwt.access_lifetime, args.access_token_lifetime);
+    set!(config.jwt.refresh_lifetime, args.refresh_token_lifetime);
+    set!(config.jwt.reset_lifetime, args.reset_token_lifetime);
+
+    set!(config.hashing.algorithm, args.hash_algorithm);
+    set!(config.hashing.memory, args.hash_memory);
+    set!(config.hashing.iterations, args.hash_iterations);
+    set!(config.hashing.parallelism, args.hash_parallelism);
+    set!(config.hashing.pepper, args.hash_pepper.clone().map(Some));
+    set!(config.hashing.concurrency, args.hash_concurrency.map(Some));
+
+    set!(config.throttle.free_attempts, args.throttle_free_attempts);
+    set!(config.throttle.base_delay, args.throttle_base_delay);
+    set!(config.throttle.max_delay, args.throttle_max_delay);
+    set!(config.throttle.lockout_threshold, args.lockout_threshold);
+    set!(config.throttle.lockout_duration, args.lockout_duration);
+
+    set!(config.registration.mode, args.registration);
+    set!(config.registration.invite_codes, args.invite_codes.clone().map(Some));
+
+    Ok(config)
+}
+
+
+
+/// Reads the passphrase of encrypted credential files from the file descriptor or environment variable given by the user, if any.
+/// 
+/// **Arguments**
+///  * `args`: The Arguments that may name a file descriptor to read the passphrase from.
+/// 
+/// **Returns**  
+/// The passphrase (without a trailing newline) if one was given, None if not, or else an Error.
+fn load_passphrase(args: &Arguments) -> Result<Option<String>, Error> {
+    // Prefer the file descriptor, since that keeps the passphrase out of the environment
+    if let Some(fd) = args.cred_passphrase_fd {
+        let mut passphrase: String = match fs::read_to_string(format!("/dev/fd/{}", fd)) {
+            Ok(passphrase) => passphrase,
+            Err(err)       => { return Err(Error::PassphraseReadError{ fd, err }); }
+        };
+        if passphrase.ends_with('\n') { passphrase.pop(); }
+        if passphrase.ends_with('\r') { passphrase.pop(); }
+        return Ok(Some(passphrase));
+    }
+
+    match std::env::var(CRED_PASSPHRASE_ENV) {
+        Ok(passphrase)                       => Ok(Some(passphrase)),
+        Err(std::env::VarError::NotPresent) => Ok(None),
+        Err(err)                             => Err(Error::PassphraseEnvError{ name: CRED_PASSPHRASE_ENV, err }),
+    }
+}
+
+/// Builds the registration policy from the given mode and (optional) invite codes file.
+/// 
+/// **Arguments**
+///  * `mode`: The mode of registration to use.
+///  * `invite_codes`: The path to the file with invite codes (one per line). Only required if `mode` is `RegistrationMode::Invite`.
+/// 
+/// **Returns**  
+/// The new RegistrationPolicy on success, or else an Error.
+fn load_registration_policy(mode: RegistrationMode, invite_codes: Option<&Path>) -> Result<RegistrationPolicy, Error> {
+    // Read the invite codes if we need them
+    let codes: HashSet<String> = match (mode, invite_codes) {
+        (RegistrationMode::Invite, Some(path)) => {
+            let data = match fs::read_to_string(path) {
+                Ok(data) => data,
+                Err(err) => { return Err(Error::InviteCodesReadError{ path: path.to_path_buf(), err }); }
+            };
+            data.lines().map(|line| line.trim()).filter(|line| !line.is_empty()).map(String::from).collect()
+        },
+        (RegistrationMode::Invite, None) => { return Err(Error::MissingInviteCodes); },
+        _                                => HashSet::new(),
     };
 
-    // Select the database
-    debug!("Selecting 'todo' database...");
-    let query = String::from("USE todo;");
-    if let Err(err) = conn.query_drop(&query) {
-        return Err(Error::MySqlQueryError{ query, err });
+    // Done
+    Ok(RegistrationPolicy {
+        mode,
+        invite_codes : codes,
+    })
+}
+
+
+
+/// Loads the keys to sign and verify JWT's with.
+/// 
+/// **Arguments**
+///  * `algorithm`: The algorithm that we should sign with.
+///  * `signing`: The path to the key that we sign with.
+///  * `verifying`: The paths to any additional keys that we accept when verifying.
+/// 
+/// **Returns**  
+/// The signing Key and a Verifier that accepts all of the keys on success, or else an Error.
+fn load_jwt_keys(algorithm: JwtAlgorithm, signing: &Path, verifying: &[PathBuf]) -> Result<(Key, Verifier), Error> {
+    // Load the signing key and make sure it's what we expect
+    let key: Key = match Key::from_file(signing) {
+        Ok(key)  => key,
+        Err(err) => { return Err(Error::JwtKeyError{ path: signing.to_path_buf(), err }); }
     };
+    if key.algorithm() != Algorithm::from(algorithm) { return Err(Error::JwtAlgorithmMismatch{ path: signing.to_path_buf(), expected: algorithm.into(), got: key.algorithm() }); }
+    if !key.can_sign() { return Err(Error::JwtKeyNotPrivate{ path: signing.to_path_buf() }); }
+    info!("Signing JWT's with {} key '{}'", key.algorithm(), key.kid());
+
+    // Load the other keys
+    let mut keys: Vec<Key> = Vec::with_capacity(1 + verifying.len());
+    keys.push(key.clone());
+    for path in verifying {
+        let key: Key = match Key::from_file(path) {
+            Ok(key)  => key,
+            Err(err) => { return Err(Error::JwtKeyError{ path: path.clone(), err }); }
+        };
+        debug!("Also accepting JWT's signed with {} key '{}'", key.algorithm(), key.kid());
+        keys.push(key);
+    }
+
+    // Done
+    Ok((key, Verifier::new(keys, jwt::DEFAULT_LEEWAY)))
+}
 
 
 
-    // Create the users table if it does not yet exist
-    debug!("Creating 'users' table if necessary...");
-    let query = String::from(
-        r"CREATE TABLE IF NOT EXISTS users (
-            id INT UNSIGNED AUTO_INCREMENT PRIMARY KEY,
-            name VARCHAR(255) NOT NULL UNIQUE,
-            pass VARCHAR(255) NOT NULL
-        );"
-    );
-    if let Err(err) = conn.query_drop(&query) {
-        return Err(Error::MySqlQueryError{ query, err });
+/// Builds the policy for hashing passwords from the given configuration.
+/// 
+/// **Arguments**
+///  * `config`: The HashingConfig that specifies the hashing parameters.
+/// 
+/// **Returns**  
+/// The new HashPolicy on success, or else an Error.
+fn load_hash_policy(config: &HashingConfig) -> Result<HashPolicy, Error> {
+    // Build the policy with the costs
+    let mut policy: HashPolicy = match HashPolicy::new(config.algorithm.into(), config.memory, config.iterations, config.parallelism) {
+        Ok(policy) => policy,
+        Err(err)   => { return Err(Error::HashPolicyError{ err }); }
     };
 
-    // Insert the root user into it if it does not exist yet
-    debug!("Checking if root user already exists...");
-    let query = format!("SELECT id, name, pass FROM users WHERE name = '{}';", root_cred.user());
-    let root_users: Vec<Account> = match conn.query_map(
-        &query,
-        |(id, name, pass)| { Account{ id, credential : Credential::new::<String, String>(name, pass).expect("Invalid username made its way into the MySQL database; this should never happen!") } }
-    ) {
-        Ok(res)  => res,
-        Err(err) => { return Err(Error::MySqlQueryError{ query, err }); }
-    };
-    if root_users.len() == 0 {
-        debug!("Inserting root user...");
-
-        // Create the (double) hashed version of the password
-        let root_cred = match Credential::from_plain(root_cred.user(), root_cred.pass()) {
-            Ok(cred) => cred,
-            Err(err) => { return Err(Error::CredentialError{ err }); }
+    // Add the pepper, if any
+    if let Some(path) = &config.pepper {
+        let pepper: Vec<u8> = match fs::read(path) {
+            Ok(pepper) => pepper,
+            Err(err)   => { return Err(Error::PepperReadError{ path: path.clone(), err }); }
         };
-
-        // Write it to the database
-        let query = format!("INSERT INTO users (name, pass) VALUES ('{}', '{}');", root_cred.user(), root_cred.pass());
-        if let Err(err) = conn.query_drop(&query) {
-            return Err(Error::MySqlQueryError{ query, err });
+        policy = match policy.with_pepper(pepper) {
+            Ok(policy) => policy,
+            Err(err)   => { return Err(Error::HashPolicyError{ err }); }
         };
-    } else {
-        debug!("Root already exists; verifying root credentials...");
+    }
+
+    // Done
+    info!("Hashing passwords with {} (m={}, t={}, p={}{})", policy.algorithm(), policy.m_cost(), policy.t_cost(), policy.p_cost(), if policy.peppered() { ", peppered" } else { "" });
+    Ok(policy)
+}
 
-        // Get the account
-        let root: &Account = &root_users[0];
 
-        // Match with the root credentials
-        match root.credential.verify(root_cred.user(), root_cred.pass()) {
-            Ok(res) => { if !res { return Err(Error::RootCredentialsOutdated); } },
-            Err(err) => { return Err(Error::CredentialVerifyError{ err }); }
+
+/// Builds the policy that new passwords have to meet from the given configuration.
+/// 
+/// **Arguments**
+///  * `config`: The PasswordConfig that specifies the rules.
+/// 
+/// **Returns**  
+/// The new PasswordPolicy on success, or else an Error.
+fn load_password_policy(config: &PasswordConfig) -> Result<PasswordPolicy, Error> {
+    // Open the list of breached passwords, if any
+    let breached: Option<BreachedPasswords> = match &config.breached {
+        Some(dir) => match BreachedPasswords::new(dir) {
+            Ok(breached) => Some(breached),
+            Err(err)     => { return Err(Error::PasswordPolicyError{ err }); }
+        },
+        None => None,
+    };
+
+    // Done
+    info!("Accepting passwords of {}-{} characters with an estimated strength of at least {} bits{}", config.min_length, config.max_length, config.min_strength, if breached.is_some() { ", unless breached" } else { "" });
+    Ok(PasswordPolicy {
+        min_length      : config.min_length,
+        max_length      : config.max_length,
+        min_classes     : config.min_classes,
+        min_strength    : config.min_strength,
+        forbid_username : config.forbid_username,
+        breached,
+    })
+}
+
+
+
+/// Opens the account store of the given kind.
+/// 
+/// **Arguments**
+///  * `config`: The Config that specifies which store to open and where to find it.
+/// 
+/// **Returns**  
+/// The opened Store on success, or else an Error.
+fn open_store(config: &Config, passphrase: Option<&str>) -> Result<Arc<dyn Store>, Error> {
+    match config.store.kind {
+        StoreKind::MySql => {
+            // Load the credentials
+            debug!("Loading MySQL credentials...");
+            let mysql_cred = match Credential::from_file_with(&config.credentials.mysql_root, passphrase) {
+                Ok(cred) => cred,
+                Err(err) => { return Err(Error::CredentialError{ err }); }
+            };
+
+            // Open the store
+            let url: &Url = match &config.store.mysql_url {
+                Some(url) => url,
+                None      => { return Err(Error::ConfigError{ err: ConfigError::MissingMySqlUrl }); }
+            };
+            info!("Preparing connections to MySQL database @ {}...", url);
+            match MySqlStore::new(url, &mysql_cred) {
+                Ok(store) => Ok(Arc::new(store)),
+                Err(err)  => Err(Error::StoreError{ err }),
+            }
+        },
+
+        StoreKind::Memory => {
+            info!("Using in-memory account store; accounts will not persist across restarts");
+            Ok(Arc::new(MemoryStore::new()))
+        },
+
+        StoreKind::Sqlite => {
+            info!("Opening SQLite database @ {}...", config.store.sqlite_path.display());
+            match SqliteStore::new(&config.store.sqlite_path) {
+                Ok(store) => Ok(Arc::new(store)),
+                Err(err)  => Err(Error::StoreError{ err }),
+            }
+        },
+    }
+}
+
+
+
+/// Makes sure the schema of the given store is at the version that this binary expects.
+/// 
+/// **Arguments**
+///  * `store`: The MigrationStore to check.
+///  * `apply`: Whether to apply any pending migrations. If not, pending migrations are an error.
+/// 
+/// **Returns**  
+/// Nothing on success, or else an Error.
+fn ensure_schema(store: &dyn MigrationStore, apply: bool) -> Result<(), Error> {
+    // Never touch databases that a newer version has migrated
+    if let Err(err) = migrations::check(store) { return Err(Error::MigrationError{ err }); }
+
+    if apply {
+        match migrations::up(store, None) {
+            Ok(applied) => { if !applied.is_empty() { info!("Migrated database schema to version {}", migrations::latest(store)); } },
+            Err(err)    => { return Err(Error::MigrationError{ err }); }
         }
+    } else {
+        let current: u32 = match migrations::current(store) {
+            Ok(current) => current,
+            Err(err)    => { return Err(Error::MigrationError{ err }); }
+        };
+        let latest: u32 = migrations::latest(store);
+        if current < latest { return Err(Error::MigrationError{ err: MigrationError::PendingMigrations{ database: current, binary: latest } }); }
+    }
+    Ok(())
+}
+
+
+
+/// Runs the given 'migrate' subcommand.
+/// 
+/// **Arguments**
+///  * `store`: The MigrationStore to migrate.
+///  * `action`: The MigrateAction to perform.
+/// 
+/// **Returns**  
+/// Nothing on success, or else an Error.
+fn migrate(store: &dyn MigrationStore, action: &MigrateAction) -> Result<(), Error> {
+    match action {
+        MigrateAction::Status => {
+            let status: Vec<MigrationStatus> = match migrations::status(store) {
+                Ok(status) => status,
+                Err(err)   => { return Err(Error::MigrationError{ err }); }
+            };
+            if status.is_empty() { println!("This store has no schema to migrate."); return Ok(()); }
+            println!("{:<8} {:<24} APPLIED", "VERSION", "NAME");
+            for migration in status {
+                let applied: String = match migration.applied_at {
+                    Some(applied_at) => applied_at.format(&Rfc3339).expect("Could not format migration time to ISO/RFC3339; this should never happen!"),
+                    None             => String::from("pending"),
+                };
+                println!("{:<8} {:<24} {}{}", migration.version, migration.name, applied, if migration.known { "" } else { " (unknown to this binary)" });
+            }
+        },
+
+        MigrateAction::Up{ to } => match migrations::up(store, *to) {
+            Ok(applied) => { println!("Applied {} migration(s); database schema is at version {}", applied.len(), migrations::current(store).map_err(|err| Error::MigrationError{ err })?); },
+            Err(err)    => { return Err(Error::MigrationError{ err }); }
+        },
+
+        MigrateAction::Down{ to } => match migrations::down(store, *to) {
+            Ok(reverted) => { println!("Reverted {} migration(s); database schema is at version {}", reverted.len(), migrations::current(store).map_err(|err| Error::MigrationError{ err })?); },
+            Err(err)     => { return Err(Error::MigrationError{ err }); }
+        },
     }
+    Ok(())
+}
 
 
 
+/// Runs the given subcommand instead of the service.
+/// 
+/// **Arguments**
+///  * `config`: The Config that specifies which store to work on (and, for some commands, how to hash passwords).
+///  * `command`: The Command to run.
+/// 
+/// **Returns**  
+/// Nothing on success, or else an Error.
+fn run_command(config: &Config, passphrase: Option<&str>, command: &Command) -> Result<(), Error> {
+    let store: Arc<dyn Store> = open_store(config, passphrase)?;
+    let store: &dyn Store = store.as_ref();
+    if let Command::Migrate{ action } = command { return migrate(store, action); }
+
+    // The other commands need an up-to-date schema, just like the service does
+    ensure_schema(store, config.store.migrate)?;
+    let res: Result<(), ManageError> = match command {
+        Command::Migrate{ .. } => unreachable!("Migrate command should have been handled already; this should never happen!"),
+
+        Command::UserAdd{ username, role, password_stdin } => {
+            let policy: HashPolicy = load_hash_policy(&config.hashing)?;
+            manage::read_password(username, *password_stdin)
+                .and_then(|password| manage::useradd(store, &policy, username, &password, *role))
+                .map(|id| println!("Added user '{}' with identifier {}", username, id))
+        },
+        Command::UserDel{ username } => manage::userdel(store, username).map(|_| println!("Removed user '{}'", username)),
+        Command::UserMod{ username, rename, display_name, clear_display_name, role, disable, enable } => {
+            let changes = UserChanges {
+                name         : rename.clone(),
+                display_name : if *clear_display_name { Some(None) } else { display_name.clone().map(Some) },
+                role         : *role,
+                disabled     : if *disable { Some(true) } else if *enable { Some(false) } else { None },
+            };
+            manage::usermod(store, username, &changes).map(|_| println!("Updated user '{}'", username))
+        },
+        Command::Passwd{ username, password_stdin } => {
+            let policy: HashPolicy = load_hash_policy(&config.hashing)?;
+            manage::read_password(username, *password_stdin)
+                .and_then(|password| manage::passwd(store, &policy, username, &password))
+                .map(|_| println!("Changed password of user '{}'", username))
+        },
+        Command::ResetToken{ username } => {
+            let lifetime: Duration = Duration::from_secs(config.jwt.reset_lifetime);
+            manage::reset_token(store, username, lifetime)
+                .map(|reset_token| println!("Reset token for user '{}' (valid for {} seconds):\n{}", username, lifetime.as_secs(), reset_token))
+        },
+
+        Command::List => {
+            let users: Vec<Account> = match store.list() {
+                Ok(users) => users,
+                Err(err)  => { return Err(Error::StoreError{ err }); }
+            };
+            println!("{:<8} {:<24} {:<8} STATUS", "ID", "NAME", "ROLE");
+            for user in users {
+                let status: String = if user.disabled {
+                    String::from("disabled")
+                } else if let Some(locked_until) = user.locked_until.filter(|locked_until| *locked_until > OffsetDateTime::now_utc()) {
+                    format!("locked until {}", locked_until.format(&Rfc3339).expect("Could not format lock time to ISO/RFC3339; this should never happen!"))
+                } else {
+                    String::from("active")
+                };
+                println!("{:<8} {:<24} {:<8} {}", user.id, user.credential.user(), user.role.name(), status);
+            }
+            Ok(())
+        },
+
+        Command::RotateRoot => {
+            let policy: HashPolicy = load_hash_policy(&config.hashing)?;
+            manage::rotate_root(store, &policy, &config.credentials.root, passphrase)
+                .map(|_| println!("Wrote new root credentials to '{}'; send SIGHUP to a running service to reload them", config.credentials.root.display()))
+        },
+    };
+    res.map_err(|err| Error::ManageError{ err })
+}
+
+
+
+/// Ensure the root user is present in the given store, and that it is an (enabled) administrator.
+/// 
+/// **Arguments**
+///  * `store`: The Store to add the root user to.
+///  * `root_cred`: The credentials for the root account. Will be used to initiate it if the root does not exist, or checked to see if the root authentication is still valid.
+///  * `policy`: The HashPolicy to hash the root password with.
+///  * `overwrite`: Whether to replace the root password if it does not match `root_cred` (which revokes the root's sessions), instead of failing.
+/// 
+/// **Returns**  
+/// Nothing on success, or else an Error.
+fn ensure_root(store: &dyn Store, root_cred: &Credential, policy: &HashPolicy, overwrite: bool) -> Result<(), Error> {
+    // Insert the root user into it if it does not exist yet
+    debug!("Checking if root user already exists...");
+    let root: Option<Account> = match store.get_by_name(root_cred.user()) {
+        Ok(root) => root,
+        Err(err) => { return Err(Error::StoreError{ err }); }
+    };
+    match root {
+        None => {
+            debug!("Inserting root user...");
+
+            // Create the (double) hashed version of the password
+            let root_cred = match Credential::from_plain_with(root_cred.user(), root_cred.pass(), policy) {
+                Ok(cred) => cred,
+                Err(err) => { return Err(Error::CredentialError{ err }); }
+            };
+
+            // Write it to the store
+            if let Err(err) = store.insert(&root_cred, Role::Admin) {
+                return Err(Error::StoreError{ err });
+            }
+        },
+
+        Some(root) => {
+            debug!("Root already exists; verifying root credentials...");
+
+            // Match with the root credentials
+            let matches: bool = match root.credential.verify_with(root_cred.user(), root_cred.pass(), policy) {
+                Ok(res)  => res,
+                Err(err) => { return Err(Error::CredentialVerifyError{ err }); }
+            };
+            if !matches && !overwrite { return Err(Error::RootCredentialsOutdated); }
+
+            // Replace the hash if the password changed or the policy has become stronger
+            match root.credential.needs_rehash(policy).map(|rehash| rehash || !matches) {
+                Ok(true) => {
+                    debug!("Rehashing root password...");
+                    let new_cred = match Credential::from_plain_with(root_cred.user(), root_cred.pass(), policy) {
+                        Ok(cred) => cred,
+                        Err(err) => { return Err(Error::CredentialError{ err }); }
+                    };
+                    if let Err(err) = store.update_password(root.id, new_cred.pass()) {
+                        return Err(Error::StoreError{ err });
+                    }
+                    if !matches {
+                        info!("Changed root password; revoking root sessions");
+                        if let Err(err) = token::end_all_sessions(store, root.id) { return Err(Error::StoreError{ err }); }
+                    }
+                },
+                Ok(false) => {},
+                Err(err)  => { return Err(Error::CredentialVerifyError{ err }); }
+            }
+
+            // Make sure nobody demoted or disabled the root user
+            if root.role != Role::Admin {
+                debug!("Restoring root role...");
+                if let Err(err) = store.set_role(root.id, Role::Admin) { return Err(Error::StoreError{ err }); }
+            }
+            if root.disabled {
+                debug!("Re-enabling root user...");
+                if let Err(err) = store.set_disabled(root.id, false) { return Err(Error::StoreError{ err }); }
+            }
+        },
+    };
+
     // That's it for now
-    debug!("Database preparation complete.");
+    debug!("Root user preparation complete.");
     Ok(())
 }
 
@@ -167,87 +788,220 @@ fn ensure_database(pool: Arc<Pool>, root_cred: &Credential) -> Result<(), Error>
 
 
 
+/// Reloads the JWT keys and the root credentials, e.g., after they have been rotated. If either cannot be loaded, the old one is kept.
+/// 
+/// Note that this blocks, since it may hash the root password and update the store.
+/// 
+/// **Arguments**
+///  * `ctx`: The Context of the service, with the keys to replace.
+///  * `config`: The Config that says where to find the keys and credentials.
+fn reload(ctx: &Context, config: &Config, passphrase: Option<&str>) {
+    debug!("Reloading JWT keys...");
+    match load_jwt_keys(config.jwt.algorithm, &config.jwt.key, &config.jwt.verify_keys) {
+        Ok((key, verifier)) => {
+            ctx.keys.replace(key, verifier);
+            info!("Reloaded JWT keys");
+        },
+        Err(err) => { error!("Could not reload JWT keys (keeping the old ones): {}", err); },
+    }
+
+    debug!("Reloading root credentials...");
+    let root_cred = match Credential::from_file_with(&config.credentials.root, passphrase) {
+        Ok(cred) => cred,
+        Err(err) => { error!("Could not reload root credentials (keeping the old ones): {}", err); return; }
+    };
+    match ensure_root(ctx.store.as_ref(), &root_cred, &ctx.hashing, true) {
+        Ok(_)    => { info!("Reloaded root credentials"); },
+        Err(err) => { error!("Could not apply reloaded root credentials: {}", err); },
+    }
+}
+
+
+
+
+
 /***** ENTRYPOINT *****/
 #[tokio::main]
 async fn main() {
-    // Read command-line arguments
+    // Read command-line arguments, and the configuration they may point to
     let args = Arguments::parse();
+    let config: Config = match load_config(&args) {
+        Ok(config) => config,
+        Err(err)   => { eprintln!("{}", err); std::process::exit(1); }
+    };
+    if args.print_config {
+        match config.redacted().to_toml() {
+            Ok(text) => { print!("{}", text); },
+            Err(err) => { eprintln!("{}", err); std::process::exit(1); }
+        }
+        return;
+    }
 
     // Setup the logger
-    if let Err(err) = TermLogger::init(if args.debug { LevelFilter::Debug } else { LevelFilter::Info }, Default::default(), TerminalMode::Mixed, ColorChoice::Auto) {
+    if let Err(err) = TermLogger::init(config.logging.level.into(), Default::default(), TerminalMode::Mixed, ColorChoice::Auto) {
         eprintln!("Could not initialize logger: {}", err);
         std::process::exit(1);
     }
     info!("Todo-Rust Authorization Service v{}", env!("CARGO_PKG_VERSION"));
+    if let Some(path) = &args.config { info!("Using configuration file '{}'", path.display()); }
+    if let Err(err) = config.validate() {
+        error!("{}", err);
+        std::process::exit(1);
+    }
+
+    // Read the passphrase of any encrypted credential files
+    let passphrase: Option<String> = match load_passphrase(&args) {
+        Ok(passphrase) => passphrase,
+        Err(err)       => { error!("{}", err); std::process::exit(1); }
+    };
+
+    // Run any subcommand instead of the service
+    if let Some(command) = &args.command {
+        if let Err(err) = run_command(&config, passphrase.as_deref(), command) {
+            error!("{}", err);
+            std::process::exit(1);
+        }
+        return;
+    }
 
 
 
     // Load the credentials
-    debug!("Loading MySQL credentials...");
-    let mysql_cred = match Credential::from_file(args.mysql_root_cred) {
+    debug!("Loading root credentials...");
+    let root_cred = match Credential::from_file_with(&config.credentials.root, passphrase.as_deref()) {
         Ok(cred) => cred,
-        Err(err) => { error!("{}", err); std::process::exit(1); }  
+        Err(err) => { error!("{}", err); std::process::exit(1); }
     };
 
-    debug!("Loading root credentials...");
-    let root_cred = match Credential::from_file(args.root_cred) {
-        Ok(cred) => cred,
+    debug!("Loading JWT keys...");
+    let (key, verifier) = match load_jwt_keys(config.jwt.algorithm, &config.jwt.key, &config.jwt.verify_keys) {
+        Ok(keys) => keys,
         Err(err) => { error!("{}", err); std::process::exit(1); }
     };
 
-    debug!("Loading JWT secret...");
-    let secret = match fs::read_to_string(args.secret) {
-        Ok(secret) => Arc::new(secret),
+    debug!("Loading password hashing policy...");
+    let hashing = match load_hash_policy(&config.hashing) {
+        Ok(hashing) => hashing,
+        Err(err)    => { error!("{}", err); std::process::exit(1); }
+    };
+
+    debug!("Loading password policy...");
+    let passwords = match load_password_policy(&config.password) {
+        Ok(passwords) => passwords,
+        Err(err)      => { error!("{}", err); std::process::exit(1); }
+    };
+
+    debug!("Loading registration policy...");
+    let policy = match load_registration_policy(config.registration.mode, config.registration.invite_codes.as_deref()) {
+        Ok(policy) => policy,
         Err(err)   => { error!("{}", err); std::process::exit(1); }
     };
 
 
 
-    // Prepare the pool for local MySQL connections
-    info!("Preparing connections to MySQL database @ {}...", &args.mysql_url);
-    let pool = match Pool::new(Opts::from_url(&format!("mysql://{}:{}@{}", mysql_cred.user(), mysql_cred.pass(), args.mysql_url)).expect("Could not get URL from Url; this should never happen!")) {
-        Ok(pool) => Arc::new(pool),
-        Err(err) => { error!("{}", Error::MySqlPoolCreateError{ url: args.mysql_url, err }); std::process::exit(1); }
+    let tls: Option<TlsState> = match (&config.server.tls_cert, &config.server.tls_key) {
+        (Some(cert), Some(key)) => {
+            debug!("Loading TLS certificate...");
+            match TlsState::new(cert, key) {
+                Ok(tls)  => Some(tls),
+                Err(err) => { error!("{}", err); std::process::exit(1); }
+            }
+        },
+        _ => None,
     };
 
-    // Prepare the database if needed
-    if let Err(err) = ensure_database(pool.clone(), &root_cred) {
+
+
+    // Open the account store
+    let store: Arc<dyn Store> = match open_store(&config, passphrase.as_deref()) {
+        Ok(store) => store,
+        Err(err)  => { error!("{}", err); std::process::exit(1); }
+    };
+
+    // Bring its schema up-to-date
+    if let Err(err) = ensure_schema(store.as_ref(), config.store.migrate) {
         error!("{}", err);
         std::process::exit(1);
-    };
+    }
 
+    // Make sure the root user is present
+    if let Err(err) = ensure_root(store.as_ref(), &root_cred, &hashing, false) {
+        error!("{}", err);
+        std::process::exit(1);
+    }
 
 
-    // Prepare the warp filter for logging in
-    debug!("Preparing warp filter for 'v1/login'...");
-    let tpool = pool.clone(); let tsecret = secret.clone();
-    let login = warp::post()
-        .and(warp::path("v1"))
-        .and(warp::path("login"))
-        .and(warp::path::end())
-        .and(warp::body::content_length_limit(1024 * 16))
-        .and(warp::body::json())
-        .and_then(move |body| { login::handle(tpool.clone(), tsecret.clone(), body) });
 
-    // Prepare the warp filter for testing login
-    debug!("Preparing warp filter for 'v1/login/test'...");
-    let tpool = pool.clone();
-    let login_test = warp::post()
-        .and(warp::path("v1"))
-        .and(warp::path("login"))
-        .and(warp::path("test"))
-        .and(warp::path::end())
-        .and(warp::body::content_length_limit(1024 * 16))
-        .and(warp::body::json())
-        .and_then(move |body| { login::handle_test(tpool.clone(), body) });
+    // Limit how many requests may hash passwords at once; more than there are cores only makes all of them slower
+    let hash_concurrency: usize = match config.hashing.concurrency {
+        Some(max) => max.get(),
+        None      => std::thread::available_parallelism().map(NonZeroUsize::get).unwrap_or(1),
+    };
+    debug!("Allowing {} request(s) to hash passwords at the same time", hash_concurrency);
+
+
+
+    // Prepare the shared context
+    let ctx = Arc::new(Context {
+        store,
+        keys         : Keys::new(key, verifier),
+        tokens       : TokenPolicy {
+            access_lifetime  : Duration::from_secs(config.jwt.access_lifetime),
+            refresh_lifetime : Duration::from_secs(config.jwt.refresh_lifetime),
+            reset_lifetime   : Duration::from_secs(config.jwt.reset_lifetime),
+        },
+        registration : policy,
+        hashing,
+        passwords,
+        throttle     : Throttle::new(ThrottlePolicy {
+            free_attempts     : config.throttle.free_attempts,
+            base_delay        : Duration::from_secs(config.throttle.base_delay),
+            max_delay         : Duration::from_secs(config.throttle.max_delay),
+            lockout_threshold : config.throttle.lockout_threshold,
+            lockout_duration  : Duration::from_secs(config.throttle.lockout_duration),
+        }),
+        challenges   : Challenges::new(),
+        metrics      : Metrics::new(),
+        hash_pool    : HashPool::new(hash_concurrency),
+    });
+
+    // Reload the JWT keys and root credentials on SIGHUP (the TLS certificate is reloaded by the server itself)
+    let mut hangup = match signal(SignalKind::hangup()) {
+        Ok(hangup) => hangup,
+        Err(err)   => { error!("{}", Error::SignalError{ err }); std::process::exit(1); }
+    };
+    let reload_ctx = ctx.clone();
+    let reload_config = Arc::new(config.clone());
+    let reload_passphrase = passphrase.clone();
+    tokio::spawn(async move {
+        while hangup.recv().await.is_some() {
+            info!("Received SIGHUP; reloading secrets");
+            let (ctx, config, passphrase) = (reload_ctx.clone(), reload_config.clone(), reload_passphrase.clone());
+            if let Err(err) = tokio::task::spawn_blocking(move || reload(&ctx, &config, passphrase.as_deref())).await {
+                error!("Reloading secrets failed: {}", err);
+            }
+        }
+    });
 
-    // Prepare the global filter
-    debug!("Preparing global warp filter...");
-    let filter = login.or(login_test);
+    // Prepare the warp filter
+    let filter = routes::filter(ctx);
 
     // Run the server
-    info!("Running warp server @ {}:{}", &args.host, &args.port);
-    warp::serve(filter)
-        .run((args.host, args.port))
-        .await;
+    let addr = SocketAddr::from((config.server.host, config.server.port));
+    match tls {
+        Some(tls) => {
+            info!("Running warp server @ https://{}", addr);
+            if let Err(err) = tls::serve(warp::service(filter), addr, Arc::new(tls)).await {
+                error!("{}", err);
+                std::process::exit(1);
+            }
+        },
+        None => {
+            warn!("Serving plain HTTP; passwords and tokens cross the network unencrypted unless a proxy in front terminates TLS (see '--tls-cert')");
+            info!("Running warp server @ http://{}", addr);
+            warp::serve(filter)
+                .run(addr)
+                .await;
+        },
+    }
 }
@@ -0,0 +1,404 @@
+/* MANAGE.rs
+ *   by Lut99
+ *
+ * Created:
+ *   18 Oct 2026, 05:14:18
+ * Last edited:
+ *   18 Oct 2026, 06:42:40
+ * Auto updated?
+ *   Yes
+ *
+ * Description:
+ *   Implements the subcommands that manage accounts directly in the
+ *   store, without going through (or even running) the HTTP server.
+ *
+ *   They are meant for operators that need to fix an account from inside
+ *   the container, e.g., because nobody can login as an administrator
+ *   anymore. Every change is still recorded in the audit log.
+**/
+
+use std::fs;
+use std::io::{self, BufRead};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use log::info;
+
+use todo_spec::credentials::{self, Credential, HashPolicy};
+use todo_spec::jwt::Role;
+
+pub use crate::errors::ManageError as Error;
+use crate::{audit, reset};
+use crate::spec::Account;
+use crate::store::Store;
+use crate::token::{self, random_token};
+
+
+/***** UNIT TESTS *****/
+#[cfg(test)]
+mod tests {
+    use todo_spec::credentials::HashAlgorithm;
+
+    use super::*;
+    use crate::errors::StoreError;
+    use crate::store::{AccountStore, MemoryStore, ResetTokenStore};
+
+    /// Returns a HashPolicy that is fast enough for testing.
+    fn weak_policy() -> HashPolicy {
+        HashPolicy::new(HashAlgorithm::Argon2id, 1024, 1, 1).unwrap()
+    }
+
+    #[test]
+    fn test_useradd_passwd() {
+        let store = MemoryStore::new();
+        let policy = weak_policy();
+
+        // Users are added with the given role and password
+        let id: u32 = useradd(&store, &policy, "john", "pass", Role::Admin).unwrap();
+        let john = store.get_by_id(id).unwrap().unwrap();
+        assert_eq!(john.role, Role::Admin);
+        assert!(john.credential.verify_with("john", "pass", &policy).unwrap());
+        assert!(matches!(useradd(&store, &policy, "john", "pass", Role::User), Err(Error::StoreError{ err: StoreError::DuplicateUser{ .. } })));
+
+        // Their password can be changed
+        passwd(&store, &policy, "john", "word").unwrap();
+        let john = store.get_by_id(id).unwrap().unwrap();
+        assert!(john.credential.verify_with("john", "word", &policy).unwrap());
+        assert!(matches!(passwd(&store, &policy, "jane", "word"), Err(Error::UnknownUser{ .. })));
+
+        // They can also get a reset token, which replaces the previous one
+        let first: String = reset_token(&store, "john", Duration::from_secs(60)).unwrap();
+        let second: String = reset_token(&store, "john", Duration::from_secs(60)).unwrap();
+        assert!(store.get_reset_token(&token::hash_token(&first)).unwrap().is_none());
+        assert_eq!(store.get_reset_token(&token::hash_token(&second)).unwrap().unwrap().user_id, id);
+        assert!(matches!(reset_token(&store, "jane", Duration::from_secs(60)), Err(Error::UnknownUser{ .. })));
+
+        // And they can be removed again
+        userdel(&store, "john").unwrap();
+        assert!(store.get_by_id(id).unwrap().is_none());
+        assert!(matches!(userdel(&store, "john"), Err(Error::UnknownUser{ .. })));
+    }
+
+    #[test]
+    fn test_usermod() {
+        let store = MemoryStore::new();
+        let policy = weak_policy();
+        let id: u32 = useradd(&store, &policy, "john", "pass", Role::User).unwrap();
+        useradd(&store, &policy, "jane", "pass", Role::User).unwrap();
+
+        // All changes are applied at once
+        usermod(&store, "john", &UserChanges{ name: Some(String::from("johnny")), display_name: Some(Some(String::from("Johnny D."))), role: Some(Role::Admin), disabled: Some(true) }).unwrap();
+        let john = store.get_by_id(id).unwrap().unwrap();
+        assert_eq!(john.credential.user(), "johnny");
+        assert_eq!(john.display_name.as_deref(), Some("Johnny D."));
+        assert_eq!(john.role, Role::Admin);
+        assert!(john.disabled);
+        assert!(store.get_by_name("john").unwrap().is_none());
+
+        // Names must be valid and unique
+        assert!(matches!(usermod(&store, "johnny", &UserChanges{ name: Some(String::from("jane")), ..Default::default() }), Err(Error::StoreError{ err: StoreError::DuplicateUser{ .. } })));
+        assert!(matches!(usermod(&store, "johnny", &UserChanges{ name: Some(String::from("JANE")), ..Default::default() }), Err(Error::StoreError{ err: StoreError::DuplicateUser{ .. } })));
+        assert!(matches!(usermod(&store, "johnny", &UserChanges{ name: Some(String::from("john doe")), ..Default::default() }), Err(Error::CredentialError{ .. })));
+        assert!(matches!(usermod(&store, "john", &UserChanges::default()), Err(Error::UnknownUser{ .. })));
+    }
+
+    #[test]
+    fn test_rotate_root() {
+        let store = MemoryStore::new();
+        let policy = weak_policy();
+        let path = std::env::temp_dir().join(format!("todo-auth-test-root-{}.cred", std::process::id()));
+        Credential::new("root", "rootpass").unwrap().serialize_to_file(&path).unwrap();
+        let id: u32 = useradd(&store, &policy, "root", "rootpass", Role::Admin).unwrap();
+
+        // The file and the store should agree on a new password afterwards
+        rotate_root(&store, &policy, &path, None).unwrap();
+        let cred = Credential::from_file(&path).unwrap();
+        assert_eq!(cred.user(), "root");
+        assert_ne!(cred.pass(), "rootpass");
+        let root = store.get_by_id(id).unwrap().unwrap();
+        assert!(root.credential.verify_with("root", cred.pass(), &policy).unwrap());
+        assert!(!root.credential.verify_with("root", "rootpass", &policy).unwrap());
+
+        // With a passphrase, the new file is encrypted, and stays that way
+        rotate_root(&store, &policy, &path, Some("passphrase")).unwrap();
+        assert!(rotate_root(&store, &policy, &path, None).is_err());
+        let cred = Credential::from_file_with(&path, Some("passphrase")).unwrap();
+        rotate_root(&store, &policy, &path, Some("passphrase")).unwrap();
+        assert!(Credential::from_file(&path).is_err());
+        let new_cred = Credential::from_file_with(&path, Some("passphrase")).unwrap();
+        assert!(new_cred.encrypted());
+        assert_ne!(new_cred.pass(), cred.pass());
+        let _ = fs::remove_file(&path);
+    }
+}
+
+
+
+
+
+/***** HELPER FUNCTIONS *****/
+/// Searches the given store for the account with the given name.
+/// 
+/// **Arguments**
+///  * `store`: The Store to search.
+///  * `username`: The name of the user to search for.
+/// 
+/// **Returns**  
+/// The Account on success, or an Error if it does not exist or we failed to search.
+fn find(store: &dyn Store, username: &str) -> Result<Account, Error> {
+    match store.get_by_name(username) {
+        Ok(Some(user)) => Ok(user),
+        Ok(None)       => Err(Error::UnknownUser{ username: username.to_string() }),
+        Err(err)       => Err(Error::StoreError{ err }),
+    }
+}
+
+/// Hashes the given password for the given user and stores it, logging out all of their sessions.
+/// 
+/// **Arguments**
+///  * `store`: The Store with the user.
+///  * `policy`: The HashPolicy to hash the password with.
+///  * `user`: The Account of the user.
+///  * `password`: The new, plain-text password of the user.
+/// 
+/// **Returns**  
+/// Nothing on success, or an Error otherwise.
+fn set_password(store: &dyn Store, policy: &HashPolicy, user: &Account, password: &str) -> Result<(), Error> {
+    let cred: Credential = match Credential::from_plain_with(user.credential.user(), password, policy) {
+        Ok(cred) => cred,
+        Err(err) => { return Err(Error::CredentialError{ err }); }
+    };
+    if let Err(err) = store.update_password(user.id, cred.pass()) { return Err(Error::StoreError{ err }); }
+    if let Err(err) = token::end_all_sessions(store, user.id) { return Err(Error::StoreError{ err }); }
+    Ok(())
+}
+
+
+
+
+
+/***** LIBRARY STRUCTS *****/
+/// Defines the changes that `usermod()` makes to an account. Anything that is None is left as-is.
+#[derive(Clone, Debug, Default)]
+pub struct UserChanges {
+    /// The new name of the user.
+    pub name         : Option<String>,
+    /// The new display name of the user, or `Some(None)` to remove it.
+    pub display_name : Option<Option<String>>,
+    /// The new role of the user.
+    pub role         : Option<Role>,
+    /// Whether the user should be disabled (true) or re-enabled (false).
+    pub disabled     : Option<bool>,
+}
+
+
+
+
+
+/***** LIBRARY FUNCTIONS *****/
+/// Reads a new password for the given user, either by prompting (twice) on the terminal or by reading a single line from stdin.
+/// 
+/// **Arguments**
+///  * `username`: The name of the user, which is shown in the prompt.
+///  * `from_stdin`: Whether to read the password from stdin instead of prompting for it. Useful when there is no terminal.
+/// 
+/// **Returns**  
+/// The password on success, or an Error if we could not read it or the two prompts did not match.
+pub fn read_password(username: &str, from_stdin: bool) -> Result<String, Error> {
+    if from_stdin {
+        let mut password = String::new();
+        if let Err(err) = io::stdin().lock().read_line(&mut password) { return Err(Error::PasswordReadError{ err }); }
+        return Ok(password.trim_end_matches([ '\r', '\n' ]).to_string());
+    }
+
+    let password1: String = match rpassword::prompt_password(format!("New password for '{}': ", username)) {
+        Ok(password) => password,
+        Err(err)     => { return Err(Error::PasswordPromptError{ err }); }
+    };
+    let password2: String = match rpassword::prompt_password("Verify password: ") {
+        Ok(password) => password,
+        Err(err)     => { return Err(Error::PasswordPromptError{ err }); }
+    };
+    if password1 != password2 { return Err(Error::PasswordMismatch); }
+    Ok(password1)
+}
+
+
+
+/// Adds a new user to the given store.
+/// 
+/// **Arguments**
+///  * `store`: The Store to add the user to.
+///  * `policy`: The HashPolicy to hash the password with.
+///  * `username`: The name of the new user.
+///  * `password`: The plain-text password of the new user.
+///  * `role`: The role of the new user.
+/// 
+/// **Returns**  
+/// The identifier of the new user on success, or an Error otherwise.
+pub fn useradd(store: &dyn Store, policy: &HashPolicy, username: &str, password: &str, role: Role) -> Result<u32, Error> {
+    let cred: Credential = match Credential::from_plain_with(username, password, policy) {
+        Ok(cred) => cred,
+        Err(err) => { return Err(Error::CredentialError{ err }); }
+    };
+    let id: u32 = match store.insert(&cred, role) {
+        Ok(id)   => id,
+        Err(err) => { return Err(Error::StoreError{ err }); }
+    };
+
+    info!("Created user '{}' with role {}", username, role);
+    audit::record_local(store, id, username, &format!("created with role {} on the command line", role));
+    Ok(id)
+}
+
+/// Removes the given user from the given store, together with their sessions and second factor.
+/// 
+/// **Arguments**
+///  * `store`: The Store to remove the user from.
+///  * `username`: The name of the user to remove.
+/// 
+/// **Returns**  
+/// Nothing on success, or an Error otherwise.
+pub fn userdel(store: &dyn Store, username: &str) -> Result<(), Error> {
+    let user: Account = find(store, username)?;
+    if let Err(err) = store.delete(user.id) { return Err(Error::StoreError{ err }); }
+
+    info!("Deleted user '{}'", username);
+    audit::record_local(store, user.id, username, "deleted on the command line");
+    Ok(())
+}
+
+/// Changes the name, role and/or status of the given user. Disabling a user also logs out all of their sessions.
+/// 
+/// **Arguments**
+///  * `store`: The Store with the user.
+///  * `username`: The (current) name of the user to change.
+///  * `changes`: The UserChanges to make.
+/// 
+/// **Returns**  
+/// Nothing on success, or an Error otherwise. If renaming fails, none of the other changes are made.
+pub fn usermod(store: &dyn Store, username: &str, changes: &UserChanges) -> Result<(), Error> {
+    let user: Account = find(store, username)?;
+
+    let mut username: &str = user.credential.user();
+    let name: Option<String> = changes.name.as_ref().map(credentials::normalize_username);
+    if let Some(name) = &name {
+        if let Err(err) = credentials::verify_username(name) { return Err(Error::CredentialError{ err }); }
+        if let Err(err) = store.rename(user.id, name) { return Err(Error::StoreError{ err }); }
+        info!("Renamed user '{}' to '{}'", username, name);
+        audit::record_local(store, user.id, name, &format!("renamed from '{}' on the command line", username));
+        username = name;
+    }
+    if let Some(display_name) = &changes.display_name {
+        if let Some(display_name) = display_name {
+            if let Err(err) = credentials::verify_display_name(display_name) { return Err(Error::CredentialError{ err }); }
+        }
+        if let Err(err) = store.set_display_name(user.id, display_name.as_deref()) { return Err(Error::StoreError{ err }); }
+        info!("Changed display name of user '{}'", username);
+        audit::record_local(store, user.id, username, &match display_name {
+            Some(display_name) => format!("display name set to '{}' on the command line", display_name),
+            None               => String::from("display name removed on the command line"),
+        });
+    }
+    if let Some(role) = changes.role {
+        if let Err(err) = store.set_role(user.id, role) { return Err(Error::StoreError{ err }); }
+        info!("Changed role of user '{}' to {}", username, role);
+        audit::record_local(store, user.id, username, &format!("role changed to {} on the command line", role));
+    }
+    if let Some(disabled) = changes.disabled {
+        if let Err(err) = store.set_disabled(user.id, disabled) { return Err(Error::StoreError{ err }); }
+        if disabled {
+            if let Err(err) = token::end_all_sessions(store, user.id) { return Err(Error::StoreError{ err }); }
+        }
+        info!("{} user '{}'", if disabled { "Disabled" } else { "Re-enabled" }, username);
+        audit::record_local(store, user.id, username, &format!("{} on the command line", if disabled { "disabled" } else { "re-enabled" }));
+    }
+    Ok(())
+}
+
+/// Sets a new password for the given user. This logs out all of their sessions.
+/// 
+/// **Arguments**
+///  * `store`: The Store with the user.
+///  * `policy`: The HashPolicy to hash the password with.
+///  * `username`: The name of the user whose password to set.
+///  * `password`: The new, plain-text password of the user.
+/// 
+/// **Returns**  
+/// Nothing on success, or an Error otherwise.
+pub fn passwd(store: &dyn Store, policy: &HashPolicy, username: &str, password: &str) -> Result<(), Error> {
+    let user: Account = find(store, username)?;
+    set_password(store, policy, &user, password)?;
+
+    info!("Changed password of user '{}'", username);
+    audit::record_local(store, user.id, username, "password reset on the command line");
+    Ok(())
+}
+
+/// Issues a one-time password reset token for the given user, which they can trade for a new password at the service.
+/// 
+/// **Arguments**
+///  * `store`: The Store with the user.
+///  * `username`: The name of the user to issue the token for.
+///  * `lifetime`: The time that the token is valid.
+/// 
+/// **Returns**  
+/// The reset token on success, or an Error otherwise.
+pub fn reset_token(store: &dyn Store, username: &str, lifetime: Duration) -> Result<String, Error> {
+    let user: Account = find(store, username)?;
+    let reset_token: String = match reset::issue(store, user.id, lifetime) {
+        Ok(reset_token) => reset_token,
+        Err(err)        => { return Err(Error::StoreError{ err }); }
+    };
+
+    info!("Issued reset token for user '{}'", username);
+    audit::record_local(store, user.id, username, "reset token issued on the command line");
+    Ok(reset_token)
+}
+
+/// Gives the root user a new, random password, and writes it to the given root credentials file. This logs out all of its sessions.
+/// 
+/// The new file is written next to the old one first, and only replaces it once the store has been updated.
+/// 
+/// **Arguments**
+///  * `store`: The Store with the root user.
+///  * `policy`: The HashPolicy to hash the password with.
+///  * `path`: The path to the root credentials file. The name of the root user is read from it.
+///  * `passphrase`: The passphrase of the root credentials file, if it is encrypted. If given, the new file is encrypted with it (even if the old one was not).
+/// 
+/// **Returns**  
+/// Nothing on success, or an Error otherwise.
+pub fn rotate_root(store: &dyn Store, policy: &HashPolicy, path: &Path, passphrase: Option<&str>) -> Result<(), Error> {
+    let root_cred: Credential = match Credential::from_file_with(path, passphrase) {
+        Ok(cred) => cred,
+        Err(err) => { return Err(Error::CredentialError{ err }); }
+    };
+    let root: Account = find(store, root_cred.user())?;
+
+    // Write the new credentials to a temporary file first, so we never lose the password
+    let password: String = random_token();
+    let mut new_path = path.as_os_str().to_owned();
+    new_path.push(".new");
+    let new_path = PathBuf::from(new_path);
+    let mut new_cred: Credential = match Credential::new(root_cred.user(), &password) {
+        Ok(cred) => cred,
+        Err(err) => { return Err(Error::CredentialError{ err }); }
+    };
+    if let Some(host) = root_cred.host() { new_cred = new_cred.with_host(host); }
+    let res: Result<(), credentials::Error> = match passphrase {
+        Some(passphrase) => new_cred.serialize_to_file_encrypted(&new_path, passphrase),
+        None             => new_cred.serialize_to_file(&new_path),
+    };
+    if let Err(err) = res { return Err(Error::CredentialError{ err }); }
+
+    // Update the store, then put the new file in place
+    if let Err(err) = set_password(store, policy, &root, &password) {
+        let _ = fs::remove_file(&new_path);
+        return Err(err);
+    }
+    if let Err(err) = fs::rename(&new_path, path) { return Err(Error::RootCredentialsRenameError{ from: new_path, to: path.to_path_buf(), err }); }
+
+    info!("Rotated password of root user '{}'", root_cred.user());
+    audit::record_local(store, root.id, root_cred.user(), "root password rotated on the command line");
+    Ok(())
+}
@@ -0,0 +1,311 @@
+/* METRICS.rs
+ *   by Lut99
+ *
+ * Created:
+ *   18 Oct 2026, 04:41:37
+ * Last edited:
+ *   18 Oct 2026, 06:24:13
+ * Auto updated?
+ *   Yes
+ *
+ * Description:
+ *   Collects the metrics of the authorization service, and renders them
+ *   in the Prometheus text format.
+ *
+ *   Requests are counted per route rather than per path, since paths
+ *   may contain usernames; anything that isn't one of our routes is
+ *   counted as 'unmatched'. That keeps the number of series bounded, no
+ *   matter what clients send.
+**/
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use log::error;
+use prometheus::{Encoder, Histogram, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGauge, Opts, Registry, TextEncoder};
+use warp::http::{Method, StatusCode};
+use warp::log::{Info, Log};
+
+use crate::spec::{Context, PoolStats};
+
+
+/***** UNIT TESTS *****/
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_route_label() {
+        assert_eq!(route_label("/v1/login"), "/v1/login");
+        assert_eq!(route_label("/v1/login/"), "/v1/login");
+        assert_eq!(route_label("/v1/admin/users/john/disable"), "/v1/admin/users/{username}/disable");
+        assert_eq!(route_label("/v1/admin/users/john/../../login"), "unmatched");
+        assert_eq!(route_label("/wp-login.php"), "unmatched");
+    }
+
+    #[test]
+    fn test_render() {
+        let metrics = Metrics::new();
+        metrics.observe_request(&Method::POST, "/v1/login", StatusCode::OK, Duration::from_millis(20));
+        metrics.observe_request(&Method::from_bytes(b"BREW").unwrap(), "/coffee", StatusCode::NOT_FOUND, Duration::from_millis(1));
+        metrics.record_login(true);
+        metrics.time_password_verify(|| ());
+
+        metrics.record_overloaded();
+
+        let text = metrics.render(Some(PoolStats{ max: 100, in_use: 3 }), PoolStats{ max: 4, in_use: 1 });
+        assert!(text.contains("todo_auth_http_requests_total{method=\"POST\",route=\"/v1/login\",status=\"200\"} 1"));
+        assert!(text.contains("todo_auth_http_requests_total{method=\"other\",route=\"unmatched\",status=\"404\"} 1"));
+        assert!(text.contains("todo_auth_logins_total{result=\"success\"} 1"));
+        assert!(text.contains("todo_auth_logins_total{result=\"failure\"} 0"));
+        assert!(text.contains("todo_auth_password_verify_duration_seconds_count 1"));
+        assert!(text.contains("todo_auth_db_pool_connections_in_use 3"));
+        assert!(text.contains("todo_auth_hash_pool_max 4"));
+        assert!(text.contains("todo_auth_hash_pool_rejections_total 1"));
+    }
+}
+
+
+
+
+
+/***** CONSTANTS *****/
+/// The routes of the service as they appear in the metrics. Segments in braces match any single segment.
+const ROUTES: [&str; 27] = [
+    "/healthz",
+    "/readyz",
+    "/metrics",
+    "/v1/login",
+    "/v1/login/test",
+    "/v1/login/totp",
+    "/v1/register",
+    "/v1/password",
+    "/v1/password/reset",
+    "/v1/display-name",
+    "/v1/totp",
+    "/v1/totp/confirm",
+    "/v1/token/refresh",
+    "/v1/logout",
+    "/v1/sessions",
+    "/v1/sessions/{id}",
+    "/v1/tokens",
+    "/v1/tokens/{name}",
+    "/v1/.well-known/jwks.json",
+    "/v1/admin/users",
+    "/v1/admin/users/{username}",
+    "/v1/admin/users/{username}/disable",
+    "/v1/admin/users/{username}/enable",
+    "/v1/admin/users/{username}/password",
+    "/v1/admin/users/{username}/reset-token",
+    "/v1/admin/lockouts/{username}",
+    "/v1/admin/events",
+];
+
+/// The buckets (in seconds) of the request latency histogram.
+const REQUEST_BUCKETS: [f64; 11] = [ 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0 ];
+/// The buckets (in seconds) of the password verification histogram. Argon2 is slow on purpose, so these start higher.
+const VERIFY_BUCKETS: [f64; 10] = [ 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0 ];
+
+
+
+
+
+/***** HELPER FUNCTIONS *****/
+/// Finds the route that the given path belongs to.
+/// 
+/// **Arguments**
+///  * `path`: The path of the request.
+/// 
+/// **Returns**  
+/// The route as it is listed in `ROUTES`, or 'unmatched' if the path matches none of them.
+fn route_label(path: &str) -> &'static str {
+    let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+    for route in ROUTES {
+        let route_segments: Vec<&str> = route.trim_matches('/').split('/').collect();
+        if route_segments.len() == segments.len() && route_segments.iter().zip(segments.iter()).all(|(r, s)| (r.starts_with('{') && *s != ".." && *s != ".") || r == s) {
+            return route;
+        }
+    }
+    "unmatched"
+}
+
+/// Returns the label for the given method. Extension methods are all lumped together, so clients cannot create arbitrarily many series.
+#[inline]
+fn method_label(method: &Method) -> &str {
+    match *method {
+        Method::GET | Method::HEAD | Method::POST | Method::PUT | Method::DELETE | Method::PATCH | Method::OPTIONS => method.as_str(),
+        _                                                                                                         => "other",
+    }
+}
+
+
+
+
+
+/***** LIBRARY STRUCTS *****/
+/// Collects the metrics of the service.
+pub struct Metrics {
+    /// The registry that knows all metrics below
+    registry : Registry,
+
+    /// The number of handled requests, per route, method and status code
+    requests         : IntCounterVec,
+    /// The time it took to handle requests, per route and method
+    request_duration : HistogramVec,
+
+    /// The number of logins, per result ('success' or 'failure')
+    logins                   : IntCounterVec,
+    /// The time it took to verify passwords (and other Argon2 hashes)
+    password_verify_duration : Histogram,
+
+    /// The maximum number of connections to the store
+    pool_max    : IntGauge,
+    /// The number of connections to the store that are in use
+    pool_in_use : IntGauge,
+
+    /// The maximum number of requests that may hash passwords at the same time
+    hash_pool_max        : IntGauge,
+    /// The number of requests that are hashing passwords
+    hash_pool_in_use     : IntGauge,
+    /// The number of requests refused because too many others were hashing passwords
+    hash_pool_rejections : IntCounter,
+}
+
+impl Metrics {
+    /// Constructor for the Metrics, which registers all metrics with zero values.
+    /// 
+    /// **Returns**  
+    /// A new Metrics instance.
+    pub fn new() -> Self {
+        let registry = Registry::new();
+        let requests = IntCounterVec::new(Opts::new("todo_auth_http_requests_total", "Number of handled HTTP requests"), &[ "route", "method", "status" ]).expect("Could not create requests metric; this should never happen!");
+        let request_duration = HistogramVec::new(HistogramOpts::new("todo_auth_http_request_duration_seconds", "Time it took to handle HTTP requests").buckets(REQUEST_BUCKETS.to_vec()), &[ "route", "method" ]).expect("Could not create request duration metric; this should never happen!");
+        let logins = IntCounterVec::new(Opts::new("todo_auth_logins_total", "Number of logins, by result"), &[ "result" ]).expect("Could not create logins metric; this should never happen!");
+        let password_verify_duration = Histogram::with_opts(HistogramOpts::new("todo_auth_password_verify_duration_seconds", "Time it took to verify a password hash").buckets(VERIFY_BUCKETS.to_vec())).expect("Could not create password verification metric; this should never happen!");
+        let pool_max = IntGauge::new("todo_auth_db_pool_connections_max", "Maximum number of connections to the store").expect("Could not create pool metric; this should never happen!");
+        let pool_in_use = IntGauge::new("todo_auth_db_pool_connections_in_use", "Number of connections to the store that are in use").expect("Could not create pool metric; this should never happen!");
+        let hash_pool_max = IntGauge::new("todo_auth_hash_pool_max", "Maximum number of requests that may hash passwords at the same time").expect("Could not create hash pool metric; this should never happen!");
+        let hash_pool_in_use = IntGauge::new("todo_auth_hash_pool_in_use", "Number of requests that are hashing passwords").expect("Could not create hash pool metric; this should never happen!");
+        let hash_pool_rejections = IntCounter::new("todo_auth_hash_pool_rejections_total", "Number of requests refused because too many others were hashing passwords").expect("Could not create hash pool metric; this should never happen!");
+
+        // Both login results should show up, even before anybody logged in
+        logins.with_label_values(&[ "success" ]);
+        logins.with_label_values(&[ "failure" ]);
+
+        registry.register(Box::new(requests.clone())).expect("Could not register requests metric; this should never happen!");
+        registry.register(Box::new(request_duration.clone())).expect("Could not register request duration metric; this should never happen!");
+        registry.register(Box::new(logins.clone())).expect("Could not register logins metric; this should never happen!");
+        registry.register(Box::new(password_verify_duration.clone())).expect("Could not register password verification metric; this should never happen!");
+        registry.register(Box::new(pool_max.clone())).expect("Could not register pool metric; this should never happen!");
+        registry.register(Box::new(pool_in_use.clone())).expect("Could not register pool metric; this should never happen!");
+        registry.register(Box::new(hash_pool_max.clone())).expect("Could not register hash pool metric; this should never happen!");
+        registry.register(Box::new(hash_pool_in_use.clone())).expect("Could not register hash pool metric; this should never happen!");
+        registry.register(Box::new(hash_pool_rejections.clone())).expect("Could not register hash pool metric; this should never happen!");
+
+        Self {
+            registry,
+            requests,
+            request_duration,
+            logins,
+            password_verify_duration,
+            pool_max,
+            pool_in_use,
+            hash_pool_max,
+            hash_pool_in_use,
+            hash_pool_rejections,
+        }
+    }
+
+
+
+    /// Records a handled request.
+    /// 
+    /// **Arguments**
+    ///  * `method`: The method of the request.
+    ///  * `path`: The path of the request. It is mapped to its route before it is recorded.
+    ///  * `status`: The status code that we replied with.
+    ///  * `elapsed`: The time it took to handle the request.
+    pub fn observe_request(&self, method: &Method, path: &str, status: StatusCode, elapsed: Duration) {
+        let route: &str  = route_label(path);
+        let method: &str = method_label(method);
+        self.requests.with_label_values(&[ route, method, status.as_str() ]).inc();
+        self.request_duration.with_label_values(&[ route, method ]).observe(elapsed.as_secs_f64());
+    }
+
+    /// Records a login.
+    /// 
+    /// **Arguments**
+    ///  * `success`: Whether the user got in.
+    #[inline]
+    pub fn record_login(&self, success: bool) {
+        self.logins.with_label_values(&[ if success { "success" } else { "failure" } ]).inc();
+    }
+
+    /// Runs the given password verification, and records how long it took.
+    /// 
+    /// **Arguments**
+    ///  * `verify`: The closure that verifies the password.
+    /// 
+    /// **Returns**  
+    /// Whatever the closure returns.
+    pub fn time_password_verify<T, F: FnOnce() -> T>(&self, verify: F) -> T {
+        let start = Instant::now();
+        let res: T = verify();
+        self.password_verify_duration.observe(start.elapsed().as_secs_f64());
+        res
+    }
+
+    /// Records a request that was refused because too many others were hashing passwords.
+    #[inline]
+    pub fn record_overloaded(&self) {
+        self.hash_pool_rejections.inc();
+    }
+
+
+
+    /// Renders the current value of all metrics in the Prometheus text format.
+    /// 
+    /// **Arguments**
+    ///  * `pool`: The current PoolStats of the store, if it has any.
+    ///  * `hash_pool`: The current PoolStats of the HashPool.
+    /// 
+    /// **Returns**  
+    /// The metrics as text.
+    pub fn render(&self, pool: Option<PoolStats>, hash_pool: PoolStats) -> String {
+        if let Some(pool) = pool {
+            self.pool_max.set(pool.max as i64);
+            self.pool_in_use.set(pool.in_use as i64);
+        }
+        self.hash_pool_max.set(hash_pool.max as i64);
+        self.hash_pool_in_use.set(hash_pool.in_use as i64);
+
+        let mut buffer: Vec<u8> = Vec::new();
+        if let Err(err) = TextEncoder::new().encode(&self.registry.gather(), &mut buffer) {
+            error!("Could not render metrics: {}", err);
+        }
+        String::from_utf8(buffer).expect("Prometheus rendered non-UTF-8 metrics; this should never happen!")
+    }
+}
+
+impl Default for Metrics {
+    #[inline]
+    fn default() -> Self { Self::new() }
+}
+
+
+
+
+
+/***** LIBRARY FUNCTIONS *****/
+/// Builds a warp wrapper that records every request in the metrics of the given Context. Use with `Filter::with()` on the global filter (after recovering, so failures are counted with the status code the client sees).
+/// 
+/// **Arguments**
+///  * `ctx`: The Context with the Metrics to record in.
+/// 
+/// **Returns**  
+/// The warp wrapper.
+pub fn track(ctx: Arc<Context>) -> Log<impl Fn(Info<'_>) + Clone + Send> {
+    warp::log::custom(move |info: Info<'_>| {
+        ctx.metrics.observe_request(info.method(), info.path(), info.status(), info.elapsed());
+    })
+}
@@ -0,0 +1,125 @@
+/* PASSWORD.rs
+ *   by Lut99
+ *
+ * Created:
+ *   18 Oct 2026, 03:35:32
+ * Last edited:
+ *   18 Oct 2026, 07:11:01
+ * Auto updated?
+ *   Yes
+ *
+ * Description:
+ *   Handles logged-in users changing their password.
+**/
+
+use std::sync::Arc;
+
+use log::{debug, error, info};
+use warp::{Rejection, Reply};
+use warp::http::StatusCode;
+use warp::reply::Response;
+
+use todo_spec::api::{AuthEventKind, ErrorCode, PasswordJson};
+use todo_spec::credentials::Credential;
+use todo_spec::password::Error as PasswordPolicyError;
+
+pub use crate::errors::PasswordError as Error;
+pub use crate::spec::{Account, Context};
+use crate::audit::{self, Client};
+use crate::reply;
+use crate::throttle::{self, ThrottleKey};
+use crate::token;
+
+
+/***** HELPER MACROS *****/
+/// Writes the given error both to stderr via error!() and returns it as a custom reject
+macro_rules! throw {
+    ($err:expr) => {
+        let err = $err;
+        error!("{}", &err);
+        return Err(warp::reject::custom(err));
+    };
+}
+
+
+
+
+
+/***** LIBRARY FUNCTIONS *****/
+/// Handles changing the password of the authenticated user.
+/// 
+/// Note that this ends all of the user's sessions, so every device has to login again; this includes the access tokens handed out in them. Personal access tokens remain valid.
+/// 
+/// Wrong current passwords are throttled like failed logins, so a stolen access token cannot be used to guess the password either.
+/// 
+/// **Arguments**
+///  * `ctx`: The Context of the service.
+///  * `client`: The Client that sent the request.
+///  * `user_id`: The identifier of the user, as taken from their access token.
+///  * `body`: The message body we got with the request.
+/// 
+/// **Returns**  
+/// The Warp reply on success, or a Warp rejection on failure.
+pub fn handle(ctx: Arc<Context>, client: Client, user_id: u32, body: PasswordJson) -> Result<Response, Rejection> {
+    info!("Handling password change (v1/password)");
+
+    // Query the store for this user
+    debug!("Searching for user {}...", user_id);
+    let user: Account = match ctx.store.get_by_id(user_id) {
+        Ok(Some(user)) => user,
+        Ok(None)       => {
+            debug!("User {} password change failure: unknown user", user_id);
+            return Ok(reply::error(StatusCode::UNAUTHORIZED, ErrorCode::Unauthorized, "Unknown user"));
+        },
+        Err(err) => { throw!(Error::StoreError{ err }); }
+    };
+
+    // Verify the old password, unless the user failed to do so too often recently
+    let user_key = ThrottleKey::user(user.credential.user());
+    if let Some(wait) = ctx.throttle.check(std::slice::from_ref(&user_key)) {
+        debug!("User '{}' password change failure: throttled for another {:.1} seconds", user.credential.user(), wait.as_secs_f64());
+        audit::record(&ctx, &client, AuthEventKind::PasswordChange, Some(user.id), Some(user.credential.user()), Some("refused: throttled"));
+        return Ok(throttle::too_many_requests(ErrorCode::TooManyRequests, wait));
+    }
+    match ctx.metrics.time_password_verify(|| user.credential.verify_with(user.credential.user(), &body.old_password, &ctx.hashing)) {
+        Ok(is_valid) => {
+            if !is_valid {
+                debug!("User '{}' password change failure: invalid credentials", user.credential.user());
+                ctx.throttle.failure(std::slice::from_ref(&user_key));
+                audit::record(&ctx, &client, AuthEventKind::PasswordChange, Some(user.id), Some(user.credential.user()), Some("refused: invalid current password"));
+                return Ok(reply::error(StatusCode::FORBIDDEN, ErrorCode::InvalidCredentials, "Invalid password"));
+            }
+        },
+        Err(err) => { throw!(Error::CredentialVerifyError{ err }); }
+    }
+    ctx.throttle.clear(&user_key);
+
+    // Check the new one against the policy
+    match ctx.passwords.check(user.credential.user(), &body.new_password) {
+        Ok(()) => {},
+        Err(PasswordPolicyError::Rejected{ violations }) => {
+            debug!("User '{}' password change failure: new password does not meet the password policy", user.credential.user());
+            audit::record(&ctx, &client, AuthEventKind::PasswordChange, Some(user.id), Some(user.credential.user()), Some("refused: new password does not meet the password policy"));
+            return Ok(reply::weak_password(violations));
+        },
+        Err(err) => { throw!(Error::PasswordPolicyError{ err }); }
+    }
+
+    // Hash the new one and store it
+    let credential: Credential = match Credential::from_plain_with(user.credential.user(), &body.new_password, &ctx.hashing) {
+        Ok(credential) => credential,
+        Err(err)       => { throw!(Error::CredentialError{ err }); }
+    };
+    if let Err(err) = ctx.store.update_password(user.id, credential.pass()) { throw!(Error::StoreError{ err }); }
+
+    // Log out every session of the user
+    if let Err(err) = token::end_all_sessions(&*ctx.store, user.id) { throw!(Error::StoreError{ err }); }
+
+    // Done
+    info!("User '{}' changed their password", user.credential.user());
+    audit::record(&ctx, &client, AuthEventKind::PasswordChange, Some(user.id), Some(user.credential.user()), Some("all sessions revoked"));
+    Ok(warp::reply::with_status(
+        String::from("success"),
+        StatusCode::OK,
+    ).into_response())
+}
@@ -0,0 +1,120 @@
+/* REGISTER.rs
+ *   by Lut99
+ *
+ * Created:
+ *   18 Oct 2026, 03:15:12
+ * Last edited:
+ *   18 Oct 2026, 06:24:13
+ * Auto updated?
+ *   Yes
+ *
+ * Description:
+ *   Handles the self-service registration part of the authorization
+ *   service.
+**/
+
+use std::sync::Arc;
+
+use log::{debug, error, info};
+use warp::{Rejection, Reply};
+use warp::http::StatusCode;
+use warp::reply::Response;
+
+use todo_spec::api::{AuthEventKind, ErrorCode, RegisterJson};
+use todo_spec::credentials::{verify_display_name, Credential, Error as CredentialError};
+use todo_spec::jwt::Role;
+use todo_spec::password::Error as PasswordPolicyError;
+
+pub use crate::errors::RegisterError as Error;
+pub use crate::spec::Context;
+use crate::audit::{self, Client};
+use crate::reply;
+use crate::store::Error as StoreError;
+
+
+
+
+
+/***** HELPER MACROS *****/
+/// Writes the given error both to stderr via error!() and returns it as a custom reject
+macro_rules! throw {
+    ($err:expr) => {
+        let err = $err;
+        error!("{}", &err);
+        return Err(warp::reject::custom(err));
+    };
+}
+
+
+
+
+
+/***** LIBRARY FUNCTIONS *****/
+/// Handles the registration of new users in the authorization service.
+/// 
+/// **Arguments**
+///  * `ctx`: The Context of the service.
+///  * `client`: The Client that sent the request.
+///  * `body`: The message body we got with the request.
+/// 
+/// **Returns**  
+/// The Warp reply on success, or a Warp rejection on failure.
+pub fn handle(ctx: Arc<Context>, client: Client, body: RegisterJson) -> Result<Response, Rejection> {
+    info!("Handling registration (v1/register)");
+
+    // Check if the user is allowed to register in the first place
+    if !ctx.registration.allows(body.invite_code.as_deref()) {
+        debug!("User '{}' registration failure: not allowed by registration policy ({:?})", body.username, ctx.registration.mode);
+        return Ok(reply::error(StatusCode::FORBIDDEN, ErrorCode::Forbidden, "Registration is not allowed"));
+    }
+
+    // Check the display name, if any
+    if let Some(display_name) = &body.display_name {
+        if let Err(err) = verify_display_name(display_name) {
+            debug!("User '{}' registration failure: {}", body.username, err);
+            return Ok(reply::error(StatusCode::BAD_REQUEST, ErrorCode::InvalidRequest, format!("{}", err)));
+        }
+    }
+
+    // Check the password against the policy before spending time on hashing it
+    match ctx.passwords.check(&body.username, &body.password) {
+        Ok(()) => {},
+        Err(PasswordPolicyError::Rejected{ violations }) => {
+            debug!("User '{}' registration failure: password does not meet the password policy", body.username);
+            return Ok(reply::weak_password(violations));
+        },
+        Err(err) => { throw!(Error::PasswordPolicyError{ err }); }
+    }
+
+    // Hash the password (which also validates the username)
+    let cred = match Credential::from_plain_with(&body.username, &body.password, &ctx.hashing) {
+        Ok(cred) => cred,
+        Err(err @ (CredentialError::InvalidUsername{ .. } | CredentialError::ConfusableUsername{ .. })) => {
+            debug!("User '{}' registration failure: {}", body.username, err);
+            return Ok(reply::error(StatusCode::BAD_REQUEST, ErrorCode::InvalidRequest, format!("{}", err)));
+        },
+        Err(err) => { throw!(Error::CredentialError{ err }); }
+    };
+
+    // Insert the user; the store tells us if it already exists
+    debug!("Inserting user '{}'...", cred.user());
+    let id: u32 = match ctx.store.insert(&cred, Role::User) {
+        Ok(id) => id,
+        Err(StoreError::DuplicateUser{ username }) => {
+            debug!("User '{}' registration failure: user already exists", username);
+            return Ok(reply::error(StatusCode::CONFLICT, ErrorCode::Conflict, format!("User '{}' already exists", username)));
+        },
+        Err(err) => { throw!(Error::StoreError{ err }); }
+    };
+    if let Some(display_name) = &body.display_name {
+        if let Err(err) = ctx.store.set_display_name(id, Some(display_name)) { throw!(Error::StoreError{ err }); }
+    }
+
+    // Done
+    info!("Registered new user '{}' with ID {}", cred.user(), id);
+    audit::record(&ctx, &client, AuthEventKind::Registration, Some(id), Some(cred.user()), None);
+    Ok(warp::reply::with_status(
+        String::from("success"),
+        StatusCode::CREATED,
+    ).into_response())
+}
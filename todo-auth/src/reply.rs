@@ -0,0 +1,363 @@
+/* REPLY.rs
+ *   by Lut99
+ *
+ * Created:
+ *   18 Oct 2026, 04:19:11
+ * Last edited:
+ *   18 Oct 2026, 07:22:47
+ * Auto updated?
+ *   Yes
+ *
+ * Description:
+ *   Defines how the authorization service reports errors to clients.
+ *
+ *   Every failure is sent as an `ApiError` JSON body, both for the
+ *   failures that the handlers reply with themselves and for the
+ *   rejections (our own errors and warp's) that end up in the global
+ *   recovery filter.
+**/
+
+use std::convert::Infallible;
+
+use log::error;
+use warp::{Rejection, Reply};
+use warp::http::StatusCode;
+use warp::reply::Response;
+
+use todo_spec::api::{ApiError, ErrorCode};
+use todo_spec::jwt;
+use todo_spec::password::Violation;
+
+use crate::errors::{AccessTokenError, AdminError, DisplayNameError, LoginError, PasswordError, RegisterError, ResetError, SessionError, StoreError, TokenError, TotpError};
+use crate::routes::IllegalPathParam;
+use crate::workers::{self, Overloaded};
+
+
+/***** UNIT TESTS *****/
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_api_error() {
+        // Problems with the store are reported without leaking any of its internals
+        let err = LoginError::StoreError{ err: StoreError::SqliteQueryError{ query: String::from("SELECT secret FROM users"), err: rusqlite::Error::InvalidQuery } };
+        let (status, body) = err.to_api_error();
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(body.code, ErrorCode::InternalError);
+        assert!(!body.message.contains("secret"));
+
+        // Errors caused by the client keep their message
+        let (status, body) = LoginError::UnknownUser{ username: String::from("john") }.to_api_error();
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(body.code, ErrorCode::UnknownUser);
+        assert_eq!(body.message, "Unknown user 'john'");
+        let (status, body) = RegisterError::StoreError{ err: StoreError::DuplicateUser{ username: String::from("john") } }.to_api_error();
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert_eq!(body.code, ErrorCode::Conflict);
+    }
+}
+
+
+
+
+
+/***** CONSTANTS *****/
+/// The message sent to clients if something went wrong internally. We don't tell them what, since that may leak details of the service.
+const INTERNAL_ERROR_MESSAGE: &str = "Internal server error";
+/// The message sent to clients if the store cannot be reached.
+const UNAVAILABLE_MESSAGE: &str = "The account store is unavailable; try again later";
+
+
+
+
+
+/***** HELPER FUNCTIONS *****/
+/// Returns the status code and body for internal errors.
+#[inline]
+fn internal() -> (StatusCode, ApiError) {
+    (StatusCode::INTERNAL_SERVER_ERROR, ApiError::new(ErrorCode::InternalError, INTERNAL_ERROR_MESSAGE))
+}
+
+/// Returns the status code and body for errors in the given credential.
+fn credential_error(err: &todo_spec::credentials::Error) -> (StatusCode, ApiError) {
+    use todo_spec::credentials::Error;
+    match err {
+        Error::InvalidUsername{ .. }    => (StatusCode::BAD_REQUEST, ApiError::new(ErrorCode::InvalidRequest, err.to_string())),
+        Error::ConfusableUsername{ .. } => (StatusCode::BAD_REQUEST, ApiError::new(ErrorCode::InvalidRequest, err.to_string())),
+        Error::InvalidDisplayName{ .. } => (StatusCode::BAD_REQUEST, ApiError::new(ErrorCode::InvalidRequest, err.to_string())),
+        Error::MissingSeparator         => (StatusCode::BAD_REQUEST, ApiError::new(ErrorCode::InvalidRequest, err.to_string())),
+
+        Error::PasswordHashError{ .. }   |
+        Error::IllegalHash{ .. }         |
+        Error::IllegalHashParams{ .. }   |
+        Error::UnknownPepper{ .. }       |
+        Error::FileOpenError{ .. }       |
+        Error::FileReadError{ .. }       |
+        Error::FileWriteError{ .. }      |
+        Error::InsecurePermissions{ .. } |
+        Error::IllegalFormat{ .. }       |
+        Error::UnsupportedVersion{ .. }  |
+        Error::IllegalCreated{ .. }      |
+        Error::MissingPassphrase         |
+        Error::WrongPassphrase           |
+        Error::UnsupportedCipher{ .. }   |
+        Error::UnsupportedKdf{ .. }      |
+        Error::IllegalEncoding{ .. }     |
+        Error::IllegalNonce              |
+        Error::KeyDerivationError{ .. }  => internal(),
+    }
+}
+
+/// Returns the status code and body for errors while checking a password against the password policy.
+fn password_policy_error(err: &todo_spec::password::Error) -> (StatusCode, ApiError) {
+    use todo_spec::password::Error;
+    match err {
+        Error::Rejected{ violations } => (StatusCode::BAD_REQUEST, ApiError::new(ErrorCode::WeakPassword, "Password does not meet the password policy").with_violations(violations.clone())),
+
+        Error::BreachedDirError{ .. }  |
+        Error::BreachedReadError{ .. } => internal(),
+    }
+}
+
+
+
+
+
+/***** LIBRARY FUNCTIONS *****/
+/// Generates a reply with the given status code and an `ApiError` as body.
+/// 
+/// **Arguments**
+///  * `status`: The status code to reply with.
+///  * `code`: The machine-readable code of the error.
+///  * `message`: The human-readable description of the error.
+/// 
+/// **Returns**  
+/// The Response to send to the client.
+pub fn error<S: Into<String>>(status: StatusCode, code: ErrorCode, message: S) -> Response {
+    warp::reply::with_status(warp::reply::json(&ApiError::new(code, message)), status).into_response()
+}
+
+/// Generates a reply that refuses a password because it does not meet the password policy.
+/// 
+/// **Arguments**
+///  * `violations`: The rules of the policy that the password does not meet.
+/// 
+/// **Returns**  
+/// The Response to send to the client.
+pub fn weak_password(violations: Vec<Violation>) -> Response {
+    let (status, body) = password_policy_error(&todo_spec::password::Error::Rejected{ violations });
+    warp::reply::with_status(warp::reply::json(&body), status).into_response()
+}
+
+/// Recovers from any rejection by turning it into an `ApiError` reply. Use with `Filter::recover()` on the global filter.
+/// 
+/// **Arguments**
+///  * `err`: The rejection to recover from.
+/// 
+/// **Returns**  
+/// The Response to send to the client. This never fails.
+pub async fn recover(err: Rejection) -> Result<Response, Infallible> {
+    // Missing or invalid tokens keep their WWW-Authenticate header
+    let err: Rejection = match jwt::recover(err).await {
+        Ok(reply) => { return Ok(reply.into_response()); },
+        Err(err)  => err,
+    };
+    // Requests refused for being too busy keep their Retry-After header
+    if err.find::<Overloaded>().is_some() { return Ok(workers::overloaded()); }
+
+    // Match our own errors first
+    let (status, body): (StatusCode, ApiError) = if let Some(err) = err.find::<LoginError>() {
+        err.to_api_error()
+    } else if let Some(err) = err.find::<RegisterError>() {
+        err.to_api_error()
+    } else if let Some(err) = err.find::<PasswordError>() {
+        err.to_api_error()
+    } else if let Some(err) = err.find::<ResetError>() {
+        err.to_api_error()
+    } else if let Some(err) = err.find::<DisplayNameError>() {
+        err.to_api_error()
+    } else if let Some(err) = err.find::<AdminError>() {
+        err.to_api_error()
+    } else if let Some(err) = err.find::<TotpError>() {
+        err.to_api_error()
+    } else if let Some(err) = err.find::<TokenError>() {
+        err.to_api_error()
+    } else if let Some(err) = err.find::<SessionError>() {
+        err.to_api_error()
+    } else if let Some(err) = err.find::<AccessTokenError>() {
+        err.to_api_error()
+
+    } else if let Some(IllegalPathParam{ raw }) = err.find::<IllegalPathParam>() {
+        (StatusCode::BAD_REQUEST, ApiError::new(ErrorCode::InvalidRequest, format!("Path segment '{}' is not valid UTF-8", raw)))
+
+    // Then warp's, most specific first
+    } else if let Some(err) = err.find::<warp::body::BodyDeserializeError>() {
+        (StatusCode::BAD_REQUEST, ApiError::new(ErrorCode::InvalidRequest, "Request body is not valid").with_details(err.to_string()))
+    } else if err.find::<warp::reject::InvalidQuery>().is_some() {
+        (StatusCode::BAD_REQUEST, ApiError::new(ErrorCode::InvalidRequest, "Query string is not valid"))
+    } else if let Some(err) = err.find::<warp::reject::MissingHeader>() {
+        (StatusCode::BAD_REQUEST, ApiError::new(ErrorCode::InvalidRequest, err.to_string()))
+    } else if let Some(err) = err.find::<warp::reject::InvalidHeader>() {
+        (StatusCode::BAD_REQUEST, ApiError::new(ErrorCode::InvalidRequest, err.to_string()))
+    } else if err.find::<warp::reject::UnsupportedMediaType>().is_some() {
+        (StatusCode::UNSUPPORTED_MEDIA_TYPE, ApiError::new(ErrorCode::InvalidRequest, "Request body must be JSON"))
+    } else if err.find::<warp::reject::LengthRequired>().is_some() {
+        (StatusCode::LENGTH_REQUIRED, ApiError::new(ErrorCode::InvalidRequest, "Request must have a Content-Length header"))
+    } else if err.find::<warp::reject::PayloadTooLarge>().is_some() {
+        (StatusCode::PAYLOAD_TOO_LARGE, ApiError::new(ErrorCode::PayloadTooLarge, "Request body is too large"))
+    } else if err.find::<warp::reject::MethodNotAllowed>().is_some() {
+        (StatusCode::METHOD_NOT_ALLOWED, ApiError::new(ErrorCode::MethodNotAllowed, "Method not allowed"))
+    } else if err.is_not_found() {
+        (StatusCode::NOT_FOUND, ApiError::new(ErrorCode::NotFound, "Not found"))
+    } else {
+        error!("Unhandled rejection: {:?}", err);
+        internal()
+    };
+    Ok(warp::reply::with_status(warp::reply::json(&body), status).into_response())
+}
+
+
+
+
+
+/***** LIBRARY TRAITS *****/
+/// Maps errors to the status code and `ApiError` that clients see for them.
+/// 
+/// Internal errors are mapped to a generic message; the details only end up in our own logs.
+pub trait ToApiError {
+    /// Returns the status code and body to reply with for this error.
+    /// 
+    /// **Returns**  
+    /// A tuple of the status code and the `ApiError`.
+    fn to_api_error(&self) -> (StatusCode, ApiError);
+}
+
+impl ToApiError for StoreError {
+    fn to_api_error(&self) -> (StatusCode, ApiError) {
+        match self {
+            StoreError::MySqlPoolCreateError{ .. } |
+            StoreError::MySqlConnectError{ .. }    |
+            StoreError::SqliteOpenError{ .. }      => (StatusCode::SERVICE_UNAVAILABLE, ApiError::new(ErrorCode::ServiceUnavailable, UNAVAILABLE_MESSAGE)),
+
+            StoreError::MySqlQueryError{ .. }    |
+            StoreError::SqliteQueryError{ .. }   |
+            StoreError::IllegalAccount{ .. }     |
+            StoreError::IllegalRole{ .. }        |
+            StoreError::IllegalEvent{ .. }       |
+            StoreError::IllegalAccessToken{ .. } => internal(),
+
+            StoreError::DuplicateUser{ .. }        => (StatusCode::CONFLICT, ApiError::new(ErrorCode::Conflict, self.to_string())),
+            StoreError::UnknownUser{ .. }          => (StatusCode::NOT_FOUND, ApiError::new(ErrorCode::UnknownUser, "Unknown user")),
+            StoreError::DuplicateAccessToken{ .. } => (StatusCode::CONFLICT, ApiError::new(ErrorCode::Conflict, self.to_string())),
+        }
+    }
+}
+
+impl ToApiError for LoginError {
+    fn to_api_error(&self) -> (StatusCode, ApiError) {
+        match self {
+            LoginError::StoreError{ err } => err.to_api_error(),
+
+            LoginError::CredentialError{ err }      => credential_error(err),
+            LoginError::CredentialVerifyError{ .. } => internal(),
+            LoginError::UnknownUser{ .. }           => (StatusCode::NOT_FOUND, ApiError::new(ErrorCode::UnknownUser, self.to_string())),
+
+            LoginError::TokenError{ err } => err.to_api_error(),
+        }
+    }
+}
+
+impl ToApiError for RegisterError {
+    fn to_api_error(&self) -> (StatusCode, ApiError) {
+        match self {
+            RegisterError::StoreError{ err } => err.to_api_error(),
+
+            RegisterError::CredentialError{ err }     => credential_error(err),
+            RegisterError::PasswordPolicyError{ err } => password_policy_error(err),
+        }
+    }
+}
+
+impl ToApiError for PasswordError {
+    fn to_api_error(&self) -> (StatusCode, ApiError) {
+        match self {
+            PasswordError::StoreError{ err } => err.to_api_error(),
+
+            PasswordError::CredentialError{ err }      => credential_error(err),
+            PasswordError::CredentialVerifyError{ .. } => internal(),
+            PasswordError::PasswordPolicyError{ err }  => password_policy_error(err),
+        }
+    }
+}
+
+impl ToApiError for ResetError {
+    fn to_api_error(&self) -> (StatusCode, ApiError) {
+        match self {
+            ResetError::StoreError{ err } => err.to_api_error(),
+
+            ResetError::CredentialError{ err }     => credential_error(err),
+            ResetError::PasswordPolicyError{ err } => password_policy_error(err),
+        }
+    }
+}
+
+impl ToApiError for DisplayNameError {
+    fn to_api_error(&self) -> (StatusCode, ApiError) {
+        match self {
+            DisplayNameError::StoreError{ err } => err.to_api_error(),
+        }
+    }
+}
+
+impl ToApiError for AdminError {
+    fn to_api_error(&self) -> (StatusCode, ApiError) {
+        match self {
+            AdminError::StoreError{ err } => err.to_api_error(),
+
+            AdminError::CredentialError{ err }     => credential_error(err),
+            AdminError::PasswordPolicyError{ err } => password_policy_error(err),
+        }
+    }
+}
+
+impl ToApiError for TotpError {
+    fn to_api_error(&self) -> (StatusCode, ApiError) {
+        match self {
+            TotpError::StoreError{ err } => err.to_api_error(),
+
+            TotpError::IllegalSecret{ .. }    => internal(),
+            TotpError::CredentialError{ err } => credential_error(err),
+
+            TotpError::TokenError{ err } => err.to_api_error(),
+        }
+    }
+}
+
+impl ToApiError for SessionError {
+    fn to_api_error(&self) -> (StatusCode, ApiError) {
+        match self {
+            SessionError::StoreError{ err } => err.to_api_error(),
+        }
+    }
+}
+
+impl ToApiError for AccessTokenError {
+    fn to_api_error(&self) -> (StatusCode, ApiError) {
+        match self {
+            AccessTokenError::StoreError{ err }      => err.to_api_error(),
+            AccessTokenError::CredentialError{ err } => credential_error(err),
+
+            AccessTokenError::MissingScope{ scope, .. } => (StatusCode::FORBIDDEN, ApiError::new(ErrorCode::Forbidden, format!("Token does not have the '{}' scope", scope))),
+        }
+    }
+}
+
+impl ToApiError for TokenError {
+    fn to_api_error(&self) -> (StatusCode, ApiError) {
+        match self {
+            TokenError::StoreError{ err } => err.to_api_error(),
+            TokenError::JwtError{ .. }    => internal(),
+        }
+    }
+}
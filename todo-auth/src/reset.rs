@@ -0,0 +1,166 @@
+/* RESET.rs
+ *   by Lut99
+ *
+ * Created:
+ *   18 Oct 2026, 05:58:59
+ * Last edited:
+ *   18 Oct 2026, 06:24:13
+ * Auto updated?
+ *   Yes
+ *
+ * Description:
+ *   Handles users who forgot their password.
+ *
+ *   Since we cannot mail anyone, an administrator issues a reset token
+ *   (see `admin::handle_reset_token()` or the `reset-token` subcommand)
+ *   and hands it to the user, who then trades it for a new password
+ *   here. Tokens are short-lived, stored hashed and can be used once;
+ *   every user has at most one.
+**/
+
+use std::ops::Add;
+use std::sync::Arc;
+use std::time::Duration;
+
+use log::{debug, error, info};
+use time::OffsetDateTime;
+use warp::{Rejection, Reply};
+use warp::http::StatusCode;
+use warp::reply::Response;
+
+use todo_spec::api::{AuthEventKind, ErrorCode, PasswordResetJson};
+use todo_spec::credentials::Credential;
+use todo_spec::password::Error as PasswordPolicyError;
+
+pub use crate::errors::ResetError as Error;
+pub use crate::spec::{Account, Context, ResetToken};
+use crate::audit::{self, Client};
+use crate::reply;
+use crate::store::{Error as StoreError, Store};
+use crate::throttle::ThrottleKey;
+use crate::token::{self, hash_token, random_token};
+
+
+/***** HELPER MACROS *****/
+/// Writes the given error both to stderr via error!() and returns it as a custom reject
+macro_rules! throw {
+    ($err:expr) => {
+        let err = $err;
+        error!("{}", &err);
+        return Err(warp::reject::custom(err));
+    };
+}
+
+
+
+
+
+/***** HELPER FUNCTIONS *****/
+/// Generates the reply for a reset token that we refuse.
+#[inline]
+fn refuse() -> Response {
+    reply::error(StatusCode::UNAUTHORIZED, ErrorCode::Unauthorized, "Invalid or expired reset token")
+}
+
+
+
+
+
+/***** LIBRARY FUNCTIONS *****/
+/// Issues a new password reset token for the given user, which replaces any token they already had.
+/// 
+/// **Arguments**
+///  * `store`: The Store to keep the (hashed) token in.
+///  * `user_id`: The identifier of the user whose password the token may reset.
+///  * `lifetime`: The time that the token is valid.
+/// 
+/// **Returns**  
+/// The token itself on success, or an Error otherwise. This is the only time the token is known; we only store its hash.
+pub fn issue(store: &dyn Store, user_id: u32, lifetime: Duration) -> Result<String, StoreError> {
+    let reset_token: String = random_token();
+    store.insert_reset_token(&ResetToken {
+        hash    : hash_token(&reset_token),
+        user_id,
+        expires : OffsetDateTime::now_utc().add(lifetime),
+    })?;
+    Ok(reset_token)
+}
+
+
+
+/// Handles setting a new password with a reset token. This logs out all of the user's sessions, and lifts any lockout.
+/// 
+/// **Arguments**
+///  * `ctx`: The Context of the service.
+///  * `client`: The Client that sent the request.
+///  * `body`: The message body we got with the request.
+/// 
+/// **Returns**  
+/// The Warp reply on success, or a Warp rejection on failure.
+pub fn handle(ctx: Arc<Context>, client: Client, body: PasswordResetJson) -> Result<Response, Rejection> {
+    info!("Handling password reset (v1/password/reset)");
+
+    // Find the token
+    let hash: String = hash_token(&body.reset_token);
+    let reset_token: ResetToken = match ctx.store.get_reset_token(&hash) {
+        Ok(Some(reset_token)) => reset_token,
+        Ok(None)              => {
+            debug!("Password reset failure: unknown reset token");
+            return Ok(refuse());
+        },
+        Err(err) => { throw!(Error::StoreError{ err }); }
+    };
+    if reset_token.expires <= OffsetDateTime::now_utc() {
+        debug!("Password reset failure: reset token of user {} has expired", reset_token.user_id);
+        if let Err(err) = ctx.store.delete_reset_token(&hash) { throw!(Error::StoreError{ err }); }
+        return Ok(refuse());
+    }
+    let user: Account = match ctx.store.get_by_id(reset_token.user_id) {
+        Ok(Some(user)) => user,
+        Ok(None)       => {
+            debug!("Password reset failure: unknown user {}", reset_token.user_id);
+            return Ok(refuse());
+        },
+        Err(err) => { throw!(Error::StoreError{ err }); }
+    };
+
+    // Check the new password before using up the token, so the user can try again
+    match ctx.passwords.check(user.credential.user(), &body.new_password) {
+        Ok(()) => {},
+        Err(PasswordPolicyError::Rejected{ violations }) => {
+            debug!("User '{}' password reset failure: new password does not meet the password policy", user.credential.user());
+            return Ok(reply::weak_password(violations));
+        },
+        Err(err) => { throw!(Error::PasswordPolicyError{ err }); }
+    }
+
+    // Use up the token; if another request beat us to it, this one loses
+    match ctx.store.delete_reset_token(&hash) {
+        Ok(true)  => {},
+        Ok(false) => {
+            debug!("User '{}' password reset failure: reset token was used concurrently", user.credential.user());
+            return Ok(refuse());
+        },
+        Err(err) => { throw!(Error::StoreError{ err }); }
+    }
+
+    // Hash the new password and store it
+    let credential: Credential = match Credential::from_plain_with(user.credential.user(), &body.new_password, &ctx.hashing) {
+        Ok(credential) => credential,
+        Err(err)       => { throw!(Error::CredentialError{ err }); }
+    };
+    if let Err(err) = ctx.store.update_password(user.id, credential.pass()) { throw!(Error::StoreError{ err }); }
+    if let Err(err) = ctx.store.reset_login_failures(user.id) { throw!(Error::StoreError{ err }); }
+    ctx.throttle.clear(&ThrottleKey::user(user.credential.user()));
+
+    // Log out every session of the user
+    if let Err(err) = token::end_all_sessions(&*ctx.store, user.id) { throw!(Error::StoreError{ err }); }
+
+    // Done
+    info!("User '{}' reset their password", user.credential.user());
+    audit::record(&ctx, &client, AuthEventKind::PasswordReset, Some(user.id), Some(user.credential.user()), Some("all sessions revoked"));
+    Ok(warp::reply::with_status(
+        String::from("success"),
+        StatusCode::OK,
+    ).into_response())
+}
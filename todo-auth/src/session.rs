@@ -0,0 +1,190 @@
+/* SESSION.rs
+ *   by Lut99
+ *
+ * Created:
+ *   18 Oct 2026, 05:38:13
+ * Last edited:
+ *   18 Oct 2026, 07:06:54
+ * Auto updated?
+ *   Yes
+ *
+ * Description:
+ *   Handles the sessions of users, i.e., the places where they are
+ *   logged in.
+ *
+ *   Every login starts a session (see `token::start_session()`), which
+ *   lives for as long as its refresh tokens are rotated. Users can see
+ *   their sessions and end any of them, which revokes its refresh
+ *   tokens and makes us refuse the access tokens handed out in it.
+ *   Other services only refuse those if they check sessions with an
+ *   `Introspector` (see `todo_spec::jwt`); otherwise, the access tokens
+ *   stay valid until they expire.
+**/
+
+use std::sync::Arc;
+
+use log::{debug, error, info};
+use warp::{Rejection, Reply};
+use warp::http::StatusCode;
+use warp::reply::Response;
+
+use todo_spec::api::{AuthEventKind, ErrorCode, SessionJson};
+use todo_spec::jwt::{Claims, Unauthorized};
+
+pub use crate::errors::SessionError as Error;
+pub use crate::spec::{Context, Session};
+use crate::audit::{self, Client};
+use crate::reply;
+use crate::token;
+
+
+/***** HELPER MACROS *****/
+/// Writes the given error both to stderr via error!() and returns it as a custom reject
+macro_rules! throw {
+    ($err:expr) => {
+        let err = $err;
+        error!("{}", &err);
+        return Err(warp::reject::custom(err));
+    };
+}
+
+
+
+
+
+/***** HELPER FUNCTIONS *****/
+/// Converts the given Session into its JSON representation.
+/// 
+/// **Arguments**
+///  * `session`: The Session to convert.
+///  * `current`: The session that made the request, if any.
+fn to_json(session: Session, current: Option<u32>) -> SessionJson {
+    SessionJson {
+        id         : session.id,
+        device     : session.device,
+        ip         : session.ip,
+        user_agent : session.user_agent,
+        created    : session.created.unix_timestamp(),
+        last_seen  : session.last_seen.unix_timestamp(),
+        current    : current == Some(session.id),
+    }
+}
+
+
+
+
+
+/***** LIBRARY FUNCTIONS *****/
+/// Checks that the session of the given (verified) access token has not been ended.
+/// 
+/// **Arguments**
+///  * `ctx`: The Context of the service.
+///  * `claims`: The Claims of the access token. Tokens without a session are always accepted.
+/// 
+/// **Returns**  
+/// The same Claims if the token may be used, or a Warp rejection otherwise.
+pub fn check(ctx: &Context, claims: Claims) -> Result<Claims, Rejection> {
+    let sid: u32 = match claims.sid {
+        Some(sid) => sid,
+        None      => { return Ok(claims); },
+    };
+    match ctx.store.get_session(sid) {
+        Ok(Some(session)) if session.user_id == claims.id => Ok(claims),
+        Ok(_) => {
+            debug!("Refusing access token of user {}: session {} has ended", claims.id, sid);
+            Err(warp::reject::custom(Unauthorized{ reason: String::from("Session has ended") }))
+        },
+        Err(err) => { throw!(Error::StoreError{ err }); }
+    }
+}
+
+
+
+/// Handles listing the sessions of the authenticated user.
+/// 
+/// **Arguments**
+///  * `ctx`: The Context of the service.
+///  * `claims`: The claims of the user, as taken from their access token.
+/// 
+/// **Returns**  
+/// The Warp reply on success, or a Warp rejection on failure.
+pub fn handle_list(ctx: Arc<Context>, claims: Claims) -> Result<Response, Rejection> {
+    info!("Handling session listing (v1/sessions)");
+
+    let sessions: Vec<Session> = match ctx.store.list_sessions(claims.id) {
+        Ok(sessions) => sessions,
+        Err(err)     => { throw!(Error::StoreError{ err }); }
+    };
+    Ok(warp::reply::with_status(
+        warp::reply::json(&sessions.into_iter().map(|session| to_json(session, claims.sid)).collect::<Vec<SessionJson>>()),
+        StatusCode::OK,
+    ).into_response())
+}
+
+
+
+/// Handles ending one of the sessions of the authenticated user. This may be the session that makes the request, which then amounts to logging out.
+/// 
+/// **Arguments**
+///  * `ctx`: The Context of the service.
+///  * `client`: The Client that sent the request.
+///  * `claims`: The claims of the user, as taken from their access token.
+///  * `id`: The identifier of the session to end.
+/// 
+/// **Returns**  
+/// The Warp reply on success, or a Warp rejection on failure.
+pub fn handle_delete(ctx: Arc<Context>, client: Client, claims: Claims, id: u32) -> Result<Response, Rejection> {
+    info!("Handling session revocation (v1/sessions/{})", id);
+
+    // Users may only end their own sessions; we pretend the others don't exist
+    let session: Session = match ctx.store.get_session(id) {
+        Ok(Some(session)) if session.user_id == claims.id => session,
+        Ok(_) => {
+            debug!("User {} session revocation failure: no session {}", claims.id, id);
+            return Ok(reply::error(StatusCode::NOT_FOUND, ErrorCode::NotFound, format!("No session with ID {}", id)));
+        },
+        Err(err) => { throw!(Error::StoreError{ err }); }
+    };
+    if let Err(err) = token::end_session(&*ctx.store, &session) { throw!(Error::StoreError{ err }); }
+
+    // Done
+    info!("User {} ended session {}", claims.id, id);
+    audit::record(&ctx, &client, AuthEventKind::SessionRevoke, Some(claims.id), None, Some(&format!("session {}", id)));
+    Ok(warp::reply::with_status(
+        String::from("success"),
+        StatusCode::OK,
+    ).into_response())
+}
+
+
+
+/// Handles ending all sessions of the authenticated user, except for the one that makes the request.
+/// 
+/// **Arguments**
+///  * `ctx`: The Context of the service.
+///  * `client`: The Client that sent the request.
+///  * `claims`: The claims of the user, as taken from their access token. If it does not belong to a session (e.g., because it is a personal access token), all sessions are ended.
+/// 
+/// **Returns**  
+/// The Warp reply on success, or a Warp rejection on failure.
+pub fn handle_delete_others(ctx: Arc<Context>, client: Client, claims: Claims) -> Result<Response, Rejection> {
+    info!("Handling revocation of other sessions (v1/sessions)");
+
+    let sessions: Vec<Session> = match ctx.store.list_sessions(claims.id) {
+        Ok(sessions) => sessions,
+        Err(err)     => { throw!(Error::StoreError{ err }); }
+    };
+    let mut ended: usize = 0;
+    for session in sessions.iter().filter(|session| Some(session.id) != claims.sid) {
+        if let Err(err) = token::end_session(&*ctx.store, session) { throw!(Error::StoreError{ err }); }
+        ended += 1;
+    }
+
+    // Done
+    info!("User {} ended {} other session(s)", claims.id, ended);
+    if ended > 0 { audit::record(&ctx, &client, AuthEventKind::SessionRevoke, Some(claims.id), None, Some(&format!("{} other session(s)", ended))); }
+    Ok(warp::reply::with_status(
+        String::from("success"),
+        StatusCode::OK,
+    ).into_response())
+}
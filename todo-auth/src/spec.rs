@@ -4,7 +4,7 @@
  * Created:
  *   19 Mar 2022, 15:35:26
  * Last edited:
- *   19 Mar 2022, 20:54:22
+ *   18 Oct 2026, 07:06:54
  * Auto updated?
  *   Yes
  *
@@ -12,12 +12,162 @@
  *   Contains authorization-local specifications.
 **/
 
-use todo_spec::credentials::Credential;
+use std::collections::HashSet;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use clap::ArgEnum;
+use log::LevelFilter;
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+
+use todo_spec::api::{AuthEventKind, Scope};
+use todo_spec::credentials::{Credential, HashAlgorithm, HashPolicy};
+use todo_spec::jwt::{Algorithm, Key, Role, Verifier};
+use todo_spec::password::PasswordPolicy;
+
+use crate::metrics::Metrics;
+use crate::store::Store;
+use crate::throttle::Throttle;
+use crate::totp::Challenges;
+use crate::workers::HashPool;
 
 
 /***** LIBRARY CONSTANTS *****/
-/// The time (in seconds) that a JWT expires after it has been handed out
-pub const JWT_EXPIRATION_TIME: u64 = 1 * 3600;
+/// The time (in seconds) that a JWT expires after it has been handed out, unless configured otherwise
+pub const JWT_EXPIRATION_TIME: u64 = 3600;
+/// The time (in seconds) that a refresh token expires after it has been handed out, unless configured otherwise
+pub const REFRESH_EXPIRATION_TIME: u64 = 30 * 24 * 3600;
+/// The time (in seconds) that a password reset token expires after it has been issued, unless configured otherwise
+pub const RESET_EXPIRATION_TIME: u64 = 3600;
+
+/// The name of the service as it is shown in authenticator apps
+pub const TOTP_ISSUER: &str = "Todo";
+/// The number of TOTP periods before and after the current one that we also accept, to allow for clock drift
+pub const TOTP_SKEW: u64 = 1;
+/// The time (in seconds) that a user has to provide their second factor after providing their password
+pub const CHALLENGE_EXPIRATION_TIME: u64 = 300;
+/// The number of wrong codes that may be given for one challenge before it is revoked
+pub const CHALLENGE_MAX_ATTEMPTS: u32 = 5;
+/// The number of recovery codes handed out when two-factor authentication is enabled
+pub const RECOVERY_CODE_COUNT: usize = 10;
+
+/// The number of audit events returned per page if the client doesn't say otherwise
+pub const AUDIT_PAGE_SIZE: u64 = 50;
+/// The maximum number of audit events returned per page
+pub const AUDIT_MAX_PAGE_SIZE: u64 = 500;
+/// The maximum length of the free-form values (usernames, User-Agents, details) that we record in the audit log; anything longer is cut off
+pub const AUDIT_FIELD_MAX_LENGTH: usize = 255;
+
+/// The prefix of every personal access token, which tells them apart from JWT's (and makes them easy to spot in leaked files)
+pub const ACCESS_TOKEN_PREFIX: &str = "todo_pat_";
+/// The maximum number of personal access tokens that a single user may have
+pub const ACCESS_TOKEN_MAX_COUNT: usize = 32;
+/// The maximum length of the name of a personal access token
+pub const ACCESS_TOKEN_NAME_MAX_LENGTH: usize = 64;
+
+/// The maximum length of the device names (and User-Agents) that we keep with a session; anything longer is cut off
+pub const SESSION_FIELD_MAX_LENGTH: usize = 255;
+
+
+
+
+
+/***** LIBRARY ENUMS *****/
+/// Defines if and how new users may register themselves.
+#[derive(ArgEnum, Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RegistrationMode {
+    /// Anyone may register a new account.
+    Open,
+    /// Only users with a valid invite code may register a new account.
+    Invite,
+    /// Nobody may register; accounts only exist if they are created by the server itself.
+    Disabled,
+}
+
+
+
+/// Defines the algorithms that the service may sign JWT's with.
+#[derive(ArgEnum, Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JwtAlgorithm {
+    /// HMAC with a shared secret. Every service that verifies tokens needs the secret, and can thus also forge them.
+    Hs256,
+    /// RSA signatures. Other services only need the public key.
+    Rs256,
+    /// Ed25519 signatures. Other services only need the public key.
+    #[clap(name = "eddsa")]
+    #[serde(rename = "eddsa")]
+    EdDsa,
+}
+
+impl From<JwtAlgorithm> for Algorithm {
+    #[inline]
+    fn from(value: JwtAlgorithm) -> Self {
+        match value {
+            JwtAlgorithm::Hs256 => Algorithm::Hs256,
+            JwtAlgorithm::Rs256 => Algorithm::Rs256,
+            JwtAlgorithm::EdDsa => Algorithm::EdDsa,
+        }
+    }
+}
+
+
+
+/// Defines the variants of Argon2 that the service may hash passwords with.
+#[derive(ArgEnum, Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PasswordAlgorithm {
+    /// Argon2d, which is strongest against GPU cracking but vulnerable to side-channel attacks.
+    Argon2d,
+    /// Argon2i, which resists side-channel attacks.
+    Argon2i,
+    /// Argon2id, a hybrid of the other two.
+    Argon2id,
+}
+
+impl From<PasswordAlgorithm> for HashAlgorithm {
+    #[inline]
+    fn from(value: PasswordAlgorithm) -> Self {
+        match value {
+            PasswordAlgorithm::Argon2d  => HashAlgorithm::Argon2d,
+            PasswordAlgorithm::Argon2i  => HashAlgorithm::Argon2i,
+            PasswordAlgorithm::Argon2id => HashAlgorithm::Argon2id,
+        }
+    }
+}
+
+
+
+/// Defines how much the service logs.
+#[derive(ArgEnum, Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogLevel {
+    /// Only log errors.
+    Error,
+    /// Log errors and warnings.
+    Warn,
+    /// Log errors, warnings and what the service is doing.
+    Info,
+    /// Also log debug prints.
+    Debug,
+    /// Log everything.
+    Trace,
+}
+
+impl From<LogLevel> for LevelFilter {
+    #[inline]
+    fn from(value: LogLevel) -> Self {
+        match value {
+            LogLevel::Error => LevelFilter::Error,
+            LogLevel::Warn  => LevelFilter::Warn,
+            LogLevel::Info  => LevelFilter::Info,
+            LogLevel::Debug => LevelFilter::Debug,
+            LogLevel::Trace => LevelFilter::Trace,
+        }
+    }
+}
 
 
 
@@ -31,5 +181,309 @@ pub struct Account {
     pub id : u32,
 
     /// The credentials of the user.
-    pub credential : Credential,
+    pub credential   : Credential,
+    /// The name under which the user is shown, if they picked one. Unlike the username, this is free-form and need not be unique.
+    pub display_name : Option<String>,
+    /// The role of the user, which determines what they may do.
+    pub role         : Role,
+    /// Whether the account has been disabled by an administrator. Disabled accounts cannot login.
+    pub disabled     : bool,
+
+    /// The number of consecutive failed logins since the last successful one.
+    pub failed_logins : u32,
+    /// The moment until which the account is locked because of too many failed logins, if at all.
+    pub locked_until  : Option<OffsetDateTime>,
+}
+
+
+
+/// Defines a stored refresh token in the database.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RefreshToken {
+    /// The (SHA-256) hash of the token; we never store the token itself.
+    pub hash    : String,
+    /// The family of the token. Every token that is rotated from the same login shares the same family.
+    pub family  : String,
+    /// The identifier of the user to whom the token was handed out.
+    pub user_id : u32,
+    /// The moment the token expires.
+    pub expires : OffsetDateTime,
+    /// Whether the token has already been exchanged for a new one.
+    pub used    : bool,
+    /// Whether the token (or rather, its family) has been revoked.
+    pub revoked : bool,
+}
+
+
+
+/// Defines a stored password reset token in the database. Every user has at most one, and it is deleted when it is used.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ResetToken {
+    /// The (SHA-256) hash of the token; we never store the token itself.
+    pub hash    : String,
+    /// The identifier of the user whose password the token may reset.
+    pub user_id : u32,
+    /// The moment the token expires.
+    pub expires : OffsetDateTime,
+}
+
+
+
+/// Defines a stored personal access token in the database.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct AccessToken {
+    /// The identifier of the token, which is assigned by the store. It is part of the token itself, so we can find it again.
+    pub id        : u32,
+    /// The identifier of the user who created the token.
+    pub user_id   : u32,
+    /// The name of the token, which is unique per user.
+    pub name      : String,
+    /// The hash of the secret part of the token, hashed like a password; we never store the token itself.
+    pub hash      : String,
+    /// What the token may be used for.
+    pub scopes    : Vec<Scope>,
+    /// The moment the token was created.
+    pub created   : OffsetDateTime,
+    /// The moment the token expires, or None if it never does.
+    pub expires   : Option<OffsetDateTime>,
+    /// The moment the token was last used, or None if it never was.
+    pub last_used : Option<OffsetDateTime>,
+}
+
+
+
+/// Defines a stored session, i.e., a login and all the tokens that are rotated from it.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Session {
+    /// The identifier of the session, which is assigned by the store. Access tokens carry it as their `sid` claim.
+    pub id         : u32,
+    /// The identifier of the user who logged in.
+    pub user_id    : u32,
+    /// The family of the refresh tokens of the session.
+    pub family     : String,
+    /// The name of the device that logged in, if it gave one.
+    pub device     : Option<String>,
+    /// The address of the client that logged in, if known.
+    pub ip         : Option<String>,
+    /// The User-Agent of the client that logged in, if any.
+    pub user_agent : Option<String>,
+    /// The moment of the login.
+    pub created    : OffsetDateTime,
+    /// The moment the session last refreshed its tokens (or logged in, if it never did).
+    pub last_seen  : OffsetDateTime,
+}
+
+
+
+/// Defines the stored TOTP secret of a user in the database.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct TotpSecret {
+    /// The identifier of the user who enrolled the secret.
+    pub user_id   : u32,
+    /// The base32-encoded secret. Unlike passwords, we need the secret itself to compute the codes.
+    pub secret    : String,
+    /// Whether the user has proven that their authenticator app works. Unconfirmed secrets are not asked for during login.
+    pub confirmed : bool,
+    /// The last time step for which a code was accepted. Codes for this step or earlier ones are refused, so they cannot be replayed.
+    pub last_step : u64,
+}
+
+
+
+/// Defines a stored event in the audit log.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct AuthEvent {
+    /// The identifier of the event, which is assigned by the store.
+    pub id         : u64,
+    /// The moment the event happened.
+    pub time       : OffsetDateTime,
+    /// The kind of event.
+    pub kind       : AuthEventKind,
+    /// The identifier of the user the event is about, if they exist.
+    pub user_id    : Option<u32>,
+    /// The name of the user the event is about, if known. Kept separately, so we can also record attempts for usernames that don't exist.
+    pub username   : Option<String>,
+    /// The IP address of the client that caused the event, if known.
+    pub ip         : Option<String>,
+    /// The User-Agent of the client that caused the event, if given.
+    pub user_agent : Option<String>,
+    /// Further details, such as the reason a login was refused.
+    pub detail     : Option<String>,
+}
+
+
+
+/// Defines which events to search for in the audit log.
+#[derive(Clone, Debug, Default)]
+pub struct EventFilter {
+    /// Only return events about the user with this name. Events that only recorded a user identifier match too, if the user still exists.
+    pub username : Option<String>,
+    /// Only return events of this kind.
+    pub kind     : Option<AuthEventKind>,
+    /// Only return events at or after this moment.
+    pub since    : Option<OffsetDateTime>,
+    /// Only return events before this moment.
+    pub until    : Option<OffsetDateTime>,
+    /// The number of matching events to skip (newest first).
+    pub offset   : u64,
+    /// The maximum number of events to return.
+    pub limit    : u64,
+}
+
+impl EventFilter {
+    /// Checks whether the given event matches this filter (ignoring the offset and limit).
+    /// 
+    /// **Arguments**
+    ///  * `event`: The AuthEvent to check.
+    ///  * `user_id`: The identifier of the user called `username`, if they exist.
+    /// 
+    /// **Returns**  
+    /// Whether the event matches.
+    pub fn matches(&self, event: &AuthEvent, user_id: Option<u32>) -> bool {
+        self.username.as_ref().map(|username| event.username.as_ref() == Some(username) || (user_id.is_some() && event.user_id == user_id)).unwrap_or(true)
+            && self.kind.map(|kind| event.kind == kind).unwrap_or(true)
+            && self.since.map(|since| event.time >= since).unwrap_or(true)
+            && self.until.map(|until| event.time < until).unwrap_or(true)
+    }
+}
+
+
+
+/// Describes the connections that a store keeps to its backend.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct PoolStats {
+    /// The maximum number of connections that the store may open.
+    pub max    : usize,
+    /// The number of connections that are currently in use by a request.
+    pub in_use : usize,
+}
+
+
+
+/// Defines the server-wide policy for registering new accounts.
+#[derive(Debug)]
+pub struct RegistrationPolicy {
+    /// The mode of registration.
+    pub mode         : RegistrationMode,
+    /// The invite codes that are accepted if the mode is `RegistrationMode::Invite`.
+    pub invite_codes : HashSet<String>,
+}
+
+impl RegistrationPolicy {
+    /// Checks whether a user with the given invite code is allowed to register.
+    /// 
+    /// **Arguments**
+    ///  * `invite_code`: The invite code given by the user, if any.
+    /// 
+    /// **Returns**  
+    /// Whether or not the user may register.
+    pub fn allows(&self, invite_code: Option<&str>) -> bool {
+        match self.mode {
+            RegistrationMode::Open     => true,
+            RegistrationMode::Invite   => match invite_code {
+                Some(code) => self.invite_codes.contains(code),
+                None       => false,
+            },
+            RegistrationMode::Disabled => false,
+        }
+    }
+}
+
+
+
+/// Defines the server-wide policy for the lifetime of the tokens that we hand out.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct TokenPolicy {
+    /// The time that access tokens (JWT's) are valid. Other services keep accepting the tokens of an ended session for this long, unless they check sessions with an `Introspector` (see `todo_spec::jwt`), so keep it short.
+    pub access_lifetime  : Duration,
+    /// The time that refresh tokens are valid.
+    pub refresh_lifetime : Duration,
+    /// The time that password reset tokens are valid.
+    pub reset_lifetime   : Duration,
+}
+
+impl Default for TokenPolicy {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            access_lifetime  : Duration::from_secs(JWT_EXPIRATION_TIME),
+            refresh_lifetime : Duration::from_secs(REFRESH_EXPIRATION_TIME),
+            reset_lifetime   : Duration::from_secs(RESET_EXPIRATION_TIME),
+        }
+    }
+}
+
+
+
+/// Keeps the keys that JWT's are signed and verified with. They may be replaced while the service runs, e.g., when it is told to reload them.
+#[derive(Debug)]
+pub struct Keys {
+    /// The key that is used to sign new JWT's, and the verifier that knows it and any older keys that are still accepted.
+    current : RwLock<(Key, Arc<Verifier>)>,
+}
+
+impl Keys {
+    /// Constructor for the Keys.
+    /// 
+    /// **Arguments**
+    ///  * `key`: The key to sign new JWT's with.
+    ///  * `verifier`: The Verifier that accepts JWT's signed with `key` (and possibly others).
+    /// 
+    /// **Returns**  
+    /// A new Keys instance.
+    #[inline]
+    pub fn new(key: Key, verifier: Verifier) -> Self {
+        Self {
+            current : RwLock::new((key, Arc::new(verifier))),
+        }
+    }
+
+
+
+    /// Returns the key to sign new JWT's with.
+    #[inline]
+    pub fn signing(&self) -> Key {
+        self.current.read().expect("Keys lock is poisoned; this should never happen!").0.clone()
+    }
+
+    /// Returns the Verifier to verify JWT's with.
+    #[inline]
+    pub fn verifier(&self) -> Arc<Verifier> {
+        self.current.read().expect("Keys lock is poisoned; this should never happen!").1.clone()
+    }
+
+    /// Replaces the keys. Tokens signed with a key that the new Verifier doesn't know are no longer accepted.
+    /// 
+    /// **Arguments**
+    ///  * `key`: The key to sign new JWT's with from now on.
+    ///  * `verifier`: The Verifier to verify JWT's with from now on.
+    pub fn replace(&self, key: Key, verifier: Verifier) {
+        *self.current.write().expect("Keys lock is poisoned; this should never happen!") = (key, Arc::new(verifier));
+    }
+}
+
+
+
+/// Collects everything that the handlers of the service share.
+pub struct Context {
+    /// The store that contains the user accounts and their tokens.
+    pub store        : Arc<dyn Store>,
+    /// The keys to sign and verify JWT's with.
+    pub keys         : Keys,
+    /// The server-wide policy for the lifetime of tokens.
+    pub tokens       : TokenPolicy,
+    /// The server-wide policy that determines who may register.
+    pub registration : RegistrationPolicy,
+    /// The server-wide policy that determines how passwords are hashed.
+    pub hashing      : HashPolicy,
+    /// The server-wide policy that determines which new passwords are accepted.
+    pub passwords    : PasswordPolicy,
+    /// Keeps track of failed logins, and knows when to delay or refuse new ones.
+    pub throttle     : Throttle,
+    /// The logins that are waiting for a second factor.
+    pub challenges   : Challenges,
+    /// The metrics that we publish for Prometheus.
+    pub metrics      : Metrics,
+    /// Limits the number of requests that hash passwords at the same time.
+    pub hash_pool    : HashPool,
 }
@@ -0,0 +1,493 @@
+/* MEMORY.rs
+ *   by Lut99
+ *
+ * Created:
+ *   18 Oct 2026, 03:31:02
+ * Last edited:
+ *   18 Oct 2026, 06:24:13
+ * Auto updated?
+ *   Yes
+ *
+ * Description:
+ *   Implements an AccountStore that keeps everything in memory. Useful
+ *   for testing and for running the service locally.
+**/
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Mutex, MutexGuard};
+
+use time::OffsetDateTime;
+
+use todo_spec::credentials::{username_key, Credential};
+use todo_spec::jwt::Role;
+
+use super::migrations::{AppliedMigration, Migration};
+use super::{account_from_row, AccessTokenStore, AccountStore, AuditStore, Error, HealthStore, MigrationStore, RefreshTokenStore, ResetTokenStore, SecondFactorStore, SessionStore};
+use crate::spec::{AccessToken, Account, AuthEvent, EventFilter, PoolStats, RefreshToken, ResetToken, Session, TotpSecret};
+
+
+/***** HELPER STRUCTS *****/
+/// A single account as it is kept in the MemoryStore.
+#[derive(Debug)]
+struct MemoryAccount {
+    /// The name of the user.
+    name          : String,
+    /// The key of the name of the user, which must be unique.
+    name_key      : String,
+    /// The hashed password of the user.
+    pass          : String,
+    /// The display name of the user, if any.
+    display_name  : Option<String>,
+    /// The role of the user.
+    role          : Role,
+    /// Whether the account is disabled.
+    disabled      : bool,
+    /// The number of consecutive failed logins.
+    failed_logins : u32,
+    /// The moment until which the account is locked, if at all.
+    locked_until  : Option<OffsetDateTime>,
+}
+
+impl MemoryAccount {
+    /// Converts the MemoryAccount into an Account with the given identifier.
+    #[inline]
+    fn to_account(&self, id: u32) -> Result<Account, Error> {
+        account_from_row(id, self.name.clone(), self.pass.clone(), self.display_name.clone(), self.role.name(), self.disabled, self.failed_logins, self.locked_until.map(|until| until.unix_timestamp()))
+    }
+}
+
+
+
+/// The actual state behind the MemoryStore's lock.
+#[derive(Debug, Default)]
+struct MemoryState {
+    /// The identifier that the next account will get.
+    next_id         : u32,
+    /// Maps account identifiers to their accounts.
+    accounts        : BTreeMap<u32, MemoryAccount>,
+    /// Maps refresh token hashes to the tokens.
+    refresh_tokens  : HashMap<String, RefreshToken>,
+    /// Maps password reset token hashes to the tokens.
+    reset_tokens    : HashMap<String, ResetToken>,
+    /// The identifier that the next session will get.
+    next_session_id : u32,
+    /// Maps session identifiers to the sessions.
+    sessions        : BTreeMap<u32, Session>,
+    /// The identifier that the next personal access token will get.
+    next_token_id   : u32,
+    /// Maps personal access token identifiers to the tokens.
+    access_tokens   : BTreeMap<u32, AccessToken>,
+    /// Maps account identifiers to their TOTP secrets.
+    totp            : HashMap<u32, TotpSecret>,
+    /// Maps account identifiers to the hashes of their unused recovery codes.
+    recovery_codes  : HashMap<u32, Vec<String>>,
+    /// The audit log, oldest first. The identifier of every event is its position plus one.
+    events          : Vec<AuthEvent>,
+}
+
+
+
+
+
+/***** LIBRARY STRUCTS *****/
+/// Implements an AccountStore that keeps all accounts in memory.
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    /// The state of the store.
+    state : Mutex<MemoryState>,
+}
+
+impl MemoryStore {
+    /// Constructor for the MemoryStore, which creates an empty store.
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+
+
+    /// Locks the internal state.
+    #[inline]
+    fn lock(&self) -> MutexGuard<'_, MemoryState> {
+        self.state.lock().expect("MemoryStore lock is poisoned; this should never happen!")
+    }
+}
+
+impl AccountStore for MemoryStore {
+    fn get_by_name(&self, name: &str) -> Result<Option<Account>, Error> {
+        let state = self.lock();
+        let name_key: String = username_key(name);
+        match state.accounts.iter().find(|(_, account)| account.name_key == name_key) {
+            Some((id, account)) => Ok(Some(account.to_account(*id)?)),
+            None                => Ok(None),
+        }
+    }
+
+    fn get_by_id(&self, id: u32) -> Result<Option<Account>, Error> {
+        let state = self.lock();
+        match state.accounts.get(&id) {
+            Some(account) => Ok(Some(account.to_account(id)?)),
+            None          => Ok(None),
+        }
+    }
+
+    fn list(&self) -> Result<Vec<Account>, Error> {
+        let state = self.lock();
+        state.accounts.iter().map(|(id, account)| account.to_account(*id)).collect()
+    }
+
+    fn insert(&self, credential: &Credential, role: Role) -> Result<u32, Error> {
+        let mut state = self.lock();
+
+        // Make sure the name is unique
+        let name_key: String = username_key(credential.user());
+        if state.accounts.values().any(|account| account.name_key == name_key) {
+            return Err(Error::DuplicateUser{ username: credential.user().to_string() });
+        }
+
+        // Insert it with the next ID (we start at 1, like AUTO_INCREMENT does)
+        state.next_id += 1;
+        let id = state.next_id;
+        state.accounts.insert(id, MemoryAccount {
+            name          : credential.user().to_string(),
+            name_key,
+            pass          : credential.pass().to_string(),
+            display_name  : None,
+            role,
+            disabled      : false,
+            failed_logins : 0,
+            locked_until  : None,
+        });
+        Ok(id)
+    }
+
+    fn update_password(&self, id: u32, hash: &str) -> Result<(), Error> {
+        let mut state = self.lock();
+        match state.accounts.get_mut(&id) {
+            Some(account) => { account.pass = hash.to_string(); Ok(()) },
+            None          => Err(Error::UnknownUser{ id }),
+        }
+    }
+
+    fn set_role(&self, id: u32, role: Role) -> Result<(), Error> {
+        let mut state = self.lock();
+        match state.accounts.get_mut(&id) {
+            Some(account) => { account.role = role; Ok(()) },
+            None          => Err(Error::UnknownUser{ id }),
+        }
+    }
+
+    fn rename(&self, id: u32, name: &str) -> Result<(), Error> {
+        let mut state = self.lock();
+
+        // Make sure the name is unique
+        let name_key: String = username_key(name);
+        if state.accounts.iter().any(|(other, account)| *other != id && account.name_key == name_key) {
+            return Err(Error::DuplicateUser{ username: name.to_string() });
+        }
+
+        match state.accounts.get_mut(&id) {
+            Some(account) => { account.name = name.to_string(); account.name_key = name_key; Ok(()) },
+            None          => Err(Error::UnknownUser{ id }),
+        }
+    }
+
+    fn set_display_name(&self, id: u32, display_name: Option<&str>) -> Result<(), Error> {
+        let mut state = self.lock();
+        match state.accounts.get_mut(&id) {
+            Some(account) => { account.display_name = display_name.map(String::from); Ok(()) },
+            None          => Err(Error::UnknownUser{ id }),
+        }
+    }
+
+    fn set_disabled(&self, id: u32, disabled: bool) -> Result<(), Error> {
+        let mut state = self.lock();
+        match state.accounts.get_mut(&id) {
+            Some(account) => { account.disabled = disabled; Ok(()) },
+            None          => Err(Error::UnknownUser{ id }),
+        }
+    }
+
+    fn record_login_failure(&self, id: u32) -> Result<u32, Error> {
+        let mut state = self.lock();
+        match state.accounts.get_mut(&id) {
+            Some(account) => { account.failed_logins += 1; Ok(account.failed_logins) },
+            None          => Err(Error::UnknownUser{ id }),
+        }
+    }
+
+    fn lock_account(&self, id: u32, until: OffsetDateTime) -> Result<(), Error> {
+        let mut state = self.lock();
+        match state.accounts.get_mut(&id) {
+            Some(account) => { account.locked_until = Some(until); Ok(()) },
+            None          => Err(Error::UnknownUser{ id }),
+        }
+    }
+
+    fn reset_login_failures(&self, id: u32) -> Result<(), Error> {
+        let mut state = self.lock();
+        match state.accounts.get_mut(&id) {
+            Some(account) => { account.failed_logins = 0; account.locked_until = None; Ok(()) },
+            None          => Err(Error::UnknownUser{ id }),
+        }
+    }
+
+    fn delete(&self, id: u32) -> Result<(), Error> {
+        let mut state = self.lock();
+        match state.accounts.remove(&id) {
+            Some(_) => {
+                // Also remove the user's tokens and second factors, like a cascading delete would
+                state.refresh_tokens.retain(|_, token| token.user_id != id);
+                state.reset_tokens.retain(|_, token| token.user_id != id);
+                state.sessions.retain(|_, session| session.user_id != id);
+                state.access_tokens.retain(|_, token| token.user_id != id);
+                state.totp.remove(&id);
+                state.recovery_codes.remove(&id);
+                Ok(())
+            },
+            None => Err(Error::UnknownUser{ id }),
+        }
+    }
+}
+
+impl RefreshTokenStore for MemoryStore {
+    fn insert_refresh_token(&self, token: &RefreshToken) -> Result<(), Error> {
+        let mut state = self.lock();
+        if !state.accounts.contains_key(&token.user_id) { return Err(Error::UnknownUser{ id: token.user_id }); }
+        state.refresh_tokens.insert(token.hash.clone(), token.clone());
+        Ok(())
+    }
+
+    fn get_refresh_token(&self, hash: &str) -> Result<Option<RefreshToken>, Error> {
+        let state = self.lock();
+        Ok(state.refresh_tokens.get(hash).cloned())
+    }
+
+    fn use_refresh_token(&self, hash: &str) -> Result<bool, Error> {
+        let mut state = self.lock();
+        match state.refresh_tokens.get_mut(hash) {
+            Some(token) if !token.used => { token.used = true; Ok(true) },
+            _                          => Ok(false),
+        }
+    }
+
+    fn revoke_refresh_family(&self, family: &str) -> Result<(), Error> {
+        let mut state = self.lock();
+        for token in state.refresh_tokens.values_mut().filter(|token| token.family == family) {
+            token.revoked = true;
+        }
+        Ok(())
+    }
+
+    fn revoke_user_refresh_tokens(&self, user_id: u32) -> Result<(), Error> {
+        let mut state = self.lock();
+        for token in state.refresh_tokens.values_mut().filter(|token| token.user_id == user_id) {
+            token.revoked = true;
+        }
+        Ok(())
+    }
+}
+
+impl ResetTokenStore for MemoryStore {
+    fn insert_reset_token(&self, token: &ResetToken) -> Result<(), Error> {
+        let mut state = self.lock();
+        if !state.accounts.contains_key(&token.user_id) { return Err(Error::UnknownUser{ id: token.user_id }); }
+        state.reset_tokens.retain(|_, other| other.user_id != token.user_id);
+        state.reset_tokens.insert(token.hash.clone(), token.clone());
+        Ok(())
+    }
+
+    fn get_reset_token(&self, hash: &str) -> Result<Option<ResetToken>, Error> {
+        let state = self.lock();
+        Ok(state.reset_tokens.get(hash).cloned())
+    }
+
+    fn delete_reset_token(&self, hash: &str) -> Result<bool, Error> {
+        let mut state = self.lock();
+        Ok(state.reset_tokens.remove(hash).is_some())
+    }
+}
+
+impl SessionStore for MemoryStore {
+    fn insert_session(&self, session: &Session) -> Result<u32, Error> {
+        let mut state = self.lock();
+        if !state.accounts.contains_key(&session.user_id) { return Err(Error::UnknownUser{ id: session.user_id }); }
+
+        // Insert it with the next ID (we start at 1, like AUTO_INCREMENT does)
+        state.next_session_id += 1;
+        let id = state.next_session_id;
+        state.sessions.insert(id, Session{ id, ..session.clone() });
+        Ok(id)
+    }
+
+    fn get_session(&self, id: u32) -> Result<Option<Session>, Error> {
+        let state = self.lock();
+        Ok(state.sessions.get(&id).cloned())
+    }
+
+    fn get_session_by_family(&self, family: &str) -> Result<Option<Session>, Error> {
+        let state = self.lock();
+        Ok(state.sessions.values().find(|session| session.family == family).cloned())
+    }
+
+    fn list_sessions(&self, user_id: u32) -> Result<Vec<Session>, Error> {
+        let state = self.lock();
+        Ok(state.sessions.values().filter(|session| session.user_id == user_id).cloned().collect())
+    }
+
+    fn touch_session(&self, id: u32, when: OffsetDateTime) -> Result<(), Error> {
+        let mut state = self.lock();
+        if let Some(session) = state.sessions.get_mut(&id) { session.last_seen = when; }
+        Ok(())
+    }
+
+    fn delete_session(&self, id: u32) -> Result<bool, Error> {
+        let mut state = self.lock();
+        Ok(state.sessions.remove(&id).is_some())
+    }
+
+    fn delete_user_sessions(&self, user_id: u32) -> Result<(), Error> {
+        let mut state = self.lock();
+        state.sessions.retain(|_, session| session.user_id != user_id);
+        Ok(())
+    }
+}
+
+impl AccessTokenStore for MemoryStore {
+    fn insert_access_token(&self, token: &AccessToken) -> Result<u32, Error> {
+        let mut state = self.lock();
+        if !state.accounts.contains_key(&token.user_id) { return Err(Error::UnknownUser{ id: token.user_id }); }
+        if state.access_tokens.values().any(|other| other.user_id == token.user_id && other.name == token.name) {
+            return Err(Error::DuplicateAccessToken{ name: token.name.clone() });
+        }
+
+        // Insert it with the next ID (we start at 1, like AUTO_INCREMENT does)
+        state.next_token_id += 1;
+        let id = state.next_token_id;
+        state.access_tokens.insert(id, AccessToken{ id, ..token.clone() });
+        Ok(id)
+    }
+
+    fn get_access_token(&self, id: u32) -> Result<Option<AccessToken>, Error> {
+        let state = self.lock();
+        Ok(state.access_tokens.get(&id).cloned())
+    }
+
+    fn list_access_tokens(&self, user_id: u32) -> Result<Vec<AccessToken>, Error> {
+        let state = self.lock();
+        Ok(state.access_tokens.values().filter(|token| token.user_id == user_id).cloned().collect())
+    }
+
+    fn touch_access_token(&self, id: u32, when: OffsetDateTime) -> Result<(), Error> {
+        let mut state = self.lock();
+        if let Some(token) = state.access_tokens.get_mut(&id) { token.last_used = Some(when); }
+        Ok(())
+    }
+
+    fn delete_access_token(&self, user_id: u32, name: &str) -> Result<bool, Error> {
+        let mut state = self.lock();
+        let len = state.access_tokens.len();
+        state.access_tokens.retain(|_, token| token.user_id != user_id || token.name != name);
+        Ok(state.access_tokens.len() < len)
+    }
+}
+
+impl SecondFactorStore for MemoryStore {
+    fn set_totp(&self, user_id: u32, secret: &str) -> Result<(), Error> {
+        let mut state = self.lock();
+        if !state.accounts.contains_key(&user_id) { return Err(Error::UnknownUser{ id: user_id }); }
+        state.totp.insert(user_id, TotpSecret{ user_id, secret: secret.to_string(), confirmed: false, last_step: 0 });
+        Ok(())
+    }
+
+    fn get_totp(&self, user_id: u32) -> Result<Option<TotpSecret>, Error> {
+        let state = self.lock();
+        Ok(state.totp.get(&user_id).cloned())
+    }
+
+    fn confirm_totp(&self, user_id: u32, recovery_codes: &[String]) -> Result<(), Error> {
+        let mut state = self.lock();
+        match state.totp.get_mut(&user_id) {
+            Some(totp) => { totp.confirmed = true; },
+            None       => { return Err(Error::UnknownUser{ id: user_id }); }
+        }
+        state.recovery_codes.insert(user_id, recovery_codes.to_vec());
+        Ok(())
+    }
+
+    fn use_totp_step(&self, user_id: u32, step: u64) -> Result<bool, Error> {
+        let mut state = self.lock();
+        match state.totp.get_mut(&user_id) {
+            Some(totp) if totp.last_step < step => { totp.last_step = step; Ok(true) },
+            _                                   => Ok(false),
+        }
+    }
+
+    fn delete_totp(&self, user_id: u32) -> Result<(), Error> {
+        let mut state = self.lock();
+        state.totp.remove(&user_id);
+        state.recovery_codes.remove(&user_id);
+        Ok(())
+    }
+
+    fn get_recovery_codes(&self, user_id: u32) -> Result<Vec<String>, Error> {
+        let state = self.lock();
+        Ok(state.recovery_codes.get(&user_id).cloned().unwrap_or_default())
+    }
+
+    fn use_recovery_code(&self, user_id: u32, hash: &str) -> Result<bool, Error> {
+        let mut state = self.lock();
+        match state.recovery_codes.get_mut(&user_id) {
+            Some(codes) => {
+                let len = codes.len();
+                codes.retain(|code| code != hash);
+                Ok(codes.len() < len)
+            },
+            None => Ok(false),
+        }
+    }
+}
+
+impl AuditStore for MemoryStore {
+    fn insert_event(&self, event: &AuthEvent) -> Result<(), Error> {
+        let mut state = self.lock();
+        let id = state.events.len() as u64 + 1;
+        state.events.push(AuthEvent{ id, ..event.clone() });
+        Ok(())
+    }
+
+    fn query_events(&self, filter: &EventFilter) -> Result<Vec<AuthEvent>, Error> {
+        let state = self.lock();
+        let user_id: Option<u32> = filter.username.as_ref().and_then(|username| state.accounts.iter().find(|(_, account)| account.name_key == username_key(username)).map(|(id, _)| *id));
+        Ok(state.events.iter()
+            .rev()
+            .filter(|event| filter.matches(event, user_id))
+            .skip(filter.offset as usize)
+            .take(filter.limit as usize)
+            .cloned()
+            .collect())
+    }
+}
+
+impl HealthStore for MemoryStore {
+    #[inline]
+    fn check_ready(&self) -> Result<(), Error> { Ok(()) }
+
+    #[inline]
+    fn pool_stats(&self) -> Option<PoolStats> { None }
+}
+
+impl MigrationStore for MemoryStore {
+    /// There is no schema to migrate.
+    #[inline]
+    fn migrations(&self) -> &'static [Migration] { &[] }
+
+    #[inline]
+    fn applied_migrations(&self) -> Result<Vec<AppliedMigration>, Error> { Ok(vec![]) }
+
+    #[inline]
+    fn apply_migration(&self, _migration: &Migration) -> Result<(), Error> { Ok(()) }
+
+    #[inline]
+    fn revert_migration(&self, _migration: &Migration) -> Result<(), Error> { Ok(()) }
+}
@@ -0,0 +1,287 @@
+/* MIGRATIONS.rs
+ *   by Lut99
+ *
+ * Created:
+ *   18 Oct 2026, 04:48:47
+ * Last edited:
+ *   18 Oct 2026, 06:24:13
+ * Auto updated?
+ *   Yes
+ *
+ * Description:
+ *   Implements versioned migrations of the schema of a store.
+ *
+ *   Every backend embeds an ordered list of migrations, and keeps track
+ *   of the ones it has applied in a `schema_migrations` table. This
+ *   module decides which of them to apply or revert; the backends only
+ *   know how to run a single one.
+**/
+
+use log::{debug, info};
+use time::OffsetDateTime;
+
+pub use crate::errors::MigrationError as Error;
+use super::MigrationStore;
+
+
+/***** UNIT TESTS *****/
+#[cfg(test)]
+mod tests {
+    use rusqlite::Connection;
+
+    use todo_spec::credentials::Credential;
+    use todo_spec::jwt::Role;
+
+    use super::*;
+    use crate::store::{AccountStore, SqliteStore};
+
+    /// Returns the path of a fresh database file for the given test.
+    fn temp_db(name: &str) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!("todo-auth-test-{}-{}.db", name, std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn test_up_down() {
+        let store = SqliteStore::new(":memory:").unwrap();
+        let latest: u32 = latest(&store);
+        assert!(status(&store).unwrap().iter().all(|migration| migration.applied_at.is_none()));
+
+        // Migrate up to some version, then all the way
+        assert_eq!(up(&store, Some(1)).unwrap().len(), 1);
+        assert_eq!(current(&store).unwrap(), 1);
+        assert_eq!(up(&store, None).unwrap().len() as u32, latest - 1);
+        assert_eq!(current(&store).unwrap(), latest);
+        assert!(up(&store, None).unwrap().is_empty());
+        store.insert(&Credential::new("john", "hash").unwrap(), Role::User).unwrap();
+
+        // Going down reverts only the latest migration by default
+        assert_eq!(down(&store, None).unwrap().len(), 1);
+        assert_eq!(current(&store).unwrap(), latest - 1);
+        assert_eq!(down(&store, Some(0)).unwrap().len() as u32, latest - 1);
+        assert_eq!(current(&store).unwrap(), 0);
+        assert!(matches!(up(&store, Some(latest + 1)), Err(Error::UnknownTarget{ .. })));
+
+        // Everything can be applied again on top of that
+        up(&store, None).unwrap();
+        assert!(store.get_by_name("john").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_too_new() {
+        let path = temp_db("too-new");
+        let store = SqliteStore::new(&path).unwrap();
+        up(&store, None).unwrap();
+        let latest: u32 = latest(&store);
+        Connection::open(&path).unwrap().execute("INSERT INTO schema_migrations (version, name, applied_at) VALUES (?1, 'from_the_future', 0);", [ latest + 1 ]).unwrap();
+
+        // We should neither use nor touch a database that a newer version has migrated
+        assert!(matches!(check(&store), Err(Error::DatabaseTooNew{ database, binary }) if database == latest + 1 && binary == latest));
+        assert!(matches!(up(&store, None), Err(Error::DatabaseTooNew{ .. })));
+        assert!(matches!(down(&store, None), Err(Error::UnknownMigration{ .. })));
+        assert!(status(&store).unwrap().iter().any(|migration| !migration.known));
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_upgrade_unversioned() {
+        // Databases from before migrations only have the original users table
+        let path = temp_db("unversioned");
+        Connection::open(&path).unwrap().execute_batch("CREATE TABLE users (id INTEGER PRIMARY KEY AUTOINCREMENT, name TEXT NOT NULL UNIQUE, pass TEXT NOT NULL); INSERT INTO users (name, pass) VALUES ('john', 'hash');").unwrap();
+
+        // They are upgraded without losing the accounts
+        let store = SqliteStore::new(&path).unwrap();
+        up(&store, None).unwrap();
+        let john = store.get_by_name("john").unwrap().unwrap();
+        assert_eq!(john.role, Role::User);
+        assert!(!john.disabled);
+        assert_eq!(store.get_by_name("JOHN").unwrap().unwrap().id, john.id);
+        let _ = std::fs::remove_file(&path);
+    }
+}
+
+
+
+
+
+/***** LIBRARY STRUCTS *****/
+/// Defines a single, versioned change to the schema of a store.
+#[derive(Clone, Copy, Debug)]
+pub struct Migration {
+    /// The version of the schema after this migration. Versions start at 1 and increase by one per migration.
+    pub version : u32,
+    /// A short, descriptive name of the migration.
+    pub name    : &'static str,
+    /// The statements that apply the migration, in order.
+    pub up      : &'static [&'static str],
+    /// The statements that revert the migration, in order.
+    pub down    : &'static [&'static str],
+}
+
+
+
+/// Records a migration that has been applied to a store.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct AppliedMigration {
+    /// The version of the migration.
+    pub version    : u32,
+    /// The name of the migration, as it was when it was applied.
+    pub name       : String,
+    /// The moment the migration was applied.
+    pub applied_at : OffsetDateTime,
+}
+
+
+
+/// Describes the state of a single migration, either one known to this binary or one applied to the store.
+#[derive(Clone, Debug)]
+pub struct MigrationStatus {
+    /// The version of the migration.
+    pub version    : u32,
+    /// The name of the migration.
+    pub name       : String,
+    /// The moment the migration was applied, or None if it hasn't been.
+    pub applied_at : Option<OffsetDateTime>,
+    /// Whether this binary knows the migration. If not, it was applied by a newer version.
+    pub known      : bool,
+}
+
+
+
+
+
+/***** LIBRARY FUNCTIONS *****/
+/// Returns the latest version that this binary can migrate the given store to.
+#[inline]
+pub fn latest(store: &dyn MigrationStore) -> u32 {
+    store.migrations().last().map(|migration| migration.version).unwrap_or(0)
+}
+
+/// Returns the version that the given store is currently at.
+/// 
+/// **Arguments**
+///  * `store`: The MigrationStore to check.
+/// 
+/// **Returns**  
+/// The version of the latest applied migration (0 if there are none), or an Error if we could not read them.
+pub fn current(store: &dyn MigrationStore) -> Result<u32, Error> {
+    match store.applied_migrations() {
+        Ok(applied) => Ok(applied.iter().map(|migration| migration.version).max().unwrap_or(0)),
+        Err(err)    => Err(Error::StoreError{ err }),
+    }
+}
+
+
+
+/// Returns the state of all migrations: the ones this binary knows, and any others that have been applied to the store.
+/// 
+/// **Arguments**
+///  * `store`: The MigrationStore to check.
+/// 
+/// **Returns**  
+/// The MigrationStatus of every migration, ordered by version, or an Error if we could not read them.
+pub fn status(store: &dyn MigrationStore) -> Result<Vec<MigrationStatus>, Error> {
+    let applied = match store.applied_migrations() {
+        Ok(applied) => applied,
+        Err(err)    => { return Err(Error::StoreError{ err }); }
+    };
+
+    let mut res: Vec<MigrationStatus> = store.migrations().iter().map(|migration| MigrationStatus {
+        version    : migration.version,
+        name       : migration.name.to_string(),
+        applied_at : applied.iter().find(|applied| applied.version == migration.version).map(|applied| applied.applied_at),
+        known      : true,
+    }).collect();
+    for applied in applied {
+        if !store.migrations().iter().any(|migration| migration.version == applied.version) {
+            res.push(MigrationStatus{ version: applied.version, name: applied.name, applied_at: Some(applied.applied_at), known: false });
+        }
+    }
+    res.sort_by_key(|migration| migration.version);
+    Ok(res)
+}
+
+/// Checks that the given store was not migrated by a newer version of the service, which we shouldn't touch.
+/// 
+/// **Arguments**
+///  * `store`: The MigrationStore to check.
+/// 
+/// **Returns**  
+/// Nothing if we know all applied migrations, or an Error otherwise.
+pub fn check(store: &dyn MigrationStore) -> Result<(), Error> {
+    let current: u32 = current(store)?;
+    let latest: u32  = latest(store);
+    if current > latest { return Err(Error::DatabaseTooNew{ database: current, binary: latest }); }
+    Ok(())
+}
+
+
+
+/// Applies the migrations of the given store that have not been applied yet.
+/// 
+/// **Arguments**
+///  * `store`: The MigrationStore to migrate.
+///  * `to`: The version to migrate up to, or None to migrate to the latest version.
+/// 
+/// **Returns**  
+/// The migrations that were applied, in order, or an Error if one of them failed. Any migrations before the failed one remain applied.
+pub fn up(store: &dyn MigrationStore, to: Option<u32>) -> Result<Vec<&'static Migration>, Error> {
+    check(store)?;
+    if let Some(to) = to {
+        if !store.migrations().iter().any(|migration| migration.version == to) { return Err(Error::UnknownTarget{ version: to }); }
+    }
+    let applied = match store.applied_migrations() {
+        Ok(applied) => applied,
+        Err(err)    => { return Err(Error::StoreError{ err }); }
+    };
+
+    let mut res: Vec<&'static Migration> = vec![];
+    for migration in store.migrations() {
+        if to.map(|to| migration.version > to).unwrap_or(false) { break; }
+        if applied.iter().any(|applied| applied.version == migration.version) { continue; }
+
+        info!("Applying migration {} ({})...", migration.version, migration.name);
+        if let Err(err) = store.apply_migration(migration) { return Err(Error::StoreError{ err }); }
+        res.push(migration);
+    }
+    if res.is_empty() { debug!("Database schema is up-to-date."); }
+    Ok(res)
+}
+
+/// Reverts applied migrations of the given store, latest first.
+/// 
+/// **Arguments**
+///  * `store`: The MigrationStore to migrate.
+///  * `to`: The version to migrate down to (use 0 to revert everything), or None to only revert the latest migration.
+/// 
+/// **Returns**  
+/// The migrations that were reverted, in order, or an Error if one of them failed. Any migrations before the failed one remain reverted.
+pub fn down(store: &dyn MigrationStore, to: Option<u32>) -> Result<Vec<&'static Migration>, Error> {
+    if let Some(to) = to {
+        if to != 0 && !store.migrations().iter().any(|migration| migration.version == to) { return Err(Error::UnknownTarget{ version: to }); }
+    }
+    let mut applied: Vec<u32> = match store.applied_migrations() {
+        Ok(applied) => applied.into_iter().map(|migration| migration.version).collect(),
+        Err(err)    => { return Err(Error::StoreError{ err }); }
+    };
+    applied.sort_unstable_by(|lhs, rhs| rhs.cmp(lhs));
+    let to: u32 = match to {
+        Some(to) => to,
+        None     => applied.get(1).copied().unwrap_or(0),
+    };
+
+    let mut res: Vec<&'static Migration> = vec![];
+    for version in applied {
+        if version <= to { break; }
+        let migration: &'static Migration = match store.migrations().iter().find(|migration| migration.version == version) {
+            Some(migration) => migration,
+            None            => { return Err(Error::UnknownMigration{ version }); }
+        };
+
+        info!("Reverting migration {} ({})...", migration.version, migration.name);
+        if let Err(err) = store.revert_migration(migration) { return Err(Error::StoreError{ err }); }
+        res.push(migration);
+    }
+    Ok(res)
+}
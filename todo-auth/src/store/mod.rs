@@ -0,0 +1,656 @@
+/* MOD.rs
+ *   by Lut99
+ *
+ * Created:
+ *   18 Oct 2026, 03:25:40
+ * Last edited:
+ *   18 Oct 2026, 06:24:13
+ * Auto updated?
+ *   Yes
+ *
+ * Description:
+ *   Defines the AccountStore and related traits, which abstract over the
+ *   backend that stores the user accounts, and their implementations.
+**/
+
+use std::str::FromStr;
+
+use clap::ArgEnum;
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+
+use todo_spec::api::{AuthEventKind, Scope};
+use todo_spec::credentials::Credential;
+use todo_spec::jwt::Role;
+
+pub use crate::errors::StoreError as Error;
+use self::migrations::{AppliedMigration, Migration};
+use crate::spec::{AccessToken, Account, AuthEvent, EventFilter, PoolStats, RefreshToken, ResetToken, Session, TotpSecret};
+
+
+/***** SUBMODULES *****/
+/// Implements the AccountStore for a MySQL database.
+pub mod mysql;
+/// Implements the AccountStore in memory (i.e., nothing persists).
+pub mod memory;
+/// Implements the AccountStore for a file-backed SQLite database.
+pub mod sqlite;
+/// Implements versioned migrations of the schema of a store.
+pub mod migrations;
+
+pub use self::mysql::MySqlStore;
+pub use self::memory::MemoryStore;
+pub use self::sqlite::SqliteStore;
+
+
+
+
+
+/***** LIBRARY ENUMS *****/
+/// Defines the possible backends for the AccountStore.
+#[derive(ArgEnum, Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StoreKind {
+    /// Use a (remote) MySQL database.
+    #[clap(name = "mysql")]
+    MySql,
+    /// Keep everything in memory; nothing is persisted across restarts.
+    Memory,
+    /// Use a local, file-backed SQLite database.
+    Sqlite,
+}
+
+
+
+
+
+/***** LIBRARY TRAITS *****/
+/// Defines the operations that a backend storing user accounts must support.
+pub trait AccountStore: Send + Sync {
+    /// Searches the store for the account with the given name. Names are compared by their key (see `credentials::username_key()`), so 'John' finds 'john'.
+    /// 
+    /// **Arguments**
+    ///  * `name`: The name of the user to search for.
+    /// 
+    /// **Returns**  
+    /// The Account if it exists, None if it doesn't, or an Error if we failed to search.
+    fn get_by_name(&self, name: &str) -> Result<Option<Account>, Error>;
+
+    /// Searches the store for the account with the given identifier.
+    /// 
+    /// **Arguments**
+    ///  * `id`: The identifier of the account to search for.
+    /// 
+    /// **Returns**  
+    /// The Account if it exists, None if it doesn't, or an Error if we failed to search.
+    fn get_by_id(&self, id: u32) -> Result<Option<Account>, Error>;
+
+    /// Returns all accounts in the store, ordered by their identifiers.
+    /// 
+    /// **Returns**  
+    /// The list of Accounts on success, or an Error otherwise.
+    fn list(&self) -> Result<Vec<Account>, Error>;
+
+    /// Inserts a new account in the store.
+    /// 
+    /// **Arguments**
+    ///  * `credential`: The Credential (with already hashed password) of the new user.
+    ///  * `role`: The role of the new user.
+    /// 
+    /// **Returns**  
+    /// The identifier of the new account on success, or an Error otherwise. If a user with the same name (or one that only differs in case) already exists, returns `Error::DuplicateUser`.
+    fn insert(&self, credential: &Credential, role: Role) -> Result<u32, Error>;
+
+    /// Updates the (hashed) password of the given account.
+    /// 
+    /// **Arguments**
+    ///  * `id`: The identifier of the account to update.
+    ///  * `hash`: The new, hashed password of the account.
+    /// 
+    /// **Returns**  
+    /// Nothing on success, or an Error otherwise. If the account does not exist, returns `Error::UnknownUser`.
+    fn update_password(&self, id: u32, hash: &str) -> Result<(), Error>;
+
+    /// Changes the role of the given account.
+    /// 
+    /// **Arguments**
+    ///  * `id`: The identifier of the account to update.
+    ///  * `role`: The new role of the account.
+    /// 
+    /// **Returns**  
+    /// Nothing on success, or an Error otherwise. If the account does not exist, returns `Error::UnknownUser`.
+    fn set_role(&self, id: u32, role: Role) -> Result<(), Error>;
+
+    /// Changes the name of the given account.
+    /// 
+    /// **Arguments**
+    ///  * `id`: The identifier of the account to update.
+    ///  * `name`: The new name of the account. It is assumed to be a valid username.
+    /// 
+    /// **Returns**  
+    /// Nothing on success, or an Error otherwise. If the account does not exist, returns `Error::UnknownUser`; if another user already has the name (or one that only differs in case), returns `Error::DuplicateUser`.
+    fn rename(&self, id: u32, name: &str) -> Result<(), Error>;
+
+    /// Changes the display name of the given account.
+    /// 
+    /// **Arguments**
+    ///  * `id`: The identifier of the account to update.
+    ///  * `display_name`: The new display name of the account, or None to remove it. It is assumed to be a valid display name.
+    /// 
+    /// **Returns**  
+    /// Nothing on success, or an Error otherwise. If the account does not exist, returns `Error::UnknownUser`.
+    fn set_display_name(&self, id: u32, display_name: Option<&str>) -> Result<(), Error>;
+
+    /// Disables or re-enables the given account.
+    /// 
+    /// **Arguments**
+    ///  * `id`: The identifier of the account to update.
+    ///  * `disabled`: Whether the account should be disabled.
+    /// 
+    /// **Returns**  
+    /// Nothing on success, or an Error otherwise. If the account does not exist, returns `Error::UnknownUser`.
+    fn set_disabled(&self, id: u32, disabled: bool) -> Result<(), Error>;
+
+    /// Registers a failed login for the given account.
+    /// 
+    /// **Arguments**
+    ///  * `id`: The identifier of the account that somebody failed to login to.
+    /// 
+    /// **Returns**  
+    /// The number of consecutive failed logins, including this one, on success, or an Error otherwise. If the account does not exist, returns `Error::UnknownUser`.
+    fn record_login_failure(&self, id: u32) -> Result<u32, Error>;
+
+    /// Locks the given account until the given moment.
+    /// 
+    /// **Arguments**
+    ///  * `id`: The identifier of the account to lock.
+    ///  * `until`: The moment the lock expires.
+    /// 
+    /// **Returns**  
+    /// Nothing on success, or an Error otherwise. If the account does not exist, returns `Error::UnknownUser`.
+    fn lock_account(&self, id: u32, until: OffsetDateTime) -> Result<(), Error>;
+
+    /// Forgets any failed logins of the given account, and lifts its lock if it has any.
+    /// 
+    /// **Arguments**
+    ///  * `id`: The identifier of the account to reset.
+    /// 
+    /// **Returns**  
+    /// Nothing on success, or an Error otherwise. If the account does not exist, returns `Error::UnknownUser`.
+    fn reset_login_failures(&self, id: u32) -> Result<(), Error>;
+
+    /// Removes the given account from the store.
+    /// 
+    /// **Arguments**
+    ///  * `id`: The identifier of the account to remove.
+    /// 
+    /// **Returns**  
+    /// Nothing on success, or an Error otherwise. If the account does not exist, returns `Error::UnknownUser`.
+    fn delete(&self, id: u32) -> Result<(), Error>;
+}
+
+
+
+
+
+/// Defines the operations that a backend storing refresh tokens must support.
+pub trait RefreshTokenStore: Send + Sync {
+    /// Inserts a new refresh token in the store.
+    /// 
+    /// **Arguments**
+    ///  * `token`: The RefreshToken (with hashed token) to insert.
+    /// 
+    /// **Returns**  
+    /// Nothing on success, or an Error otherwise.
+    fn insert_refresh_token(&self, token: &RefreshToken) -> Result<(), Error>;
+
+    /// Searches the store for the refresh token with the given hash.
+    /// 
+    /// **Arguments**
+    ///  * `hash`: The hash of the token to search for.
+    /// 
+    /// **Returns**  
+    /// The RefreshToken if it exists, None if it doesn't, or an Error if we failed to search.
+    fn get_refresh_token(&self, hash: &str) -> Result<Option<RefreshToken>, Error>;
+
+    /// Marks the refresh token with the given hash as used, but only if it wasn't already.
+    /// 
+    /// **Arguments**
+    ///  * `hash`: The hash of the token to mark.
+    /// 
+    /// **Returns**  
+    /// True if we marked the token, or false if it was already used (or does not exist). Returns an Error if we failed to update the store.
+    fn use_refresh_token(&self, hash: &str) -> Result<bool, Error>;
+
+    /// Revokes all refresh tokens in the given family.
+    /// 
+    /// **Arguments**
+    ///  * `family`: The family of tokens to revoke.
+    /// 
+    /// **Returns**  
+    /// Nothing on success, or an Error otherwise.
+    fn revoke_refresh_family(&self, family: &str) -> Result<(), Error>;
+
+    /// Revokes all refresh tokens of the given user, across all families.
+    /// 
+    /// **Arguments**
+    ///  * `user_id`: The identifier of the user whose tokens to revoke.
+    /// 
+    /// **Returns**  
+    /// Nothing on success, or an Error otherwise.
+    fn revoke_user_refresh_tokens(&self, user_id: u32) -> Result<(), Error>;
+}
+
+
+
+/// Defines the operations that a backend storing password reset tokens must support.
+pub trait ResetTokenStore: Send + Sync {
+    /// Inserts a new password reset token in the store, replacing any reset token that its user already had.
+    /// 
+    /// **Arguments**
+    ///  * `token`: The ResetToken (with hashed token) to insert.
+    /// 
+    /// **Returns**  
+    /// Nothing on success, or an Error otherwise.
+    fn insert_reset_token(&self, token: &ResetToken) -> Result<(), Error>;
+
+    /// Searches the store for the password reset token with the given hash.
+    /// 
+    /// **Arguments**
+    ///  * `hash`: The hash of the token to search for.
+    /// 
+    /// **Returns**  
+    /// The ResetToken if it exists, None if it doesn't, or an Error if we failed to search.
+    fn get_reset_token(&self, hash: &str) -> Result<Option<ResetToken>, Error>;
+
+    /// Removes the password reset token with the given hash, which uses it up.
+    /// 
+    /// **Arguments**
+    ///  * `hash`: The hash of the token to remove.
+    /// 
+    /// **Returns**  
+    /// True if we removed the token, or false if it was already used (or does not exist). Returns an Error if we failed to update the store.
+    fn delete_reset_token(&self, hash: &str) -> Result<bool, Error>;
+}
+
+
+
+/// Defines the operations that a backend storing sessions must support.
+pub trait SessionStore: Send + Sync {
+    /// Inserts a new session in the store.
+    /// 
+    /// **Arguments**
+    ///  * `session`: The Session to insert. Its identifier is ignored; the store assigns a new one.
+    /// 
+    /// **Returns**  
+    /// The identifier of the new session on success, or an Error otherwise.
+    fn insert_session(&self, session: &Session) -> Result<u32, Error>;
+
+    /// Searches the store for the session with the given identifier.
+    /// 
+    /// **Arguments**
+    ///  * `id`: The identifier of the session.
+    /// 
+    /// **Returns**  
+    /// The Session if it exists, None if it doesn't, or an Error if we failed to search.
+    fn get_session(&self, id: u32) -> Result<Option<Session>, Error>;
+
+    /// Searches the store for the session with the given family of refresh tokens.
+    /// 
+    /// **Arguments**
+    ///  * `family`: The family of the refresh tokens of the session.
+    /// 
+    /// **Returns**  
+    /// The Session if it exists, None if it doesn't, or an Error if we failed to search.
+    fn get_session_by_family(&self, family: &str) -> Result<Option<Session>, Error>;
+
+    /// Returns all sessions of the given user, ordered by their identifiers.
+    /// 
+    /// **Arguments**
+    ///  * `user_id`: The identifier of the user whose sessions to return.
+    /// 
+    /// **Returns**  
+    /// The list of Sessions on success, or an Error otherwise.
+    fn list_sessions(&self, user_id: u32) -> Result<Vec<Session>, Error>;
+
+    /// Updates the moment the given session was last seen.
+    /// 
+    /// **Arguments**
+    ///  * `id`: The identifier of the session.
+    ///  * `when`: The moment it was seen.
+    /// 
+    /// **Returns**  
+    /// Nothing on success, or an Error otherwise.
+    fn touch_session(&self, id: u32, when: OffsetDateTime) -> Result<(), Error>;
+
+    /// Removes the given session from the store. Note that this does not revoke its refresh tokens.
+    /// 
+    /// **Arguments**
+    ///  * `id`: The identifier of the session to remove.
+    /// 
+    /// **Returns**  
+    /// Whether the session existed on success, or an Error otherwise.
+    fn delete_session(&self, id: u32) -> Result<bool, Error>;
+
+    /// Removes all sessions of the given user from the store. Note that this does not revoke their refresh tokens.
+    /// 
+    /// **Arguments**
+    ///  * `user_id`: The identifier of the user whose sessions to remove.
+    /// 
+    /// **Returns**  
+    /// Nothing on success, or an Error otherwise.
+    fn delete_user_sessions(&self, user_id: u32) -> Result<(), Error>;
+}
+
+
+
+/// Defines the operations that a backend storing personal access tokens must support.
+pub trait AccessTokenStore: Send + Sync {
+    /// Inserts a new personal access token in the store.
+    /// 
+    /// **Arguments**
+    ///  * `token`: The AccessToken (with hashed secret) to insert. Its identifier is ignored; the store assigns a new one.
+    /// 
+    /// **Returns**  
+    /// The identifier of the new token on success, or an Error otherwise. If the user already has a token with the same name, returns `Error::DuplicateAccessToken`.
+    fn insert_access_token(&self, token: &AccessToken) -> Result<u32, Error>;
+
+    /// Searches the store for the personal access token with the given identifier.
+    /// 
+    /// **Arguments**
+    ///  * `id`: The identifier of the token.
+    /// 
+    /// **Returns**  
+    /// The AccessToken if it exists, None if it doesn't, or an Error if we failed to search.
+    fn get_access_token(&self, id: u32) -> Result<Option<AccessToken>, Error>;
+
+    /// Returns all personal access tokens of the given user, ordered by their identifiers.
+    /// 
+    /// **Arguments**
+    ///  * `user_id`: The identifier of the user whose tokens to return.
+    /// 
+    /// **Returns**  
+    /// The list of AccessTokens on success, or an Error otherwise.
+    fn list_access_tokens(&self, user_id: u32) -> Result<Vec<AccessToken>, Error>;
+
+    /// Records that the given personal access token has been used.
+    /// 
+    /// **Arguments**
+    ///  * `id`: The identifier of the token.
+    ///  * `when`: The moment the token was used.
+    /// 
+    /// **Returns**  
+    /// Nothing on success, or an Error otherwise.
+    fn touch_access_token(&self, id: u32, when: OffsetDateTime) -> Result<(), Error>;
+
+    /// Removes the personal access token with the given name from the given user, which revokes it.
+    /// 
+    /// **Arguments**
+    ///  * `user_id`: The identifier of the user who owns the token.
+    ///  * `name`: The name of the token.
+    /// 
+    /// **Returns**  
+    /// Whether there was such a token on success, or an Error otherwise.
+    fn delete_access_token(&self, user_id: u32, name: &str) -> Result<bool, Error>;
+}
+
+
+
+/// Defines the operations that a backend storing second factors (TOTP secrets and recovery codes) must support.
+pub trait SecondFactorStore: Send + Sync {
+    /// Stores a new, unconfirmed TOTP secret for the given user, replacing any secret they already had.
+    /// 
+    /// **Arguments**
+    ///  * `user_id`: The identifier of the user who enrolls the secret.
+    ///  * `secret`: The base32-encoded secret.
+    /// 
+    /// **Returns**  
+    /// Nothing on success, or an Error otherwise. If the account does not exist, returns `Error::UnknownUser`.
+    fn set_totp(&self, user_id: u32, secret: &str) -> Result<(), Error>;
+
+    /// Searches the store for the TOTP secret of the given user.
+    /// 
+    /// **Arguments**
+    ///  * `user_id`: The identifier of the user whose secret to search for.
+    /// 
+    /// **Returns**  
+    /// The TotpSecret if the user has one, None if they don't, or an Error if we failed to search.
+    fn get_totp(&self, user_id: u32) -> Result<Option<TotpSecret>, Error>;
+
+    /// Marks the TOTP secret of the given user as confirmed, and replaces their recovery codes with the given ones.
+    /// 
+    /// **Arguments**
+    ///  * `user_id`: The identifier of the user whose secret to confirm.
+    ///  * `recovery_codes`: The (hashed) recovery codes of the user.
+    /// 
+    /// **Returns**  
+    /// Nothing on success, or an Error otherwise. If the user has no secret, returns `Error::UnknownUser`.
+    fn confirm_totp(&self, user_id: u32, recovery_codes: &[String]) -> Result<(), Error>;
+
+    /// Marks the given time step as used for the given user, but only if it is later than the last step they used.
+    /// 
+    /// **Arguments**
+    ///  * `user_id`: The identifier of the user who provided a code.
+    ///  * `step`: The time step that the code matched.
+    /// 
+    /// **Returns**  
+    /// True if we marked the step, or false if the step (or a later one) was already used. Returns an Error if we failed to update the store.
+    fn use_totp_step(&self, user_id: u32, step: u64) -> Result<bool, Error>;
+
+    /// Removes the TOTP secret and the recovery codes of the given user.
+    /// 
+    /// **Arguments**
+    ///  * `user_id`: The identifier of the user whose second factor to remove.
+    /// 
+    /// **Returns**  
+    /// Nothing on success, or an Error otherwise.
+    fn delete_totp(&self, user_id: u32) -> Result<(), Error>;
+
+    /// Returns the (hashed) recovery codes of the given user that have not been used yet.
+    /// 
+    /// **Arguments**
+    ///  * `user_id`: The identifier of the user whose codes to return.
+    /// 
+    /// **Returns**  
+    /// The hashes of the recovery codes on success, or an Error otherwise.
+    fn get_recovery_codes(&self, user_id: u32) -> Result<Vec<String>, Error>;
+
+    /// Removes the given recovery code, so it cannot be used again.
+    /// 
+    /// **Arguments**
+    ///  * `user_id`: The identifier of the user who used the code.
+    ///  * `hash`: The hash of the code that was used.
+    /// 
+    /// **Returns**  
+    /// True if we removed the code, or false if it was already used. Returns an Error if we failed to update the store.
+    fn use_recovery_code(&self, user_id: u32, hash: &str) -> Result<bool, Error>;
+}
+
+
+
+/// Defines the operations that a backend storing the audit log must support.
+pub trait AuditStore: Send + Sync {
+    /// Appends the given event to the audit log.
+    /// 
+    /// **Arguments**
+    ///  * `event`: The AuthEvent to store. Its identifier is ignored; the store assigns a new one.
+    /// 
+    /// **Returns**  
+    /// Nothing on success, or an Error otherwise.
+    fn insert_event(&self, event: &AuthEvent) -> Result<(), Error>;
+
+    /// Searches the audit log for the events that match the given filter.
+    /// 
+    /// **Arguments**
+    ///  * `filter`: The EventFilter that determines which events to return.
+    /// 
+    /// **Returns**  
+    /// The matching events (newest first) on success, or an Error otherwise.
+    fn query_events(&self, filter: &EventFilter) -> Result<Vec<AuthEvent>, Error>;
+}
+
+
+
+/// Defines the operations with which a backend reports on its own health.
+pub trait HealthStore: Send + Sync {
+    /// Checks whether the store can serve requests, i.e., whether it can reach its backend and the `users` table exists.
+    /// 
+    /// **Returns**  
+    /// Nothing if the store is ready, or an Error describing why it is not.
+    fn check_ready(&self) -> Result<(), Error>;
+
+    /// Returns statistics about the connections that the store keeps to its backend.
+    /// 
+    /// **Returns**  
+    /// The PoolStats of the store, or None if it does not use connections.
+    fn pool_stats(&self) -> Option<PoolStats>;
+}
+
+
+
+/// Defines the operations that a backend must support to migrate its schema. Which migrations to apply is decided by the `migrations` module.
+pub trait MigrationStore: Send + Sync {
+    /// Returns the migrations of this backend.
+    /// 
+    /// **Returns**  
+    /// The migrations, ordered by version.
+    fn migrations(&self) -> &'static [Migration];
+
+    /// Returns the migrations that have been applied to the backend.
+    /// 
+    /// **Returns**  
+    /// The AppliedMigrations (in no particular order) on success, or an Error otherwise.
+    fn applied_migrations(&self) -> Result<Vec<AppliedMigration>, Error>;
+
+    /// Applies the given migration, and records that it has been.
+    /// 
+    /// **Arguments**
+    ///  * `migration`: The Migration to apply.
+    /// 
+    /// **Returns**  
+    /// Nothing on success, or an Error otherwise.
+    fn apply_migration(&self, migration: &Migration) -> Result<(), Error>;
+
+    /// Reverts the given migration, and forgets that it has been applied.
+    /// 
+    /// **Arguments**
+    ///  * `migration`: The Migration to revert.
+    /// 
+    /// **Returns**  
+    /// Nothing on success, or an Error otherwise.
+    fn revert_migration(&self, migration: &Migration) -> Result<(), Error>;
+}
+
+
+
+/// Collects all of the store traits, so that one backend can be passed around as one object.
+pub trait Store: AccountStore + RefreshTokenStore + ResetTokenStore + SessionStore + AccessTokenStore + SecondFactorStore + AuditStore + HealthStore + MigrationStore {}
+
+impl<T: AccountStore + RefreshTokenStore + ResetTokenStore + SessionStore + AccessTokenStore + SecondFactorStore + AuditStore + HealthStore + MigrationStore> Store for T {}
+
+
+
+
+
+/***** HELPER FUNCTIONS *****/
+/// Converts a raw row from some store into an Account.
+/// 
+/// **Arguments**
+///  * `id`: The identifier of the account.
+///  * `name`: The name of the user.
+///  * `pass`: The hashed password of the user.
+///  * `display_name`: The display name of the user, if any.
+///  * `role`: The name of the role of the user.
+///  * `disabled`: Whether the account is disabled.
+///  * `failed_logins`: The number of consecutive failed logins of the user.
+///  * `locked_until`: The UNIX timestamp until which the account is locked, if any.
+/// 
+/// **Returns**  
+/// The new Account on success, or an Error if the stored username or role is not valid.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn account_from_row(id: u32, name: String, pass: String, display_name: Option<String>, role: &str, disabled: bool, failed_logins: u32, locked_until: Option<i64>) -> Result<Account, Error> {
+    let credential: Credential = match Credential::new(name, pass) {
+        Ok(credential) => credential,
+        Err(err)       => { return Err(Error::IllegalAccount{ id, err }); }
+    };
+    let role: Role = match role.parse() {
+        Ok(role) => role,
+        Err(err) => { return Err(Error::IllegalRole{ id, err }); }
+    };
+    Ok(Account{ id, credential, display_name, role, disabled, failed_logins, locked_until: locked_until.map(time_from_row) })
+}
+
+
+
+/// Converts a raw row from some store into an AuthEvent.
+/// 
+/// **Arguments**
+///  * `id`: The identifier of the event.
+///  * `time`: The UNIX timestamp of the event.
+///  * `kind`: The name of the kind of event.
+///  * `user_id`: The identifier of the user the event is about, if any.
+///  * `username`: The name of the user the event is about, if any.
+///  * `ip`: The IP address of the client, if any.
+///  * `user_agent`: The User-Agent of the client, if any.
+///  * `detail`: Further details about the event, if any.
+/// 
+/// **Returns**  
+/// The new AuthEvent on success, or an Error if the stored kind is not valid.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn event_from_row(id: u64, time: i64, kind: &str, user_id: Option<u32>, username: Option<String>, ip: Option<String>, user_agent: Option<String>, detail: Option<String>) -> Result<AuthEvent, Error> {
+    let kind: AuthEventKind = match kind.parse() {
+        Ok(kind) => kind,
+        Err(err) => { return Err(Error::IllegalEvent{ id, err }); }
+    };
+    Ok(AuthEvent{ id, time: time_from_row(time), kind, user_id, username, ip, user_agent, detail })
+}
+
+
+
+/// Converts a raw row from some store into an AccessToken.
+/// 
+/// **Arguments**
+///  * `id`: The identifier of the token.
+///  * `user_id`: The identifier of the user who owns the token.
+///  * `name`: The name of the token.
+///  * `hash`: The hash of the secret of the token.
+///  * `scopes`: The names of the scopes of the token, separated by commas.
+///  * `created`: The UNIX timestamp at which the token was created.
+///  * `expires`: The UNIX timestamp at which the token expires, if any.
+///  * `last_used`: The UNIX timestamp at which the token was last used, if any.
+/// 
+/// **Returns**  
+/// The new AccessToken on success, or an Error if one of the stored scopes is not valid.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn access_token_from_row(id: u32, user_id: u32, name: String, hash: String, scopes: &str, created: i64, expires: Option<i64>, last_used: Option<i64>) -> Result<AccessToken, Error> {
+    let scopes: Vec<Scope> = match scopes.split(',').filter(|scope| !scope.is_empty()).map(Scope::from_str).collect() {
+        Ok(scopes) => scopes,
+        Err(err)   => { return Err(Error::IllegalAccessToken{ id, err }); }
+    };
+    Ok(AccessToken{ id, user_id, name, hash, scopes, created: time_from_row(created), expires: expires.map(time_from_row), last_used: last_used.map(time_from_row) })
+}
+
+/// Converts the scopes of an AccessToken into the way they are kept in a store.
+/// 
+/// **Arguments**
+///  * `scopes`: The scopes to convert.
+/// 
+/// **Returns**  
+/// The names of the scopes, separated by commas.
+pub(crate) fn scopes_to_row(scopes: &[Scope]) -> String {
+    scopes.iter().map(Scope::name).collect::<Vec<&str>>().join(",")
+}
+
+
+
+/// Converts a UNIX timestamp from some store into an OffsetDateTime.
+/// 
+/// **Arguments**
+///  * `timestamp`: The number of seconds since the UNIX epoch.
+/// 
+/// **Returns**  
+/// The matching OffsetDateTime.
+#[inline]
+pub(crate) fn time_from_row(timestamp: i64) -> OffsetDateTime {
+    OffsetDateTime::from_unix_timestamp(timestamp).expect("Store contains an out-of-range timestamp; this should never happen!")
+}
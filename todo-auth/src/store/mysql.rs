@@ -0,0 +1,903 @@
+/* MYSQL.rs
+ *   by Lut99
+ *
+ * Created:
+ *   18 Oct 2026, 03:36:19
+ * Last edited:
+ *   18 Oct 2026, 06:24:13
+ * Auto updated?
+ *   Yes
+ *
+ * Description:
+ *   Implements an AccountStore that is backed by a (remote) MySQL
+ *   database.
+**/
+
+use std::ops::{Deref, DerefMut};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use log::{debug, info};
+use mysql::{params, Conn, Opts, OptsBuilder, Pool, PooledConn, TxOpts};
+use mysql::consts::CapabilityFlags;
+use mysql::prelude::Queryable;
+use time::OffsetDateTime;
+use url::Url;
+
+use todo_spec::credentials::{username_key, Credential};
+use todo_spec::jwt::Role;
+
+use super::migrations::{AppliedMigration, Migration};
+use super::{access_token_from_row, account_from_row, event_from_row, scopes_to_row, time_from_row, AccessTokenStore, AccountStore, AuditStore, Error, HealthStore, MigrationStore, RefreshTokenStore, ResetTokenStore, SecondFactorStore, SessionStore};
+use crate::spec::{AccessToken, Account, AuthEvent, EventFilter, PoolStats, RefreshToken, ResetToken, Session, TotpSecret};
+
+
+/***** CONSTANTS *****/
+/// The name of the database we use within the MySQL server.
+const DATABASE_NAME: &str = "todo";
+/// The minimum number of connections that the pool keeps open.
+const POOL_MIN_CONNECTIONS: usize = 10;
+/// The maximum number of connections that the pool opens.
+const POOL_MAX_CONNECTIONS: usize = 100;
+
+/// The MySQL error code for duplicate entries in a UNIQUE column.
+const MYSQL_DUPLICATE_ENTRY: u16 = 1062;
+/// The MySQL error code for rows that reference a row in another table that does not exist.
+const MYSQL_NO_REFERENCED_ROW: u16 = 1452;
+
+/// The table in which we record which migrations have been applied. It is created outside of the migrations themselves.
+const MIGRATIONS_TABLE: &str = r"CREATE TABLE IF NOT EXISTS schema_migrations (
+    version INT UNSIGNED PRIMARY KEY,
+    name VARCHAR(255) NOT NULL,
+    applied_at BIGINT NOT NULL
+);";
+
+/// The migrations of the database, in order. Never change a migration that has been released; add a new one instead.
+const MIGRATIONS: [Migration; 10] = [
+    // Databases from before we had migrations already have this table, so don't fail on those
+    Migration {
+        version : 1,
+        name    : "create_users",
+        up      : &[ r"CREATE TABLE IF NOT EXISTS users (
+            id INT UNSIGNED AUTO_INCREMENT PRIMARY KEY,
+            name VARCHAR(255) NOT NULL UNIQUE,
+            pass VARCHAR(255) NOT NULL
+        );" ],
+        down    : &[ "DROP TABLE users;" ],
+    },
+    Migration {
+        version : 2,
+        name    : "create_refresh_tokens",
+        up      : &[ r"CREATE TABLE refresh_tokens (
+            hash CHAR(64) PRIMARY KEY,
+            family VARCHAR(64) NOT NULL,
+            user_id INT UNSIGNED NOT NULL,
+            expires BIGINT NOT NULL,
+            used BOOLEAN NOT NULL DEFAULT FALSE,
+            revoked BOOLEAN NOT NULL DEFAULT FALSE,
+            INDEX (family),
+            FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+        );" ],
+        down    : &[ "DROP TABLE refresh_tokens;" ],
+    },
+    Migration {
+        version : 3,
+        name    : "add_login_failures",
+        up      : &[ "ALTER TABLE users ADD COLUMN failed_logins INT UNSIGNED NOT NULL DEFAULT 0, ADD COLUMN locked_until BIGINT;" ],
+        down    : &[ "ALTER TABLE users DROP COLUMN locked_until, DROP COLUMN failed_logins;" ],
+    },
+    Migration {
+        version : 4,
+        name    : "create_totp",
+        up      : &[
+            r"CREATE TABLE totp (
+                user_id INT UNSIGNED PRIMARY KEY,
+                secret VARCHAR(255) NOT NULL,
+                confirmed BOOLEAN NOT NULL DEFAULT FALSE,
+                last_step BIGINT UNSIGNED NOT NULL DEFAULT 0,
+                FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+            );",
+            r"CREATE TABLE recovery_codes (
+                user_id INT UNSIGNED NOT NULL,
+                hash VARCHAR(255) NOT NULL,
+                PRIMARY KEY (user_id, hash),
+                FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+            );",
+        ],
+        down    : &[ "DROP TABLE recovery_codes;", "DROP TABLE totp;" ],
+    },
+    Migration {
+        version : 5,
+        name    : "add_roles",
+        up      : &[ "ALTER TABLE users ADD COLUMN role VARCHAR(16) NOT NULL DEFAULT 'user', ADD COLUMN disabled BOOLEAN NOT NULL DEFAULT FALSE;" ],
+        down    : &[ "ALTER TABLE users DROP COLUMN disabled, DROP COLUMN role;" ],
+    },
+    Migration {
+        version : 6,
+        name    : "create_auth_events",
+        up      : &[ r"CREATE TABLE auth_events (
+            id BIGINT UNSIGNED AUTO_INCREMENT PRIMARY KEY,
+            time BIGINT NOT NULL,
+            kind VARCHAR(32) NOT NULL,
+            user_id INT UNSIGNED,
+            username VARCHAR(255),
+            ip VARCHAR(45),
+            user_agent VARCHAR(255),
+            detail VARCHAR(255),
+            INDEX (username),
+            INDEX (time)
+        );" ],
+        down    : &[ "DROP TABLE auth_events;" ],
+    },
+    Migration {
+        version : 7,
+        name    : "create_access_tokens",
+        up      : &[ r"CREATE TABLE access_tokens (
+            id INT UNSIGNED AUTO_INCREMENT PRIMARY KEY,
+            user_id INT UNSIGNED NOT NULL,
+            name VARCHAR(64) NOT NULL,
+            hash VARCHAR(255) NOT NULL,
+            scopes VARCHAR(255) NOT NULL,
+            created BIGINT NOT NULL,
+            expires BIGINT,
+            last_used BIGINT,
+            UNIQUE (user_id, name),
+            FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+        );" ],
+        down    : &[ "DROP TABLE access_tokens;" ],
+    },
+    Migration {
+        version : 8,
+        name    : "create_sessions",
+        up      : &[ r"CREATE TABLE sessions (
+            id INT UNSIGNED AUTO_INCREMENT PRIMARY KEY,
+            user_id INT UNSIGNED NOT NULL,
+            family VARCHAR(64) NOT NULL UNIQUE,
+            device VARCHAR(255),
+            ip VARCHAR(64),
+            user_agent VARCHAR(255),
+            created BIGINT NOT NULL,
+            last_seen BIGINT NOT NULL,
+            FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+        );" ],
+        down    : &[ "DROP TABLE sessions;" ],
+    },
+    Migration {
+        version : 9,
+        name    : "create_reset_tokens",
+        up      : &[ r"CREATE TABLE reset_tokens (
+            hash CHAR(64) PRIMARY KEY,
+            user_id INT UNSIGNED NOT NULL UNIQUE,
+            expires BIGINT NOT NULL,
+            FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+        );" ],
+        down    : &[ "DROP TABLE reset_tokens;" ],
+    },
+    // The default collation already ignores case (and accents), which would make 'jöhn' the same as 'john'; we compare names by key instead.
+    // Names used to be ASCII-only, so LOWER() folds them just like `username_key()` does. Names that only differ in case make this fail; rename them first.
+    Migration {
+        version : 10,
+        name    : "add_name_keys",
+        up      : &[
+            "ALTER TABLE users MODIFY name VARCHAR(255) CHARACTER SET utf8mb4 COLLATE utf8mb4_bin NOT NULL;",
+            "ALTER TABLE users ADD COLUMN name_key VARCHAR(255) CHARACTER SET utf8mb4 COLLATE utf8mb4_bin;",
+            "UPDATE users SET name_key = LOWER(name);",
+            "ALTER TABLE users ADD UNIQUE INDEX users_name_key (name_key);",
+            "ALTER TABLE users ADD COLUMN display_name VARCHAR(512) CHARACTER SET utf8mb4;",
+        ],
+        down    : &[
+            "ALTER TABLE users DROP COLUMN display_name;",
+            "ALTER TABLE users DROP INDEX users_name_key;",
+            "ALTER TABLE users DROP COLUMN name_key;",
+            "ALTER TABLE users MODIFY name VARCHAR(255) NOT NULL;",
+        ],
+    },
+];
+
+/// The columns to select for an account, in the order of `AccountRow`.
+const ACCOUNT_COLUMNS: &str = "id, name, pass, display_name, role, disabled, failed_logins, locked_until";
+
+
+
+
+
+/***** HELPER FUNCTIONS *****/
+/// The raw columns of an account, as selected by `ACCOUNT_COLUMNS`.
+type AccountRow = (u32, String, String, Option<String>, String, bool, u32, Option<i64>);
+
+/// Converts the raw columns of an account into an Account.
+#[inline]
+fn to_account((id, name, pass, display_name, role, disabled, failed_logins, locked_until): AccountRow) -> Result<Account, Error> {
+    account_from_row(id, name, pass, display_name, &role, disabled, failed_logins, locked_until)
+}
+
+
+
+/// The columns to select for a personal access token, in the order of `AccessTokenRow`.
+const ACCESS_TOKEN_COLUMNS: &str = "id, user_id, name, hash, scopes, created, expires, last_used";
+
+/// The raw columns of a personal access token, as selected by `ACCESS_TOKEN_COLUMNS`.
+type AccessTokenRow = (u32, u32, String, String, String, i64, Option<i64>, Option<i64>);
+
+/// Converts the raw columns of a personal access token into an AccessToken.
+#[inline]
+fn to_access_token((id, user_id, name, hash, scopes, created, expires, last_used): AccessTokenRow) -> Result<AccessToken, Error> {
+    access_token_from_row(id, user_id, name, hash, &scopes, created, expires, last_used)
+}
+
+
+/// The columns to select for a session, in the order of `SessionRow`.
+const SESSION_COLUMNS: &str = "id, user_id, family, device, ip, user_agent, created, last_seen";
+
+/// The raw columns of a session, as selected by `SESSION_COLUMNS`.
+type SessionRow = (u32, u32, String, Option<String>, Option<String>, Option<String>, i64, i64);
+
+/// Converts the raw columns of a session into a Session.
+#[inline]
+fn to_session((id, user_id, family, device, ip, user_agent, created, last_seen): SessionRow) -> Session {
+    Session{ id, user_id, family, device, ip, user_agent, created: time_from_row(created), last_seen: time_from_row(last_seen) }
+}
+
+
+
+/// The raw columns of an audit event, in the order they are selected by `query_events()`.
+type EventRow = (u64, i64, String, Option<u32>, Option<String>, Option<String>, Option<String>, Option<String>);
+
+/// Converts the raw columns of an audit event into an AuthEvent.
+#[inline]
+fn to_event((id, time, kind, user_id, username, ip, user_agent, detail): EventRow) -> Result<AuthEvent, Error> {
+    event_from_row(id, time, &kind, user_id, username, ip, user_agent, detail)
+}
+
+
+
+
+
+/***** HELPER STRUCTS *****/
+/// A connection from the pool that counts as in use until it is dropped.
+struct CountedConn<'a> {
+    /// The connection itself
+    conn   : PooledConn,
+    /// The number of connections in use, which we decrement again when dropped
+    in_use : &'a AtomicUsize,
+}
+
+impl<'a> Drop for CountedConn<'a> {
+    #[inline]
+    fn drop(&mut self) {
+        self.in_use.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl<'a> Deref for CountedConn<'a> {
+    type Target = PooledConn;
+
+    #[inline]
+    fn deref(&self) -> &Self::Target { &self.conn }
+}
+
+impl<'a> DerefMut for CountedConn<'a> {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut Self::Target { &mut self.conn }
+}
+
+
+
+
+
+/***** LIBRARY STRUCTS *****/
+/// Implements an AccountStore that uses a MySQL database.
+pub struct MySqlStore {
+    /// The pool of connections to the database.
+    pool   : Pool,
+    /// The number of connections from the pool that are currently in use.
+    in_use : AtomicUsize,
+}
+
+impl MySqlStore {
+    /// Constructor for the MySqlStore, which connects to the given MySQL server and makes sure the desired database structure is present.
+    /// 
+    /// **Arguments**
+    ///  * `url`: The address:port of the MySQL server to connect to.
+    ///  * `cred`: The (plain-text) credentials of the MySQL user to connect with.
+    /// 
+    /// **Returns**  
+    /// The new MySqlStore on success, or else an Error.
+    pub fn new(url: &Url, cred: &Credential) -> Result<Self, Error> {
+        let opts = Opts::from_url(&format!("mysql://{}:{}@{}", cred.user(), cred.pass(), url)).expect("Could not get URL from Url; this should never happen!");
+
+        // Create the database if it does not yet exist
+        info!("Preparig database...");
+        debug!("Creating '{}' database if necessary...", DATABASE_NAME);
+        let mut conn: Conn = match Conn::new(opts.clone()) {
+            Ok(conn) => conn,
+            Err(err) => { return Err(Error::MySqlConnectError{ err }); }
+        };
+        let query = format!("CREATE DATABASE IF NOT EXISTS {};", DATABASE_NAME);
+        if let Err(err) = conn.query_drop(&query) {
+            return Err(Error::MySqlQueryError{ query, err });
+        };
+        drop(conn);
+
+        // Prepare the pool of connections for that database. Note that we want to know the matched rows instead of the changed rows for UPDATEs.
+        let opts = OptsBuilder::from_opts(opts)
+            .db_name(Some(DATABASE_NAME))
+            .additional_capabilities(CapabilityFlags::CLIENT_FOUND_ROWS);
+        let pool = match Pool::new_manual(POOL_MIN_CONNECTIONS, POOL_MAX_CONNECTIONS, opts) {
+            Ok(pool) => pool,
+            Err(err) => { return Err(Error::MySqlPoolCreateError{ url: url.clone(), err }); }
+        };
+        let store = Self{ pool, in_use: AtomicUsize::new(0) };
+
+        // Create the table that tracks the migrations; the migrations themselves create the rest
+        let mut conn = store.conn()?;
+        debug!("Creating 'schema_migrations' table if necessary...");
+        if let Err(err) = conn.query_drop(MIGRATIONS_TABLE) {
+            return Err(Error::MySqlQueryError{ query: MIGRATIONS_TABLE.to_string(), err });
+        };
+        drop(conn);
+
+        // Done
+        debug!("Database preparation complete.");
+        Ok(store)
+    }
+
+
+
+    /// Returns a new connection from the pool.
+    fn conn(&self) -> Result<CountedConn<'_>, Error> {
+        match self.pool.get_conn() {
+            Ok(conn) => {
+                self.in_use.fetch_add(1, Ordering::Relaxed);
+                Ok(CountedConn{ conn, in_use: &self.in_use })
+            },
+            Err(err) => Err(Error::MySqlConnectError{ err }),
+        }
+    }
+}
+
+impl AccountStore for MySqlStore {
+    fn get_by_name(&self, name: &str) -> Result<Option<Account>, Error> {
+        let mut conn = self.conn()?;
+
+        let query = format!("SELECT {} FROM users WHERE name_key = ?;", ACCOUNT_COLUMNS);
+        match conn.exec_first::<AccountRow, _, _>(&query, (username_key(name),)) {
+            Ok(Some(row)) => Ok(Some(to_account(row)?)),
+            Ok(None)      => Ok(None),
+            Err(err)      => Err(Error::MySqlQueryError{ query, err }),
+        }
+    }
+
+    fn get_by_id(&self, id: u32) -> Result<Option<Account>, Error> {
+        let mut conn = self.conn()?;
+
+        let query = format!("SELECT {} FROM users WHERE id = ?;", ACCOUNT_COLUMNS);
+        match conn.exec_first::<AccountRow, _, _>(&query, (id,)) {
+            Ok(Some(row)) => Ok(Some(to_account(row)?)),
+            Ok(None)      => Ok(None),
+            Err(err)      => Err(Error::MySqlQueryError{ query, err }),
+        }
+    }
+
+    fn list(&self) -> Result<Vec<Account>, Error> {
+        let mut conn = self.conn()?;
+
+        let query = format!("SELECT {} FROM users ORDER BY id;", ACCOUNT_COLUMNS);
+        match conn.query::<AccountRow, _>(&query) {
+            Ok(rows) => rows.into_iter().map(to_account).collect(),
+            Err(err) => Err(Error::MySqlQueryError{ query, err }),
+        }
+    }
+
+    fn insert(&self, credential: &Credential, role: Role) -> Result<u32, Error> {
+        let mut conn = self.conn()?;
+
+        let query = String::from("INSERT INTO users (name, name_key, pass, role) VALUES (?, ?, ?, ?);");
+        match conn.exec_drop(&query, (credential.user(), username_key(credential.user()), credential.pass(), role.name())) {
+            Ok(_) => Ok(conn.last_insert_id() as u32),
+            Err(mysql::Error::MySqlError(mysql::MySqlError{ code: MYSQL_DUPLICATE_ENTRY, .. })) => Err(Error::DuplicateUser{ username: credential.user().to_string() }),
+            Err(err) => Err(Error::MySqlQueryError{ query, err }),
+        }
+    }
+
+    fn update_password(&self, id: u32, hash: &str) -> Result<(), Error> {
+        let mut conn = self.conn()?;
+
+        let query = String::from("UPDATE users SET pass = ? WHERE id = ?;");
+        if let Err(err) = conn.exec_drop(&query, (hash, id)) {
+            return Err(Error::MySqlQueryError{ query, err });
+        }
+        if conn.affected_rows() == 0 { return Err(Error::UnknownUser{ id }); }
+        Ok(())
+    }
+
+    fn set_role(&self, id: u32, role: Role) -> Result<(), Error> {
+        let mut conn = self.conn()?;
+
+        let query = String::from("UPDATE users SET role = ? WHERE id = ?;");
+        if let Err(err) = conn.exec_drop(&query, (role.name(), id)) {
+            return Err(Error::MySqlQueryError{ query, err });
+        }
+        if conn.affected_rows() == 0 { return Err(Error::UnknownUser{ id }); }
+        Ok(())
+    }
+
+    fn rename(&self, id: u32, name: &str) -> Result<(), Error> {
+        let mut conn = self.conn()?;
+
+        let query = String::from("UPDATE users SET name = ?, name_key = ? WHERE id = ?;");
+        match conn.exec_drop(&query, (name, username_key(name), id)) {
+            Ok(_) => {},
+            Err(mysql::Error::MySqlError(mysql::MySqlError{ code: MYSQL_DUPLICATE_ENTRY, .. })) => { return Err(Error::DuplicateUser{ username: name.to_string() }); },
+            Err(err) => { return Err(Error::MySqlQueryError{ query, err }); },
+        }
+        if conn.affected_rows() == 0 { return Err(Error::UnknownUser{ id }); }
+        Ok(())
+    }
+
+    fn set_display_name(&self, id: u32, display_name: Option<&str>) -> Result<(), Error> {
+        let mut conn = self.conn()?;
+
+        let query = String::from("UPDATE users SET display_name = ? WHERE id = ?;");
+        if let Err(err) = conn.exec_drop(&query, (display_name, id)) {
+            return Err(Error::MySqlQueryError{ query, err });
+        }
+        if conn.affected_rows() == 0 { return Err(Error::UnknownUser{ id }); }
+        Ok(())
+    }
+
+    fn set_disabled(&self, id: u32, disabled: bool) -> Result<(), Error> {
+        let mut conn = self.conn()?;
+
+        let query = String::from("UPDATE users SET disabled = ? WHERE id = ?;");
+        if let Err(err) = conn.exec_drop(&query, (disabled, id)) {
+            return Err(Error::MySqlQueryError{ query, err });
+        }
+        if conn.affected_rows() == 0 { return Err(Error::UnknownUser{ id }); }
+        Ok(())
+    }
+
+    fn record_login_failure(&self, id: u32) -> Result<u32, Error> {
+        let mut conn = self.conn()?;
+
+        // MySQL has no RETURNING, so we let LAST_INSERT_ID() carry the new value back to us
+        let query = String::from("UPDATE users SET failed_logins = LAST_INSERT_ID(failed_logins + 1) WHERE id = ?;");
+        if let Err(err) = conn.exec_drop(&query, (id,)) {
+            return Err(Error::MySqlQueryError{ query, err });
+        }
+        if conn.affected_rows() == 0 { return Err(Error::UnknownUser{ id }); }
+        Ok(conn.last_insert_id() as u32)
+    }
+
+    fn lock_account(&self, id: u32, until: OffsetDateTime) -> Result<(), Error> {
+        let mut conn = self.conn()?;
+
+        let query = String::from("UPDATE users SET locked_until = ? WHERE id = ?;");
+        if let Err(err) = conn.exec_drop(&query, (until.unix_timestamp(), id)) {
+            return Err(Error::MySqlQueryError{ query, err });
+        }
+        if conn.affected_rows() == 0 { return Err(Error::UnknownUser{ id }); }
+        Ok(())
+    }
+
+    fn reset_login_failures(&self, id: u32) -> Result<(), Error> {
+        let mut conn = self.conn()?;
+
+        let query = String::from("UPDATE users SET failed_logins = 0, locked_until = NULL WHERE id = ?;");
+        if let Err(err) = conn.exec_drop(&query, (id,)) {
+            return Err(Error::MySqlQueryError{ query, err });
+        }
+        if conn.affected_rows() == 0 { return Err(Error::UnknownUser{ id }); }
+        Ok(())
+    }
+
+    fn delete(&self, id: u32) -> Result<(), Error> {
+        let mut conn = self.conn()?;
+
+        let query = String::from("DELETE FROM users WHERE id = ?;");
+        if let Err(err) = conn.exec_drop(&query, (id,)) {
+            return Err(Error::MySqlQueryError{ query, err });
+        }
+        if conn.affected_rows() == 0 { return Err(Error::UnknownUser{ id }); }
+        Ok(())
+    }
+}
+
+impl RefreshTokenStore for MySqlStore {
+    fn insert_refresh_token(&self, token: &RefreshToken) -> Result<(), Error> {
+        let mut conn = self.conn()?;
+
+        let query = String::from("INSERT INTO refresh_tokens (hash, family, user_id, expires, used, revoked) VALUES (?, ?, ?, ?, ?, ?);");
+        match conn.exec_drop(&query, (&token.hash, &token.family, token.user_id, token.expires.unix_timestamp(), token.used, token.revoked)) {
+            Ok(_)    => Ok(()),
+            Err(err) => Err(Error::MySqlQueryError{ query, err }),
+        }
+    }
+
+    fn get_refresh_token(&self, hash: &str) -> Result<Option<RefreshToken>, Error> {
+        let mut conn = self.conn()?;
+
+        let query = String::from("SELECT hash, family, user_id, expires, used, revoked FROM refresh_tokens WHERE hash = ?;");
+        match conn.exec_first::<(String, String, u32, i64, bool, bool), _, _>(&query, (hash,)) {
+            Ok(Some((hash, family, user_id, expires, used, revoked))) => Ok(Some(RefreshToken{ hash, family, user_id, expires: time_from_row(expires), used, revoked })),
+            Ok(None)                                                   => Ok(None),
+            Err(err)                                                   => Err(Error::MySqlQueryError{ query, err }),
+        }
+    }
+
+    fn use_refresh_token(&self, hash: &str) -> Result<bool, Error> {
+        let mut conn = self.conn()?;
+
+        let query = String::from("UPDATE refresh_tokens SET used = TRUE WHERE hash = ? AND used = FALSE;");
+        if let Err(err) = conn.exec_drop(&query, (hash,)) {
+            return Err(Error::MySqlQueryError{ query, err });
+        }
+        Ok(conn.affected_rows() > 0)
+    }
+
+    fn revoke_refresh_family(&self, family: &str) -> Result<(), Error> {
+        let mut conn = self.conn()?;
+
+        let query = String::from("UPDATE refresh_tokens SET revoked = TRUE WHERE family = ?;");
+        match conn.exec_drop(&query, (family,)) {
+            Ok(_)    => Ok(()),
+            Err(err) => Err(Error::MySqlQueryError{ query, err }),
+        }
+    }
+
+    fn revoke_user_refresh_tokens(&self, user_id: u32) -> Result<(), Error> {
+        let mut conn = self.conn()?;
+
+        let query = String::from("UPDATE refresh_tokens SET revoked = TRUE WHERE user_id = ?;");
+        match conn.exec_drop(&query, (user_id,)) {
+            Ok(_)    => Ok(()),
+            Err(err) => Err(Error::MySqlQueryError{ query, err }),
+        }
+    }
+}
+
+impl ResetTokenStore for MySqlStore {
+    fn insert_reset_token(&self, token: &ResetToken) -> Result<(), Error> {
+        let mut conn = self.conn()?;
+
+        // Every user has at most one token, so this replaces any older one
+        let query = String::from("REPLACE INTO reset_tokens (hash, user_id, expires) VALUES (?, ?, ?);");
+        match conn.exec_drop(&query, (&token.hash, token.user_id, token.expires.unix_timestamp())) {
+            Ok(_)    => Ok(()),
+            Err(err) => Err(Error::MySqlQueryError{ query, err }),
+        }
+    }
+
+    fn get_reset_token(&self, hash: &str) -> Result<Option<ResetToken>, Error> {
+        let mut conn = self.conn()?;
+
+        let query = String::from("SELECT hash, user_id, expires FROM reset_tokens WHERE hash = ?;");
+        match conn.exec_first::<(String, u32, i64), _, _>(&query, (hash,)) {
+            Ok(Some((hash, user_id, expires))) => Ok(Some(ResetToken{ hash, user_id, expires: time_from_row(expires) })),
+            Ok(None)                           => Ok(None),
+            Err(err)                           => Err(Error::MySqlQueryError{ query, err }),
+        }
+    }
+
+    fn delete_reset_token(&self, hash: &str) -> Result<bool, Error> {
+        let mut conn = self.conn()?;
+
+        let query = String::from("DELETE FROM reset_tokens WHERE hash = ?;");
+        if let Err(err) = conn.exec_drop(&query, (hash,)) {
+            return Err(Error::MySqlQueryError{ query, err });
+        }
+        Ok(conn.affected_rows() > 0)
+    }
+}
+
+impl SessionStore for MySqlStore {
+    fn insert_session(&self, session: &Session) -> Result<u32, Error> {
+        let mut conn = self.conn()?;
+
+        let query = String::from("INSERT INTO sessions (user_id, family, device, ip, user_agent, created, last_seen) VALUES (?, ?, ?, ?, ?, ?, ?);");
+        match conn.exec_drop(&query, (session.user_id, &session.family, &session.device, &session.ip, &session.user_agent, session.created.unix_timestamp(), session.last_seen.unix_timestamp())) {
+            Ok(_) => Ok(conn.last_insert_id() as u32),
+            Err(mysql::Error::MySqlError(mysql::MySqlError{ code: MYSQL_NO_REFERENCED_ROW, .. })) => Err(Error::UnknownUser{ id: session.user_id }),
+            Err(err) => Err(Error::MySqlQueryError{ query, err }),
+        }
+    }
+
+    fn get_session(&self, id: u32) -> Result<Option<Session>, Error> {
+        let mut conn = self.conn()?;
+
+        let query = format!("SELECT {} FROM sessions WHERE id = ?;", SESSION_COLUMNS);
+        match conn.exec_first::<SessionRow, _, _>(&query, (id,)) {
+            Ok(row)  => Ok(row.map(to_session)),
+            Err(err) => Err(Error::MySqlQueryError{ query, err }),
+        }
+    }
+
+    fn get_session_by_family(&self, family: &str) -> Result<Option<Session>, Error> {
+        let mut conn = self.conn()?;
+
+        let query = format!("SELECT {} FROM sessions WHERE family = ?;", SESSION_COLUMNS);
+        match conn.exec_first::<SessionRow, _, _>(&query, (family,)) {
+            Ok(row)  => Ok(row.map(to_session)),
+            Err(err) => Err(Error::MySqlQueryError{ query, err }),
+        }
+    }
+
+    fn list_sessions(&self, user_id: u32) -> Result<Vec<Session>, Error> {
+        let mut conn = self.conn()?;
+
+        let query = format!("SELECT {} FROM sessions WHERE user_id = ? ORDER BY id;", SESSION_COLUMNS);
+        match conn.exec::<SessionRow, _, _>(&query, (user_id,)) {
+            Ok(rows) => Ok(rows.into_iter().map(to_session).collect()),
+            Err(err) => Err(Error::MySqlQueryError{ query, err }),
+        }
+    }
+
+    fn touch_session(&self, id: u32, when: OffsetDateTime) -> Result<(), Error> {
+        let mut conn = self.conn()?;
+
+        let query = String::from("UPDATE sessions SET last_seen = ? WHERE id = ?;");
+        match conn.exec_drop(&query, (when.unix_timestamp(), id)) {
+            Ok(_)    => Ok(()),
+            Err(err) => Err(Error::MySqlQueryError{ query, err }),
+        }
+    }
+
+    fn delete_session(&self, id: u32) -> Result<bool, Error> {
+        let mut conn = self.conn()?;
+
+        let query = String::from("DELETE FROM sessions WHERE id = ?;");
+        if let Err(err) = conn.exec_drop(&query, (id,)) {
+            return Err(Error::MySqlQueryError{ query, err });
+        }
+        Ok(conn.affected_rows() > 0)
+    }
+
+    fn delete_user_sessions(&self, user_id: u32) -> Result<(), Error> {
+        let mut conn = self.conn()?;
+
+        let query = String::from("DELETE FROM sessions WHERE user_id = ?;");
+        match conn.exec_drop(&query, (user_id,)) {
+            Ok(_)    => Ok(()),
+            Err(err) => Err(Error::MySqlQueryError{ query, err }),
+        }
+    }
+}
+
+impl AccessTokenStore for MySqlStore {
+    fn insert_access_token(&self, token: &AccessToken) -> Result<u32, Error> {
+        let mut conn = self.conn()?;
+
+        let query = String::from("INSERT INTO access_tokens (user_id, name, hash, scopes, created, expires, last_used) VALUES (?, ?, ?, ?, ?, ?, ?);");
+        match conn.exec_drop(&query, (token.user_id, &token.name, &token.hash, scopes_to_row(&token.scopes), token.created.unix_timestamp(), token.expires.map(|expires| expires.unix_timestamp()), token.last_used.map(|last_used| last_used.unix_timestamp()))) {
+            Ok(_) => Ok(conn.last_insert_id() as u32),
+            Err(mysql::Error::MySqlError(mysql::MySqlError{ code: MYSQL_DUPLICATE_ENTRY, .. }))   => Err(Error::DuplicateAccessToken{ name: token.name.clone() }),
+            Err(mysql::Error::MySqlError(mysql::MySqlError{ code: MYSQL_NO_REFERENCED_ROW, .. })) => Err(Error::UnknownUser{ id: token.user_id }),
+            Err(err) => Err(Error::MySqlQueryError{ query, err }),
+        }
+    }
+
+    fn get_access_token(&self, id: u32) -> Result<Option<AccessToken>, Error> {
+        let mut conn = self.conn()?;
+
+        let query = format!("SELECT {} FROM access_tokens WHERE id = ?;", ACCESS_TOKEN_COLUMNS);
+        match conn.exec_first::<AccessTokenRow, _, _>(&query, (id,)) {
+            Ok(Some(row)) => Ok(Some(to_access_token(row)?)),
+            Ok(None)      => Ok(None),
+            Err(err)      => Err(Error::MySqlQueryError{ query, err }),
+        }
+    }
+
+    fn list_access_tokens(&self, user_id: u32) -> Result<Vec<AccessToken>, Error> {
+        let mut conn = self.conn()?;
+
+        let query = format!("SELECT {} FROM access_tokens WHERE user_id = ? ORDER BY id;", ACCESS_TOKEN_COLUMNS);
+        match conn.exec::<AccessTokenRow, _, _>(&query, (user_id,)) {
+            Ok(rows) => rows.into_iter().map(to_access_token).collect(),
+            Err(err) => Err(Error::MySqlQueryError{ query, err }),
+        }
+    }
+
+    fn touch_access_token(&self, id: u32, when: OffsetDateTime) -> Result<(), Error> {
+        let mut conn = self.conn()?;
+
+        let query = String::from("UPDATE access_tokens SET last_used = ? WHERE id = ?;");
+        match conn.exec_drop(&query, (when.unix_timestamp(), id)) {
+            Ok(_)    => Ok(()),
+            Err(err) => Err(Error::MySqlQueryError{ query, err }),
+        }
+    }
+
+    fn delete_access_token(&self, user_id: u32, name: &str) -> Result<bool, Error> {
+        let mut conn = self.conn()?;
+
+        let query = String::from("DELETE FROM access_tokens WHERE user_id = ? AND name = ?;");
+        if let Err(err) = conn.exec_drop(&query, (user_id, name)) {
+            return Err(Error::MySqlQueryError{ query, err });
+        }
+        Ok(conn.affected_rows() > 0)
+    }
+}
+
+impl SecondFactorStore for MySqlStore {
+    fn set_totp(&self, user_id: u32, secret: &str) -> Result<(), Error> {
+        let mut conn = self.conn()?;
+
+        let query = String::from("INSERT INTO totp (user_id, secret, confirmed, last_step) VALUES (?, ?, FALSE, 0) ON DUPLICATE KEY UPDATE secret = VALUES(secret), confirmed = FALSE, last_step = 0;");
+        match conn.exec_drop(&query, (user_id, secret)) {
+            Ok(_) => Ok(()),
+            Err(mysql::Error::MySqlError(mysql::MySqlError{ code: MYSQL_NO_REFERENCED_ROW, .. })) => Err(Error::UnknownUser{ id: user_id }),
+            Err(err) => Err(Error::MySqlQueryError{ query, err }),
+        }
+    }
+
+    fn get_totp(&self, user_id: u32) -> Result<Option<TotpSecret>, Error> {
+        let mut conn = self.conn()?;
+
+        let query = String::from("SELECT user_id, secret, confirmed, last_step FROM totp WHERE user_id = ?;");
+        match conn.exec_first::<(u32, String, bool, u64), _, _>(&query, (user_id,)) {
+            Ok(Some((user_id, secret, confirmed, last_step))) => Ok(Some(TotpSecret{ user_id, secret, confirmed, last_step })),
+            Ok(None)                                          => Ok(None),
+            Err(err)                                          => Err(Error::MySqlQueryError{ query, err }),
+        }
+    }
+
+    fn confirm_totp(&self, user_id: u32, recovery_codes: &[String]) -> Result<(), Error> {
+        let mut conn = self.conn()?;
+
+        // Confirm the secret and swap the recovery codes in one go
+        let mut tx = match conn.start_transaction(TxOpts::default()) {
+            Ok(tx)   => tx,
+            Err(err) => { return Err(Error::MySqlQueryError{ query: String::from("START TRANSACTION;"), err }); }
+        };
+        let query = String::from("UPDATE totp SET confirmed = TRUE WHERE user_id = ?;");
+        if let Err(err) = tx.exec_drop(&query, (user_id,)) {
+            return Err(Error::MySqlQueryError{ query, err });
+        }
+        if tx.affected_rows() == 0 { return Err(Error::UnknownUser{ id: user_id }); }
+        let query = String::from("DELETE FROM recovery_codes WHERE user_id = ?;");
+        if let Err(err) = tx.exec_drop(&query, (user_id,)) {
+            return Err(Error::MySqlQueryError{ query, err });
+        }
+        let query = String::from("INSERT INTO recovery_codes (user_id, hash) VALUES (?, ?);");
+        if let Err(err) = tx.exec_batch(&query, recovery_codes.iter().map(|hash| (user_id, hash))) {
+            return Err(Error::MySqlQueryError{ query, err });
+        }
+        match tx.commit() {
+            Ok(_)    => Ok(()),
+            Err(err) => Err(Error::MySqlQueryError{ query: String::from("COMMIT;"), err }),
+        }
+    }
+
+    fn use_totp_step(&self, user_id: u32, step: u64) -> Result<bool, Error> {
+        let mut conn = self.conn()?;
+
+        let query = String::from("UPDATE totp SET last_step = ? WHERE user_id = ? AND last_step < ?;");
+        if let Err(err) = conn.exec_drop(&query, (step, user_id, step)) {
+            return Err(Error::MySqlQueryError{ query, err });
+        }
+        Ok(conn.affected_rows() > 0)
+    }
+
+    fn delete_totp(&self, user_id: u32) -> Result<(), Error> {
+        let mut conn = self.conn()?;
+
+        for query in [ "DELETE FROM totp WHERE user_id = ?;", "DELETE FROM recovery_codes WHERE user_id = ?;" ] {
+            if let Err(err) = conn.exec_drop(query, (user_id,)) {
+                return Err(Error::MySqlQueryError{ query: query.to_string(), err });
+            }
+        }
+        Ok(())
+    }
+
+    fn get_recovery_codes(&self, user_id: u32) -> Result<Vec<String>, Error> {
+        let mut conn = self.conn()?;
+
+        let query = String::from("SELECT hash FROM recovery_codes WHERE user_id = ?;");
+        match conn.exec(&query, (user_id,)) {
+            Ok(codes) => Ok(codes),
+            Err(err)  => Err(Error::MySqlQueryError{ query, err }),
+        }
+    }
+
+    fn use_recovery_code(&self, user_id: u32, hash: &str) -> Result<bool, Error> {
+        let mut conn = self.conn()?;
+
+        let query = String::from("DELETE FROM recovery_codes WHERE user_id = ? AND hash = ?;");
+        if let Err(err) = conn.exec_drop(&query, (user_id, hash)) {
+            return Err(Error::MySqlQueryError{ query, err });
+        }
+        Ok(conn.affected_rows() > 0)
+    }
+}
+
+impl AuditStore for MySqlStore {
+    fn insert_event(&self, event: &AuthEvent) -> Result<(), Error> {
+        let mut conn = self.conn()?;
+
+        let query = String::from("INSERT INTO auth_events (time, kind, user_id, username, ip, user_agent, detail) VALUES (?, ?, ?, ?, ?, ?, ?);");
+        match conn.exec_drop(&query, (event.time.unix_timestamp(), event.kind.name(), event.user_id, &event.username, &event.ip, &event.user_agent, &event.detail)) {
+            Ok(_)    => Ok(()),
+            Err(err) => Err(Error::MySqlQueryError{ query, err }),
+        }
+    }
+
+    fn query_events(&self, filter: &EventFilter) -> Result<Vec<AuthEvent>, Error> {
+        let mut conn = self.conn()?;
+
+        // Parameters that are NULL match everything
+        let query = String::from("SELECT id, time, kind, user_id, username, ip, user_agent, detail FROM auth_events WHERE (:username IS NULL OR username = :username OR user_id = (SELECT id FROM users WHERE name_key = :username_key)) AND (:kind IS NULL OR kind = :kind) AND (:since IS NULL OR time >= :since) AND (:until IS NULL OR time < :until) ORDER BY id DESC LIMIT :limit OFFSET :offset;");
+        match conn.exec::<EventRow, _, _>(&query, params! {
+            "username"     => &filter.username,
+            "username_key" => filter.username.as_ref().map(username_key),
+            "kind"         => filter.kind.map(|kind| kind.name()),
+            "since"        => filter.since.map(|since| since.unix_timestamp()),
+            "until"        => filter.until.map(|until| until.unix_timestamp()),
+            "limit"        => filter.limit,
+            "offset"       => filter.offset,
+        }) {
+            Ok(rows) => rows.into_iter().map(to_event).collect(),
+            Err(err) => Err(Error::MySqlQueryError{ query, err }),
+        }
+    }
+}
+
+impl HealthStore for MySqlStore {
+    fn check_ready(&self) -> Result<(), Error> {
+        let mut conn = self.conn()?;
+
+        let query = String::from("SELECT 1 FROM users LIMIT 1;");
+        match conn.query_drop(&query) {
+            Ok(_)    => Ok(()),
+            Err(err) => Err(Error::MySqlQueryError{ query, err }),
+        }
+    }
+
+    #[inline]
+    fn pool_stats(&self) -> Option<PoolStats> {
+        Some(PoolStats {
+            max    : POOL_MAX_CONNECTIONS,
+            in_use : self.in_use.load(Ordering::Relaxed),
+        })
+    }
+}
+
+impl MigrationStore for MySqlStore {
+    #[inline]
+    fn migrations(&self) -> &'static [Migration] { &MIGRATIONS }
+
+    fn applied_migrations(&self) -> Result<Vec<AppliedMigration>, Error> {
+        let mut conn = self.conn()?;
+
+        let query = String::from("SELECT version, name, applied_at FROM schema_migrations;");
+        match conn.query::<(u32, String, i64), _>(&query) {
+            Ok(rows) => Ok(rows.into_iter().map(|(version, name, applied_at)| AppliedMigration{ version, name, applied_at: time_from_row(applied_at) }).collect()),
+            Err(err) => Err(Error::MySqlQueryError{ query, err }),
+        }
+    }
+
+    fn apply_migration(&self, migration: &Migration) -> Result<(), Error> {
+        let mut conn = self.conn()?;
+
+        // MySQL commits schema changes implicitly, so we cannot do this in a transaction; if a statement fails, the ones before it remain applied
+        for query in migration.up {
+            if let Err(err) = conn.query_drop(query) { return Err(Error::MySqlQueryError{ query: query.to_string(), err }); }
+        }
+        let query = String::from("INSERT INTO schema_migrations (version, name, applied_at) VALUES (:version, :name, :applied_at);");
+        match conn.exec_drop(&query, params! { "version" => migration.version, "name" => migration.name, "applied_at" => OffsetDateTime::now_utc().unix_timestamp() }) {
+            Ok(_)    => Ok(()),
+            Err(err) => Err(Error::MySqlQueryError{ query, err }),
+        }
+    }
+
+    fn revert_migration(&self, migration: &Migration) -> Result<(), Error> {
+        let mut conn = self.conn()?;
+
+        for query in migration.down {
+            if let Err(err) = conn.query_drop(query) { return Err(Error::MySqlQueryError{ query: query.to_string(), err }); }
+        }
+        let query = String::from("DELETE FROM schema_migrations WHERE version = :version;");
+        match conn.exec_drop(&query, params! { "version" => migration.version }) {
+            Ok(_)    => Ok(()),
+            Err(err) => Err(Error::MySqlQueryError{ query, err }),
+        }
+    }
+}
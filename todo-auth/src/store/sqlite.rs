@@ -0,0 +1,925 @@
+/* SQLITE.rs
+ *   by Lut99
+ *
+ * Created:
+ *   18 Oct 2026, 03:44:51
+ * Last edited:
+ *   18 Oct 2026, 06:24:13
+ * Auto updated?
+ *   Yes
+ *
+ * Description:
+ *   Implements an AccountStore that is backed by a local SQLite database
+ *   file.
+**/
+
+use std::path::Path;
+use std::sync::{Mutex, MutexGuard, TryLockError};
+
+use log::{debug, info};
+use rusqlite::{params, Connection, ErrorCode, OptionalExtension, Row};
+use time::OffsetDateTime;
+
+use todo_spec::credentials::{username_key, Credential};
+use todo_spec::jwt::Role;
+
+use super::migrations::{AppliedMigration, Migration};
+use super::{access_token_from_row, account_from_row, event_from_row, scopes_to_row, time_from_row, AccessTokenStore, AccountStore, AuditStore, Error, HealthStore, MigrationStore, RefreshTokenStore, ResetTokenStore, SecondFactorStore, SessionStore};
+use crate::spec::{AccessToken, Account, AuthEvent, EventFilter, PoolStats, RefreshToken, ResetToken, Session, TotpSecret};
+
+
+/***** CONSTANTS *****/
+/// The table in which we record which migrations have been applied. It is created outside of the migrations themselves.
+const MIGRATIONS_TABLE: &str = r"CREATE TABLE IF NOT EXISTS schema_migrations (
+    version INTEGER PRIMARY KEY,
+    name TEXT NOT NULL,
+    applied_at INTEGER NOT NULL
+);";
+
+/// The migrations of the database, in order. Never change a migration that has been released; add a new one instead.
+const MIGRATIONS: [Migration; 10] = [
+    // Databases from before we had migrations already have this table, so don't fail on those
+    Migration {
+        version : 1,
+        name    : "create_users",
+        up      : &[ r"CREATE TABLE IF NOT EXISTS users (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            name TEXT NOT NULL UNIQUE,
+            pass TEXT NOT NULL
+        );" ],
+        down    : &[ "DROP TABLE users;" ],
+    },
+    Migration {
+        version : 2,
+        name    : "create_refresh_tokens",
+        up      : &[
+            r"CREATE TABLE refresh_tokens (
+                hash TEXT PRIMARY KEY,
+                family TEXT NOT NULL,
+                user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+                expires INTEGER NOT NULL,
+                used INTEGER NOT NULL DEFAULT 0,
+                revoked INTEGER NOT NULL DEFAULT 0
+            );",
+            "CREATE INDEX refresh_tokens_family ON refresh_tokens (family);",
+        ],
+        down    : &[ "DROP TABLE refresh_tokens;" ],
+    },
+    Migration {
+        version : 3,
+        name    : "add_login_failures",
+        up      : &[
+            "ALTER TABLE users ADD COLUMN failed_logins INTEGER NOT NULL DEFAULT 0;",
+            "ALTER TABLE users ADD COLUMN locked_until INTEGER;",
+        ],
+        down    : &[
+            "ALTER TABLE users DROP COLUMN locked_until;",
+            "ALTER TABLE users DROP COLUMN failed_logins;",
+        ],
+    },
+    Migration {
+        version : 4,
+        name    : "create_totp",
+        up      : &[
+            r"CREATE TABLE totp (
+                user_id INTEGER PRIMARY KEY REFERENCES users(id) ON DELETE CASCADE,
+                secret TEXT NOT NULL,
+                confirmed INTEGER NOT NULL DEFAULT 0,
+                last_step INTEGER NOT NULL DEFAULT 0
+            );",
+            r"CREATE TABLE recovery_codes (
+                user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+                hash TEXT NOT NULL,
+                PRIMARY KEY (user_id, hash)
+            );",
+        ],
+        down    : &[ "DROP TABLE recovery_codes;", "DROP TABLE totp;" ],
+    },
+    Migration {
+        version : 5,
+        name    : "add_roles",
+        up      : &[
+            "ALTER TABLE users ADD COLUMN role TEXT NOT NULL DEFAULT 'user';",
+            "ALTER TABLE users ADD COLUMN disabled INTEGER NOT NULL DEFAULT 0;",
+        ],
+        down    : &[
+            "ALTER TABLE users DROP COLUMN disabled;",
+            "ALTER TABLE users DROP COLUMN role;",
+        ],
+    },
+    Migration {
+        version : 6,
+        name    : "create_auth_events",
+        up      : &[
+            r"CREATE TABLE auth_events (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                time INTEGER NOT NULL,
+                kind TEXT NOT NULL,
+                user_id INTEGER,
+                username TEXT,
+                ip TEXT,
+                user_agent TEXT,
+                detail TEXT
+            );",
+            "CREATE INDEX auth_events_username ON auth_events (username);",
+            "CREATE INDEX auth_events_time ON auth_events (time);",
+        ],
+        down    : &[ "DROP TABLE auth_events;" ],
+    },
+    Migration {
+        version : 7,
+        name    : "create_access_tokens",
+        up      : &[ r"CREATE TABLE access_tokens (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+            name TEXT NOT NULL,
+            hash TEXT NOT NULL,
+            scopes TEXT NOT NULL,
+            created INTEGER NOT NULL,
+            expires INTEGER,
+            last_used INTEGER,
+            UNIQUE (user_id, name)
+        );" ],
+        down    : &[ "DROP TABLE access_tokens;" ],
+    },
+    Migration {
+        version : 8,
+        name    : "create_sessions",
+        up      : &[ r"CREATE TABLE sessions (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+            family TEXT NOT NULL UNIQUE,
+            device TEXT,
+            ip TEXT,
+            user_agent TEXT,
+            created INTEGER NOT NULL,
+            last_seen INTEGER NOT NULL
+        );" ],
+        down    : &[ "DROP TABLE sessions;" ],
+    },
+    Migration {
+        version : 9,
+        name    : "create_reset_tokens",
+        up      : &[ r"CREATE TABLE reset_tokens (
+            hash TEXT PRIMARY KEY,
+            user_id INTEGER NOT NULL UNIQUE REFERENCES users(id) ON DELETE CASCADE,
+            expires INTEGER NOT NULL
+        );" ],
+        down    : &[ "DROP TABLE reset_tokens;" ],
+    },
+    // Names used to be ASCII-only, so lower() folds them just like `username_key()` does. Names that only differ in case make this fail; rename them first.
+    Migration {
+        version : 10,
+        name    : "add_name_keys",
+        up      : &[
+            "ALTER TABLE users ADD COLUMN name_key TEXT;",
+            "UPDATE users SET name_key = lower(name);",
+            "CREATE UNIQUE INDEX users_name_key ON users(name_key);",
+            "ALTER TABLE users ADD COLUMN display_name TEXT;",
+        ],
+        down    : &[
+            "ALTER TABLE users DROP COLUMN display_name;",
+            "DROP INDEX users_name_key;",
+            "ALTER TABLE users DROP COLUMN name_key;",
+        ],
+    },
+];
+
+/// The columns to select for an account, in the order expected by `read_account()`.
+const ACCOUNT_COLUMNS: &str = "id, name, pass, display_name, role, disabled, failed_logins, locked_until";
+
+
+
+
+
+/***** HELPER FUNCTIONS *****/
+/// The raw columns of an account, as selected by `ACCOUNT_COLUMNS`.
+type AccountRow = (u32, String, String, Option<String>, String, bool, u32, Option<i64>);
+
+/// Reads the columns of an account from the given row.
+#[inline]
+fn read_account(row: &Row) -> Result<AccountRow, rusqlite::Error> {
+    Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?, row.get(5)?, row.get(6)?, row.get(7)?))
+}
+
+/// Converts the raw columns of an account into an Account.
+#[inline]
+fn to_account((id, name, pass, display_name, role, disabled, failed_logins, locked_until): AccountRow) -> Result<Account, Error> {
+    account_from_row(id, name, pass, display_name, &role, disabled, failed_logins, locked_until)
+}
+
+
+
+/// The columns to select for a personal access token, in the order expected by `read_access_token()`.
+const ACCESS_TOKEN_COLUMNS: &str = "id, user_id, name, hash, scopes, created, expires, last_used";
+
+/// The raw columns of a personal access token, as selected by `ACCESS_TOKEN_COLUMNS`.
+type AccessTokenRow = (u32, u32, String, String, String, i64, Option<i64>, Option<i64>);
+
+/// Reads the columns of a personal access token from the given row.
+#[inline]
+fn read_access_token(row: &Row) -> Result<AccessTokenRow, rusqlite::Error> {
+    Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?, row.get(5)?, row.get(6)?, row.get(7)?))
+}
+
+/// Converts the raw columns of a personal access token into an AccessToken.
+#[inline]
+fn to_access_token((id, user_id, name, hash, scopes, created, expires, last_used): AccessTokenRow) -> Result<AccessToken, Error> {
+    access_token_from_row(id, user_id, name, hash, &scopes, created, expires, last_used)
+}
+
+
+/// The columns to select for a session, in the order expected by `read_session()`.
+const SESSION_COLUMNS: &str = "id, user_id, family, device, ip, user_agent, created, last_seen";
+
+/// The raw columns of a session, as selected by `SESSION_COLUMNS`.
+type SessionRow = (u32, u32, String, Option<String>, Option<String>, Option<String>, i64, i64);
+
+/// Reads the columns of a session from the given row.
+#[inline]
+fn read_session(row: &Row) -> Result<SessionRow, rusqlite::Error> {
+    Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?, row.get(5)?, row.get(6)?, row.get(7)?))
+}
+
+/// Converts the raw columns of a session into a Session.
+#[inline]
+fn to_session((id, user_id, family, device, ip, user_agent, created, last_seen): SessionRow) -> Session {
+    Session{ id, user_id, family, device, ip, user_agent, created: time_from_row(created), last_seen: time_from_row(last_seen) }
+}
+
+
+
+/// The raw columns of an audit event, in the order they are selected by `query_events()`.
+type EventRow = (u64, i64, String, Option<u32>, Option<String>, Option<String>, Option<String>, Option<String>);
+
+/// Reads the columns of an audit event from the given row.
+#[inline]
+fn read_event(row: &Row) -> Result<EventRow, rusqlite::Error> {
+    Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?, row.get(5)?, row.get(6)?, row.get(7)?))
+}
+
+/// Converts the raw columns of an audit event into an AuthEvent.
+#[inline]
+fn to_event((id, time, kind, user_id, username, ip, user_agent, detail): EventRow) -> Result<AuthEvent, Error> {
+    event_from_row(id, time, &kind, user_id, username, ip, user_agent, detail)
+}
+
+
+
+
+
+/***** LIBRARY STRUCTS *****/
+/// Implements an AccountStore that uses a SQLite database file.
+pub struct SqliteStore {
+    /// The connection to the database. SQLite connections cannot be shared between threads, so we lock it.
+    conn : Mutex<Connection>,
+}
+
+impl SqliteStore {
+    /// Constructor for the SqliteStore, which opens (or creates) the given database file and makes sure the desired database structure is present.
+    /// 
+    /// **Generic types**
+    ///  * `P`: The Path-like type of the path that is passed to the function.
+    /// 
+    /// **Arguments**
+    ///  * `path`: The location of the SQLite database file.
+    /// 
+    /// **Returns**  
+    /// The new SqliteStore on success, or else an Error.
+    pub fn new<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let path: &Path = path.as_ref();
+
+        // Open the database
+        info!("Preparig database...");
+        debug!("Opening SQLite database '{}'...", path.display());
+        let conn = match Connection::open(path) {
+            Ok(conn) => conn,
+            Err(err) => { return Err(Error::SqliteOpenError{ path: path.to_path_buf(), err }); }
+        };
+
+        // SQLite does not enforce foreign keys by default
+        let query = String::from("PRAGMA foreign_keys = ON;");
+        if let Err(err) = conn.execute_batch(&query) {
+            return Err(Error::SqliteQueryError{ query, err });
+        }
+
+        // Create the table that tracks the migrations; the migrations themselves create the rest
+        debug!("Creating 'schema_migrations' table if necessary...");
+        if let Err(err) = conn.execute_batch(MIGRATIONS_TABLE) {
+            return Err(Error::SqliteQueryError{ query: MIGRATIONS_TABLE.to_string(), err });
+        }
+
+        // Done
+        debug!("Database preparation complete.");
+        Ok(Self {
+            conn : Mutex::new(conn),
+        })
+    }
+
+
+
+    /// Locks the internal connection.
+    #[inline]
+    fn lock(&self) -> MutexGuard<'_, Connection> {
+        self.conn.lock().expect("SqliteStore lock is poisoned; this should never happen!")
+    }
+}
+
+impl AccountStore for SqliteStore {
+    fn get_by_name(&self, name: &str) -> Result<Option<Account>, Error> {
+        let conn = self.lock();
+
+        let query = format!("SELECT {} FROM users WHERE name_key = ?1;", ACCOUNT_COLUMNS);
+        match conn.query_row(&query, params![username_key(name)], read_account).optional() {
+            Ok(Some(row)) => Ok(Some(to_account(row)?)),
+            Ok(None)      => Ok(None),
+            Err(err)      => Err(Error::SqliteQueryError{ query, err }),
+        }
+    }
+
+    fn get_by_id(&self, id: u32) -> Result<Option<Account>, Error> {
+        let conn = self.lock();
+
+        let query = format!("SELECT {} FROM users WHERE id = ?1;", ACCOUNT_COLUMNS);
+        match conn.query_row(&query, params![id], read_account).optional() {
+            Ok(Some(row)) => Ok(Some(to_account(row)?)),
+            Ok(None)      => Ok(None),
+            Err(err)      => Err(Error::SqliteQueryError{ query, err }),
+        }
+    }
+
+    fn list(&self) -> Result<Vec<Account>, Error> {
+        let conn = self.lock();
+
+        let query = format!("SELECT {} FROM users ORDER BY id;", ACCOUNT_COLUMNS);
+        let mut stmt = match conn.prepare(&query) {
+            Ok(stmt) => stmt,
+            Err(err) => { return Err(Error::SqliteQueryError{ query, err }); }
+        };
+        let rows: Result<Vec<AccountRow>, rusqlite::Error> = match stmt.query_map([], read_account) {
+            Ok(rows) => rows.collect(),
+            Err(err) => Err(err),
+        };
+        match rows {
+            Ok(rows) => rows.into_iter().map(to_account).collect(),
+            Err(err) => Err(Error::SqliteQueryError{ query, err }),
+        }
+    }
+
+    fn insert(&self, credential: &Credential, role: Role) -> Result<u32, Error> {
+        let conn = self.lock();
+
+        let query = String::from("INSERT INTO users (name, name_key, pass, role) VALUES (?1, ?2, ?3, ?4);");
+        match conn.execute(&query, params![credential.user(), username_key(credential.user()), credential.pass(), role.name()]) {
+            Ok(_) => Ok(conn.last_insert_rowid() as u32),
+            Err(rusqlite::Error::SqliteFailure(rusqlite::ffi::Error{ code: ErrorCode::ConstraintViolation, .. }, _)) => Err(Error::DuplicateUser{ username: credential.user().to_string() }),
+            Err(err) => Err(Error::SqliteQueryError{ query, err }),
+        }
+    }
+
+    fn update_password(&self, id: u32, hash: &str) -> Result<(), Error> {
+        let conn = self.lock();
+
+        let query = String::from("UPDATE users SET pass = ?1 WHERE id = ?2;");
+        match conn.execute(&query, params![hash, id]) {
+            Ok(0)    => Err(Error::UnknownUser{ id }),
+            Ok(_)    => Ok(()),
+            Err(err) => Err(Error::SqliteQueryError{ query, err }),
+        }
+    }
+
+    fn set_role(&self, id: u32, role: Role) -> Result<(), Error> {
+        let conn = self.lock();
+
+        let query = String::from("UPDATE users SET role = ?1 WHERE id = ?2;");
+        match conn.execute(&query, params![role.name(), id]) {
+            Ok(0)    => Err(Error::UnknownUser{ id }),
+            Ok(_)    => Ok(()),
+            Err(err) => Err(Error::SqliteQueryError{ query, err }),
+        }
+    }
+
+    fn rename(&self, id: u32, name: &str) -> Result<(), Error> {
+        let conn = self.lock();
+
+        let query = String::from("UPDATE users SET name = ?1, name_key = ?2 WHERE id = ?3;");
+        match conn.execute(&query, params![name, username_key(name), id]) {
+            Ok(0)    => Err(Error::UnknownUser{ id }),
+            Ok(_)    => Ok(()),
+            Err(rusqlite::Error::SqliteFailure(rusqlite::ffi::Error{ code: ErrorCode::ConstraintViolation, .. }, _)) => Err(Error::DuplicateUser{ username: name.to_string() }),
+            Err(err) => Err(Error::SqliteQueryError{ query, err }),
+        }
+    }
+
+    fn set_display_name(&self, id: u32, display_name: Option<&str>) -> Result<(), Error> {
+        let conn = self.lock();
+
+        let query = String::from("UPDATE users SET display_name = ?1 WHERE id = ?2;");
+        match conn.execute(&query, params![display_name, id]) {
+            Ok(0)    => Err(Error::UnknownUser{ id }),
+            Ok(_)    => Ok(()),
+            Err(err) => Err(Error::SqliteQueryError{ query, err }),
+        }
+    }
+
+    fn set_disabled(&self, id: u32, disabled: bool) -> Result<(), Error> {
+        let conn = self.lock();
+
+        let query = String::from("UPDATE users SET disabled = ?1 WHERE id = ?2;");
+        match conn.execute(&query, params![disabled, id]) {
+            Ok(0)    => Err(Error::UnknownUser{ id }),
+            Ok(_)    => Ok(()),
+            Err(err) => Err(Error::SqliteQueryError{ query, err }),
+        }
+    }
+
+    fn record_login_failure(&self, id: u32) -> Result<u32, Error> {
+        let conn = self.lock();
+
+        let query = String::from("UPDATE users SET failed_logins = failed_logins + 1 WHERE id = ?1 RETURNING failed_logins;");
+        match conn.query_row(&query, params![id], |row| row.get(0)).optional() {
+            Ok(Some(failed_logins)) => Ok(failed_logins),
+            Ok(None)                => Err(Error::UnknownUser{ id }),
+            Err(err)                => Err(Error::SqliteQueryError{ query, err }),
+        }
+    }
+
+    fn lock_account(&self, id: u32, until: OffsetDateTime) -> Result<(), Error> {
+        let conn = self.lock();
+
+        let query = String::from("UPDATE users SET locked_until = ?1 WHERE id = ?2;");
+        match conn.execute(&query, params![until.unix_timestamp(), id]) {
+            Ok(0)    => Err(Error::UnknownUser{ id }),
+            Ok(_)    => Ok(()),
+            Err(err) => Err(Error::SqliteQueryError{ query, err }),
+        }
+    }
+
+    fn reset_login_failures(&self, id: u32) -> Result<(), Error> {
+        let conn = self.lock();
+
+        let query = String::from("UPDATE users SET failed_logins = 0, locked_until = NULL WHERE id = ?1;");
+        match conn.execute(&query, params![id]) {
+            Ok(0)    => Err(Error::UnknownUser{ id }),
+            Ok(_)    => Ok(()),
+            Err(err) => Err(Error::SqliteQueryError{ query, err }),
+        }
+    }
+
+    fn delete(&self, id: u32) -> Result<(), Error> {
+        let conn = self.lock();
+
+        let query = String::from("DELETE FROM users WHERE id = ?1;");
+        match conn.execute(&query, params![id]) {
+            Ok(0)    => Err(Error::UnknownUser{ id }),
+            Ok(_)    => Ok(()),
+            Err(err) => Err(Error::SqliteQueryError{ query, err }),
+        }
+    }
+}
+
+impl RefreshTokenStore for SqliteStore {
+    fn insert_refresh_token(&self, token: &RefreshToken) -> Result<(), Error> {
+        let conn = self.lock();
+
+        let query = String::from("INSERT INTO refresh_tokens (hash, family, user_id, expires, used, revoked) VALUES (?1, ?2, ?3, ?4, ?5, ?6);");
+        match conn.execute(&query, params![token.hash, token.family, token.user_id, token.expires.unix_timestamp(), token.used, token.revoked]) {
+            Ok(_)    => Ok(()),
+            Err(err) => Err(Error::SqliteQueryError{ query, err }),
+        }
+    }
+
+    fn get_refresh_token(&self, hash: &str) -> Result<Option<RefreshToken>, Error> {
+        let conn = self.lock();
+
+        let query = String::from("SELECT hash, family, user_id, expires, used, revoked FROM refresh_tokens WHERE hash = ?1;");
+        match conn.query_row(&query, params![hash], |row| Ok(RefreshToken{ hash: row.get(0)?, family: row.get(1)?, user_id: row.get(2)?, expires: time_from_row(row.get(3)?), used: row.get(4)?, revoked: row.get(5)? })).optional() {
+            Ok(token) => Ok(token),
+            Err(err)  => Err(Error::SqliteQueryError{ query, err }),
+        }
+    }
+
+    fn use_refresh_token(&self, hash: &str) -> Result<bool, Error> {
+        let conn = self.lock();
+
+        let query = String::from("UPDATE refresh_tokens SET used = 1 WHERE hash = ?1 AND used = 0;");
+        match conn.execute(&query, params![hash]) {
+            Ok(n)    => Ok(n > 0),
+            Err(err) => Err(Error::SqliteQueryError{ query, err }),
+        }
+    }
+
+    fn revoke_refresh_family(&self, family: &str) -> Result<(), Error> {
+        let conn = self.lock();
+
+        let query = String::from("UPDATE refresh_tokens SET revoked = 1 WHERE family = ?1;");
+        match conn.execute(&query, params![family]) {
+            Ok(_)    => Ok(()),
+            Err(err) => Err(Error::SqliteQueryError{ query, err }),
+        }
+    }
+
+    fn revoke_user_refresh_tokens(&self, user_id: u32) -> Result<(), Error> {
+        let conn = self.lock();
+
+        let query = String::from("UPDATE refresh_tokens SET revoked = 1 WHERE user_id = ?1;");
+        match conn.execute(&query, params![user_id]) {
+            Ok(_)    => Ok(()),
+            Err(err) => Err(Error::SqliteQueryError{ query, err }),
+        }
+    }
+}
+
+impl ResetTokenStore for SqliteStore {
+    fn insert_reset_token(&self, token: &ResetToken) -> Result<(), Error> {
+        let conn = self.lock();
+
+        // Every user has at most one token, so this replaces any older one
+        let query = String::from("INSERT OR REPLACE INTO reset_tokens (hash, user_id, expires) VALUES (?1, ?2, ?3);");
+        match conn.execute(&query, params![token.hash, token.user_id, token.expires.unix_timestamp()]) {
+            Ok(_)    => Ok(()),
+            Err(err) => Err(Error::SqliteQueryError{ query, err }),
+        }
+    }
+
+    fn get_reset_token(&self, hash: &str) -> Result<Option<ResetToken>, Error> {
+        let conn = self.lock();
+
+        let query = String::from("SELECT hash, user_id, expires FROM reset_tokens WHERE hash = ?1;");
+        match conn.query_row(&query, params![hash], |row| Ok(ResetToken{ hash: row.get(0)?, user_id: row.get(1)?, expires: time_from_row(row.get(2)?) })).optional() {
+            Ok(token) => Ok(token),
+            Err(err)  => Err(Error::SqliteQueryError{ query, err }),
+        }
+    }
+
+    fn delete_reset_token(&self, hash: &str) -> Result<bool, Error> {
+        let conn = self.lock();
+
+        let query = String::from("DELETE FROM reset_tokens WHERE hash = ?1;");
+        match conn.execute(&query, params![hash]) {
+            Ok(n)    => Ok(n > 0),
+            Err(err) => Err(Error::SqliteQueryError{ query, err }),
+        }
+    }
+}
+
+impl SessionStore for SqliteStore {
+    fn insert_session(&self, session: &Session) -> Result<u32, Error> {
+        let conn = self.lock();
+
+        let query = String::from("INSERT INTO sessions (user_id, family, device, ip, user_agent, created, last_seen) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7);");
+        match conn.execute(&query, params![session.user_id, session.family, session.device, session.ip, session.user_agent, session.created.unix_timestamp(), session.last_seen.unix_timestamp()]) {
+            Ok(_) => Ok(conn.last_insert_rowid() as u32),
+            Err(rusqlite::Error::SqliteFailure(rusqlite::ffi::Error{ extended_code: rusqlite::ffi::SQLITE_CONSTRAINT_FOREIGNKEY, .. }, _)) => Err(Error::UnknownUser{ id: session.user_id }),
+            Err(err) => Err(Error::SqliteQueryError{ query, err }),
+        }
+    }
+
+    fn get_session(&self, id: u32) -> Result<Option<Session>, Error> {
+        let conn = self.lock();
+
+        let query = format!("SELECT {} FROM sessions WHERE id = ?1;", SESSION_COLUMNS);
+        match conn.query_row(&query, params![id], read_session).optional() {
+            Ok(row)  => Ok(row.map(to_session)),
+            Err(err) => Err(Error::SqliteQueryError{ query, err }),
+        }
+    }
+
+    fn get_session_by_family(&self, family: &str) -> Result<Option<Session>, Error> {
+        let conn = self.lock();
+
+        let query = format!("SELECT {} FROM sessions WHERE family = ?1;", SESSION_COLUMNS);
+        match conn.query_row(&query, params![family], read_session).optional() {
+            Ok(row)  => Ok(row.map(to_session)),
+            Err(err) => Err(Error::SqliteQueryError{ query, err }),
+        }
+    }
+
+    fn list_sessions(&self, user_id: u32) -> Result<Vec<Session>, Error> {
+        let conn = self.lock();
+
+        let query = format!("SELECT {} FROM sessions WHERE user_id = ?1 ORDER BY id;", SESSION_COLUMNS);
+        let mut stmt = match conn.prepare(&query) {
+            Ok(stmt) => stmt,
+            Err(err) => { return Err(Error::SqliteQueryError{ query, err }); }
+        };
+        let rows: Result<Vec<SessionRow>, rusqlite::Error> = match stmt.query_map(params![user_id], read_session) {
+            Ok(rows) => rows.collect(),
+            Err(err) => Err(err),
+        };
+        match rows {
+            Ok(rows) => Ok(rows.into_iter().map(to_session).collect()),
+            Err(err) => Err(Error::SqliteQueryError{ query, err }),
+        }
+    }
+
+    fn touch_session(&self, id: u32, when: OffsetDateTime) -> Result<(), Error> {
+        let conn = self.lock();
+
+        let query = String::from("UPDATE sessions SET last_seen = ?1 WHERE id = ?2;");
+        match conn.execute(&query, params![when.unix_timestamp(), id]) {
+            Ok(_)    => Ok(()),
+            Err(err) => Err(Error::SqliteQueryError{ query, err }),
+        }
+    }
+
+    fn delete_session(&self, id: u32) -> Result<bool, Error> {
+        let conn = self.lock();
+
+        let query = String::from("DELETE FROM sessions WHERE id = ?1;");
+        match conn.execute(&query, params![id]) {
+            Ok(n)    => Ok(n > 0),
+            Err(err) => Err(Error::SqliteQueryError{ query, err }),
+        }
+    }
+
+    fn delete_user_sessions(&self, user_id: u32) -> Result<(), Error> {
+        let conn = self.lock();
+
+        let query = String::from("DELETE FROM sessions WHERE user_id = ?1;");
+        match conn.execute(&query, params![user_id]) {
+            Ok(_)    => Ok(()),
+            Err(err) => Err(Error::SqliteQueryError{ query, err }),
+        }
+    }
+}
+
+impl AccessTokenStore for SqliteStore {
+    fn insert_access_token(&self, token: &AccessToken) -> Result<u32, Error> {
+        let conn = self.lock();
+
+        let query = String::from("INSERT INTO access_tokens (user_id, name, hash, scopes, created, expires, last_used) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7);");
+        match conn.execute(&query, params![token.user_id, token.name, token.hash, scopes_to_row(&token.scopes), token.created.unix_timestamp(), token.expires.map(|expires| expires.unix_timestamp()), token.last_used.map(|last_used| last_used.unix_timestamp())]) {
+            Ok(_) => Ok(conn.last_insert_rowid() as u32),
+            Err(rusqlite::Error::SqliteFailure(rusqlite::ffi::Error{ extended_code: rusqlite::ffi::SQLITE_CONSTRAINT_FOREIGNKEY, .. }, _)) => Err(Error::UnknownUser{ id: token.user_id }),
+            Err(rusqlite::Error::SqliteFailure(rusqlite::ffi::Error{ code: ErrorCode::ConstraintViolation, .. }, _)) => Err(Error::DuplicateAccessToken{ name: token.name.clone() }),
+            Err(err) => Err(Error::SqliteQueryError{ query, err }),
+        }
+    }
+
+    fn get_access_token(&self, id: u32) -> Result<Option<AccessToken>, Error> {
+        let conn = self.lock();
+
+        let query = format!("SELECT {} FROM access_tokens WHERE id = ?1;", ACCESS_TOKEN_COLUMNS);
+        match conn.query_row(&query, params![id], read_access_token).optional() {
+            Ok(Some(row)) => Ok(Some(to_access_token(row)?)),
+            Ok(None)      => Ok(None),
+            Err(err)      => Err(Error::SqliteQueryError{ query, err }),
+        }
+    }
+
+    fn list_access_tokens(&self, user_id: u32) -> Result<Vec<AccessToken>, Error> {
+        let conn = self.lock();
+
+        let query = format!("SELECT {} FROM access_tokens WHERE user_id = ?1 ORDER BY id;", ACCESS_TOKEN_COLUMNS);
+        let mut stmt = match conn.prepare(&query) {
+            Ok(stmt) => stmt,
+            Err(err) => { return Err(Error::SqliteQueryError{ query, err }); }
+        };
+        let rows: Result<Vec<AccessTokenRow>, rusqlite::Error> = match stmt.query_map(params![user_id], read_access_token) {
+            Ok(rows) => rows.collect(),
+            Err(err) => Err(err),
+        };
+        match rows {
+            Ok(rows) => rows.into_iter().map(to_access_token).collect(),
+            Err(err) => Err(Error::SqliteQueryError{ query, err }),
+        }
+    }
+
+    fn touch_access_token(&self, id: u32, when: OffsetDateTime) -> Result<(), Error> {
+        let conn = self.lock();
+
+        let query = String::from("UPDATE access_tokens SET last_used = ?1 WHERE id = ?2;");
+        match conn.execute(&query, params![when.unix_timestamp(), id]) {
+            Ok(_)    => Ok(()),
+            Err(err) => Err(Error::SqliteQueryError{ query, err }),
+        }
+    }
+
+    fn delete_access_token(&self, user_id: u32, name: &str) -> Result<bool, Error> {
+        let conn = self.lock();
+
+        let query = String::from("DELETE FROM access_tokens WHERE user_id = ?1 AND name = ?2;");
+        match conn.execute(&query, params![user_id, name]) {
+            Ok(n)    => Ok(n > 0),
+            Err(err) => Err(Error::SqliteQueryError{ query, err }),
+        }
+    }
+}
+
+impl SecondFactorStore for SqliteStore {
+    fn set_totp(&self, user_id: u32, secret: &str) -> Result<(), Error> {
+        let conn = self.lock();
+
+        let query = String::from("INSERT INTO totp (user_id, secret, confirmed, last_step) VALUES (?1, ?2, 0, 0) ON CONFLICT (user_id) DO UPDATE SET secret = excluded.secret, confirmed = 0, last_step = 0;");
+        match conn.execute(&query, params![user_id, secret]) {
+            Ok(_) => Ok(()),
+            Err(rusqlite::Error::SqliteFailure(rusqlite::ffi::Error{ code: ErrorCode::ConstraintViolation, .. }, _)) => Err(Error::UnknownUser{ id: user_id }),
+            Err(err) => Err(Error::SqliteQueryError{ query, err }),
+        }
+    }
+
+    fn get_totp(&self, user_id: u32) -> Result<Option<TotpSecret>, Error> {
+        let conn = self.lock();
+
+        let query = String::from("SELECT user_id, secret, confirmed, last_step FROM totp WHERE user_id = ?1;");
+        match conn.query_row(&query, params![user_id], |row| Ok(TotpSecret{ user_id: row.get(0)?, secret: row.get(1)?, confirmed: row.get(2)?, last_step: row.get(3)? })).optional() {
+            Ok(totp) => Ok(totp),
+            Err(err) => Err(Error::SqliteQueryError{ query, err }),
+        }
+    }
+
+    fn confirm_totp(&self, user_id: u32, recovery_codes: &[String]) -> Result<(), Error> {
+        let mut conn = self.lock();
+
+        // Confirm the secret and swap the recovery codes in one go
+        let tx = match conn.transaction() {
+            Ok(tx)   => tx,
+            Err(err) => { return Err(Error::SqliteQueryError{ query: String::from("BEGIN;"), err }); }
+        };
+        let query = String::from("UPDATE totp SET confirmed = 1 WHERE user_id = ?1;");
+        match tx.execute(&query, params![user_id]) {
+            Ok(0)    => { return Err(Error::UnknownUser{ id: user_id }); },
+            Ok(_)    => {},
+            Err(err) => { return Err(Error::SqliteQueryError{ query, err }); }
+        }
+        let query = String::from("DELETE FROM recovery_codes WHERE user_id = ?1;");
+        if let Err(err) = tx.execute(&query, params![user_id]) {
+            return Err(Error::SqliteQueryError{ query, err });
+        }
+        let query = String::from("INSERT INTO recovery_codes (user_id, hash) VALUES (?1, ?2);");
+        for hash in recovery_codes {
+            if let Err(err) = tx.execute(&query, params![user_id, hash]) {
+                return Err(Error::SqliteQueryError{ query, err });
+            }
+        }
+        match tx.commit() {
+            Ok(_)    => Ok(()),
+            Err(err) => Err(Error::SqliteQueryError{ query: String::from("COMMIT;"), err }),
+        }
+    }
+
+    fn use_totp_step(&self, user_id: u32, step: u64) -> Result<bool, Error> {
+        let conn = self.lock();
+
+        let query = String::from("UPDATE totp SET last_step = ?1 WHERE user_id = ?2 AND last_step < ?1;");
+        match conn.execute(&query, params![step, user_id]) {
+            Ok(n)    => Ok(n > 0),
+            Err(err) => Err(Error::SqliteQueryError{ query, err }),
+        }
+    }
+
+    fn delete_totp(&self, user_id: u32) -> Result<(), Error> {
+        let conn = self.lock();
+
+        for query in [ "DELETE FROM totp WHERE user_id = ?1;", "DELETE FROM recovery_codes WHERE user_id = ?1;" ] {
+            if let Err(err) = conn.execute(query, params![user_id]) {
+                return Err(Error::SqliteQueryError{ query: query.to_string(), err });
+            }
+        }
+        Ok(())
+    }
+
+    fn get_recovery_codes(&self, user_id: u32) -> Result<Vec<String>, Error> {
+        let conn = self.lock();
+
+        let query = String::from("SELECT hash FROM recovery_codes WHERE user_id = ?1;");
+        let mut stmt = match conn.prepare(&query) {
+            Ok(stmt) => stmt,
+            Err(err) => { return Err(Error::SqliteQueryError{ query, err }); }
+        };
+        let codes: Result<Vec<String>, rusqlite::Error> = match stmt.query_map(params![user_id], |row| row.get(0)) {
+            Ok(rows) => rows.collect(),
+            Err(err) => Err(err),
+        };
+        match codes {
+            Ok(codes) => Ok(codes),
+            Err(err)  => Err(Error::SqliteQueryError{ query, err }),
+        }
+    }
+
+    fn use_recovery_code(&self, user_id: u32, hash: &str) -> Result<bool, Error> {
+        let conn = self.lock();
+
+        let query = String::from("DELETE FROM recovery_codes WHERE user_id = ?1 AND hash = ?2;");
+        match conn.execute(&query, params![user_id, hash]) {
+            Ok(n)    => Ok(n > 0),
+            Err(err) => Err(Error::SqliteQueryError{ query, err }),
+        }
+    }
+}
+
+impl AuditStore for SqliteStore {
+    fn insert_event(&self, event: &AuthEvent) -> Result<(), Error> {
+        let conn = self.lock();
+
+        let query = String::from("INSERT INTO auth_events (time, kind, user_id, username, ip, user_agent, detail) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7);");
+        match conn.execute(&query, params![event.time.unix_timestamp(), event.kind.name(), event.user_id, event.username, event.ip, event.user_agent, event.detail]) {
+            Ok(_)    => Ok(()),
+            Err(err) => Err(Error::SqliteQueryError{ query, err }),
+        }
+    }
+
+    fn query_events(&self, filter: &EventFilter) -> Result<Vec<AuthEvent>, Error> {
+        let conn = self.lock();
+
+        // Parameters that are NULL match everything
+        let query = String::from("SELECT id, time, kind, user_id, username, ip, user_agent, detail FROM auth_events WHERE (?1 IS NULL OR username = ?1 OR user_id = (SELECT id FROM users WHERE name_key = ?7)) AND (?2 IS NULL OR kind = ?2) AND (?3 IS NULL OR time >= ?3) AND (?4 IS NULL OR time < ?4) ORDER BY id DESC LIMIT ?5 OFFSET ?6;");
+        let mut stmt = match conn.prepare(&query) {
+            Ok(stmt) => stmt,
+            Err(err) => { return Err(Error::SqliteQueryError{ query, err }); }
+        };
+        let rows: Result<Vec<EventRow>, rusqlite::Error> = match stmt.query_map(params![filter.username, filter.kind.map(|kind| kind.name()), filter.since.map(|since| since.unix_timestamp()), filter.until.map(|until| until.unix_timestamp()), filter.limit, filter.offset, filter.username.as_ref().map(username_key)], read_event) {
+            Ok(rows) => rows.collect(),
+            Err(err) => Err(err),
+        };
+        match rows {
+            Ok(rows) => rows.into_iter().map(to_event).collect(),
+            Err(err) => Err(Error::SqliteQueryError{ query, err }),
+        }
+    }
+}
+
+impl HealthStore for SqliteStore {
+    fn check_ready(&self) -> Result<(), Error> {
+        let conn = self.lock();
+
+        let query = String::from("SELECT 1 FROM users LIMIT 1;");
+        match conn.query_row(&query, [], |_| Ok(())).optional() {
+            Ok(_)    => Ok(()),
+            Err(err) => Err(Error::SqliteQueryError{ query, err }),
+        }
+    }
+
+    fn pool_stats(&self) -> Option<PoolStats> {
+        // There is only the one connection, which is in use if somebody holds the lock
+        Some(PoolStats {
+            max    : 1,
+            in_use : if matches!(self.conn.try_lock(), Err(TryLockError::WouldBlock)) { 1 } else { 0 },
+        })
+    }
+}
+
+impl MigrationStore for SqliteStore {
+    #[inline]
+    fn migrations(&self) -> &'static [Migration] { &MIGRATIONS }
+
+    fn applied_migrations(&self) -> Result<Vec<AppliedMigration>, Error> {
+        let conn = self.lock();
+
+        let query = String::from("SELECT version, name, applied_at FROM schema_migrations;");
+        let mut stmt = match conn.prepare(&query) {
+            Ok(stmt) => stmt,
+            Err(err) => { return Err(Error::SqliteQueryError{ query, err }); }
+        };
+        let rows: Result<Vec<(u32, String, i64)>, rusqlite::Error> = match stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?))) {
+            Ok(rows) => rows.collect(),
+            Err(err) => Err(err),
+        };
+        match rows {
+            Ok(rows) => Ok(rows.into_iter().map(|(version, name, applied_at)| AppliedMigration{ version, name, applied_at: time_from_row(applied_at) }).collect()),
+            Err(err) => Err(Error::SqliteQueryError{ query, err }),
+        }
+    }
+
+    fn apply_migration(&self, migration: &Migration) -> Result<(), Error> {
+        let mut conn = self.lock();
+
+        // SQLite can change the schema in a transaction, so a migration is applied completely or not at all
+        let tx = match conn.transaction() {
+            Ok(tx)   => tx,
+            Err(err) => { return Err(Error::SqliteQueryError{ query: String::from("BEGIN TRANSACTION;"), err }); }
+        };
+        for query in migration.up {
+            if let Err(err) = tx.execute_batch(query) { return Err(Error::SqliteQueryError{ query: query.to_string(), err }); }
+        }
+        let query = String::from("INSERT INTO schema_migrations (version, name, applied_at) VALUES (?1, ?2, ?3);");
+        if let Err(err) = tx.execute(&query, params![migration.version, migration.name, OffsetDateTime::now_utc().unix_timestamp()]) {
+            return Err(Error::SqliteQueryError{ query, err });
+        }
+        match tx.commit() {
+            Ok(_)    => Ok(()),
+            Err(err) => Err(Error::SqliteQueryError{ query: String::from("COMMIT;"), err }),
+        }
+    }
+
+    fn revert_migration(&self, migration: &Migration) -> Result<(), Error> {
+        let mut conn = self.lock();
+
+        let tx = match conn.transaction() {
+            Ok(tx)   => tx,
+            Err(err) => { return Err(Error::SqliteQueryError{ query: String::from("BEGIN TRANSACTION;"), err }); }
+        };
+        for query in migration.down {
+            if let Err(err) = tx.execute_batch(query) { return Err(Error::SqliteQueryError{ query: query.to_string(), err }); }
+        }
+        let query = String::from("DELETE FROM schema_migrations WHERE version = ?1;");
+        if let Err(err) = tx.execute(&query, params![migration.version]) {
+            return Err(Error::SqliteQueryError{ query, err });
+        }
+        match tx.commit() {
+            Ok(_)    => Ok(()),
+            Err(err) => Err(Error::SqliteQueryError{ query: String::from("COMMIT;"), err }),
+        }
+    }
+}
@@ -0,0 +1,297 @@
+/* THROTTLE.rs
+ *   by Lut99
+ *
+ * Created:
+ *   18 Oct 2026, 03:46:55
+ * Last edited:
+ *   18 Oct 2026, 06:24:13
+ * Auto updated?
+ *   Yes
+ *
+ * Description:
+ *   Implements the brute-force protection of the login routes.
+ *
+ *   Failed logins are counted per IP address and per username. After a
+ *   few free attempts, every next attempt has to wait exponentially
+ *   longer. This is kept in memory only; the (persistent) account lockout
+ *   is kept in the store instead.
+**/
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use warp::Reply;
+use warp::http::StatusCode;
+use warp::reply::Response;
+
+use todo_spec::api::ErrorCode;
+use todo_spec::credentials::username_key;
+
+use crate::reply;
+
+
+/***** UNIT TESTS *****/
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns a policy with one free attempt, a base delay of a second and a maximum delay of a minute.
+    fn test_policy() -> ThrottlePolicy {
+        ThrottlePolicy {
+            free_attempts     : 1,
+            base_delay        : Duration::from_secs(1),
+            max_delay         : Duration::from_secs(60),
+            lockout_threshold : 0,
+            lockout_duration  : Duration::ZERO,
+        }
+    }
+
+    #[test]
+    fn test_delay() {
+        let policy = test_policy();
+        assert_eq!(policy.delay(0), Duration::ZERO);
+        assert_eq!(policy.delay(1), Duration::ZERO);
+        assert_eq!(policy.delay(2), Duration::from_secs(1));
+        assert_eq!(policy.delay(3), Duration::from_secs(2));
+        assert_eq!(policy.delay(6), Duration::from_secs(16));
+        assert_eq!(policy.delay(8), Duration::from_secs(60));
+        assert_eq!(policy.delay(u32::MAX), Duration::from_secs(60));
+    }
+
+    #[test]
+    fn test_throttle() {
+        let throttle = Throttle::new(test_policy());
+        let ip   = ThrottleKey::Ip("127.0.0.1".parse().unwrap());
+        let john = ThrottleKey::User("john".into());
+        let jane = ThrottleKey::User("jane".into());
+        assert_eq!(ThrottleKey::user("JOHN"), john);
+
+        // The first failure is free
+        throttle.failure(&[ ip.clone(), john.clone() ]);
+        assert_eq!(throttle.check(&[ ip.clone(), john.clone() ]), None);
+
+        // The second isn't, for both the IP and the user
+        throttle.failure(&[ ip.clone(), john.clone() ]);
+        assert!(throttle.check(std::slice::from_ref(&john)).is_some());
+        assert!(throttle.check(&[ ip.clone(), jane.clone() ]).is_some());
+        assert_eq!(throttle.check(&[ jane ]), None);
+
+        // Succeeding for a user only clears that user
+        throttle.clear(&john);
+        assert_eq!(throttle.check(&[ john ]), None);
+        assert!(throttle.check(&[ ip ]).is_some());
+    }
+}
+
+
+
+
+
+/***** CONSTANTS *****/
+/// The number of entries after which we start forgetting the ones that are no longer relevant.
+const MAX_ENTRIES: usize = 65536;
+
+
+
+
+
+/***** LIBRARY FUNCTIONS *****/
+/// Generates the reply for a login attempt that has to wait.
+/// 
+/// **Arguments**
+///  * `code`: The error code to send, which tells the client whether it was throttled (`TooManyRequests`) or the account is locked (`AccountLocked`).
+///  * `wait`: The time that the client has to wait before trying again.
+/// 
+/// **Returns**  
+/// A '429 Too Many Requests' response with a 'Retry-After' header.
+pub fn too_many_requests(code: ErrorCode, wait: Duration) -> Response {
+    // Round up, so the client doesn't retry just before it may
+    let secs: u64 = wait.as_secs() + if wait.subsec_nanos() > 0 { 1 } else { 0 };
+    let message: String = match code {
+        ErrorCode::AccountLocked => format!("Account is locked after too many failed logins; try again in {} seconds", secs),
+        _                        => format!("Too many failed logins; try again in {} seconds", secs),
+    };
+    warp::reply::with_header(
+        reply::error(StatusCode::TOO_MANY_REQUESTS, code, message),
+        "Retry-After",
+        secs.to_string(),
+    ).into_response()
+}
+
+
+
+
+
+/***** LIBRARY STRUCTS *****/
+/// Defines the thresholds of the brute-force protection.
+#[derive(Clone, Debug)]
+pub struct ThrottlePolicy {
+    /// The number of failed logins per IP address or username before we start delaying attempts.
+    pub free_attempts     : u32,
+    /// The delay after the first failed login that isn't free. Every next failure doubles it.
+    pub base_delay        : Duration,
+    /// The maximum delay between attempts.
+    pub max_delay         : Duration,
+    /// The number of consecutive failed logins after which an account is locked. If 0, accounts are never locked.
+    pub lockout_threshold : u32,
+    /// The time that an account stays locked.
+    pub lockout_duration  : Duration,
+}
+
+impl ThrottlePolicy {
+    /// Computes the delay after the given number of failures.
+    /// 
+    /// **Arguments**
+    ///  * `failures`: The number of failed logins so far.
+    /// 
+    /// **Returns**  
+    /// The time that the next attempt has to wait.
+    pub fn delay(&self, failures: u32) -> Duration {
+        if failures <= self.free_attempts { return Duration::ZERO; }
+        let factor: u32 = 1u32.checked_shl(failures - self.free_attempts - 1).unwrap_or(u32::MAX);
+        self.base_delay.saturating_mul(factor).min(self.max_delay)
+    }
+
+    /// Checks whether the given number of consecutive failures means an account should be locked.
+    /// 
+    /// **Arguments**
+    ///  * `failures`: The number of consecutive failed logins of the account.
+    /// 
+    /// **Returns**  
+    /// Whether or not to lock the account.
+    #[inline]
+    pub fn should_lock(&self, failures: u32) -> bool {
+        self.lockout_threshold > 0 && failures >= self.lockout_threshold
+    }
+}
+
+
+
+/// Identifies the source of failed logins that we throttle.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum ThrottleKey {
+    /// Failed logins from the given IP address.
+    Ip(IpAddr),
+    /// Failed logins for the given username, by its key (see `ThrottleKey::user()`).
+    User(String),
+}
+
+impl ThrottleKey {
+    /// Constructor for a ThrottleKey for the given username.
+    /// 
+    /// Usernames are keyed the same way the store looks them up, so 'JOHN' is throttled together with 'john'.
+    /// 
+    /// **Arguments**
+    ///  * `username`: The username to throttle.
+    /// 
+    /// **Returns**  
+    /// A new `ThrottleKey::User`.
+    #[inline]
+    pub fn user(username: &str) -> Self {
+        ThrottleKey::User(username_key(username))
+    }
+}
+
+
+
+/// Keeps track of the failed logins of one ThrottleKey.
+#[derive(Debug)]
+struct ThrottleEntry {
+    /// The number of failed logins so far.
+    failures      : u32,
+    /// The moment before which no new attempts are allowed.
+    blocked_until : Instant,
+    /// The moment after which we forget about the failed logins.
+    forget_at     : Instant,
+}
+
+
+
+/// Keeps track of failed logins in memory, and decides when new attempts have to wait.
+#[derive(Debug)]
+pub struct Throttle {
+    /// The thresholds to apply.
+    policy  : ThrottlePolicy,
+    /// The failed logins per key.
+    entries : Mutex<HashMap<ThrottleKey, ThrottleEntry>>,
+}
+
+impl Throttle {
+    /// Constructor for the Throttle, which starts without any failed logins.
+    /// 
+    /// **Arguments**
+    ///  * `policy`: The thresholds to apply.
+    /// 
+    /// **Returns**  
+    /// A new Throttle instance.
+    pub fn new(policy: ThrottlePolicy) -> Self {
+        Self {
+            policy,
+            entries : Mutex::new(HashMap::new()),
+        }
+    }
+
+
+
+    /// Locks the internal entries.
+    #[inline]
+    fn lock(&self) -> MutexGuard<'_, HashMap<ThrottleKey, ThrottleEntry>> {
+        self.entries.lock().expect("Throttle lock is poisoned; this should never happen!")
+    }
+
+
+
+    /// Checks whether a new login attempt for the given keys has to wait.
+    /// 
+    /// **Arguments**
+    ///  * `keys`: The keys that identify the attempt (e.g., its IP address and username).
+    /// 
+    /// **Returns**  
+    /// The longest time that any of the keys still has to wait, or None if the attempt may go ahead.
+    pub fn check(&self, keys: &[ThrottleKey]) -> Option<Duration> {
+        let entries = self.lock();
+        let now = Instant::now();
+        keys.iter()
+            .filter_map(|key| entries.get(key))
+            .filter(|entry| entry.blocked_until > now)
+            .map(|entry| entry.blocked_until - now)
+            .max()
+    }
+
+    /// Registers a failed login for the given keys.
+    /// 
+    /// **Arguments**
+    ///  * `keys`: The keys that identify the attempt (e.g., its IP address and username).
+    pub fn failure(&self, keys: &[ThrottleKey]) {
+        let mut entries = self.lock();
+        let now = Instant::now();
+
+        // Make sure we don't grow indefinitely if somebody tries lots of usernames or addresses
+        if entries.len() >= MAX_ENTRIES { entries.retain(|_, entry| entry.forget_at > now); }
+
+        for key in keys {
+            let entry = entries.entry(key.clone()).or_insert(ThrottleEntry{ failures: 0, blocked_until: now, forget_at: now });
+            if entry.forget_at <= now { entry.failures = 0; }
+            entry.failures      = entry.failures.saturating_add(1);
+            entry.blocked_until = now + self.policy.delay(entry.failures);
+            entry.forget_at     = entry.blocked_until + self.policy.max_delay;
+        }
+    }
+
+    /// Forgets about any failed logins for the given key, e.g., after a successful login or when an administrator lifts a lockout.
+    /// 
+    /// **Arguments**
+    ///  * `key`: The key to forget.
+    #[inline]
+    pub fn clear(&self, key: &ThrottleKey) {
+        self.lock().remove(key);
+    }
+
+
+
+    /// Returns the thresholds of this Throttle.
+    #[inline]
+    pub fn policy(&self) -> &ThrottlePolicy { &self.policy }
+}
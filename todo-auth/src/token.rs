@@ -0,0 +1,342 @@
+/* TOKEN.rs
+ *   by Lut99
+ *
+ * Created:
+ *   18 Oct 2026, 03:10:42
+ * Last edited:
+ *   18 Oct 2026, 06:24:13
+ * Auto updated?
+ *   Yes
+ *
+ * Description:
+ *   Handles issuing, refreshing and revoking the tokens handed out by
+ *   the authorization service.
+ *
+ *   Every login hands out a short-lived JWT (the access token) and a
+ *   long-lived refresh token. Refresh tokens are single-use: refreshing
+ *   hands out a new one in the same 'family'. If a refresh token is used
+ *   twice, we assume it was stolen and revoke the whole family.
+ *
+ *   Each family belongs to a session, which the user can see and end
+ *   (see `session.rs`). Access tokens carry the identifier of their
+ *   session, so they stop working as soon as it is ended.
+ *
+ *   Finally, it publishes the public keys with which access tokens can be
+ *   verified, so other services don't need any secrets to do so.
+**/
+
+use std::ops::Add;
+use std::sync::Arc;
+
+use log::{debug, error, info, warn};
+use rand_core::{OsRng, RngCore};
+use sha2::{Digest, Sha256};
+use time::OffsetDateTime;
+use warp::{Rejection, Reply};
+use warp::http::StatusCode;
+use warp::reply::Response;
+
+use todo_spec::api::{AuthEventKind, ErrorCode, RefreshJson, TokenJson};
+use todo_spec::jwt::Claims;
+
+pub use crate::errors::TokenError as Error;
+use crate::audit::{self, Client};
+use crate::reply;
+use crate::spec::{Account, Context, RefreshToken, Session, SESSION_FIELD_MAX_LENGTH};
+use crate::store::{Error as StoreError, Store};
+
+
+/***** HELPER MACROS *****/
+/// Writes the given error both to stderr via error!() and returns it as a custom reject
+macro_rules! throw {
+    ($err:expr) => {
+        let err = $err;
+        error!("{}", &err);
+        return Err(warp::reject::custom(err));
+    };
+}
+
+
+
+
+
+/***** HELPER FUNCTIONS *****/
+/// Generates a new random, URL-safe token.
+/// 
+/// **Returns**  
+/// The new token as a String.
+pub(crate) fn random_token() -> String {
+    let mut bytes = [0; 32];
+    OsRng.fill_bytes(&mut bytes);
+    base64::encode_config(bytes, base64::URL_SAFE_NO_PAD)
+}
+
+/// Hashes the given token for storage.
+/// 
+/// Note that we don't need a slow hash like for passwords, since the tokens are random and long enough not to be guessable.
+/// 
+/// **Arguments**
+///  * `token`: The token to hash.
+/// 
+/// **Returns**  
+/// The hex-encoded SHA-256 hash of the token.
+pub(crate) fn hash_token(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+/// Generates the reply for a refresh token that we refuse.
+#[inline]
+fn refuse() -> Response {
+    reply::error(StatusCode::UNAUTHORIZED, ErrorCode::Unauthorized, "Invalid refresh token")
+}
+
+
+
+
+
+/***** LIBRARY FUNCTIONS *****/
+/// Issues a new access token and refresh token for the given user in the given session.
+/// 
+/// **Arguments**
+///  * `ctx`: The Context of the service.
+///  * `user`: The Account of the user to issue the tokens for.
+///  * `session`: The Session to issue the tokens in. The refresh token joins its family.
+/// 
+/// **Returns**  
+/// The new pair of tokens on success, or else an Error.
+pub fn issue(ctx: &Context, user: &Account, session: &Session) -> Result<TokenJson, Error> {
+    // Generate the access token
+    let claims = Claims::new(user.id, ctx.tokens.access_lifetime).with_role(user.role).with_session(session.id);
+    let claims = match &user.display_name { Some(display_name) => claims.with_name(display_name), None => claims };
+    let access_token = match claims.sign(&ctx.keys.signing()) {
+        Ok(token) => token,
+        Err(err)  => { return Err(Error::JwtError{ err }); }
+    };
+
+    // Generate the refresh token and store its hash
+    let refresh_token = random_token();
+    if let Err(err) = ctx.store.insert_refresh_token(&RefreshToken {
+        hash    : hash_token(&refresh_token),
+        family  : session.family.clone(),
+        user_id : user.id,
+        expires : OffsetDateTime::now_utc().add(ctx.tokens.refresh_lifetime),
+        used    : false,
+        revoked : false,
+    }) {
+        return Err(Error::StoreError{ err });
+    }
+
+    // Done
+    Ok(TokenJson {
+        access_token,
+        refresh_token,
+        expires_in : ctx.tokens.access_lifetime.as_secs(),
+    })
+}
+
+/// Starts a new session for the given user, who has just logged in, and issues its first tokens.
+/// 
+/// **Arguments**
+///  * `ctx`: The Context of the service.
+///  * `client`: The Client that logged in.
+///  * `user`: The Account of the user who logged in.
+///  * `device`: The name of the device that logged in, if it gave one.
+/// 
+/// **Returns**  
+/// The new pair of tokens on success, or else an Error.
+pub fn start_session(ctx: &Context, client: &Client, user: &Account, device: Option<&str>) -> Result<TokenJson, Error> {
+    let now: OffsetDateTime = OffsetDateTime::now_utc();
+    let mut session: Session = Session {
+        id         : 0,
+        user_id    : user.id,
+        family     : random_token(),
+        device     : device.map(str::trim).filter(|device| !device.is_empty()).map(|device| audit::truncate(device, SESSION_FIELD_MAX_LENGTH)),
+        ip         : client.addr.map(|addr| addr.ip().to_string()),
+        user_agent : client.user_agent.as_deref().map(|user_agent| audit::truncate(user_agent, SESSION_FIELD_MAX_LENGTH)),
+        created    : now,
+        last_seen  : now,
+    };
+    session.id = match ctx.store.insert_session(&session) {
+        Ok(id)   => id,
+        Err(err) => { return Err(Error::StoreError{ err }); }
+    };
+    issue(ctx, user, &session)
+}
+
+/// Ends the given session, revoking its refresh tokens. Access tokens that carry it are no longer accepted either.
+/// 
+/// **Arguments**
+///  * `store`: The Store with the session.
+///  * `session`: The Session to end.
+/// 
+/// **Returns**  
+/// Nothing on success, or an Error otherwise.
+pub fn end_session(store: &dyn Store, session: &Session) -> Result<(), StoreError> {
+    store.revoke_refresh_family(&session.family)?;
+    store.delete_session(session.id)?;
+    Ok(())
+}
+
+/// Ends all sessions of the given user, revoking all of their refresh tokens. This logs them out everywhere.
+/// 
+/// **Arguments**
+///  * `store`: The Store with the sessions.
+///  * `user_id`: The identifier of the user to log out.
+/// 
+/// **Returns**  
+/// Nothing on success, or an Error otherwise.
+pub fn end_all_sessions(store: &dyn Store, user_id: u32) -> Result<(), StoreError> {
+    store.revoke_user_refresh_tokens(user_id)?;
+    store.delete_user_sessions(user_id)?;
+    Ok(())
+}
+
+
+
+/// Handles exchanging a refresh token for a new access token (and a new refresh token).
+/// 
+/// **Arguments**
+///  * `ctx`: The Context of the service.
+///  * `client`: The Client that sent the request.
+///  * `body`: The message body we got with the request.
+/// 
+/// **Returns**  
+/// The Warp reply on success, or a Warp rejection on failure.
+pub fn handle_refresh(ctx: Arc<Context>, client: Client, body: RefreshJson) -> Result<Response, Rejection> {
+    info!("Handling token refresh (v1/token/refresh)");
+
+    // Search the token
+    let hash = hash_token(&body.refresh_token);
+    let token: RefreshToken = match ctx.store.get_refresh_token(&hash) {
+        Ok(Some(token)) => token,
+        Ok(None)        => {
+            debug!("Token refresh failure: unknown refresh token");
+            return Ok(refuse());
+        },
+        Err(err) => { throw!(Error::StoreError{ err }); }
+    };
+
+    // Check if it's still valid
+    if token.revoked {
+        debug!("Token refresh failure: refresh token is revoked");
+        return Ok(refuse());
+    }
+    if token.expires < OffsetDateTime::now_utc() {
+        debug!("Token refresh failure: refresh token has expired");
+        return Ok(refuse());
+    }
+
+    // Mark it as used; if someone beat us to it, this token is being reused and was probably stolen
+    let first_use: bool = match ctx.store.use_refresh_token(&hash) {
+        Ok(first_use) => first_use,
+        Err(err)      => { throw!(Error::StoreError{ err }); }
+    };
+    let session: Option<Session> = match ctx.store.get_session_by_family(&token.family) {
+        Ok(session) => session,
+        Err(err)    => { throw!(Error::StoreError{ err }); }
+    };
+    if token.used || !first_use {
+        warn!("Refresh token for user {} was reused; revoking the token family", token.user_id);
+        let res: Result<(), StoreError> = match &session {
+            Some(session) => end_session(&*ctx.store, session),
+            None          => ctx.store.revoke_refresh_family(&token.family),
+        };
+        if let Err(err) = res { throw!(Error::StoreError{ err }); }
+        audit::record(&ctx, &client, AuthEventKind::TokenRevoke, Some(token.user_id), None, Some("refresh token reused; revoked its family"));
+        return Ok(refuse());
+    }
+
+    // Tokens from before we had sessions (or whose session has been ended) are no good anymore
+    let session: Session = match session {
+        Some(session) => session,
+        None          => {
+            debug!("Token refresh failure: refresh token does not belong to a session");
+            return Ok(refuse());
+        },
+    };
+
+    // Fetch the user again, since their role may have changed (or they may have been disabled) since they logged in
+    let user: Account = match ctx.store.get_by_id(token.user_id) {
+        Ok(Some(user)) if !user.disabled => user,
+        Ok(_)                            => {
+            debug!("Token refresh failure: user {} is disabled or no longer exists", token.user_id);
+            return Ok(refuse());
+        },
+        Err(err) => { throw!(Error::StoreError{ err }); }
+    };
+
+    // Issue new tokens in the same session
+    if let Err(err) = ctx.store.touch_session(session.id, OffsetDateTime::now_utc()) { warn!("Could not update when session {} was last seen: {}", session.id, err); }
+    let tokens = match issue(&ctx, &user, &session) {
+        Ok(tokens) => tokens,
+        Err(err)   => { throw!(err); }
+    };
+
+    // Done
+    debug!("Refreshed tokens for user {}", token.user_id);
+    audit::record(&ctx, &client, AuthEventKind::TokenRefresh, Some(user.id), Some(user.credential.user()), None);
+    Ok(warp::reply::with_status(
+        warp::reply::json(&tokens),
+        StatusCode::OK,
+    ).into_response())
+}
+
+
+
+/// Handles logging out, which ends the session of the given refresh token (revoking all tokens in its family).
+/// 
+/// **Arguments**
+///  * `ctx`: The Context of the service.
+///  * `client`: The Client that sent the request.
+///  * `body`: The message body we got with the request.
+/// 
+/// **Returns**  
+/// The Warp reply on success, or a Warp rejection on failure.
+pub fn handle_logout(ctx: Arc<Context>, client: Client, body: RefreshJson) -> Result<impl Reply, Rejection> {
+    info!("Handling logout (v1/logout)");
+
+    // Search the token; if it doesn't exist, there's nothing to revoke
+    let hash = hash_token(&body.refresh_token);
+    match ctx.store.get_refresh_token(&hash) {
+        Ok(Some(token)) => {
+            let res: Result<(), StoreError> = match ctx.store.get_session_by_family(&token.family) {
+                Ok(Some(session)) => end_session(&*ctx.store, &session),
+                Ok(None)          => ctx.store.revoke_refresh_family(&token.family),
+                Err(err)          => Err(err),
+            };
+            if let Err(err) = res { throw!(Error::StoreError{ err }); }
+            debug!("Revoked refresh tokens of user {}", token.user_id);
+            audit::record(&ctx, &client, AuthEventKind::TokenRevoke, Some(token.user_id), None, Some("logout"));
+        },
+        Ok(None) => {
+            debug!("Logout of unknown refresh token; nothing to revoke");
+        },
+        Err(err) => { throw!(Error::StoreError{ err }); }
+    }
+
+    // Done
+    Ok(warp::reply::with_status(
+        String::from("success"),
+        StatusCode::OK,
+    ))
+}
+
+
+
+/// Handles publishing the public keys with which our access tokens may be verified, as a JWK set.
+/// 
+/// **Arguments**
+///  * `ctx`: The Context of the service.
+/// 
+/// **Returns**  
+/// The Warp reply on success, or a Warp rejection on failure.
+pub async fn handle_jwks(ctx: Arc<Context>) -> Result<impl Reply, Rejection> {
+    info!("Handling JWKS request (v1/.well-known/jwks.json)");
+
+    // Other services may cache this, but not for so long that they miss a rotation
+    Ok(warp::reply::with_header(
+        warp::reply::json(&ctx.keys.verifier().jwks()),
+        "Cache-Control",
+        "public, max-age=300",
+    ))
+}
@@ -0,0 +1,582 @@
+/* TOTP.rs
+ *   by Lut99
+ *
+ * Created:
+ *   18 Oct 2026, 03:55:05
+ * Last edited:
+ *   18 Oct 2026, 07:03:49
+ * Auto updated?
+ *   Yes
+ *
+ * Description:
+ *   Handles two-factor authentication with time-based one-time passwords
+ *   (TOTP).
+ *
+ *   Users enroll an authenticator app by requesting a secret and then
+ *   confirming it with a first code, which also hands out a set of
+ *   one-time recovery codes. From then on, a login with the correct
+ *   password only yields a challenge, which has to be completed with a
+ *   code (or a recovery code) before any tokens are handed out.
+**/
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use log::{debug, error, info, warn};
+use rand_core::{OsRng, RngCore};
+use time::OffsetDateTime;
+use warp::{Rejection, Reply};
+use warp::http::StatusCode;
+use warp::reply::Response;
+
+use todo_spec::api::{AuthEventKind, ErrorCode, RecoveryCodesJson, SecondFactorLoginJson, TotpCodeJson, TotpEnrollJson};
+use todo_spec::totp::{base32_encode, Totp, DIGITS};
+
+pub use crate::errors::TotpError as Error;
+pub use crate::spec::{Account, Context, TotpSecret};
+use crate::audit::{self, Client};
+use crate::login;
+use crate::reply;
+use crate::spec::{CHALLENGE_EXPIRATION_TIME, CHALLENGE_MAX_ATTEMPTS, RECOVERY_CODE_COUNT, TOTP_ISSUER, TOTP_SKEW};
+use crate::throttle::{self, ThrottleKey};
+use crate::token;
+
+
+/***** UNIT TESTS *****/
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_challenges() {
+        let challenges = Challenges::new();
+
+        // A challenge may be completed once
+        let challenge = challenges.create(42, Some(String::from("laptop")));
+        assert_eq!(challenges.get(&challenge), Some(42));
+        assert_eq!(challenges.complete(&challenge), Some(String::from("laptop")));
+        assert_eq!(challenges.get(&challenge), None);
+
+        // Too many failed attempts revoke it
+        let challenge = challenges.create(42, None);
+        for _ in 0..CHALLENGE_MAX_ATTEMPTS - 1 { challenges.failure(&challenge); }
+        assert_eq!(challenges.get(&challenge), Some(42));
+        challenges.failure(&challenge);
+        assert_eq!(challenges.get(&challenge), None);
+        assert_eq!(challenges.get("unknown"), None);
+    }
+
+    #[test]
+    fn test_recovery_codes() {
+        let code = generate_recovery_code();
+        assert_eq!(code.len(), RECOVERY_CODE_LENGTH + 1);
+        assert_eq!(normalize_recovery_code(&code).len(), RECOVERY_CODE_LENGTH);
+        assert_eq!(normalize_recovery_code(" abcde-fghij "), "ABCDEFGHIJ");
+    }
+}
+
+
+
+
+
+/***** CONSTANTS *****/
+/// The number of (base32) characters in a recovery code, excluding the separator.
+const RECOVERY_CODE_LENGTH: usize = 10;
+
+
+
+
+
+/***** HELPER MACROS *****/
+/// Writes the given error both to stderr via error!() and returns it as a custom reject
+macro_rules! throw {
+    ($err:expr) => {
+        let err = $err;
+        error!("{}", &err);
+        return Err(warp::reject::custom(err));
+    };
+}
+
+
+
+
+
+/***** HELPER FUNCTIONS *****/
+/// Generates a new, random recovery code.
+/// 
+/// **Returns**  
+/// The new code, formatted as two groups of five characters (e.g., `ABCDE-FGHIJ`).
+fn generate_recovery_code() -> String {
+    let mut bytes = [0; (RECOVERY_CODE_LENGTH * 5).div_ceil(8)];
+    OsRng.fill_bytes(&mut bytes);
+    let code = base32_encode(&bytes);
+    format!("{}-{}", &code[..RECOVERY_CODE_LENGTH / 2], &code[RECOVERY_CODE_LENGTH / 2..RECOVERY_CODE_LENGTH])
+}
+
+/// Normalizes a recovery code as given by the user, so that it doesn't matter how they type it.
+/// 
+/// **Arguments**
+///  * `code`: The code as given by the user.
+/// 
+/// **Returns**  
+/// The code without separators or whitespace, in uppercase.
+fn normalize_recovery_code(code: &str) -> String {
+    code.chars().filter(|c| *c != '-' && !c.is_whitespace()).map(|c| c.to_ascii_uppercase()).collect()
+}
+
+/// Checks the given code against the second factor of the given user. Accepted codes cannot be used again.
+/// 
+/// **Arguments**
+///  * `ctx`: The Context of the service.
+///  * `totp`: The TOTP secret of the user.
+///  * `code`: The code given by the user. This may be a code from their authenticator app or one of their recovery codes.
+/// 
+/// **Returns**  
+/// Whether the code was accepted on success, or an Error if we failed to check it.
+fn check_code(ctx: &Context, totp: &TotpSecret, code: &str) -> Result<bool, Error> {
+    let code: &str = code.trim();
+
+    // Codes with the length of a TOTP are checked as such
+    if code.len() == DIGITS {
+        let generator: Totp = match Totp::from_base32(&totp.secret) {
+            Ok(generator) => generator,
+            Err(err)      => { return Err(Error::IllegalSecret{ user_id: totp.user_id, err }); }
+        };
+        return match generator.verify(code, OffsetDateTime::now_utc(), TOTP_SKEW) {
+            // Only accept the step if it is newer than the last one, or the code could be replayed
+            Some(step) => match ctx.store.use_totp_step(totp.user_id, step) {
+                Ok(used) => Ok(used),
+                Err(err) => Err(Error::StoreError{ err }),
+            },
+            None => Ok(false),
+        };
+    }
+
+    // Otherwise, try the recovery codes
+    let code: String = normalize_recovery_code(code);
+    if code.len() != RECOVERY_CODE_LENGTH { return Ok(false); }
+    let hashes: Vec<String> = match ctx.store.get_recovery_codes(totp.user_id) {
+        Ok(hashes) => hashes,
+        Err(err)   => { return Err(Error::StoreError{ err }); }
+    };
+    for hash in hashes {
+        match ctx.metrics.time_password_verify(|| ctx.hashing.verify(&hash, &code)) {
+            Ok(true)  => {
+                return match ctx.store.use_recovery_code(totp.user_id, &hash) {
+                    Ok(used) => {
+                        if used { info!("User {} used a recovery code", totp.user_id); }
+                        Ok(used)
+                    },
+                    Err(err) => Err(Error::StoreError{ err }),
+                };
+            },
+            Ok(false) => {},
+            Err(err)  => { return Err(Error::CredentialError{ err }); }
+        }
+    }
+    Ok(false)
+}
+
+/// Generates the reply for a code that we refuse.
+#[inline]
+fn refuse() -> Response {
+    reply::error(StatusCode::FORBIDDEN, ErrorCode::InvalidCode, "Invalid authentication code")
+}
+
+
+
+
+
+/***** LIBRARY STRUCTS *****/
+/// Keeps track of a single login that is waiting for its second factor.
+#[derive(Debug)]
+struct Challenge {
+    /// The identifier of the user who is logging in.
+    user_id  : u32,
+    /// The name of the device that is logging in, if it gave one.
+    device   : Option<String>,
+    /// The moment the challenge expires.
+    expires  : Instant,
+    /// The number of wrong codes given so far.
+    attempts : u32,
+}
+
+
+
+/// Keeps track of the logins that are waiting for their second factor, in memory.
+#[derive(Debug, Default)]
+pub struct Challenges {
+    /// Maps challenges to the logins they belong to.
+    challenges : Mutex<HashMap<String, Challenge>>,
+}
+
+impl Challenges {
+    /// Constructor for the Challenges, which starts without any.
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+
+
+    /// Locks the internal challenges.
+    #[inline]
+    fn lock(&self) -> MutexGuard<'_, HashMap<String, Challenge>> {
+        self.challenges.lock().expect("Challenges lock is poisoned; this should never happen!")
+    }
+
+
+
+    /// Creates a new challenge for the given user.
+    /// 
+    /// **Arguments**
+    ///  * `user_id`: The identifier of the user who provided the correct password.
+    ///  * `device`: The name of the device that is logging in, if it gave one. It is remembered for the session that is started once the challenge is completed.
+    /// 
+    /// **Returns**  
+    /// The new challenge, which the user has to send back together with their code.
+    pub fn create(&self, user_id: u32, device: Option<String>) -> String {
+        let mut challenges = self.lock();
+        let now = Instant::now();
+
+        // Forget about the challenges that nobody completed
+        challenges.retain(|_, challenge| challenge.expires > now);
+
+        let challenge = token::random_token();
+        challenges.insert(challenge.clone(), Challenge{ user_id, device, expires: now + Duration::from_secs(CHALLENGE_EXPIRATION_TIME), attempts: 0 });
+        challenge
+    }
+
+    /// Returns the user to whom the given challenge belongs.
+    /// 
+    /// **Arguments**
+    ///  * `challenge`: The challenge to search for.
+    /// 
+    /// **Returns**  
+    /// The identifier of the user, or None if the challenge does not exist (anymore).
+    pub fn get(&self, challenge: &str) -> Option<u32> {
+        let challenges = self.lock();
+        match challenges.get(challenge) {
+            Some(challenge) if challenge.expires > Instant::now() => Some(challenge.user_id),
+            _                                                     => None,
+        }
+    }
+
+    /// Registers a wrong code for the given challenge, revoking it if that happens too often.
+    /// 
+    /// **Arguments**
+    ///  * `challenge`: The challenge for which a wrong code was given.
+    pub fn failure(&self, challenge: &str) {
+        let mut challenges = self.lock();
+        if let Some(entry) = challenges.get_mut(challenge) {
+            entry.attempts += 1;
+            if entry.attempts >= CHALLENGE_MAX_ATTEMPTS {
+                warn!("Revoking login challenge of user {} after {} wrong codes", entry.user_id, entry.attempts);
+                challenges.remove(challenge);
+            }
+        }
+    }
+
+    /// Removes the given challenge, so it cannot be completed twice.
+    /// 
+    /// **Arguments**
+    ///  * `challenge`: The challenge that was completed.
+    /// 
+    /// **Returns**  
+    /// The name of the device that is logging in, if it gave one.
+    #[inline]
+    pub fn complete(&self, challenge: &str) -> Option<String> {
+        self.lock().remove(challenge).and_then(|challenge| challenge.device)
+    }
+}
+
+
+
+
+
+/***** LIBRARY FUNCTIONS *****/
+/// Handles starting the enrollment of an authenticator app for the authenticated user.
+/// 
+/// The returned secret only becomes active once it is confirmed (see `handle_confirm()`). Enrolling again before that simply replaces it.
+/// 
+/// **Arguments**
+///  * `ctx`: The Context of the service.
+///  * `user_id`: The identifier of the user, as taken from their access token.
+/// 
+/// **Returns**  
+/// The Warp reply on success, or a Warp rejection on failure.
+pub fn handle_enroll(ctx: Arc<Context>, user_id: u32) -> Result<Response, Rejection> {
+    info!("Handling TOTP enrollment (v1/totp)");
+
+    // Query the store for this user
+    debug!("Searching for user {}...", user_id);
+    let user: Account = match ctx.store.get_by_id(user_id) {
+        Ok(Some(user)) => user,
+        Ok(None)       => {
+            debug!("User {} TOTP enrollment failure: unknown user", user_id);
+            return Ok(reply::error(StatusCode::UNAUTHORIZED, ErrorCode::Unauthorized, "Unknown user"));
+        },
+        Err(err) => { throw!(Error::StoreError{ err }); }
+    };
+
+    // Refuse to silently replace a working second factor
+    match ctx.store.get_totp(user.id) {
+        Ok(Some(totp)) if totp.confirmed => {
+            debug!("User '{}' TOTP enrollment failure: already enabled", user.credential.user());
+            return Ok(reply::error(StatusCode::CONFLICT, ErrorCode::Conflict, "Two-factor authentication is already enabled"));
+        },
+        Ok(_)    => {},
+        Err(err) => { throw!(Error::StoreError{ err }); }
+    }
+
+    // Generate a new secret and store it
+    let totp = Totp::generate();
+    if let Err(err) = ctx.store.set_totp(user.id, &totp.secret()) { throw!(Error::StoreError{ err }); }
+
+    // Done
+    info!("User '{}' started enrolling an authenticator app", user.credential.user());
+    Ok(warp::reply::with_status(
+        warp::reply::json(&TotpEnrollJson{ secret: totp.secret(), uri: totp.uri(TOTP_ISSUER, user.credential.user()) }),
+        StatusCode::OK,
+    ).into_response())
+}
+
+
+
+/// Handles confirming the enrollment of an authenticator app with a first code, which enables two-factor authentication for the authenticated user.
+/// 
+/// **Arguments**
+///  * `ctx`: The Context of the service.
+///  * `client`: The Client that sent the request.
+///  * `user_id`: The identifier of the user, as taken from their access token.
+///  * `body`: The message body we got with the request.
+/// 
+/// **Returns**  
+/// The Warp reply on success, or a Warp rejection on failure.
+pub fn handle_confirm(ctx: Arc<Context>, client: Client, user_id: u32, body: TotpCodeJson) -> Result<Response, Rejection> {
+    info!("Handling TOTP confirmation (v1/totp/confirm)");
+
+    // Query the store for the pending secret
+    let totp: TotpSecret = match ctx.store.get_totp(user_id) {
+        Ok(Some(totp)) if !totp.confirmed => totp,
+        Ok(Some(_))                       => {
+            debug!("User {} TOTP confirmation failure: already enabled", user_id);
+            return Ok(reply::error(StatusCode::CONFLICT, ErrorCode::Conflict, "Two-factor authentication is already enabled"));
+        },
+        Ok(None) => {
+            debug!("User {} TOTP confirmation failure: nothing to confirm", user_id);
+            return Ok(reply::error(StatusCode::NOT_FOUND, ErrorCode::NotFound, "No authenticator app is being enrolled"));
+        },
+        Err(err) => { throw!(Error::StoreError{ err }); }
+    };
+
+    // Only TOTP codes prove that the app works (and there are no recovery codes yet anyway)
+    if body.code.trim().len() != DIGITS {
+        debug!("User {} TOTP confirmation failure: not a TOTP code", user_id);
+        return Ok(refuse());
+    }
+    match check_code(&ctx, &totp, &body.code) {
+        Ok(true)  => {},
+        Ok(false) => {
+            debug!("User {} TOTP confirmation failure: invalid code", user_id);
+            return Ok(refuse());
+        },
+        Err(err) => { throw!(err); }
+    }
+
+    // Generate the recovery codes, and store their hashes together with the confirmation
+    let codes: Vec<String> = (0..RECOVERY_CODE_COUNT).map(|_| generate_recovery_code()).collect();
+    let mut hashes: Vec<String> = Vec::with_capacity(codes.len());
+    for code in &codes {
+        match ctx.hashing.hash(normalize_recovery_code(code)) {
+            Ok(hash) => { hashes.push(hash); },
+            Err(err) => { throw!(Error::CredentialError{ err }); }
+        }
+    }
+    if let Err(err) = ctx.store.confirm_totp(user_id, &hashes) { throw!(Error::StoreError{ err }); }
+
+    // Done
+    info!("User {} enabled two-factor authentication", user_id);
+    audit::record(&ctx, &client, AuthEventKind::SecondFactorChange, Some(user_id), None, Some("enabled"));
+    Ok(warp::reply::with_status(
+        warp::reply::json(&RecoveryCodesJson{ codes }),
+        StatusCode::OK,
+    ).into_response())
+}
+
+
+
+/// Handles disabling two-factor authentication for the authenticated user. This requires a valid code, so a stolen access token is not enough.
+/// 
+/// Wrong codes are throttled and count towards the account lockout just like failed logins, so the code cannot be brute-forced with the access token either.
+/// 
+/// **Arguments**
+///  * `ctx`: The Context of the service.
+///  * `client`: The Client that sent the request.
+///  * `user_id`: The identifier of the user, as taken from their access token.
+///  * `body`: The message body we got with the request.
+/// 
+/// **Returns**  
+/// The Warp reply on success, or a Warp rejection on failure.
+pub fn handle_disable(ctx: Arc<Context>, client: Client, user_id: u32, body: TotpCodeJson) -> Result<Response, Rejection> {
+    info!("Handling TOTP removal (v1/totp)");
+
+    // Query the store for this user
+    debug!("Searching for user {}...", user_id);
+    let user: Account = match ctx.store.get_by_id(user_id) {
+        Ok(Some(user)) => user,
+        Ok(None)       => {
+            debug!("User {} TOTP removal failure: unknown user", user_id);
+            return Ok(reply::error(StatusCode::UNAUTHORIZED, ErrorCode::Unauthorized, "Unknown user"));
+        },
+        Err(err) => { throw!(Error::StoreError{ err }); }
+    };
+
+    // Query the store for the secret
+    let totp: TotpSecret = match ctx.store.get_totp(user_id) {
+        Ok(Some(totp)) => totp,
+        Ok(None)       => {
+            debug!("User {} TOTP removal failure: not enabled", user_id);
+            return Ok(reply::error(StatusCode::NOT_FOUND, ErrorCode::NotFound, "Two-factor authentication is not enabled"));
+        },
+        Err(err) => { throw!(Error::StoreError{ err }); }
+    };
+
+    // Unconfirmed secrets may be removed freely; confirmed ones require a code
+    if totp.confirmed {
+        let keys: [ThrottleKey; 1] = [ ThrottleKey::user(user.credential.user()) ];
+        if let Some(wait) = ctx.throttle.check(&keys) {
+            debug!("User '{}' TOTP removal failure: throttled for another {:.1} seconds", user.credential.user(), wait.as_secs_f64());
+            audit::record(&ctx, &client, AuthEventKind::SecondFactorChange, Some(user.id), Some(user.credential.user()), Some("refused: throttled"));
+            return Ok(throttle::too_many_requests(ErrorCode::TooManyRequests, wait));
+        }
+        if let Some(wait) = login::locked_for(&user) {
+            debug!("User '{}' TOTP removal failure: account is locked for another {} seconds", user.credential.user(), wait.as_secs());
+            audit::record(&ctx, &client, AuthEventKind::SecondFactorChange, Some(user.id), Some(user.credential.user()), Some("refused: account is locked"));
+            return Ok(throttle::too_many_requests(ErrorCode::AccountLocked, wait));
+        }
+
+        match check_code(&ctx, &totp, &body.code) {
+            Ok(true)  => {
+                if let Err(err) = login::clear_failures(&ctx, &user) { throw!(Error::StoreError{ err }); }
+            },
+            Ok(false) => {
+                debug!("User '{}' TOTP removal failure: invalid code", user.credential.user());
+                match login::record_failure(&ctx, &keys, &user) {
+                    Ok(Some(failures)) => { audit::record(&ctx, &client, AuthEventKind::SecondFactorChange, Some(user.id), Some(user.credential.user()), Some(&format!("refused: invalid code; account locked after {} consecutive failures", failures))); },
+                    Ok(None)           => { audit::record(&ctx, &client, AuthEventKind::SecondFactorChange, Some(user.id), Some(user.credential.user()), Some("refused: invalid code")); },
+                    Err(err)           => { throw!(Error::StoreError{ err }); }
+                }
+                return Ok(refuse());
+            },
+            Err(err) => { throw!(err); }
+        }
+    }
+    if let Err(err) = ctx.store.delete_totp(user_id) { throw!(Error::StoreError{ err }); }
+
+    // Done
+    info!("User '{}' disabled two-factor authentication", user.credential.user());
+    if totp.confirmed { audit::record(&ctx, &client, AuthEventKind::SecondFactorChange, Some(user.id), Some(user.credential.user()), Some("disabled")); }
+    Ok(warp::reply::with_status(
+        String::from("success"),
+        StatusCode::OK,
+    ).into_response())
+}
+
+
+
+/// Handles the second step of logging in, which completes a challenge with a code and hands out the tokens.
+/// 
+/// Wrong codes count against the challenge, but also against the user's throttle and account lockout; otherwise, anyone who knows the password could keep requesting fresh challenges to guess with. Both are only cleared once a correct code is given.
+/// 
+/// **Arguments**
+///  * `ctx`: The Context of the service.
+///  * `client`: The Client that is trying to login.
+///  * `body`: The message body we got with the request.
+/// 
+/// **Returns**  
+/// The Warp reply on success, or a Warp rejection on failure.
+pub fn handle_login(ctx: Arc<Context>, client: Client, body: SecondFactorLoginJson) -> Result<Response, Rejection> {
+    info!("Handling second factor login (v1/login/totp)");
+
+    // Find out who is trying to login
+    let user_id: u32 = match ctx.challenges.get(&body.challenge) {
+        Some(user_id) => user_id,
+        None          => {
+            debug!("Second factor login failure: unknown or expired challenge");
+            return Ok(reply::error(StatusCode::UNAUTHORIZED, ErrorCode::Unauthorized, "Unknown or expired challenge; login again"));
+        },
+    };
+    debug!("Searching for user {}...", user_id);
+    let user: Account = match ctx.store.get_by_id(user_id) {
+        Ok(Some(user)) if !user.disabled => user,
+        Ok(_)                            => {
+            debug!("User {} second factor login failure: account is disabled or no longer exists", user_id);
+            audit::record(&ctx, &client, AuthEventKind::LoginFailure, Some(user_id), None, Some("account is disabled"));
+            return Ok(reply::error(StatusCode::FORBIDDEN, ErrorCode::AccountDisabled, "Account is disabled"));
+        },
+        Err(err) => { throw!(Error::StoreError{ err }); }
+    };
+
+    // Don't check any codes if the user (or the client's address) failed too often
+    let user_key = ThrottleKey::user(user.credential.user());
+    let keys: Vec<ThrottleKey> = match client.addr {
+        Some(addr) => vec![ ThrottleKey::Ip(addr.ip()), user_key ],
+        None       => vec![ user_key ],
+    };
+    if let Some(wait) = ctx.throttle.check(&keys) {
+        debug!("User '{}' second factor login failure: throttled for another {:.1} seconds", user.credential.user(), wait.as_secs_f64());
+        audit::record(&ctx, &client, AuthEventKind::LoginFailure, Some(user.id), Some(user.credential.user()), Some("throttled"));
+        return Ok(throttle::too_many_requests(ErrorCode::TooManyRequests, wait));
+    }
+    if let Some(wait) = login::locked_for(&user) {
+        debug!("User '{}' second factor login failure: account is locked for another {} seconds", user.credential.user(), wait.as_secs());
+        audit::record(&ctx, &client, AuthEventKind::LoginFailure, Some(user.id), Some(user.credential.user()), Some("account is locked"));
+        return Ok(throttle::too_many_requests(ErrorCode::AccountLocked, wait));
+    }
+
+    let totp: TotpSecret = match ctx.store.get_totp(user_id) {
+        Ok(Some(totp)) if totp.confirmed => totp,
+        Ok(_)                            => {
+            // Two-factor authentication was disabled in the meantime; let them start over
+            debug!("User '{}' second factor login failure: not enabled", user.credential.user());
+            ctx.challenges.complete(&body.challenge);
+            return Ok(reply::error(StatusCode::UNAUTHORIZED, ErrorCode::Unauthorized, "Unknown or expired challenge; login again"));
+        },
+        Err(err) => { throw!(Error::StoreError{ err }); }
+    };
+
+    // Check the code
+    let device: Option<String> = match check_code(&ctx, &totp, &body.code) {
+        Ok(true)  => ctx.challenges.complete(&body.challenge),
+        Ok(false) => {
+            debug!("User '{}' second factor login failure: invalid code", user.credential.user());
+            ctx.challenges.failure(&body.challenge);
+            match login::record_failure(&ctx, &keys, &user) {
+                Ok(Some(failures)) => { audit::record(&ctx, &client, AuthEventKind::LoginFailure, Some(user.id), Some(user.credential.user()), Some(&format!("invalid second factor; account locked after {} consecutive failures", failures))); },
+                Ok(None)           => { audit::record(&ctx, &client, AuthEventKind::LoginFailure, Some(user.id), Some(user.credential.user()), Some("invalid second factor")); },
+                Err(err)           => { throw!(Error::StoreError{ err }); }
+            }
+            return Ok(refuse());
+        },
+        Err(err) => { throw!(err); }
+    };
+
+    // Success! Forget about earlier failures, and generate the tokens for this user.
+    debug!("User '{}' second factor login success; generating tokens", user.credential.user());
+    if let Err(err) = login::clear_failures(&ctx, &user) { throw!(Error::StoreError{ err }); }
+    let tokens = match token::start_session(&ctx, &client, &user, device.as_deref()) {
+        Ok(tokens) => tokens,
+        Err(err)   => { throw!(Error::TokenError{ err }); }
+    };
+    audit::record(&ctx, &client, AuthEventKind::LoginSuccess, Some(user.id), Some(user.credential.user()), Some("with second factor"));
+    audit::record(&ctx, &client, AuthEventKind::TokenIssue, Some(user.id), Some(user.credential.user()), None);
+
+    // Return the tokens!
+    info!("User {} authorized with JWT for {} seconds", user_id, tokens.expires_in);
+    Ok(warp::reply::with_status(
+        warp::reply::json(&tokens),
+        StatusCode::OK,
+    ).into_response())
+}
@@ -0,0 +1,222 @@
+/* WORKERS.rs
+ *   by Lut99
+ *
+ * Created:
+ *   18 Oct 2026, 04:57:49
+ * Last edited:
+ *   18 Oct 2026, 05:33:22
+ * Auto updated?
+ *   Yes
+ *
+ * Description:
+ *   Moves the blocking work of the handlers off warp's async runtime.
+ *
+ *   The stores are synchronous, so every handler that touches one runs
+ *   on tokio's blocking thread pool instead. Handlers that hash
+ *   passwords additionally need a permit from the HashPool, which caps
+ *   how many Argon2 computations run at the same time; if none is left,
+ *   the request is refused right away instead of queueing up.
+**/
+
+use std::panic;
+use std::sync::Arc;
+
+use log::warn;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use warp::{Rejection, Reply};
+use warp::reject::Reject;
+use warp::http::StatusCode;
+use warp::reply::Response;
+
+use todo_spec::api::ErrorCode;
+
+use crate::reply;
+use crate::spec::{Context, PoolStats};
+
+
+/***** UNIT TESTS *****/
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hash_pool() {
+        let pool = HashPool::new(1);
+        assert_eq!(pool.stats(), PoolStats{ max: 1, in_use: 0 });
+
+        // While the only permit is taken, nothing else gets one
+        let permit = pool.permits.clone().try_acquire_owned().unwrap();
+        assert_eq!(pool.stats(), PoolStats{ max: 1, in_use: 1 });
+        assert!(pool.permits.clone().try_acquire_owned().is_err());
+        drop(permit);
+        assert_eq!(pool.stats(), PoolStats{ max: 1, in_use: 0 });
+    }
+}
+
+
+
+
+
+/***** CONSTANTS *****/
+/// The time (in seconds) that clients are told to wait before retrying a request that we refused because we were too busy.
+const OVERLOADED_RETRY_AFTER: u64 = 1;
+
+
+
+
+
+/***** HELPER FUNCTIONS *****/
+/// Takes a permit from the HashPool of the given Context, without waiting for one.
+/// 
+/// **Arguments**
+///  * `ctx`: The Context of the service, with the HashPool to take a permit from.
+/// 
+/// **Returns**  
+/// The permit, or None if all of them are in use (which is logged and counted).
+fn try_permit(ctx: &Context) -> Option<OwnedSemaphorePermit> {
+    // Refuse instead of waiting; a queue would only make every client wait longer
+    match ctx.hash_pool.permits.clone().try_acquire_owned() {
+        Ok(permit) => Some(permit),
+        Err(_)     => {
+            warn!("Refusing request: all {} password hashing slots are in use", ctx.hash_pool.max);
+            ctx.metrics.record_overloaded();
+            None
+        },
+    }
+}
+
+
+
+/// Generates the reply for a request that we are too busy to handle.
+/// 
+/// **Returns**  
+/// A '503 Service Unavailable' response with a 'Retry-After' header.
+pub(crate) fn overloaded() -> Response {
+    warp::reply::with_header(
+        reply::error(StatusCode::SERVICE_UNAVAILABLE, ErrorCode::Overloaded, "The service is too busy; try again later"),
+        "Retry-After",
+        OVERLOADED_RETRY_AFTER.to_string(),
+    ).into_response()
+}
+
+
+
+
+
+/***** LIBRARY STRUCTS *****/
+/// Rejection for requests that are refused by `verifying()` because the HashPool is saturated. `reply::recover()` turns it into the same reply that `hashing()` gives.
+#[derive(Debug)]
+pub struct Overloaded;
+
+impl Reject for Overloaded {}
+
+
+
+/// Limits the number of requests that hash passwords at the same time.
+#[derive(Debug)]
+pub struct HashPool {
+    /// The permits that the requests take, one each
+    permits : Arc<Semaphore>,
+    /// The total number of permits
+    max     : usize,
+}
+
+impl HashPool {
+    /// Constructor for the HashPool.
+    /// 
+    /// **Arguments**
+    ///  * `max`: The maximum number of requests that may hash passwords at the same time.
+    /// 
+    /// **Returns**  
+    /// A new HashPool instance.
+    pub fn new(max: usize) -> Self {
+        Self {
+            permits : Arc::new(Semaphore::new(max)),
+            max,
+        }
+    }
+
+
+
+    /// Returns how many requests may hash passwords at the same time, and how many are doing so right now.
+    #[inline]
+    pub fn stats(&self) -> PoolStats {
+        PoolStats{ max: self.max, in_use: self.max - self.permits.available_permits() }
+    }
+}
+
+
+
+
+
+/***** LIBRARY FUNCTIONS *****/
+/// Runs the given handler on the blocking thread pool, so that it may use the store (or do anything else that blocks) without stalling other requests.
+/// 
+/// If the handler panics, the panic is continued here, just as if it had been run inline.
+/// 
+/// **Arguments**
+///  * `ctx`: The Context of the service, which is passed on to the handler.
+///  * `handler`: The handler to run.
+/// 
+/// **Returns**  
+/// Whatever the handler returns.
+pub async fn blocking<T, F>(ctx: Arc<Context>, handler: F) -> Result<T, Rejection>
+where
+    T: Send + 'static,
+    F: FnOnce(Arc<Context>) -> Result<T, Rejection> + Send + 'static,
+{
+    match tokio::task::spawn_blocking(move || handler(ctx)).await {
+        Ok(res)  => res,
+        Err(err) => match err.try_into_panic() {
+            Ok(payload) => panic::resume_unwind(payload),
+            Err(err)    => panic!("Blocking handler was cancelled ({}); this should never happen!", err),
+        },
+    }
+}
+
+/// Runs the given handler on the blocking thread pool like `blocking()`, but only if the HashPool of the given Context has room for it. Use for every handler that hashes or verifies passwords.
+/// 
+/// **Arguments**
+///  * `ctx`: The Context of the service, with the HashPool to take a permit from. It is passed on to the handler.
+///  * `handler`: The handler to run. It keeps the permit until it returns.
+/// 
+/// **Returns**  
+/// Whatever the handler returns, or a '503 Service Unavailable' reply if the HashPool is saturated.
+pub async fn hashing<F>(ctx: Arc<Context>, handler: F) -> Result<Response, Rejection>
+where
+    F: FnOnce(Arc<Context>) -> Result<Response, Rejection> + Send + 'static,
+{
+    let permit: OwnedSemaphorePermit = match try_permit(&ctx) {
+        Some(permit) => permit,
+        None         => { return Ok(overloaded()); },
+    };
+    blocking(ctx, move |ctx| {
+        let res = handler(ctx);
+        drop(permit);
+        res
+    }).await
+}
+
+/// Runs the given check on the blocking thread pool like `hashing()`, but rejects with `Overloaded` instead of replying if the HashPool is saturated. Use in filters that verify hashes before the handler runs.
+/// 
+/// **Arguments**
+///  * `ctx`: The Context of the service, with the HashPool to take a permit from. It is passed on to the check.
+///  * `check`: The check to run. It keeps the permit until it returns.
+/// 
+/// **Returns**  
+/// Whatever the check returns, or an `Overloaded` rejection if the HashPool is saturated.
+pub async fn verifying<T, F>(ctx: Arc<Context>, check: F) -> Result<T, Rejection>
+where
+    T: Send + 'static,
+    F: FnOnce(Arc<Context>) -> Result<T, Rejection> + Send + 'static,
+{
+    let permit: OwnedSemaphorePermit = match try_permit(&ctx) {
+        Some(permit) => permit,
+        None         => { return Err(warp::reject::custom(Overloaded)); },
+    };
+    blocking(ctx, move |ctx| {
+        let res = check(ctx);
+        drop(permit);
+        res
+    }).await
+}
@@ -4,7 +4,7 @@
  * Created:
  *   16 Mar 2022, 18:02:45
  * Last edited:
- *   19 Mar 2022, 18:48:50
+ *   18 Oct 2026, 06:42:40
  * Auto updated?
  *   Yes
  *
@@ -13,6 +13,7 @@
  *   todo-client tool.
 **/
 
+use std::collections::HashMap;
 use std::io::BufReader;
 use std::fmt::{Display, Formatter, Result as FResult};
 use std::fs::{self, File};
@@ -25,7 +26,8 @@ use clap::Parser;
 use serde::{Serialize, Serializer, Deserialize, Deserializer};
 use serde::de::{self, Visitor};
 
-use todo_spec::credentials::Credential;
+use todo_spec::credentials::{Credential, Error as CredentialError};
+use todo_spec::password::PasswordPolicy;
 
 pub use crate::errors::ConfigError as Error;
 
@@ -84,7 +86,7 @@ impl FromStr for Url {
     type Err = url::ParseError;
     #[inline]
     fn from_str(value: &str) -> Result<Self, Self::Err> {
-        url::Url::from_str(value).map(|res| Url(res))
+        url::Url::from_str(value).map(Url)
     }
 }
 
@@ -104,7 +106,7 @@ impl<'de> Deserialize<'de> for Url {
         D: Deserializer<'de>
     {
         // Simply deserialize as string
-        deserializer.deserialize_str(UrlVisitor).map(|res| Url(res))
+        deserializer.deserialize_str(UrlVisitor).map(Url)
     }
 }
 
@@ -130,6 +132,9 @@ pub struct Arguments {
     /// The location of the logging file
     #[clap(short, long, help = "The location of the logging file for the client.")]
     log_path : Option<PathBuf>,
+    /// Whether to skip verifying certificates
+    #[clap(long, help = "If given, does NOT verify the certificates of hosts. Only use this for local development; anyone on the network can read your password.")]
+    insecure : bool,
 
     /// Defined the subcommand that is run
     #[clap(subcommand)]
@@ -146,6 +151,10 @@ enum ArgumentSubcommand {
     Generate {
         #[clap(short, long, help = "The location of the output file.", default_value = "./user.cred")]
         output : PathBuf,
+        #[clap(long, help = "If given, binds the credentials to the given Todo server.")]
+        host    : Option<url::Url>,
+        #[clap(short, long, help = "If given, prompts for a passphrase and encrypts the credential file with it.")]
+        encrypt : bool,
 
         #[clap(help = "The username to login with.")]
         username : String,
@@ -153,6 +162,13 @@ enum ArgumentSubcommand {
         password : bool,
     },
 
+    /// A subcommand that shows what is in a .cred file
+    #[clap(name = "show", about = "Show the username, creation time and host of a credential file. Prompts for its passphrase if it is encrypted.")]
+    Show {
+        #[clap(help = "The location of the credential file.", default_value = "./user.cred")]
+        file : PathBuf,
+    },
+
     /// A Subcommand that logs the user in remotely
     #[clap(name = "login", about = "Login to a Todo server.")]
     Login {
@@ -166,6 +182,23 @@ enum ArgumentSubcommand {
         password : bool,
     },
 
+    /// A subcommand that changes the user's password remotely
+    #[clap(name = "passwd", about = "Change your password on a Todo server.")]
+    Passwd {
+        #[clap(long, help = "The remote host to change the password on. If omitted, uses the value specified in the configuration file (see the 'login' subcommand).")]
+        host : Option<url::Url>,
+
+        #[clap(help = "The username whose password to change.")]
+        username : String,
+    },
+
+    /// A subcommand that resets a forgotten password remotely
+    #[clap(name = "reset-password", about = "Set a new password on a Todo server using a one-time reset token from an administrator.")]
+    ResetPassword {
+        #[clap(long, help = "The remote host to reset the password on. If omitted, uses the value specified in the configuration file (see the 'login' subcommand).")]
+        host : Option<url::Url>,
+    },
+
     /// No subcommand is used
     #[clap(name = "run", about = "Runs the normal interface to the Todo tool.")]
     Run {
@@ -180,6 +213,19 @@ enum ArgumentSubcommand {
 
 
 /***** FILE STRUCTS *****/
+/// Defines how to verify the certificate of one host.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct HostTls {
+    /// A PEM file with the CA certificates to trust for this host, instead of the default ones.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ca_bundle   : Option<PathBuf>,
+    /// The SHA-256 fingerprint of the certificate that the host must present (e.g., as printed by 'openssl x509 -noout -fingerprint -sha256').
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fingerprint : Option<String>,
+}
+
+
+
 /// Defines the config-file part of the Config struct.
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ConfigFile {
@@ -188,6 +234,10 @@ pub struct ConfigFile {
 
     /// Defines the default host to connect to.
     host : Option<Url>,
+
+    /// Defines how to verify the certificates of specific hosts, by 'hostname' or 'hostname:port'.
+    #[serde(default)]
+    tls : HashMap<String, HostTls>,
 }
 
 impl Default for ConfigFile {
@@ -195,6 +245,8 @@ impl Default for ConfigFile {
         Self {
             log_path : dirs_2::config_dir().expect("Could not get standard user configuration directory").join("todo/todo.log"),
             host : None,
+
+            tls : HashMap::new(),
         }
     }
 }
@@ -213,14 +265,48 @@ pub enum Action {
         output     : PathBuf,
         /// The credentials to write
         credential : Credential,
+        /// The passphrase to encrypt the credentials with, if any
+        passphrase : Option<String>,
+    },
+
+    /// The user wants to see what is in a credential file.
+    Show {
+        /// The file that was read
+        file       : PathBuf,
+        /// The credentials in it
+        credential : Credential,
     },
 
     /// The user wants to login somewhere remotely.
     Login {
         /// The hostname of the host to login to.
-        host       : url::Url,
-        /// The credentials to login
-        credential : Credential,
+        host     : url::Url,
+        /// The username to login with
+        username : String,
+        /// The (plain-text) password to login with
+        password : String,
+    },
+
+    /// The user wants to change their password somewhere remotely.
+    Passwd {
+        /// The hostname of the host to change the password on.
+        host         : url::Url,
+        /// The username whose password to change
+        username     : String,
+        /// The current (plain-text) password of the user
+        old_password : String,
+        /// The new (plain-text) password of the user
+        new_password : String,
+    },
+
+    /// The user wants to reset a forgotten password somewhere remotely.
+    ResetPassword {
+        /// The hostname of the host to reset the password on.
+        host         : url::Url,
+        /// The one-time token that allows the reset
+        reset_token  : String,
+        /// The new (plain-text) password of the user
+        new_password : String,
     },
 
     /// The user wants to run the tool normally.
@@ -234,6 +320,33 @@ pub enum Action {
 
 
 
+/// Defines how to verify the certificates of hosts.
+#[derive(Clone, Debug, Default)]
+pub struct TlsConfig {
+    /// If true, certificates are not verified at all.
+    pub insecure : bool,
+    /// The settings for specific hosts, by 'hostname' or 'hostname:port'.
+    pub hosts    : HashMap<String, HostTls>,
+}
+
+impl TlsConfig {
+    /// Returns the settings for the host in the given URL, if any.
+    /// 
+    /// **Arguments**
+    ///  * `url`: The URL to find the settings for. Settings for 'hostname:port' take precedence over those for 'hostname'.
+    /// 
+    /// **Returns**  
+    /// The HostTls for that host, or None if it has no specific settings.
+    pub fn host(&self, url: &url::Url) -> Option<&HostTls> {
+        let host: &str = url.host_str()?;
+        url.port_or_known_default()
+            .and_then(|port| self.hosts.get(&format!("{}:{}", host, port)))
+            .or_else(|| self.hosts.get(host))
+    }
+}
+
+
+
 /// Joins the Arguments and the File together in one Config struct.
 #[derive(Debug)]
 pub struct Config {
@@ -241,6 +354,8 @@ pub struct Config {
     pub config_path : PathBuf,
     /// The location of the logging file
     pub log_path    : PathBuf,
+    /// How to verify the certificates of hosts
+    pub tls         : TlsConfig,
 
     /// The subcommand that is run
     pub action : Action,
@@ -263,7 +378,7 @@ impl Config {
                 // If it's not-found, we generate it first
                 if err.kind() == std::io::ErrorKind::NotFound {
                     // Make sure the path exists
-                    if let Err(err) = fs::create_dir_all(&args.config_path.parent().expect("Config path does not have a parent-part; this should never happen!")) {
+                    if let Err(err) = fs::create_dir_all(args.config_path.parent().expect("Config path does not have a parent-part; this should never happen!")) {
                         return Err(Error::DirCreateError{ path: args.config_path, err });
                     }
 
@@ -310,13 +425,13 @@ impl Config {
         // Resolve the toplevel arguments in the config first
         let config_path = args.config_path;
         let log_path    = args.log_path.unwrap_or(file.log_path);
+        let tls         = TlsConfig{ insecure: args.insecure, hosts: file.tls };
 
         // Next, match on the Arguments' subcommand to make an Action
         let action = match args.subcommand {
-            ArgumentSubcommand::Generate{ output, username, password } => {
+            ArgumentSubcommand::Generate{ output, host, encrypt, username, password } => {
                 // Decide what method of authentication to use
-                let cred: Credential;
-                if password {
+                let cred: Credential = if password {
                     // Prompt the user for a password
                     let password1 = match rpassword::prompt_password("Credential password:") {
                         Ok(password) => password,
@@ -331,40 +446,144 @@ impl Config {
                     // Be sure they are the same
                     if password1 != password2 { return Err(Error::UnmatchingPasswords); }
 
+                    // Refuse weak passwords; hosts enforce their own policy, but these credentials may never reach one
+                    if let Err(err) = PasswordPolicy::default().check(&username, &password1) { return Err(Error::WeakPassword{ err }); }
+
                     // Create a Credential from it
-                    cred = match Credential::from_plain(username, password1) {
+                    match Credential::from_plain(username, password1) {
                         Ok(cred) => cred,
                         Err(err) => { return Err(Error::CredentialError{ err }); }
-                    };
+                    }
                 } else {
                     return Err(Error::NoCredentials);
-                }
+                };
+                let cred: Credential = match host {
+                    Some(host) => cred.with_host(host.as_str()),
+                    None       => cred,
+                };
+
+                // Ask for the passphrase to encrypt it with, if any
+                let passphrase: Option<String> = if encrypt {
+                    let passphrase1 = match rpassword::prompt_password("Encryption passphrase:") {
+                        Ok(passphrase) => passphrase,
+                        Err(err)       => { return Err(Error::PasswordPromptError{ err }); }
+                    };
+                    let passphrase2 = match rpassword::prompt_password("Verify passphrase:") {
+                        Ok(passphrase) => passphrase,
+                        Err(err)       => { return Err(Error::PasswordPromptError{ err }); }
+                    };
+                    if passphrase1.is_empty() { return Err(Error::EmptyPassphrase); }
+                    if passphrase1 != passphrase2 { return Err(Error::UnmatchingPassphrases); }
+                    Some(passphrase1)
+                } else {
+                    None
+                };
+
+                // Create the action with that
+                Action::Generate{ output, credential: cred, passphrase }
+            },
+
+            ArgumentSubcommand::Show{ file } => {
+                // Only ask for a passphrase if the file turns out to need one
+                let cred: Credential = match Credential::from_file(&file) {
+                    Ok(cred)                                  => cred,
+                    Err(CredentialError::MissingPassphrase) => {
+                        let passphrase = match rpassword::prompt_password(format!("Passphrase for '{}':", file.display())) {
+                            Ok(passphrase) => passphrase,
+                            Err(err)       => { return Err(Error::PasswordPromptError{ err }); }
+                        };
+                        match Credential::from_file_with(&file, Some(&passphrase)) {
+                            Ok(cred) => cred,
+                            Err(err) => { return Err(Error::CredentialError{ err }); }
+                        }
+                    },
+                    Err(err) => { return Err(Error::CredentialError{ err }); }
+                };
 
                 // Create the action with that
-                Action::Generate{ output, credential: cred }
+                Action::Show{ file, credential: cred }
             },
 
             ArgumentSubcommand::Login{ host, username, password } => {
                 // Decide what method of authentication to use
-                let cred: Credential;
-                if password {
+                let password: String = if password {
                     // Prompt the user for a password
-                    let password = match rpassword::prompt_password(format!("Password for '{}':", &host)) {
+                    match rpassword::prompt_password(format!("Password for '{}':", &host)) {
                         Ok(password) => password,
                         Err(err)     => { return Err(Error::PasswordPromptError{ err }); }  
-                    };
-
-                    // Create a Credential from it
-                    cred = match Credential::from_plain(username, password) {
-                        Ok(cred) => cred,
-                        Err(err) => { return Err(Error::CredentialError{ err }); }
-                    };
+                    }
                 } else {
                     return Err(Error::NoCredentials);
-                }
+                };
 
                 // With the user having provided us their credentials, build the action
-                Action::Login{ host, credential: cred }
+                Action::Login{ host, username, password }
+            },
+
+            ArgumentSubcommand::Passwd{ host, username } => {
+                // Resolve the host
+                let host = match host {
+                    Some(host) => host,
+                    None => match file.host {
+                        Some(host) => host.0,
+                        None       => { return Err(Error::NotLoggedIn); }
+                    }
+                };
+
+                // Prompt the user for their current password
+                let old_password = match rpassword::prompt_password(format!("Current password for '{}':", &host)) {
+                    Ok(password) => password,
+                    Err(err)     => { return Err(Error::PasswordPromptError{ err }); }
+                };
+                // Prompt the user for a new password
+                let new_password1 = match rpassword::prompt_password("New password:") {
+                    Ok(password) => password,
+                    Err(err)     => { return Err(Error::PasswordPromptError{ err }); }
+                };
+                // Prompt the user for the new password again to verify
+                let new_password2 = match rpassword::prompt_password("Verify password:") {
+                    Ok(password) => password,
+                    Err(err)     => { return Err(Error::PasswordPromptError{ err }); }
+                };
+
+                // Be sure they are the same
+                if new_password1 != new_password2 { return Err(Error::UnmatchingPasswords); }
+
+                // Use that to build the action
+                Action::Passwd{ host, username, old_password, new_password: new_password1 }
+            },
+
+            ArgumentSubcommand::ResetPassword{ host } => {
+                // Resolve the host
+                let host = match host {
+                    Some(host) => host,
+                    None => match file.host {
+                        Some(host) => host.0,
+                        None       => { return Err(Error::NotLoggedIn); }
+                    }
+                };
+
+                // Prompt the user for the token they got
+                let reset_token = match rpassword::prompt_password(format!("Reset token for '{}':", &host)) {
+                    Ok(reset_token) => reset_token.trim().to_string(),
+                    Err(err)        => { return Err(Error::PasswordPromptError{ err }); }
+                };
+                // Prompt the user for a new password
+                let new_password1 = match rpassword::prompt_password("New password:") {
+                    Ok(password) => password,
+                    Err(err)     => { return Err(Error::PasswordPromptError{ err }); }
+                };
+                // Prompt the user for the new password again to verify
+                let new_password2 = match rpassword::prompt_password("Verify password:") {
+                    Ok(password) => password,
+                    Err(err)     => { return Err(Error::PasswordPromptError{ err }); }
+                };
+
+                // Be sure they are the same
+                if new_password1 != new_password2 { return Err(Error::UnmatchingPasswords); }
+
+                // Use that to build the action
+                Action::ResetPassword{ host, reset_token, new_password: new_password1 }
             },
 
             ArgumentSubcommand::Run{ host } => {
@@ -386,7 +605,8 @@ impl Config {
         Ok(Self {
             config_path,
             log_path,
-            
+            tls,
+
             action,
         })
     }
@@ -4,7 +4,7 @@
  * Created:
  *   17 Mar 2022, 09:26:00
  * Last edited:
- *   19 Mar 2022, 21:42:23
+ *   18 Oct 2026, 06:42:40
  * Auto updated?
  *   Yes
  *
@@ -19,7 +19,9 @@ use std::path::PathBuf;
 use reqwest::StatusCode;
 use url::Url;
 
+use todo_spec::api::ApiError;
 use todo_spec::credentials::Error as CredentialError;
+use todo_spec::password::Error as PasswordError;
 
 
 /***** ERRORS *****/
@@ -44,10 +46,16 @@ pub enum ConfigError {
     NoCredentials,
     /// Could not create a Credential struct
     CredentialError{ err: CredentialError },
+    /// The given password does not meet the password policy
+    WeakPassword{ err: PasswordError },
     /// Could not prompt the user for a password
     PasswordPromptError{ err: std::io::Error },
     /// The first password and the second password asked do not match
     UnmatchingPasswords,
+    /// The passphrase to encrypt a credential file with was empty
+    EmptyPassphrase,
+    /// The first passphrase and the second passphrase asked do not match
+    UnmatchingPassphrases,
 
     /// The user was not logged in
     NotLoggedIn,
@@ -66,8 +74,11 @@ impl Display for ConfigError {
 
             ConfigError::NoCredentials              => write!(f, "Did not specify a method to provide credentials"),
             ConfigError::CredentialError{ err }     => write!(f, "Could not create a Credential: {}", err),
+            ConfigError::WeakPassword{ err }        => write!(f, "{}", err),
             ConfigError::PasswordPromptError{ err } => write!(f, "Could not prompt for a password: {}", err),
             ConfigError::UnmatchingPasswords        => write!(f, "Passwords do not match; aborting"),
+            ConfigError::EmptyPassphrase            => write!(f, "Passphrase may not be empty; aborting"),
+            ConfigError::UnmatchingPassphrases      => write!(f, "Passphrases do not match; aborting"),
 
             ConfigError::NotLoggedIn => write!(f, "You are not logged-in; run the login subcommand first"),
         }
@@ -123,21 +134,77 @@ pub enum LoginError {
     SerializeError{ err: serde_json::Error },
     /// Could not append a path to a host URL
     UrlJoinError{ host: Url, path: String, err: url::ParseError },
+    /// Could not prepare the HTTP client for the host
+    TlsError{ err: TlsError },
     /// Could not send the login request.
     RequestError{ err: reqwest::Error },
-    /// The server returned a non-valid response
-    ResponseError{ status: StatusCode, response: String },
+    /// The host presented a certificate that we do not trust
+    CertificateError{ host: Url, reason: String },
+    /// The server refused the request with the given error
+    ResponseError{ status: StatusCode, error: ApiError },
+    /// The server returned a non-valid response that isn't an `ApiError` either
+    UnexpectedResponse{ status: StatusCode, response: String },
+    /// Could not parse the body of the server's response
+    ResponseParseError{ err: reqwest::Error },
+
+    /// Could not prompt the user for their second factor
+    CodePromptError{ err: std::io::Error },
+
+    /// The server refused the given username or password
+    InvalidCredentials,
+    /// The server refused the given second factor
+    InvalidCode,
+    /// The server refused the given password reset token
+    InvalidResetToken,
 }
 
 impl Display for LoginError {
     fn fmt(&self, f: &mut Formatter<'_>) -> FResult {
         match self {
-            LoginError::SerializeError{ err }           => write!(f, "Could not serialize the login request body: {}", err),
-            LoginError::UrlJoinError{ host, path, err } => write!(f, "Could not append path '{}' to host '{}': {}", path, host, err),
-            LoginError::RequestError{ err }             => write!(f, "Could not send login request: {}", err),
-            LoginError::ResponseError{ status, response } => write!(f, "Host responded with status code {}{}\n\nResponse:\n{}\n", status.as_u16(), if status.canonical_reason().is_some() { format!(" ({})", status.canonical_reason().unwrap()) } else { String::new() }, response),
+            LoginError::SerializeError{ err }                  => write!(f, "Could not serialize the login request body: {}", err),
+            LoginError::UrlJoinError{ host, path, err }        => write!(f, "Could not append path '{}' to host '{}': {}", path, host, err),
+            LoginError::TlsError{ err }                        => write!(f, "{}", err),
+            LoginError::RequestError{ err }                    => write!(f, "Could not send login request: {}", err),
+            LoginError::CertificateError{ host, reason }       => write!(f, "Could not verify the certificate of '{}': {}\n\nIf the host uses a self-signed or private certificate, add its CA bundle ('ca_bundle') or SHA-256 fingerprint ('fingerprint') under 'tls' in the config file.", host, reason),
+            LoginError::ResponseError{ status, error }         => write!(f, "{} (host responded with {} '{}')", error, status.as_u16(), error.code),
+            LoginError::UnexpectedResponse{ status, response } => write!(f, "Host responded with status code {}{}\n\nResponse:\n{}\n", status.as_u16(), if status.canonical_reason().is_some() { format!(" ({})", status.canonical_reason().unwrap()) } else { String::new() }, response),
+            LoginError::ResponseParseError{ err }              => write!(f, "Could not parse host response: {}", err),
+
+            LoginError::CodePromptError{ err } => write!(f, "Could not prompt for an authentication code: {}", err),
+
+            LoginError::InvalidCredentials => write!(f, "Invalid username or password"),
+            LoginError::InvalidCode        => write!(f, "Invalid authentication code"),
+            LoginError::InvalidResetToken  => write!(f, "Invalid or expired reset token"),
         }
     }
 }
 
 impl Error for LoginError {}
+
+
+
+/// Errors that occur while preparing TLS connections.
+#[derive(Debug)]
+pub enum TlsError {
+    /// Could not read the given CA bundle
+    CaBundleReadError{ path: PathBuf, err: std::io::Error },
+    /// The given CA bundle does not contain any (valid) certificates
+    EmptyCaBundle{ path: PathBuf },
+    /// The fingerprint configured for a host is not a SHA-256 hash
+    IllegalFingerprint{ host: String, raw: String },
+    /// Could not build the HTTP client
+    ClientBuildError{ err: reqwest::Error },
+}
+
+impl Display for TlsError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FResult {
+        match self {
+            TlsError::CaBundleReadError{ path, err }  => write!(f, "Could not read CA bundle '{}': {}", path.display(), err),
+            TlsError::EmptyCaBundle{ path }           => write!(f, "CA bundle '{}' does not contain any PEM-encoded certificates", path.display()),
+            TlsError::IllegalFingerprint{ host, raw } => write!(f, "Fingerprint '{}' for host '{}' is not a SHA-256 hash (expected 64 hexadecimal digits, optionally separated by colons)", raw, host),
+            TlsError::ClientBuildError{ err }         => write!(f, "Could not build HTTP client: {}", err),
+        }
+    }
+}
+
+impl Error for TlsError {}
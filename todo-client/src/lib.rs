@@ -4,7 +4,7 @@
  * Created:
  *   16 Mar 2022, 18:00:42
  * Last edited:
- *   19 Mar 2022, 21:29:03
+ *   18 Oct 2026, 07:15:57
  * Auto updated?
  *   Yes
  *
@@ -12,6 +12,9 @@
  *   Library module for the todo-client.
 **/
 
+// We keep unit tests at the top of each file
+#![allow(clippy::items_after_test_module)]
+
 /// Enables the use of the lazy_static! macro
 #[macro_use] extern crate lazy_static;
 
@@ -22,5 +25,7 @@ pub mod cli;
 /// Implements the terminal UI.
 pub mod tui;
 
+/// Builds HTTP clients that verify hosts as configured
+pub mod tls;
 /// Handles everything login-related
 pub mod login;
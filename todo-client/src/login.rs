@@ -4,7 +4,7 @@
  * Created:
  *   19 Mar 2022, 21:26:21
  * Last edited:
- *   19 Mar 2022, 22:06:06
+ *   18 Oct 2026, 06:11:04
  * Auto updated?
  *   Yes
  *
@@ -16,57 +16,281 @@ use url::Url;
 
 use log::{debug, info};
 use reqwest::StatusCode;
-use reqwest::blocking::Response;
-use todo_spec::api::LoginJson;
-use todo_spec::credentials::Credential;
+use reqwest::blocking::{Client, Response};
+use todo_spec::api::{ApiError, ErrorCode, LoginJson, PasswordJson, PasswordResetJson, SecondFactorJson, SecondFactorLoginJson, TokenJson};
 
 use crate::errors::LoginError as Error;
+use crate::tls;
+
+
+/***** HELPER FUNCTIONS *****/
+/// Computes the URL of the given API path on the given host.
+/// 
+/// **Arguments**
+///  * `host`: The host that serves the API.
+///  * `path`: The path of the endpoint on that host.
+/// 
+/// **Returns**  
+/// The full URL of the endpoint on success, or an Error otherwise.
+fn endpoint(host: &Url, path: &str) -> Result<Url, Error> {
+    match host.join(path) {
+        Ok(url)  => Ok(url),
+        Err(err) => Err(Error::UrlJoinError{ host: host.clone(), path: path.to_string(), err }),
+    }
+}
+
+/// Describes the device we are running on, for the host's list of sessions.
+/// 
+/// **Returns**  
+/// 'todoctl on <hostname>' if the hostname is known, or just 'todoctl' otherwise.
+fn device_name() -> String {
+    match std::env::var("HOSTNAME").or_else(|_| std::env::var("COMPUTERNAME")) {
+        Ok(host) if !host.is_empty() => format!("todoctl on {}", host),
+        _                            => String::from("todoctl"),
+    }
+}
+
+/// Turns an error while sending a request into an Error, explaining it if the host's certificate was refused.
+/// 
+/// **Arguments**
+///  * `host`: The host that we tried to send the request to.
+///  * `err`: The error that reqwest returned.
+/// 
+/// **Returns**  
+/// The matching Error.
+fn request_error(host: &Url, err: reqwest::Error) -> Error {
+    match tls::certificate_error(&err) {
+        Some(reason) => Error::CertificateError{ host: host.clone(), reason },
+        None         => Error::RequestError{ err },
+    }
+}
+
+/// Turns an unexpected response into an Error.
+/// 
+/// **Arguments**
+///  * `response`: The Response with the unexpected status code.
+/// 
+/// **Returns**  
+/// An `Error::ResponseError` if the host sent an `ApiError`, or an `Error::UnexpectedResponse` with the raw body otherwise.
+fn unexpected(response: Response) -> Error {
+    let status = response.status();
+    let body: String = response.text().unwrap_or_else(|_| "<unparseable response>".to_string());
+    match serde_json::from_str::<ApiError>(&body) {
+        Ok(error) => Error::ResponseError{ status, error },
+        Err(_)    => Error::UnexpectedResponse{ status, response: body },
+    }
+}
+
+/// Turns a response that refuses the request into an Error.
+/// 
+/// **Arguments**
+///  * `response`: The Response that refuses the request.
+///  * `codes`: The error codes that mean the host refused what the user gave us (e.g., their password).
+///  * `refusal`: The Error to return for those codes.
+/// 
+/// **Returns**  
+/// `refusal` if the host refused with one of the given codes (or didn't send an `ApiError` at all), or whatever `unexpected()` makes of it otherwise.
+fn refused(response: Response, codes: &[ErrorCode], refusal: Error) -> Error {
+    match unexpected(response) {
+        Error::ResponseError{ error, .. } if codes.contains(&error.code) => refusal,
+        Error::UnexpectedResponse{ .. }                                  => refusal,
+        err                                                              => err,
+    }
+}
+
+
+
+
+
+/// Completes a login that requires a second factor, by prompting the user for a code and sending it to the host.
+/// 
+/// **Arguments**
+///  * `client`: The Client to send the request with (see `tls::client()`).
+///  * `host`: The host to login to.
+///  * `challenge`: The challenge that the host handed out for this login.
+/// 
+/// **Returns**  
+/// The tokens of the new session on success, or an Error otherwise. If the code is refused, returns `Error::InvalidCode`.
+fn second_factor(client: &Client, host: &Url, challenge: SecondFactorJson) -> Result<TokenJson, Error> {
+    // Ask the user for the code
+    let code: String = match rpassword::prompt_password("Authentication code (or recovery code):") {
+        Ok(code) => code,
+        Err(err) => { return Err(Error::CodePromptError{ err }); }
+    };
+
+    // Send it to the host
+    let url = endpoint(host, "v1/login/totp")?;
+    debug!("Sending second factor to '{}'...", &url);
+    let response: Response = match client.post(url.to_string())
+        .json(&SecondFactorLoginJson{ challenge: challenge.challenge, code })
+        .send()
+    {
+        Ok(response) => response,
+        Err(err)     => { return Err(request_error(host, err)); }
+    };
+
+    // Parse the tokens from the response
+    let status = response.status();
+    debug!("Host '{}' responsed with status code {} ({})", host, status.as_u16(), status.canonical_reason().unwrap_or("???"));
+    match status {
+        StatusCode::OK                                   => response.json().map_err(|err| Error::ResponseParseError{ err }),
+        StatusCode::FORBIDDEN | StatusCode::UNAUTHORIZED => Err(refused(response, &[ ErrorCode::InvalidCode, ErrorCode::Unauthorized ], Error::InvalidCode)),
+        _                                                => Err(unexpected(response)),
+    }
+}
+
+
+
 
 
 /***** LIBRARY FUNCTIONS *****/
 /// Tentatively logs a user in, just returning true or false depending on whether it was a success.
 /// 
 /// **Arguments**
+///  * `client`: The Client to send the requests with (see `tls::client()`).
 ///  * `host`: The host to login to.
-///  * `cred`: The Credentials to login with.
+///  * `username`: The name of the user to login as.
+///  * `password`: The (plain-text) password of the user.
 /// 
 /// **Returns**  
 /// Whether or not the credentials provided are valid for this host on success, or an Error otherwise.
-pub fn test_login(host: Url, cred: Credential) -> Result<bool, Error> {
+pub fn test_login(client: &Client, host: Url, username: String, password: String) -> Result<bool, Error> {
     info!("Testing credentials at '{}'...", &host);
 
-    // Prepare the request body
-    println!("{}", cred.pass());
-    let body = LoginJson{ username: cred.user().to_string(), password: cred.pass().to_string() };
-    let body = match serde_json::to_string(&body) {
-        Ok(body) => body,
-        Err(err) => { return Err(Error::SerializeError{ err }); }
-    };
-
     // Compute the path to send the request to
-    let url = match host.join("v1/login/test") {
-        Ok(url)  => url,
-        Err(err) => { return Err(Error::UrlJoinError{ host, path: "v1/login/test".to_string(), err }); }
-    };
+    let url = endpoint(&host, "v1/login/test")?;
 
     // Send the login request
     debug!("Sending test login request to '{}'...", &url);
-    let client = reqwest::blocking::Client::new();
-    let response: Response = match client.post(&url.to_string())
-        .body(body)
+    let response: Response = match client.post(url.to_string())
+        .json(&LoginJson{ username, password, device: None })
+        .send()
+    {
+        Ok(response) => response,
+        Err(err)     => { return Err(request_error(&host, err)); }
+    };
+
+    // Match the result on the status
+    let status = response.status();
+    debug!("Host '{}' responsed with status code {} ({})", &host, status.as_u16(), status.canonical_reason().unwrap_or("???"));
+    match status {
+        StatusCode::OK                                => Ok(true),
+        StatusCode::NOT_FOUND | StatusCode::FORBIDDEN => match refused(response, &[ ErrorCode::InvalidCredentials, ErrorCode::UnknownUser ], Error::InvalidCredentials) {
+            Error::InvalidCredentials => Ok(false),
+            err                       => Err(err),
+        },
+        _ => Err(unexpected(response)),
+    }
+}
+
+
+
+/// Logs a user in, returning the tokens handed out by the host.
+/// 
+/// If the user has enabled two-factor authentication, this prompts them for a code on the terminal.
+/// 
+/// **Arguments**
+///  * `client`: The Client to send the requests with (see `tls::client()`).
+///  * `host`: The host to login to.
+///  * `username`: The name of the user to login as.
+///  * `password`: The (plain-text) password of the user.
+/// 
+/// **Returns**  
+/// The tokens of the new session on success, or an Error otherwise. If the credentials are refused, returns `Error::InvalidCredentials`; if the second factor is, returns `Error::InvalidCode`.
+pub fn login(client: &Client, host: &Url, username: &str, password: &str) -> Result<TokenJson, Error> {
+    info!("Logging in at '{}'...", host);
+
+    // Send the login request
+    let url = endpoint(host, "v1/login")?;
+    debug!("Sending login request to '{}'...", &url);
+    let response: Response = match client.post(url.to_string())
+        .json(&LoginJson{ username: username.to_string(), password: password.to_string(), device: Some(device_name()) })
+        .send()
+    {
+        Ok(response) => response,
+        Err(err)     => { return Err(request_error(host, err)); }
+    };
+
+    // Parse the tokens from the response
+    let status = response.status();
+    debug!("Host '{}' responsed with status code {} ({})", host, status.as_u16(), status.canonical_reason().unwrap_or("???"));
+    match status {
+        StatusCode::OK                                => response.json().map_err(|err| Error::ResponseParseError{ err }),
+        StatusCode::ACCEPTED                          => second_factor(client, host, response.json().map_err(|err| Error::ResponseParseError{ err })?),
+        StatusCode::NOT_FOUND | StatusCode::FORBIDDEN => Err(refused(response, &[ ErrorCode::InvalidCredentials, ErrorCode::UnknownUser ], Error::InvalidCredentials)),
+        _                                             => Err(unexpected(response)),
+    }
+}
+
+
+
+/// Changes the password of the given user.
+/// 
+/// **Arguments**
+///  * `client`: The Client to send the requests with (see `tls::client()`).
+///  * `host`: The host to change the password on.
+///  * `username`: The name of the user whose password to change.
+///  * `old_password`: The current (plain-text) password of the user.
+///  * `new_password`: The new (plain-text) password of the user.
+/// 
+/// **Returns**  
+/// Nothing on success, or an Error otherwise. If the current credentials are refused, returns `Error::InvalidCredentials`.
+pub fn change_password(client: &Client, host: Url, username: String, old_password: String, new_password: String) -> Result<(), Error> {
+    // Login first to prove who we are
+    let tokens: TokenJson = login(client, &host, &username, &old_password)?;
+
+    // Send the password change request
+    let url = endpoint(&host, "v1/password")?;
+    debug!("Sending password change request to '{}'...", &url);
+    let response: Response = match client.post(url.to_string())
+        .bearer_auth(&tokens.access_token)
+        .json(&PasswordJson{ old_password, new_password })
         .send()
     {
         Ok(response) => response,
-        Err(err)     => { return Err(Error::RequestError{ err }); }
+        Err(err)     => { return Err(request_error(&host, err)); }
     };
 
     // Check if any errors occured
     let status = response.status();
     debug!("Host '{}' responsed with status code {} ({})", &host, status.as_u16(), status.canonical_reason().unwrap_or("???"));
-    if status != StatusCode::OK && status != StatusCode::NOT_FOUND && status != StatusCode::FORBIDDEN {
-        return Err(Error::ResponseError{ status, response: response.text().unwrap_or("<unparseable response>".to_string()) });
+    match status {
+        StatusCode::OK        => Ok(()),
+        StatusCode::FORBIDDEN => Err(refused(response, &[ ErrorCode::InvalidCredentials ], Error::InvalidCredentials)),
+        _                     => Err(unexpected(response)),
     }
+}
+
 
-    // Done; match the result of the status
-    Ok(response.status() == StatusCode::OK)
+
+/// Sets a new password on the given host using a one-time reset token handed out by an administrator.
+/// 
+/// **Arguments**
+///  * `client`: The Client to send the request with (see `tls::client()`).
+///  * `host`: The host to reset the password on.
+///  * `reset_token`: The reset token that the administrator handed out.
+///  * `new_password`: The new (plain-text) password of the user.
+/// 
+/// **Returns**  
+/// Nothing on success, or an Error otherwise. If the token is refused, returns `Error::InvalidResetToken`.
+pub fn reset_password(client: &Client, host: Url, reset_token: String, new_password: String) -> Result<(), Error> {
+    // Send the password reset request
+    let url = endpoint(&host, "v1/password/reset")?;
+    debug!("Sending password reset request to '{}'...", &url);
+    let response: Response = match client.post(url.to_string())
+        .json(&PasswordResetJson{ reset_token, new_password })
+        .send()
+    {
+        Ok(response) => response,
+        Err(err)     => { return Err(request_error(&host, err)); }
+    };
+
+    // Check if any errors occured
+    let status = response.status();
+    debug!("Host '{}' responsed with status code {} ({})", &host, status.as_u16(), status.canonical_reason().unwrap_or("???"));
+    match status {
+        StatusCode::OK           => Ok(()),
+        StatusCode::UNAUTHORIZED => Err(refused(response, &[ ErrorCode::Unauthorized ], Error::InvalidResetToken)),
+        _                        => Err(unexpected(response)),
+    }
 }
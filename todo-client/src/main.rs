@@ -4,7 +4,7 @@
  * Created:
  *   16 Mar 2022, 18:01:21
  * Last edited:
- *   19 Mar 2022, 21:52:57
+ *   18 Oct 2026, 06:42:40
  * Auto updated?
  *   Yes
  *
@@ -18,7 +18,8 @@ use log::{error, info};
 use simplelog::{LevelFilter, WriteLogger};
 
 use todo_client::cli::{Action, Config};
-use todo_client::login;
+use todo_client::errors::LoginError;
+use todo_client::{login, tls};
 use todo_client::tui::TerminalUi;
 
 
@@ -47,29 +48,74 @@ fn main() {
 
     // Switch on the subcommand used
     match config.action {
-        Action::Generate{ output, credential } => {
+        Action::Generate{ output, credential, passphrase } => {
             info!("Generating credentials...");
             println!("Generating credentials...");
 
             // Simply call the credential's function
-            if let Err(err) = credential.serialize_to_file(output) { error!("{}", &err); eprintln!("{}", err); std::process::exit(1); }
+            let res = match passphrase {
+                Some(passphrase) => credential.serialize_to_file_encrypted(output, &passphrase),
+                None             => credential.serialize_to_file(output),
+            };
+            if let Err(err) = res { error!("{}", &err); eprintln!("{}", err); std::process::exit(1); }
             println!("Done.\n");
         }
 
-        Action::Login{ host, credential } => {
+        Action::Show{ file, credential } => {
+            info!("Showing credentials in '{}'...", file.display());
+            println!("Username  : {}", credential.user());
+            println!("Created   : {}", credential.created().map(|created| created.to_string()).unwrap_or_else(|| String::from("unknown")));
+            println!("Host      : {}", credential.host().unwrap_or("any"));
+            println!("Encrypted : {}", if credential.encrypted() { "yes" } else { "no" });
+            println!();
+        }
+
+        Action::Login{ host, username, password } => {
             info!("Attempting to connect to '{}'...", &host);
 
-            // Call the appropriate function
-            let result = match login::test_login(host, credential) {
-                Ok(result) => result,
+            // Call the appropriate function (which asks for a second factor if the server wants one)
+            let client = match tls::client(&config.tls, &host) {
+                Ok(client) => client,
                 Err(err)   => { error!("{}", &err); eprintln!("Login failed: {}", err); std::process::exit(1); }
             };
+            match login::login(&client, &host, &username, &password) {
+                Ok(_)                               => { println!("Login OK"); },
+                Err(LoginError::InvalidCredentials) => { println!("Login failed: invalid credentials"); },
+                Err(LoginError::InvalidCode)        => { println!("Login failed: invalid authentication code"); },
+                Err(err)                            => { error!("{}", &err); eprintln!("Login failed: {}", err); std::process::exit(1); }
+            }
+            println!();
+        },
+
+        Action::Passwd{ host, username, old_password, new_password } => {
+            info!("Changing password at '{}'...", &host);
+
+            // Call the appropriate function
+            let client = match tls::client(&config.tls, &host) {
+                Ok(client) => client,
+                Err(err)   => { error!("{}", &err); eprintln!("Password change failed: {}", err); std::process::exit(1); }
+            };
+            match login::change_password(&client, host, username, old_password, new_password) {
+                Ok(_)                               => { println!("Password changed; all other sessions have been logged out"); },
+                Err(LoginError::InvalidCredentials) => { println!("Password change failed: invalid credentials"); },
+                Err(LoginError::InvalidCode)        => { println!("Password change failed: invalid authentication code"); },
+                Err(err)                            => { error!("{}", &err); eprintln!("Password change failed: {}", err); std::process::exit(1); }
+            }
+            println!();
+        },
+
+        Action::ResetPassword{ host, reset_token, new_password } => {
+            info!("Resetting password at '{}'...", &host);
 
-            // Show the result
-            if result {
-                println!("Login OK");
-            } else {
-                println!("Login failed: invalid credentials");
+            // Call the appropriate function
+            let client = match tls::client(&config.tls, &host) {
+                Ok(client) => client,
+                Err(err)   => { error!("{}", &err); eprintln!("Password reset failed: {}", err); std::process::exit(1); }
+            };
+            match login::reset_password(&client, host, reset_token, new_password) {
+                Ok(_)                              => { println!("Password reset; all sessions have been logged out"); },
+                Err(LoginError::InvalidResetToken) => { println!("Password reset failed: invalid or expired reset token"); },
+                Err(err)                           => { error!("{}", &err); eprintln!("Password reset failed: {}", err); std::process::exit(1); }
             }
             println!();
         },
@@ -0,0 +1,266 @@
+/* TLS.rs
+ *   by Lut99
+ *
+ * Created:
+ *   18 Oct 2026, 04:30:00
+ * Last edited:
+ *   18 Oct 2026, 07:15:57
+ * Auto updated?
+ *   Yes
+ *
+ * Description:
+ *   Builds the HTTP clients with which we talk to hosts, verifying their
+ *   certificates as configured: against the default CA's, against a
+ *   custom CA bundle, against a pinned fingerprint, or (with
+ *   '--insecure') not at all.
+**/
+
+use std::error::Error as _;
+use std::fs;
+use std::io::BufReader;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use log::{debug, warn};
+use reqwest::blocking::Client;
+use rustls::{Certificate, ClientConfig, RootCertStore, ServerName};
+use rustls::client::{ServerCertVerified, ServerCertVerifier, WebPkiVerifier};
+use sha2::{Digest, Sha256};
+use url::Url;
+
+pub use crate::errors::TlsError as Error;
+use crate::cli::TlsConfig;
+
+
+/***** UNIT TESTS *****/
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::cli::HostTls;
+
+    /// The fingerprint of the test certificate, in the format printed by openssl.
+    const FINGERPRINT: &str = "sha256 Fingerprint=00:11:22:33:44:55:66:77:88:99:AA:BB:CC:DD:EE:FF:00:11:22:33:44:55:66:77:88:99:AA:BB:CC:DD:EE:FF";
+
+    /// Builds a TlsConfig with the given settings for 'example.com'.
+    fn config(settings: HostTls) -> TlsConfig {
+        TlsConfig{ insecure: false, hosts: HashMap::from([ (String::from("example.com"), settings) ]) }
+    }
+
+    #[test]
+    fn test_parse_fingerprint() {
+        let expected: [u8; 32] = [ 0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88, 0x99, 0xAA, 0xBB, 0xCC, 0xDD, 0xEE, 0xFF, 0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88, 0x99, 0xAA, 0xBB, 0xCC, 0xDD, 0xEE, 0xFF ];
+
+        // The openssl prefix, colons, whitespace and case don't matter
+        assert_eq!(parse_fingerprint(FINGERPRINT), Some(expected));
+        assert_eq!(parse_fingerprint(&FINGERPRINT[19..]), Some(expected));
+        assert_eq!(parse_fingerprint(&FINGERPRINT[19..].replace(':', "").to_lowercase()), Some(expected));
+        assert_eq!(parse_fingerprint(&FINGERPRINT[19..].replace(':', " ")), Some(expected));
+
+        // Anything that isn't 32 bytes of hex is refused
+        assert_eq!(parse_fingerprint(&FINGERPRINT[19..FINGERPRINT.len() - 3]), None);
+        assert_eq!(parse_fingerprint(&format!("{}:00", &FINGERPRINT[19..])), None);
+        assert_eq!(parse_fingerprint(""), None);
+        assert_eq!(parse_fingerprint(&FINGERPRINT[19..].replace("AA", "AG")), None);
+        assert_eq!(parse_fingerprint(&format!("+F{}", &FINGERPRINT[21..])), None);
+        assert_eq!(parse_fingerprint(&FINGERPRINT[19..].replace("00:", "+0:")), None);
+    }
+
+    #[test]
+    fn test_pinned_verifier() {
+        let cert = Certificate(b"not really a certificate".to_vec());
+        let server_name = ServerName::try_from("example.com").unwrap();
+        let verify = |fingerprint: [u8; 32]| PinnedVerifier{ fingerprint, inner: None }.verify_server_cert(&cert, &[], &server_name, &mut std::iter::empty(), &[], SystemTime::now());
+
+        // Only the certificate with the pinned fingerprint is accepted
+        assert!(verify(Sha256::digest(&cert.0).into()).is_ok());
+        assert!(verify(parse_fingerprint(FINGERPRINT).unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_client() {
+        let host = Url::parse("https://example.com").unwrap();
+
+        // Broken settings are refused when building the client
+        let res = client(&config(HostTls{ ca_bundle: None, fingerprint: Some(String::from("+F:00")) }), &host);
+        assert!(matches!(res, Err(Error::IllegalFingerprint{ .. })));
+        let path = std::env::temp_dir().join(format!("todoctl-test-{}.pem", std::process::id()));
+        let res = client(&config(HostTls{ ca_bundle: Some(path.clone()), fingerprint: None }), &host);
+        assert!(matches!(res, Err(Error::CaBundleReadError{ .. })));
+        fs::write(&path, "not a certificate").unwrap();
+        let res = client(&config(HostTls{ ca_bundle: Some(path.clone()), fingerprint: None }), &host);
+        assert!(matches!(res, Err(Error::EmptyCaBundle{ .. })));
+        let _ = fs::remove_file(&path);
+
+        // Settings for other hosts, or '--insecure', don't need any
+        assert!(client(&config(HostTls::default()), &Url::parse("https://example.org").unwrap()).is_ok());
+        assert!(client(&config(HostTls{ ca_bundle: None, fingerprint: Some(FINGERPRINT.into()) }), &host).is_ok());
+        assert!(client(&TlsConfig{ insecure: true, hosts: HashMap::new() }, &host).is_ok());
+    }
+}
+
+
+
+
+
+/***** HELPER FUNCTIONS *****/
+/// Formats the given fingerprint the way openssl does (colon-separated uppercase hex).
+fn format_fingerprint(fingerprint: &[u8]) -> String {
+    fingerprint.iter().map(|b| format!("{:02X}", b)).collect::<Vec<String>>().join(":")
+}
+
+/// Parses the given fingerprint.
+/// 
+/// **Arguments**
+///  * `raw`: The fingerprint as hexadecimal digits. Colons, whitespace and case are ignored, as is an 'sha256 Fingerprint=' prefix as printed by openssl.
+/// 
+/// **Returns**  
+/// The 32 bytes of the fingerprint, or None if it is not a SHA-256 hash.
+fn parse_fingerprint(raw: &str) -> Option<[u8; 32]> {
+    let raw: &str = raw.rsplit('=').next().unwrap_or(raw);
+    let digits: Vec<u8> = raw.bytes().filter(|c| *c != b':' && !c.is_ascii_whitespace()).collect();
+    // Check the digits ourselves, since `u8::from_str_radix()` also accepts a leading '+'
+    if digits.len() != 64 || !digits.iter().all(u8::is_ascii_hexdigit) { return None; }
+    let mut res = [ 0; 32 ];
+    for (i, pair) in digits.chunks(2).enumerate() {
+        res[i] = u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok()?;
+    }
+    Some(res)
+}
+
+/// Loads the certificates in the given CA bundle.
+fn load_ca_bundle(path: &std::path::Path) -> Result<RootCertStore, Error> {
+    let data: Vec<u8> = match fs::read(path) {
+        Ok(data) => data,
+        Err(err) => { return Err(Error::CaBundleReadError{ path: path.to_path_buf(), err }); }
+    };
+    let certs: Vec<Vec<u8>> = match rustls_pemfile::certs(&mut BufReader::new(data.as_slice())) {
+        Ok(certs) => certs,
+        Err(err)  => { return Err(Error::CaBundleReadError{ path: path.to_path_buf(), err }); }
+    };
+    let mut roots = RootCertStore::empty();
+    let (added, ignored) = roots.add_parsable_certificates(&certs);
+    if ignored > 0 { warn!("Ignored {} invalid certificate(s) in CA bundle '{}'", ignored, path.display()); }
+    if added == 0 { return Err(Error::EmptyCaBundle{ path: path.to_path_buf() }); }
+    Ok(roots)
+}
+
+
+
+
+
+/***** HELPER STRUCTS *****/
+/// Accepts any certificate. Only used with '--insecure'.
+struct NoVerifier;
+
+impl ServerCertVerifier for NoVerifier {
+    fn verify_server_cert(&self, _end_entity: &Certificate, _intermediates: &[Certificate], _server_name: &ServerName, _scts: &mut dyn Iterator<Item = &[u8]>, _ocsp_response: &[u8], _now: SystemTime) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+}
+
+
+
+/// Only accepts the certificate with the given fingerprint (and, if given, only if it is also valid according to a CA bundle).
+struct PinnedVerifier {
+    /// The SHA-256 hash of the certificate we accept
+    fingerprint : [u8; 32],
+    /// Also verifies the chain, if any
+    inner       : Option<WebPkiVerifier>,
+}
+
+impl ServerCertVerifier for PinnedVerifier {
+    fn verify_server_cert(&self, end_entity: &Certificate, intermediates: &[Certificate], server_name: &ServerName, scts: &mut dyn Iterator<Item = &[u8]>, ocsp_response: &[u8], now: SystemTime) -> Result<ServerCertVerified, rustls::Error> {
+        let fingerprint = Sha256::digest(&end_entity.0);
+        if fingerprint.as_slice() != self.fingerprint {
+            return Err(rustls::Error::General(format!("certificate fingerprint {} does not match the pinned fingerprint {}", format_fingerprint(&fingerprint), format_fingerprint(&self.fingerprint))));
+        }
+        match &self.inner {
+            Some(inner) => inner.verify_server_cert(end_entity, intermediates, server_name, scts, ocsp_response, now),
+            None        => Ok(ServerCertVerified::assertion()),
+        }
+    }
+}
+
+
+
+
+
+/***** LIBRARY FUNCTIONS *****/
+/// Builds an HTTP client for the given host that verifies its certificate as configured.
+/// 
+/// **Arguments**
+///  * `config`: The TlsConfig that says how to verify certificates.
+///  * `host`: The URL of the host that the client will talk to.
+/// 
+/// **Returns**  
+/// The new Client on success, or an Error if the configuration for this host is invalid.
+pub fn client(config: &TlsConfig, host: &Url) -> Result<Client, Error> {
+    if host.scheme() == "http" {
+        warn!("Connecting to '{}' over plain HTTP; the password is sent unencrypted", host);
+        return Client::builder().build().map_err(|err| Error::ClientBuildError{ err });
+    }
+
+    // Decide how to verify the host
+    let builder = ClientConfig::builder().with_safe_defaults();
+    let tls_config: ClientConfig = if config.insecure {
+        warn!("Not verifying the certificate of '{}' (--insecure)", host);
+        eprintln!("WARNING: '--insecure' is given, so the certificate of '{}' is NOT verified.\nAnyone on the network may read or change what is sent, including your password. Only use this for local development!\n", host);
+        builder.with_custom_certificate_verifier(Arc::new(NoVerifier)).with_no_client_auth()
+    } else {
+        let settings = match config.host(host) {
+            Some(settings) => settings,
+            None           => {
+                debug!("Verifying '{}' against the default CA's", host);
+                return Client::builder().use_rustls_tls().build().map_err(|err| Error::ClientBuildError{ err });
+            },
+        };
+        let roots: Option<RootCertStore> = match &settings.ca_bundle {
+            Some(path) => {
+                debug!("Verifying '{}' against CA bundle '{}'", host, path.display());
+                Some(load_ca_bundle(path)?)
+            },
+            None => None,
+        };
+        match (&settings.fingerprint, roots) {
+            (Some(raw), roots) => {
+                let fingerprint: [u8; 32] = match parse_fingerprint(raw) {
+                    Some(fingerprint) => fingerprint,
+                    None              => { return Err(Error::IllegalFingerprint{ host: host.host_str().unwrap_or_default().to_string(), raw: raw.clone() }); }
+                };
+                debug!("Pinning the certificate of '{}' to {}", host, format_fingerprint(&fingerprint));
+                builder.with_custom_certificate_verifier(Arc::new(PinnedVerifier{ fingerprint, inner: roots.map(|roots| WebPkiVerifier::new(roots, None)) })).with_no_client_auth()
+            },
+            (None, Some(roots)) => builder.with_root_certificates(roots).with_no_client_auth(),
+            (None, None)        => {
+                return Client::builder().use_rustls_tls().build().map_err(|err| Error::ClientBuildError{ err });
+            },
+        }
+    };
+
+    // Build the client with it
+    Client::builder().use_preconfigured_tls(tls_config).build().map_err(|err| Error::ClientBuildError{ err })
+}
+
+
+
+/// Finds out if the given request error is caused by a certificate we refused.
+/// 
+/// **Arguments**
+///  * `err`: The error that reqwest returned.
+/// 
+/// **Returns**  
+/// Why the certificate was refused, or None if the error has another cause.
+pub fn certificate_error(err: &reqwest::Error) -> Option<String> {
+    let mut source: Option<&(dyn std::error::Error + 'static)> = err.source();
+    while let Some(err) = source {
+        if let Some(err) = err.downcast_ref::<rustls::Error>() { return Some(err.to_string()); }
+        // I/O errors don't list the error they wrap as their source, but that of the error they wrap (and they may wrap each other)
+        source = match err.downcast_ref::<std::io::Error>().and_then(|err| err.get_ref()) {
+            Some(inner) => Some(inner as &(dyn std::error::Error + 'static)),
+            None        => err.source(),
+        };
+    }
+    None
+}
@@ -4,7 +4,7 @@
  * Created:
  *   19 Mar 2022, 12:11:12
  * Last edited:
- *   19 Mar 2022, 21:05:25
+ *   18 Oct 2026, 06:24:13
  * Auto updated?
  *   Yes
  *
@@ -13,10 +13,305 @@
  *   client and services.
 **/
 
+use std::fmt::{Display, Formatter, Result as FResult};
+use std::str::FromStr;
+
 use serde::{Serialize, Deserialize};
 
+pub use crate::errors::AuditError as Error;
+use crate::jwt::Role;
+use crate::password::Violation;
+
+
+/***** LIBRARY ENUMS *****/
+/// Defines the machine-readable codes of the errors that the services may return.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    /// The request is malformed, e.g., because its body is not valid JSON or a field has an illegal value.
+    InvalidRequest,
+    /// The request lacks a valid access token, refresh token or challenge.
+    Unauthorized,
+    /// The given password is not correct.
+    InvalidCredentials,
+    /// The given username does not exist.
+    UnknownUser,
+    /// The given authentication code (or recovery code) is not correct.
+    InvalidCode,
+    /// The account has been disabled by an administrator.
+    AccountDisabled,
+    /// The account is locked because of too many failed logins.
+    AccountLocked,
+    /// The client has failed to login too often recently, and has to wait.
+    TooManyRequests,
+    /// The user is not allowed to do this.
+    Forbidden,
+    /// The requested resource (or route) does not exist.
+    NotFound,
+    /// The route exists, but not with this method.
+    MethodNotAllowed,
+    /// The request conflicts with the current state, e.g., because a user already exists.
+    Conflict,
+    /// The request body is too large.
+    PayloadTooLarge,
+    /// The service cannot reach a backend it depends on.
+    ServiceUnavailable,
+    /// The given password does not meet the password policy of the service.
+    WeakPassword,
+    /// The service is too busy to handle the request right now, and the client should try again later.
+    Overloaded,
+    /// Something went wrong in the service itself.
+    InternalError,
+}
+
+impl ErrorCode {
+    /// Returns the name of the code as it appears in the API.
+    #[inline]
+    pub fn name(&self) -> &'static str {
+        match self {
+            ErrorCode::InvalidRequest     => "invalid_request",
+            ErrorCode::Unauthorized       => "unauthorized",
+            ErrorCode::InvalidCredentials => "invalid_credentials",
+            ErrorCode::UnknownUser        => "unknown_user",
+            ErrorCode::InvalidCode        => "invalid_code",
+            ErrorCode::AccountDisabled    => "account_disabled",
+            ErrorCode::AccountLocked      => "account_locked",
+            ErrorCode::TooManyRequests    => "too_many_requests",
+            ErrorCode::Forbidden          => "forbidden",
+            ErrorCode::NotFound           => "not_found",
+            ErrorCode::MethodNotAllowed   => "method_not_allowed",
+            ErrorCode::Conflict           => "conflict",
+            ErrorCode::PayloadTooLarge    => "payload_too_large",
+            ErrorCode::ServiceUnavailable => "service_unavailable",
+            ErrorCode::WeakPassword       => "weak_password",
+            ErrorCode::Overloaded         => "overloaded",
+            ErrorCode::InternalError      => "internal_error",
+        }
+    }
+}
+
+impl Display for ErrorCode {
+    #[inline]
+    fn fmt(&self, f: &mut Formatter<'_>) -> FResult {
+        write!(f, "{}", self.name())
+    }
+}
+
+
+
+/// Defines the kinds of events that the authorization service records in its audit log.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuthEventKind {
+    /// A user provided valid credentials (and, if needed, a valid second factor).
+    LoginSuccess,
+    /// A login attempt was refused.
+    LoginFailure,
+    /// A new pair of tokens was handed out after a login.
+    TokenIssue,
+    /// A refresh token was exchanged for a new pair of tokens.
+    TokenRefresh,
+    /// Refresh tokens were revoked, either by logging out or because one was reused.
+    TokenRevoke,
+    /// A user changed their password.
+    PasswordChange,
+    /// A new user registered themselves.
+    Registration,
+    /// A user enabled or disabled two-factor authentication.
+    SecondFactorChange,
+    /// An administrator changed another user.
+    AdminAction,
+    /// A user created a personal access token.
+    AccessTokenCreate,
+    /// A user revoked one of their personal access tokens.
+    AccessTokenRevoke,
+    /// A user ended one or more of their sessions from another session.
+    SessionRevoke,
+    /// A user set a new password with a reset token.
+    PasswordReset,
+    /// A user changed their display name.
+    DisplayNameChange,
+}
+
+impl AuthEventKind {
+    /// Returns the name of the kind as it appears in the API and the store.
+    #[inline]
+    pub fn name(&self) -> &'static str {
+        match self {
+            AuthEventKind::LoginSuccess       => "login_success",
+            AuthEventKind::LoginFailure       => "login_failure",
+            AuthEventKind::TokenIssue         => "token_issue",
+            AuthEventKind::TokenRefresh       => "token_refresh",
+            AuthEventKind::TokenRevoke        => "token_revoke",
+            AuthEventKind::PasswordChange     => "password_change",
+            AuthEventKind::Registration       => "registration",
+            AuthEventKind::SecondFactorChange => "second_factor_change",
+            AuthEventKind::AdminAction        => "admin_action",
+            AuthEventKind::AccessTokenCreate  => "access_token_create",
+            AuthEventKind::AccessTokenRevoke  => "access_token_revoke",
+            AuthEventKind::SessionRevoke      => "session_revoke",
+            AuthEventKind::PasswordReset      => "password_reset",
+            AuthEventKind::DisplayNameChange  => "display_name_change",
+        }
+    }
+}
+
+impl Display for AuthEventKind {
+    #[inline]
+    fn fmt(&self, f: &mut Formatter<'_>) -> FResult {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for AuthEventKind {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "login_success"        => Ok(AuthEventKind::LoginSuccess),
+            "login_failure"        => Ok(AuthEventKind::LoginFailure),
+            "token_issue"          => Ok(AuthEventKind::TokenIssue),
+            "token_refresh"        => Ok(AuthEventKind::TokenRefresh),
+            "token_revoke"         => Ok(AuthEventKind::TokenRevoke),
+            "password_change"      => Ok(AuthEventKind::PasswordChange),
+            "registration"         => Ok(AuthEventKind::Registration),
+            "second_factor_change" => Ok(AuthEventKind::SecondFactorChange),
+            "admin_action"         => Ok(AuthEventKind::AdminAction),
+            "access_token_create"  => Ok(AuthEventKind::AccessTokenCreate),
+            "access_token_revoke"  => Ok(AuthEventKind::AccessTokenRevoke),
+            "session_revoke"       => Ok(AuthEventKind::SessionRevoke),
+            "password_reset"       => Ok(AuthEventKind::PasswordReset),
+            "display_name_change"  => Ok(AuthEventKind::DisplayNameChange),
+            raw                    => Err(Error::UnknownEventKind{ raw: raw.to_string() }),
+        }
+    }
+}
+
+
+
+/// Defines what a personal access token may be used for.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, Ord, PartialEq, PartialOrd, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Scope {
+    /// Managing the own account: changing the password, two-factor authentication and personal access tokens.
+    Account,
+    /// The administrative routes. The user still has to be an administrator.
+    Admin,
+}
+
+impl Scope {
+    /// Returns the name of the scope as it appears in the API and the store.
+    #[inline]
+    pub fn name(&self) -> &'static str {
+        match self {
+            Scope::Account => "account",
+            Scope::Admin   => "admin",
+        }
+    }
+}
+
+impl Display for Scope {
+    #[inline]
+    fn fmt(&self, f: &mut Formatter<'_>) -> FResult {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for Scope {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "account" => Ok(Scope::Account),
+            "admin"   => Ok(Scope::Admin),
+            raw       => Err(Error::UnknownScope{ raw: raw.to_string() }),
+        }
+    }
+}
+
+
+
+
 
 /***** LIBRARY STRUCTS *****/
+/// Defines the JSON body of every error response of the services.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct ApiError {
+    /// The machine-readable code of the error
+    pub code       : ErrorCode,
+    /// A human-readable description of the error
+    pub message    : String,
+    /// Further details about the error, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub details    : Option<String>,
+    /// The rules of the password policy that a given password does not meet, if that is what went wrong
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub violations : Vec<Violation>,
+}
+
+impl ApiError {
+    /// Constructor for the ApiError, without any details.
+    /// 
+    /// **Arguments**
+    ///  * `code`: The machine-readable code of the error.
+    ///  * `message`: The human-readable description of the error.
+    /// 
+    /// **Returns**  
+    /// A new ApiError instance.
+    #[inline]
+    pub fn new<S: Into<String>>(code: ErrorCode, message: S) -> Self {
+        Self {
+            code,
+            message    : message.into(),
+            details    : None,
+            violations : vec![],
+        }
+    }
+
+    /// Adds the given details to the ApiError.
+    /// 
+    /// **Arguments**
+    ///  * `details`: Further details about the error.
+    /// 
+    /// **Returns**  
+    /// The same ApiError, but with the details.
+    #[inline]
+    pub fn with_details<S: Into<String>>(mut self, details: S) -> Self {
+        self.details = Some(details.into());
+        self
+    }
+
+    /// Adds the given rules of the password policy that a password does not meet to the ApiError.
+    /// 
+    /// **Arguments**
+    ///  * `violations`: The rules that the password does not meet.
+    /// 
+    /// **Returns**  
+    /// The same ApiError, but with the violations.
+    #[inline]
+    pub fn with_violations(mut self, violations: Vec<Violation>) -> Self {
+        self.violations = violations;
+        self
+    }
+}
+
+impl Display for ApiError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FResult {
+        match &self.details {
+            Some(details) => write!(f, "{} ({})", self.message, details)?,
+            None          => write!(f, "{}", self.message)?,
+        }
+        for violation in &self.violations {
+            write!(f, "\n - Password {}", violation)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ApiError {}
+
+
+
 /// Defines the JSON for the login struct.
 #[derive(Serialize, Deserialize)]
 pub struct LoginJson {
@@ -24,6 +319,251 @@ pub struct LoginJson {
     pub username : String,
     /// The password of the user
     pub password : String,
+    /// A name for the device that logs in, which is shown in the user's list of sessions
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub device   : Option<String>,
+}
+
+
+
+/// Defines the JSON that the login endpoint returns (with '202 Accepted') if the user has to provide a second factor.
+#[derive(Serialize, Deserialize)]
+pub struct SecondFactorJson {
+    /// The challenge that identifies this login attempt when sending the code
+    pub challenge  : String,
+    /// The number of seconds until the challenge expires
+    pub expires_in : u64,
+}
+
+
+
+/// Defines the JSON for the second step of a login, which completes a challenge with a code.
+#[derive(Serialize, Deserialize)]
+pub struct SecondFactorLoginJson {
+    /// The challenge handed out by the login endpoint
+    pub challenge : String,
+    /// The code from the user's authenticator app, or one of their recovery codes
+    pub code      : String,
+}
+
+
+
+/// Defines the JSON for the register struct.
+#[derive(Serialize, Deserialize)]
+pub struct RegisterJson {
+    /// The username of the new user
+    pub username     : String,
+    /// The password of the new user
+    pub password     : String,
+    /// The invite code to register with, if the server requires one
+    #[serde(default)]
+    pub invite_code  : Option<String>,
+    /// The name under which the new user would like to be shown, if any
+    #[serde(default)]
+    pub display_name : Option<String>,
+}
+
+
+
+/// Defines the JSON for the tokens that are handed out on a successful login or refresh.
+#[derive(Serialize, Deserialize)]
+pub struct TokenJson {
+    /// The (short-lived) JWT that authenticates the user with the services
+    pub access_token  : String,
+    /// The (long-lived) token that can be exchanged for a new access token
+    pub refresh_token : String,
+    /// The number of seconds until the access token expires
+    pub expires_in    : u64,
+}
+
+
+
+/// Defines the JSON for the password struct, used by a logged-in user to change their password.
+#[derive(Serialize, Deserialize)]
+pub struct PasswordJson {
+    /// The current password of the user
+    pub old_password : String,
+    /// The password the user would like to have instead
+    pub new_password : String,
+}
+
+
+
+/// Defines the JSON for the display name struct, used by a logged-in user to change the name under which they are shown.
+#[derive(Serialize, Deserialize)]
+pub struct DisplayNameJson {
+    /// The display name the user would like to have, or None to remove it
+    #[serde(default)]
+    pub display_name : Option<String>,
+}
+
+
+
+/// Defines the JSON for the refresh struct, used to refresh tokens or to logout.
+#[derive(Serialize, Deserialize)]
+pub struct RefreshJson {
+    /// The refresh token to exchange or revoke
+    pub refresh_token : String,
+}
+
+
+
+/// Defines the JSON with a new TOTP secret, returned when a user starts enrolling an authenticator app.
+#[derive(Serialize, Deserialize)]
+pub struct TotpEnrollJson {
+    /// The base32-encoded secret, for typing into the app manually
+    pub secret : String,
+    /// The 'otpauth://' URI with the secret, for showing as a QR code
+    pub uri    : String,
+}
+
+
+
+/// Defines the JSON with a single code, used to confirm or disable two-factor authentication.
+#[derive(Serialize, Deserialize)]
+pub struct TotpCodeJson {
+    /// The code from the user's authenticator app (or, when disabling, one of their recovery codes)
+    pub code : String,
+}
+
+
+
+/// Defines the JSON with the one-time recovery codes that are handed out when two-factor authentication is enabled.
+#[derive(Serialize, Deserialize)]
+pub struct RecoveryCodesJson {
+    /// The recovery codes. Each of them may be used once instead of a code from the authenticator app.
+    pub codes : Vec<String>,
+}
+
+
+
+/// Defines the JSON that describes a single user to an administrator.
+#[derive(Serialize, Deserialize)]
+pub struct UserJson {
+    /// The identifier of the user
+    pub id       : u32,
+    /// The name of the user
+    pub username     : String,
+    /// The name under which the user is shown, if they have one
+    #[serde(default)]
+    pub display_name : Option<String>,
+    /// The role of the user
+    pub role         : Role,
+    /// Whether the user has been disabled by an administrator
+    pub disabled     : bool,
+    /// Whether the user is currently locked out because of too many failed logins
+    pub locked       : bool,
+}
+
+
+
+/// Defines the JSON for the new user struct, used by an administrator to create an account.
+#[derive(Serialize, Deserialize)]
+pub struct NewUserJson {
+    /// The username of the new user
+    pub username     : String,
+    /// The password of the new user
+    pub password     : String,
+    /// The role of the new user (a normal user if omitted)
+    #[serde(default)]
+    pub role         : Role,
+    /// The name under which the new user is shown, if any
+    #[serde(default)]
+    pub display_name : Option<String>,
+}
+
+
+
+/// Defines the JSON for the password reset struct, used by an administrator to set the password of another user.
+#[derive(Serialize, Deserialize)]
+pub struct ResetPasswordJson {
+    /// The new password of the user
+    pub password : String,
+}
+
+
+
+/// Defines the JSON that is returned when an administrator issues a password reset token for a user. This is the only time the token itself is shown.
+#[derive(Serialize, Deserialize)]
+pub struct ResetTokenJson {
+    /// The token, to be handed to the user (e.g., for use with `PasswordResetJson`)
+    pub reset_token : String,
+    /// The number of seconds until the token expires
+    pub expires_in  : u64,
+}
+
+
+
+/// Defines the JSON for the password reset struct, used by a user who forgot their password to set a new one with a reset token.
+#[derive(Serialize, Deserialize)]
+pub struct PasswordResetJson {
+    /// The reset token that an administrator issued for the user
+    pub reset_token  : String,
+    /// The password the user would like to have instead
+    pub new_password : String,
+}
+
+
+
+/// Defines the JSON that describes a single authentication event to an administrator.
+#[derive(Serialize, Deserialize)]
+pub struct AuthEventJson {
+    /// The identifier of the event
+    pub id         : u64,
+    /// The moment the event happened, as a UNIX timestamp
+    pub time       : i64,
+    /// The kind of event
+    pub kind       : AuthEventKind,
+    /// The identifier of the user the event is about, if they exist
+    pub user_id    : Option<u32>,
+    /// The name of the user the event is about, if known
+    pub username   : Option<String>,
+    /// The IP address of the client that caused the event, if known
+    pub ip         : Option<String>,
+    /// The User-Agent of the client that caused the event, if given
+    pub user_agent : Option<String>,
+    /// Further details, such as the reason a login was refused
+    pub detail     : Option<String>,
+}
+
+
+
+/// Defines the query parameters with which administrators search the authentication events. Every parameter is optional.
+#[derive(Default, Serialize, Deserialize)]
+pub struct AuthEventQuery {
+    /// Only return events about the user with this name
+    pub user   : Option<String>,
+    /// Only return events of this kind
+    #[serde(rename = "type")]
+    pub kind   : Option<AuthEventKind>,
+    /// Only return events at or after this UNIX timestamp
+    pub since  : Option<i64>,
+    /// Only return events before this UNIX timestamp
+    pub until  : Option<i64>,
+    /// The number of (matching) events to skip
+    pub offset : Option<u64>,
+    /// The maximum number of events to return
+    pub limit  : Option<u64>,
+}
+
+
+
+/// Defines the JSON with one page of authentication events, newest first.
+#[derive(Serialize, Deserialize)]
+pub struct AuthEventPageJson {
+    /// The events on this page
+    pub events      : Vec<AuthEventJson>,
+    /// The offset of the next page, or None if this is the last one
+    pub next_offset : Option<u64>,
+}
+
+
+
+/// Defines the JSON that the health and readiness checks reply with.
+#[derive(Serialize, Deserialize)]
+pub struct HealthJson {
+    /// The status of the service; 'ok' or 'ready'
+    pub status : String,
 }
 
 
@@ -31,3 +571,66 @@ pub struct LoginJson {
 
 /***** LIBRARY FUNCTIONS *****/
 
+
+
+/// Defines the JSON for the new personal access token struct, used by a logged-in user to create one.
+#[derive(Serialize, Deserialize)]
+pub struct NewAccessTokenJson {
+    /// The name of the token, which is unique per user
+    pub name       : String,
+    /// What the token may be used for
+    pub scopes     : Vec<Scope>,
+    /// The number of seconds until the token expires (it never does if omitted)
+    #[serde(default)]
+    pub expires_in : Option<u64>,
+}
+
+
+
+/// Defines the JSON that describes a single personal access token to its owner. The token itself is never included.
+#[derive(Serialize, Deserialize)]
+pub struct AccessTokenJson {
+    /// The name of the token
+    pub name      : String,
+    /// What the token may be used for
+    pub scopes    : Vec<Scope>,
+    /// The moment the token was created, as a UNIX timestamp
+    pub created   : i64,
+    /// The moment the token expires, as a UNIX timestamp, or None if it never does
+    pub expires   : Option<i64>,
+    /// The moment the token was last used, as a UNIX timestamp, or None if it never was
+    pub last_used : Option<i64>,
+}
+
+
+
+/// Defines the JSON that is returned when a personal access token is created. This is the only time the token itself is shown.
+#[derive(Serialize, Deserialize)]
+pub struct CreatedAccessTokenJson {
+    /// The token, to be used as a bearer token
+    pub token : String,
+    /// The description of the new token
+    #[serde(flatten)]
+    pub info  : AccessTokenJson,
+}
+
+
+
+/// Defines the JSON that describes a single session (i.e., a login and the tokens rotated from it) to its owner.
+#[derive(Serialize, Deserialize)]
+pub struct SessionJson {
+    /// The identifier of the session
+    pub id         : u32,
+    /// The name of the device that logged in, if it gave one
+    pub device     : Option<String>,
+    /// The address of the client that logged in, if known
+    pub ip         : Option<String>,
+    /// The User-Agent of the client that logged in, if any
+    pub user_agent : Option<String>,
+    /// The moment of the login, as a UNIX timestamp
+    pub created    : i64,
+    /// The moment the session last refreshed its tokens, as a UNIX timestamp
+    pub last_seen  : i64,
+    /// Whether this is the session that made the request
+    pub current    : bool,
+}
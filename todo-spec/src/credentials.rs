@@ -4,7 +4,7 @@
  * Created:
  *   17 Mar 2022, 18:35:32
  * Last edited:
- *   19 Mar 2022, 20:36:54
+ *   18 Oct 2026, 07:11:43
  * Auto updated?
  *   Yes
  *
@@ -13,14 +13,27 @@
  *   client and the server.
 **/
 
-use std::fs::File;
+use std::fmt::{Debug, Display, Formatter, Result as FResult};
+use std::fs::{File, OpenOptions};
 use std::io::{Read, Write};
+#[cfg(unix)]
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
 use std::path::Path;
 
-use argon2::Argon2;
+use argon2::{Algorithm, Argon2, Params, ParamsBuilder, Version};
 use argon2::password_hash::{ PasswordHash, PasswordHasher, PasswordVerifier, SaltString };
 use argon2::password_hash::rand_core::OsRng;
-use regex::Regex;
+use caseless::default_case_fold_str;
+use chacha20poly1305::{KeyInit, XChaCha20Poly1305, XNonce};
+use chacha20poly1305::aead::Aead;
+use rand_core::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use time::OffsetDateTime;
+use time::format_description::well_known::Rfc3339;
+use unicode_normalization::UnicodeNormalization;
+use unicode_normalization::char::is_combining_mark;
+use unicode_security::{GeneralSecurityProfile, RestrictionLevel, RestrictionLevelDetection};
 use unicode_segmentation::UnicodeSegmentation;
 
 pub use crate::errors::CredentialError as Error;
@@ -55,17 +68,17 @@ mod tests {
 
         // Now match it with a matching username/password
         match cred.verify("john", "this_is_secret!") {
-            Ok(res)  => { assert_eq!(res, true); },
+            Ok(res)  => { assert!(res); },
             Err(err) => { panic!("verify_password() should not have crashed, but it returned: {}", err); },
         }
         // Now match it with a not-matching username
         match cred.verify("john2", "this_is_secret!") {
-            Ok(res)  => { assert_eq!(res, false); },
+            Ok(res)  => { assert!(!res); },
             Err(err) => { panic!("verify_password() should not have crashed, but it returned: {}", err); },
         }
         // Now match it with a not-matching password
         match cred.verify("john", "this_is_secret?") {
-            Ok(res)  => { assert_eq!(res, false); },
+            Ok(res)  => { assert!(!res); },
             Err(err) => { panic!("verify_password() should not have crashed, but it returned: {}", err); },
         }
     }
@@ -89,7 +102,7 @@ mod tests {
     #[test]
     fn test_illegal_username() {
         // Make a hash with a username that is illegal
-        let _ = match Credential::from_plain("john#$", "this_is_secret!") {
+        match Credential::from_plain("john#$", "this_is_secret!") {
             Ok(_)    => { panic!("new() should have crashed due to illegal username, but it didn't"); },
             Err(err) => {
                 match err {
@@ -99,6 +112,173 @@ mod tests {
             },
         };
     }
+
+    #[test]
+    fn test_unicode_username() {
+        // Letters from any script are fine, and are stored normalized
+        assert!(verify_username("jöhn_doe-2").is_ok());
+        assert!(verify_username("Дмитрий").is_ok());
+        assert!(verify_username("山田太郎").is_ok());
+        assert!(verify_username("tanaka山田").is_ok());
+        assert_eq!(Credential::new("ｊｏｈｎ", "hash").unwrap().user(), "john");
+        assert_eq!(Credential::new("jo\u{0308}hn", "hash").unwrap().user(), "jöhn");
+
+        // Whitespace, punctuation and overly long names are not
+        assert!(matches!(verify_username(""), Err(Error::InvalidUsername{ .. })));
+        assert!(matches!(verify_username("john doe"), Err(Error::InvalidUsername{ .. })));
+        assert!(matches!(verify_username("john+doe"), Err(Error::InvalidUsername{ .. })));
+        assert!(matches!(verify_username("john\u{200B}"), Err(Error::InvalidUsername{ .. })));
+        assert!(matches!(verify_username("a".repeat(MAX_USERNAME_LENGTH + 1)), Err(Error::InvalidUsername{ .. })));
+
+        // Neither are names that mix in lookalikes from other scripts
+        assert!(matches!(verify_username("j\u{043E}hn"), Err(Error::ConfusableUsername{ .. })));
+        assert!(matches!(verify_username("pаypal"), Err(Error::ConfusableUsername{ .. })));
+
+        // Names that only differ in case (or width) are the same name
+        assert_eq!(username_key("John"), username_key("john"));
+        assert_eq!(username_key("ＪＯＨＮ"), username_key("john"));
+        assert_eq!(username_key("Straße"), username_key("STRASSE"));
+        assert_ne!(username_key("john"), username_key("jöhn"));
+        let cred = Credential::from_plain_with("John", "this_is_secret!", &HashPolicy::new(HashAlgorithm::Argon2id, 1024, 1, 1).unwrap()).unwrap();
+        assert!(cred.verify("JOHN", "this_is_secret!").unwrap());
+        assert!(!cred.verify("jane", "this_is_secret!").unwrap());
+
+        // Display names are free-form, within reason
+        assert!(verify_display_name("John Doe (Ops) 🚀").is_ok());
+        assert!(matches!(verify_display_name(""), Err(Error::InvalidDisplayName{ .. })));
+        assert!(matches!(verify_display_name(" John"), Err(Error::InvalidDisplayName{ .. })));
+        assert!(matches!(verify_display_name("John\nDoe"), Err(Error::InvalidDisplayName{ .. })));
+    }
+
+    #[test]
+    fn test_serialize() {
+        // Credentials survive a round trip, including their metadata
+        let cred = Credential::from_plain("john", "this_is_secret!").unwrap().with_host("https://todo.example.com");
+        let serialized = cred.serialize();
+        assert!(serialized.contains(&format!("\"version\":{}", CREDENTIAL_FILE_VERSION)));
+        let parsed = Credential::deserialize(&serialized).unwrap();
+        assert_eq!(parsed.user(), "john");
+        assert_eq!(parsed.pass(), cred.pass());
+        assert_eq!(parsed.host(), Some("https://todo.example.com"));
+        assert!(parsed.created().is_some());
+        assert_eq!(parsed.serialize(), serialized);
+
+        // The old format can still be read
+        let parsed = Credential::deserialize("john+$argon2id$hash").unwrap();
+        assert_eq!(parsed.user(), "john");
+        assert_eq!(parsed.pass(), "$argon2id$hash");
+        assert_eq!(parsed.host(), None);
+        assert_eq!(parsed.created(), None);
+        assert!(matches!(Credential::deserialize("john"), Err(Error::MissingSeparator)));
+
+        // Broken or future files are not
+        assert!(matches!(Credential::deserialize("{\"version\":1,\"username\":\"john\"}"), Err(Error::IllegalFormat{ .. })));
+        assert!(matches!(Credential::deserialize("{\"version\":1,\"username\":\"john\",\"password\":\"x\",\"created\":\"yesterday\"}"), Err(Error::IllegalCreated{ .. })));
+        assert!(matches!(Credential::deserialize("{\"version\":2,\"username\":\"john\",\"password\":\"x\"}"), Err(Error::UnsupportedVersion{ version: 2 })));
+        assert!(matches!(Credential::deserialize("{\"version\":1,\"username\":\"john doe\",\"password\":\"x\"}"), Err(Error::InvalidUsername{ .. })));
+    }
+
+    #[test]
+    fn test_encrypted() {
+        // Encrypted credentials only come back with the right passphrase
+        let cred = Credential::from_plain("john", "this_is_secret!").unwrap().with_host("https://todo.example.com");
+        let encrypted = cred.serialize_encrypted("correct horse").unwrap();
+        assert!(!encrypted.contains("john"));
+        assert!(!encrypted.contains(cred.pass()));
+        assert!(matches!(Credential::deserialize(&encrypted), Err(Error::MissingPassphrase)));
+        assert!(matches!(Credential::deserialize_with(&encrypted, Some("battery staple")), Err(Error::WrongPassphrase)));
+        let parsed = Credential::deserialize_with(&encrypted, Some("correct horse")).unwrap();
+        assert_eq!(parsed.user(), "john");
+        assert_eq!(parsed.pass(), cred.pass());
+        assert_eq!(parsed.host(), Some("https://todo.example.com"));
+        assert!(parsed.encrypted());
+
+        // Salts and nonces are fresh every time, and unencrypted credentials ignore the passphrase
+        assert_ne!(cred.serialize_encrypted("correct horse").unwrap(), encrypted);
+        let parsed = Credential::deserialize_with(cred.serialize(), Some("correct horse")).unwrap();
+        assert!(!parsed.encrypted());
+
+        // Tampering is noticed
+        let mut file: serde_json::Value = serde_json::from_str(&encrypted).unwrap();
+        file["cipher"] = "aes256gcm".into();
+        assert!(matches!(Credential::deserialize_with(file.to_string(), Some("correct horse")), Err(Error::UnsupportedCipher{ .. })));
+        let mut file: serde_json::Value = serde_json::from_str(&encrypted).unwrap();
+        file["kdf"]["t_cost"] = 3.into();
+        assert!(matches!(Credential::deserialize_with(file.to_string(), Some("correct horse")), Err(Error::WrongPassphrase)));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_file_permissions() {
+        let path = std::env::temp_dir().join(format!("todo-spec-test-{}.cred", std::process::id()));
+        let cred = Credential::new("john", "$argon2id$hash").unwrap();
+
+        // Files are only readable by their owner, even if they existed before
+        std::fs::write(&path, "").unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o644)).unwrap();
+        cred.serialize_to_file(&path).unwrap();
+        assert_eq!(std::fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+        assert_eq!(Credential::from_file(&path).unwrap().pass(), "$argon2id$hash");
+
+        // Files that others can read are refused, but not those that they can only write
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o640)).unwrap();
+        assert!(matches!(Credential::from_file(&path), Err(Error::InsecurePermissions{ mode: 0o640, .. })));
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o604)).unwrap();
+        assert!(matches!(Credential::from_file(&path), Err(Error::InsecurePermissions{ mode: 0o604, .. })));
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o620)).unwrap();
+        assert!(Credential::from_file(&path).is_ok());
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_hash_policy() {
+        // Hash with a custom policy, and make sure it ends up in the hash
+        let policy = HashPolicy::new(HashAlgorithm::Argon2i, 1024, 2, 1).unwrap();
+        let cred = Credential::from_plain_with("john", "this_is_secret!", &policy).unwrap();
+        assert!(cred.pass().starts_with("$argon2i$v=19$m=1024,t=2,p=1$"));
+
+        // It should verify regardless of the policy that is used to verify it
+        assert!(cred.verify("john", "this_is_secret!").unwrap());
+        assert!(cred.verify_with("john", "this_is_secret!", &policy).unwrap());
+        assert!(!cred.verify_with("john", "this_is_secret?", &policy).unwrap());
+
+        // Illegal costs should be refused
+        assert!(matches!(HashPolicy::new(HashAlgorithm::Argon2id, 1, 1, 1), Err(Error::IllegalHashParams{ .. })));
+        assert!(matches!(HashPolicy::new(HashAlgorithm::Argon2id, 1024, 0, 1), Err(Error::IllegalHashParams{ .. })));
+    }
+
+    #[test]
+    fn test_hash_rehash() {
+        let weak   = HashPolicy::new(HashAlgorithm::Argon2id, 1024, 1, 1).unwrap();
+        let strong = HashPolicy::new(HashAlgorithm::Argon2id, 2048, 2, 1).unwrap();
+        let cred = Credential::from_plain_with("john", "this_is_secret!", &weak).unwrap();
+
+        // A hash made with lower costs or another variant should be rehashed; a hash with the same or higher costs not
+        assert!(cred.needs_rehash(&strong).unwrap());
+        assert!(!cred.needs_rehash(&weak).unwrap());
+        assert!(!Credential::from_plain_with("john", "this_is_secret!", &strong).unwrap().needs_rehash(&weak).unwrap());
+        assert!(cred.needs_rehash(&HashPolicy::new(HashAlgorithm::Argon2i, 1024, 1, 1).unwrap()).unwrap());
+
+        // Adding a pepper means rehashing too
+        assert!(cred.needs_rehash(&weak.clone().with_pepper(b"pepper".to_vec()).unwrap()).unwrap());
+    }
+
+    #[test]
+    fn test_hash_pepper() {
+        let plain    = HashPolicy::new(HashAlgorithm::Argon2id, 1024, 1, 1).unwrap();
+        let peppered = plain.clone().with_pepper(b"pepper".to_vec()).unwrap();
+        let cred = Credential::from_plain_with("john", "this_is_secret!", &peppered).unwrap();
+
+        // It should only verify with the same pepper
+        assert!(cred.verify_with("john", "this_is_secret!", &peppered).unwrap());
+        assert!(!cred.verify_with("john", "this_is_secret?", &peppered).unwrap());
+        assert!(matches!(cred.verify_with("john", "this_is_secret!", &plain), Err(Error::UnknownPepper{ .. })));
+        assert!(matches!(cred.verify_with("john", "this_is_secret!", &plain.clone().with_pepper(b"salt".to_vec()).unwrap()), Err(Error::UnknownPepper{ .. })));
+
+        // Unpeppered hashes should still verify with a peppered policy
+        let old = Credential::from_plain_with("john", "this_is_secret!", &plain).unwrap();
+        assert!(old.verify_with("john", "this_is_secret!", &peppered).unwrap());
+    }
 }
 
 
@@ -106,48 +286,488 @@ mod tests {
 
 
 /***** CONSTANTS *****/
-/// Defines the regular expression that is used to match usernames.
-const USERNAME_REGEX: &str = r"^[0-9a-zA-Z_-]+$";
+/// The maximum length (in characters, after normalization) of a username.
+pub const MAX_USERNAME_LENGTH: usize = 64;
+/// The maximum length (in characters) of a display name.
+pub const MAX_DISPLAY_NAME_LENGTH: usize = 128;
+/// The version of the credential file format that we write.
+pub const CREDENTIAL_FILE_VERSION: u32 = 1;
+
+/// The only key derivation function we use for encrypted credential files.
+const ENCRYPTION_KDF: &str = "argon2id";
+/// The only cipher we use for encrypted credential files.
+const ENCRYPTION_CIPHER: &str = "xchacha20poly1305";
+/// The amount of memory (in KiB) used to derive the key of an encrypted credential file.
+const ENCRYPTION_M_COST: u32 = 19 * 1024;
+/// The number of iterations used to derive the key of an encrypted credential file.
+const ENCRYPTION_T_COST: u32 = 2;
+/// The number of lanes used to derive the key of an encrypted credential file.
+const ENCRYPTION_P_COST: u32 = 1;
+/// The length (in bytes) of the salt of an encrypted credential file.
+const ENCRYPTION_SALT_LENGTH: usize = 16;
+/// The length (in bytes) of the nonce of an encrypted credential file.
+const ENCRYPTION_NONCE_LENGTH: usize = 24;
 
 
 
 
 
 /***** HELPER FUNCTIONS *****/
-/// Verifies the given username.
+/// Builds the Argon2 parameters from the given costs and key identifier.
+/// 
+/// **Arguments**
+///  * `m_cost`: The memory cost, in KiB.
+///  * `t_cost`: The time cost, in iterations.
+///  * `p_cost`: The degree of parallelism.
+///  * `keyid`: The identifier of the pepper used, or an empty slice if none.
+/// 
+/// **Returns**  
+/// The new Params on success, or an argon2::Error if any of them is out of range.
+fn build_params(m_cost: u32, t_cost: u32, p_cost: u32, keyid: &[u8]) -> Result<Params, argon2::Error> {
+    let mut builder = ParamsBuilder::new();
+    builder.m_cost(m_cost)?.t_cost(t_cost)?.p_cost(p_cost)?.keyid(keyid)?;
+    builder.params()
+}
+
+/// Derives the key that encrypts a credential file from the given passphrase.
+/// 
+/// **Arguments**
+///  * `passphrase`: The passphrase to derive the key from.
+///  * `kdf`: The parameters (including the salt) to derive the key with.
+/// 
+/// **Returns**  
+/// The 256-bit key on success, or else an Error.
+fn derive_key(passphrase: &str, kdf: &KdfParams) -> Result<[u8; 32], Error> {
+    if kdf.algorithm != ENCRYPTION_KDF { return Err(Error::UnsupportedKdf{ kdf: kdf.algorithm.clone() }); }
+    let salt: Vec<u8> = match base64::decode(&kdf.salt) {
+        Ok(salt) => salt,
+        Err(err) => { return Err(Error::IllegalEncoding{ what: "salt", err }); }
+    };
+    let params: Params = match Params::new(kdf.m_cost, kdf.t_cost, kdf.p_cost, Some(32)) {
+        Ok(params) => params,
+        Err(err)   => { return Err(Error::KeyDerivationError{ err }); }
+    };
+
+    let mut key = [0; 32];
+    if let Err(err) = Argon2::new(Algorithm::Argon2id, Version::V0x13, params).hash_password_into(passphrase.as_bytes(), &salt, &mut key) {
+        return Err(Error::KeyDerivationError{ err });
+    }
+    Ok(key)
+}
+
+/// Computes the identifier of the given pepper, which is stored alongside every hash that uses it.
+/// 
+/// **Arguments**
+///  * `pepper`: The pepper to compute the identifier of.
+/// 
+/// **Returns**  
+/// The first four bytes of the SHA-256 hash of the pepper. This is enough to tell peppers apart, but reveals nothing useful about them.
+fn pepper_id(pepper: &[u8]) -> [u8; 4] {
+    let digest = Sha256::digest(pepper);
+    [ digest[0], digest[1], digest[2], digest[3] ]
+}
+
+
+
+
+
+/***** LIBRARY FUNCTIONS *****/
+/// Brings the given username in its canonical form (NFKC), which is the form in which it is stored and compared.
 /// 
 /// **Generic types**
 ///  * `S`: The String-like type of the username that is passed to the function.
 /// 
 /// **Arguments**
-///  * `username`: The given username to verify.
+///  * `username`: The username to normalize.
+/// 
+/// **Returns**  
+/// The normalized username. Note that it may still be invalid; see `verify_username()`.
+#[inline]
+pub fn normalize_username<S: AsRef<str>>(username: S) -> String {
+    username.as_ref().nfkc().collect()
+}
+
+/// Computes the key of the given username, which is what makes two usernames "the same" (e.g., 'John' and 'john').
+/// 
+/// **Generic types**
+///  * `S`: The String-like type of the username that is passed to the function.
+/// 
+/// **Arguments**
+///  * `username`: The username to compute the key of.
+/// 
+/// **Returns**  
+/// The case-folded, NFKC-normalized username.
+pub fn username_key<S: AsRef<str>>(username: S) -> String {
+    default_case_fold_str(&normalize_username(username)).nfkc().collect()
+}
+
+/// Verifies that the given username only consists of legal characters.
+/// 
+/// Usernames may contain (Unicode) letters, digits and combining marks, as well as underscores and dashes. Mixing scripts is only allowed in the combinations that UTS #39 calls "highly restrictive" (e.g., Latin with Han), which prevents names such as 'jоhn' with a Cyrillic 'о'.
+/// 
+/// **Generic types**
+///  * `S`: The String-like type of the username that is passed to the function.
+/// 
+/// **Arguments**
+///  * `username`: The given username to verify. It should already be normalized (see `normalize_username()`).
 /// 
 /// **Returns**  
 /// Nothing if the username is valid, or an Error describing why it isn't otherwise.
-fn verify_username<S: AsRef<str>>(username: S) -> Result<(), Error> {
+pub fn verify_username<S: AsRef<str>>(username: S) -> Result<(), Error> {
     // Convert string-like to string
     let username: &str = username.as_ref();
 
-    // Make sure that it does not contain any illegal characters
-    let re = Regex::new(USERNAME_REGEX).expect("Illegal Regex for matching usernames; this should never happen!");
-    if !re.is_match(username) { return Err(Error::InvalidUsername{ username: username.to_string() }); }
+    // Make sure that it has a sensible length and does not contain any illegal characters
+    let length: usize = username.chars().count();
+    if length == 0 || length > MAX_USERNAME_LENGTH || username.nfkc().ne(username.chars()) {
+        return Err(Error::InvalidUsername{ username: username.to_string() });
+    }
+    if !username.chars().all(|c| c == '_' || c == '-' || ((c.is_alphanumeric() || is_combining_mark(c)) && c.identifier_allowed())) {
+        return Err(Error::InvalidUsername{ username: username.to_string() });
+    }
+
+    // Make sure it does not mix scripts; underscores and dashes belong to every script, so they do not count
+    let letters: String = username.chars().filter(|c| *c != '_' && *c != '-').collect();
+    if !letters.as_str().check_restriction_level(RestrictionLevel::HighlyRestrictive) {
+        return Err(Error::ConfusableUsername{ username: username.to_string() });
+    }
 
     // Look OK!
     Ok(())
 }
 
+/// Verifies that the given display name is acceptable.
+/// 
+/// Display names are free-form, but they may not be empty, too long, padded with whitespace or contain control characters.
+/// 
+/// **Arguments**
+///  * `name`: The display name to verify.
+/// 
+/// **Returns**  
+/// Nothing if the display name is valid, or an Error describing why it isn't otherwise.
+pub fn verify_display_name(name: &str) -> Result<(), Error> {
+    let length: usize = name.chars().count();
+    if length == 0 || length > MAX_DISPLAY_NAME_LENGTH || name.trim() != name || name.chars().any(char::is_control) {
+        return Err(Error::InvalidDisplayName{ name: name.to_string() });
+    }
+    Ok(())
+}
+
+
+
+
+
+/***** LIBRARY ENUMS *****/
+/// Defines the variants of Argon2 that passwords may be hashed with.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum HashAlgorithm {
+    /// Argon2d, which maximizes resistance against GPU cracking but is vulnerable to side-channel attacks.
+    Argon2d,
+    /// Argon2i, which resists side-channel attacks but is weaker against GPU cracking.
+    Argon2i,
+    /// Argon2id, a hybrid of the other two. This is the recommended variant.
+    Argon2id,
+}
+
+impl Default for HashAlgorithm {
+    #[inline]
+    fn default() -> Self { HashAlgorithm::Argon2id }
+}
+
+impl Display for HashAlgorithm {
+    #[inline]
+    fn fmt(&self, f: &mut Formatter<'_>) -> FResult {
+        write!(f, "{}", Algorithm::from(*self))
+    }
+}
+
+impl From<HashAlgorithm> for Algorithm {
+    #[inline]
+    fn from(value: HashAlgorithm) -> Self {
+        match value {
+            HashAlgorithm::Argon2d  => Algorithm::Argon2d,
+            HashAlgorithm::Argon2i  => Algorithm::Argon2i,
+            HashAlgorithm::Argon2id => Algorithm::Argon2id,
+        }
+    }
+}
+
 
 
 
 
 /***** LIBRARY STRUCTS *****/
+/// Defines how passwords are hashed.
+/// 
+/// Every hash remembers the parameters it was created with, so changing the policy does not break existing hashes. Use `HashPolicy::needs_rehash()` to find out which hashes are weaker than the policy.
+#[derive(Clone)]
+pub struct HashPolicy {
+    /// The variant of Argon2 to hash with
+    algorithm : HashAlgorithm,
+    /// The costs to hash with, including the identifier of the pepper (if any)
+    params    : Params,
+    /// The server-wide secret that is mixed into every hash, if any
+    pepper    : Option<Vec<u8>>,
+}
+
+impl HashPolicy {
+    /// Constructor for the HashPolicy, which hashes without a pepper.
+    /// 
+    /// **Arguments**
+    ///  * `algorithm`: The variant of Argon2 to hash with.
+    ///  * `m_cost`: The amount of memory to use per hash, in KiB.
+    ///  * `t_cost`: The number of iterations to perform per hash.
+    ///  * `p_cost`: The degree of parallelism per hash.
+    /// 
+    /// **Returns**  
+    /// The new HashPolicy on success, or an Error if the costs are out of range.
+    pub fn new(algorithm: HashAlgorithm, m_cost: u32, t_cost: u32, p_cost: u32) -> Result<Self, Error> {
+        let params = match build_params(m_cost, t_cost, p_cost, &[]) {
+            Ok(params) => params,
+            Err(err)   => { return Err(Error::IllegalHashParams{ err }); }
+        };
+
+        Ok(Self {
+            algorithm,
+            params,
+            pepper : None,
+        })
+    }
+
+    /// Adds a pepper to the HashPolicy, which is a secret that is mixed into every new hash but not stored with it.
+    /// 
+    /// Hashes made with a pepper can only be verified if the policy has that same pepper. Hashes without a pepper remain valid, and are considered weaker than the policy.
+    /// 
+    /// **Generic types**
+    ///  * `B`: The Bytes-like type of the pepper.
+    /// 
+    /// **Arguments**
+    ///  * `pepper`: The pepper to use.
+    /// 
+    /// **Returns**  
+    /// The HashPolicy with the pepper on success, or an Error if the pepper is unusable.
+    pub fn with_pepper<B: Into<Vec<u8>>>(mut self, pepper: B) -> Result<Self, Error> {
+        let pepper: Vec<u8> = pepper.into();
+        if pepper.len() > argon2::MAX_SECRET_LEN { return Err(Error::IllegalHashParams{ err: argon2::Error::SecretTooLong }); }
+
+        // Store the pepper's identifier in the parameters, so it ends up in every hash we make
+        self.params = match build_params(self.params.m_cost(), self.params.t_cost(), self.params.p_cost(), &pepper_id(&pepper)) {
+            Ok(params) => params,
+            Err(err)   => { return Err(Error::IllegalHashParams{ err }); }
+        };
+        self.pepper = Some(pepper);
+        Ok(self)
+    }
+
+
+
+    /// Returns the Argon2 hasher for this policy.
+    fn hasher(&self) -> Argon2<'_> {
+        match &self.pepper {
+            Some(pepper) => Argon2::new_with_secret(pepper, self.algorithm.into(), Version::V0x13, self.params.clone()).expect("Pepper is too long even though it was checked; this should never happen!"),
+            None         => Argon2::new(self.algorithm.into(), Version::V0x13, self.params.clone()),
+        }
+    }
+
+    /// Parses the given PHC string into a hash and its Argon2 parameters.
+    fn parse(hash: &str) -> Result<(PasswordHash<'_>, Params), Error> {
+        let hash = match PasswordHash::new(hash) {
+            Ok(hash) => hash,
+            Err(err) => { return Err(Error::IllegalHash{ err }); }
+        };
+        let params = match Params::try_from(&hash) {
+            Ok(params) => params,
+            Err(err)   => { return Err(Error::IllegalHash{ err }); }
+        };
+        Ok((hash, params))
+    }
+
+
+
+    /// Hashes the given password according to this policy.
+    /// 
+    /// **Generic types**
+    ///  * `B`: The Bytes-like type of the password that is passed to the function.
+    /// 
+    /// **Arguments**
+    ///  * `password`: The plain-text password to hash.
+    /// 
+    /// **Returns**  
+    /// The hash as a PHC string on success, or else an Error.
+    pub fn hash<B: AsRef<[u8]>>(&self, password: B) -> Result<String, Error> {
+        let salt = SaltString::generate(&mut OsRng);
+        match self.hasher().hash_password(password.as_ref(), &salt) {
+            Ok(hash) => Ok(hash.to_string()),
+            Err(err) => Err(Error::PasswordHashError{ err }),
+        }
+    }
+
+    /// Checks the given password against the given hash.
+    /// 
+    /// The hash is verified with the parameters it was created with, not with those of the policy. Only the pepper is taken from the policy.
+    /// 
+    /// **Generic types**
+    ///  * `B`: The Bytes-like type of the password that is passed to the function.
+    /// 
+    /// **Arguments**
+    ///  * `hash`: The PHC string of the hash to verify against.
+    ///  * `password`: The plain-text password to verify.
+    /// 
+    /// **Returns**  
+    /// Whether or not the password matches on success, or an Error if the hash is invalid or uses a pepper we do not have.
+    pub fn verify<B: AsRef<[u8]>>(&self, hash: &str, password: B) -> Result<bool, Error> {
+        let (hash, params) = Self::parse(hash)?;
+
+        // Only use our pepper if the hash was made with it
+        let argon2: Argon2 = if params.keyid().is_empty() {
+            Argon2::default()
+        } else if self.pepper.is_some() && params.keyid() == self.params.keyid() {
+            self.hasher()
+        } else {
+            return Err(Error::UnknownPepper{ keyid: params.keyid().iter().map(|b| format!("{:02x}", b)).collect() });
+        };
+        Ok(argon2.verify_password(password.as_ref(), &hash).is_ok())
+    }
+
+    /// Checks whether the given hash is weaker than this policy, and should thus be replaced by a new one.
+    /// 
+    /// **Arguments**
+    ///  * `hash`: The PHC string of the hash to check.
+    /// 
+    /// **Returns**  
+    /// True if the hash uses another algorithm or pepper than the policy, or if any of its costs is lower. Returns an Error if the hash is invalid.
+    pub fn needs_rehash(&self, hash: &str) -> Result<bool, Error> {
+        let (hash, params) = Self::parse(hash)?;
+        Ok(hash.algorithm != Algorithm::from(self.algorithm).ident()
+            || hash.version != Some(Version::V0x13.into())
+            || params.m_cost() < self.params.m_cost()
+            || params.t_cost() < self.params.t_cost()
+            || params.p_cost() < self.params.p_cost()
+            || params.keyid() != self.params.keyid())
+    }
+
+
+
+    /// Returns the variant of Argon2 that this policy hashes with.
+    #[inline]
+    pub fn algorithm(&self) -> HashAlgorithm { self.algorithm }
+
+    /// Returns the memory cost (in KiB) of this policy.
+    #[inline]
+    pub fn m_cost(&self) -> u32 { self.params.m_cost() }
+
+    /// Returns the time cost (in iterations) of this policy.
+    #[inline]
+    pub fn t_cost(&self) -> u32 { self.params.t_cost() }
+
+    /// Returns the degree of parallelism of this policy.
+    #[inline]
+    pub fn p_cost(&self) -> u32 { self.params.p_cost() }
+
+    /// Returns whether this policy peppers its hashes.
+    #[inline]
+    pub fn peppered(&self) -> bool { self.pepper.is_some() }
+}
+
+impl Default for HashPolicy {
+    /// Returns the default policy of the argon2 crate, without a pepper.
+    #[inline]
+    fn default() -> Self {
+        Self {
+            algorithm : HashAlgorithm::default(),
+            params    : Params::default(),
+            pepper    : None,
+        }
+    }
+}
+
+impl Debug for HashPolicy {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FResult {
+        // Never show the pepper itself
+        f.debug_struct("HashPolicy")
+            .field("algorithm", &self.algorithm)
+            .field("m_cost", &self.params.m_cost())
+            .field("t_cost", &self.params.t_cost())
+            .field("p_cost", &self.params.p_cost())
+            .field("peppered", &self.pepper.is_some())
+            .finish()
+    }
+}
+
+
+
+/// Defines the layout of a (versioned) credential file on disk.
+#[derive(Deserialize, Serialize)]
+struct CredentialFile {
+    /// The version of the format, which is always `CREDENTIAL_FILE_VERSION` when we write it.
+    version  : u32,
+    /// The username of the user
+    username : String,
+    /// The password of the user, which is a PHC-formatted hash for user credentials.
+    password : String,
+    /// When the credential was first written, as an RFC 3339 timestamp.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    created  : Option<String>,
+    /// The host this credential is meant for, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    host     : Option<String>,
+}
+
+/// Defines the layout of a passphrase-encrypted credential file on disk. The ciphertext is an encrypted `CredentialFile`.
+#[derive(Deserialize, Serialize)]
+struct EncryptedCredentialFile {
+    /// The version of the format, which is always `CREDENTIAL_FILE_VERSION` when we write it.
+    version    : u32,
+    /// How the key is derived from the passphrase.
+    kdf        : KdfParams,
+    /// The cipher that the credential is encrypted with.
+    cipher     : String,
+    /// The base64-encoded nonce that the credential is encrypted with.
+    nonce      : String,
+    /// The base64-encoded, encrypted credential.
+    ciphertext : String,
+}
+
+/// Defines how the key of an encrypted credential file is derived from its passphrase.
+#[derive(Deserialize, Serialize)]
+struct KdfParams {
+    /// The key derivation function to use.
+    algorithm : String,
+    /// The amount of memory (in KiB) to use.
+    m_cost    : u32,
+    /// The number of iterations to perform.
+    t_cost    : u32,
+    /// The number of lanes to use.
+    p_cost    : u32,
+    /// The base64-encoded salt.
+    salt      : String,
+}
+
+/// Only reads the header of a credential file, so we can complain about the version before complaining about its fields.
+#[derive(Deserialize)]
+struct CredentialFileHeader {
+    /// The version of the format
+    version : u32,
+    /// The cipher the file is encrypted with, if it is.
+    #[serde(default)]
+    cipher  : Option<String>,
+}
+
+
+
 /// Defines one of multiple types of Credentials.
 #[derive(Debug, Eq, PartialEq)]
 pub struct Credential {
     /// The username of the user
-    username : String,
+    username  : String,
     /// The password of the user
-    password : String,
+    password  : String,
+    /// When the credential was first written to a file, if it was.
+    created   : Option<OffsetDateTime>,
+    /// The host this credential is bound to, if any.
+    host      : Option<String>,
+    /// Whether the credential was read from an encrypted file.
+    encrypted : bool,
 }
 
 impl Credential {
@@ -158,26 +778,29 @@ impl Credential {
     ///  * `S2`: The String-like type of the password that is passed to the function.
     /// 
     /// **Arguments**
-    ///  * `username`: The username of the user to whom the given password belongs.
+    ///  * `username`: The username of the user to whom the given password belongs. It is normalized first (see `normalize_username()`).
     ///  * `password`: The (already hashed!) password.
     /// 
     /// **Returns**  
     /// The new Credential instance on success, or else an Error.
     pub fn new<S1: Into<String>, S2: Into<String>>(username: S1, password: S2) -> Result<Self, Error> {
-        // Convert the String-like username into a String
-        let username = username.into();
+        // Convert the String-like username into a (normalized) String
+        let username = normalize_username(username.into());
 
         // Verify the username first
         verify_username(&username)?;
 
         // Return it as a new Credential
         Ok(Self {
-            username : username,
+            username,
             password : password.into(),
+            created   : None,
+            host      : None,
+            encrypted : false,
         })
     }
 
-    /// Constructor for the Credential that takes a plain-text password and hashes it.
+    /// Constructor for the Credential that takes a plain-text password and hashes it with the default HashPolicy.
     /// 
     /// **Generic types**
     ///  * `S`: The String-like type of the username that is passed to the function.
@@ -189,36 +812,48 @@ impl Credential {
     /// 
     /// **Returns**  
     /// The new Credential instance on success, or else an Error.
+    #[inline]
     pub fn from_plain<S: Into<String>, B: AsRef<[u8]>>(username: S, password: B) -> Result<Self, Error> {
-        // Convert String-like into String
-        let username = username.into();
-        // Convert bytes-like into bytes
-        let password = password.as_ref();
+        Self::from_plain_with(username, password, &HashPolicy::default())
+    }
+
+    /// Constructor for the Credential that takes a plain-text password and hashes it with the given HashPolicy.
+    /// 
+    /// **Generic types**
+    ///  * `S`: The String-like type of the username that is passed to the function.
+    ///  * `B`: The Bytes-like type of the password that is passed to the function.
+    /// 
+    /// **Arguments**
+    ///  * `username`: The username of the user to whom the given password belongs. It is normalized first (see `normalize_username()`).
+    ///  * `password`: The plain text password we would like to hash.
+    ///  * `policy`: The HashPolicy that determines how to hash the password.
+    /// 
+    /// **Returns**  
+    /// The new Credential instance on success, or else an Error.
+    pub fn from_plain_with<S: Into<String>, B: AsRef<[u8]>>(username: S, password: B, policy: &HashPolicy) -> Result<Self, Error> {
+        // Convert String-like into (normalized) String
+        let username = normalize_username(username.into());
 
         // Verify that the username contains no illegal characters
         verify_username(&username)?;
 
-        // Generate a random salt
-        let salt = SaltString::generate(&mut OsRng);
-
-        // Build the hasher with default config
-        let argon2 = Argon2::default();
-
         // Hash the password
-        let hash = match argon2.hash_password(password, &salt) {
-            Ok(hash) => hash.to_string(),
-            Err(err) => { return Err(Error::PasswordHashError{ err }); }  
-        };
+        let hash = policy.hash(password)?;
 
         // Create a new Credential with this hash
         Ok(Self{
             username,
-            password : hash
+            password : hash,
+            created   : None,
+            host      : None,
+            encrypted : false,
         })
     }
 
     /// Constructor for the Credential that loads a username/hashed password pair from disk.
     /// 
+    /// On unix, files that can be read by anyone but their owner are refused. Encrypted files are refused as well; use `from_file_with()` for those.
+    /// 
     /// **Generic types**
     ///  * `P`: The Path-like type of the path that is passed to the function.
     /// 
@@ -227,7 +862,25 @@ impl Credential {
     /// 
     /// **Returns**  
     /// The new Credential instance on success, or else an Error.
+    #[inline]
     pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        Self::from_file_with(path, None)
+    }
+
+    /// Constructor for the Credential that loads a username/hashed password pair from disk, decrypting it with the given passphrase if the file is encrypted.
+    /// 
+    /// On unix, files that can be read by anyone but their owner are refused.
+    /// 
+    /// **Generic types**
+    ///  * `P`: The Path-like type of the path that is passed to the function.
+    /// 
+    /// **Arguments**
+    ///  * `path`: A path-like that refers to the file where the username and hashed password is stored.
+    ///  * `passphrase`: The passphrase to decrypt the file with, if it is encrypted. Ignored for unencrypted files.
+    /// 
+    /// **Returns**  
+    /// The new Credential instance on success, or else an Error.
+    pub fn from_file_with<P: AsRef<Path>>(path: P, passphrase: Option<&str>) -> Result<Self, Error> {
         // Convert Path-like into Path
         let path: &Path = path.as_ref();
 
@@ -237,6 +890,16 @@ impl Credential {
             Err(err)   => { return Err(Error::FileOpenError{ path: path.to_path_buf(), err }); }
         };
 
+        // Make sure nobody else can read it
+        #[cfg(unix)]
+        {
+            let mode: u32 = match handle.metadata() {
+                Ok(metadata) => metadata.permissions().mode() & 0o777,
+                Err(err)     => { return Err(Error::FileReadError{ path: path.to_path_buf(), err }); }
+            };
+            if mode & 0o044 != 0 { return Err(Error::InsecurePermissions{ path: path.to_path_buf(), mode }); }
+        }
+
         // Try to read everything in the file
         let mut data = String::new();
         if let Err(err) = handle.read_to_string(&mut data) {
@@ -245,11 +908,13 @@ impl Credential {
 
         // Use the 'from_string' constructor to split the string and deal with it
         drop(handle);
-        Self::deserialize(data)
+        Self::deserialize_with(data, passphrase)
     }
 
     /// Constructor for the Credential that takes a given username/password string and parses it.
     /// 
+    /// Both the versioned format written by `serialize()` and the older `username+password` format are accepted, but encrypted credentials are not; use `deserialize_with()` for those.
+    /// 
     /// **Generic types**
     ///  * `S`: The String-like type of the serialized username/password pair that is passed to the function.
     /// 
@@ -258,10 +923,29 @@ impl Credential {
     /// 
     /// **Returns**  
     /// The new Credential instance on success, or else an Error.
+    #[inline]
     pub fn deserialize<S: Into<String>>(string: S) -> Result<Self, Error> {
+        Self::deserialize_with(string, None)
+    }
+
+    /// Constructor for the Credential that takes a given username/password string and parses it, decrypting it with the given passphrase if it is encrypted.
+    /// 
+    /// **Generic types**
+    ///  * `S`: The String-like type of the serialized username/password pair that is passed to the function.
+    /// 
+    /// **Arguments**
+    ///  * `string`: A String-like that constains the serialized username/password pair we will base a new Credential on.
+    ///  * `passphrase`: The passphrase to decrypt the credential with, if it is encrypted. Ignored for unencrypted credentials.
+    /// 
+    /// **Returns**  
+    /// The new Credential instance on success, or else an Error.
+    pub fn deserialize_with<S: Into<String>>(string: S, passphrase: Option<&str>) -> Result<Self, Error> {
         // Convert the String-like into a String
         let string: String = string.into();
 
+        // Usernames cannot contain braces, so anything that looks like an object is the versioned format
+        if string.trim_start().starts_with('{') { return Self::deserialize_versioned(&string, passphrase); }
+
         // Try to split the string on the first '+' we see
         let mut found = false;
         let mut username = String::with_capacity(string.len());
@@ -279,6 +963,7 @@ impl Credential {
         if !found { return Err(Error::MissingSeparator); }
 
         // Make sure the username is legal
+        let mut username: String = normalize_username(username);
         verify_username(&username)?;
 
         // That's it! Now return it
@@ -287,13 +972,137 @@ impl Credential {
         Ok(Self{
             username,
             password,
+            created   : None,
+            host      : None,
+            encrypted : false,
         })
     }
 
+    /// Parses a credential in the versioned format.
+    /// 
+    /// **Arguments**
+    ///  * `string`: The serialized credential.
+    ///  * `passphrase`: The passphrase to decrypt the credential with, if it is encrypted.
+    /// 
+    /// **Returns**  
+    /// The new Credential instance on success, or else an Error.
+    fn deserialize_versioned(string: &str, passphrase: Option<&str>) -> Result<Self, Error> {
+        // Check the version first, so that newer files get a helpful error
+        let header: CredentialFileHeader = match serde_json::from_str(string) {
+            Ok(header) => header,
+            Err(err)   => { return Err(Error::IllegalFormat{ err }); }
+        };
+        if header.version != CREDENTIAL_FILE_VERSION { return Err(Error::UnsupportedVersion{ version: header.version }); }
+        if header.cipher.is_some() {
+            let passphrase: &str = match passphrase {
+                Some(passphrase) => passphrase,
+                None             => { return Err(Error::MissingPassphrase); }
+            };
+            return Self::decrypt(string, passphrase);
+        }
+
+        // Now parse the rest
+        let file: CredentialFile = match serde_json::from_str(string) {
+            Ok(file) => file,
+            Err(err) => { return Err(Error::IllegalFormat{ err }); }
+        };
+        Self::from_credential_file(file)
+    }
+
+    /// Decrypts a credential in the encrypted, versioned format.
+    /// 
+    /// **Arguments**
+    ///  * `string`: The serialized, encrypted credential.
+    ///  * `passphrase`: The passphrase to decrypt the credential with.
+    /// 
+    /// **Returns**  
+    /// The new Credential instance on success, or else an Error (e.g., `Error::WrongPassphrase` if the passphrase was not the right one).
+    fn decrypt(string: &str, passphrase: &str) -> Result<Self, Error> {
+        // Parse the envelope
+        let file: EncryptedCredentialFile = match serde_json::from_str(string) {
+            Ok(file) => file,
+            Err(err) => { return Err(Error::IllegalFormat{ err }); }
+        };
+        if file.cipher != ENCRYPTION_CIPHER { return Err(Error::UnsupportedCipher{ cipher: file.cipher }); }
+        let nonce: Vec<u8> = match base64::decode(&file.nonce) {
+            Ok(nonce) if nonce.len() == ENCRYPTION_NONCE_LENGTH => nonce,
+            Ok(_)                                              => { return Err(Error::IllegalNonce); }
+            Err(err)                                           => { return Err(Error::IllegalEncoding{ what: "nonce", err }); }
+        };
+        let ciphertext: Vec<u8> = match base64::decode(&file.ciphertext) {
+            Ok(ciphertext) => ciphertext,
+            Err(err)       => { return Err(Error::IllegalEncoding{ what: "ciphertext", err }); }
+        };
+
+        // Decrypt it; since the cipher is authenticated, a wrong passphrase is all but guaranteed to fail here
+        let key: [u8; 32] = derive_key(passphrase, &file.kdf)?;
+        let cipher = XChaCha20Poly1305::new_from_slice(&key).expect("Could not create cipher from 256-bit key; this should never happen!");
+        let plaintext: Vec<u8> = match cipher.decrypt(XNonce::from_slice(&nonce), ciphertext.as_slice()) {
+            Ok(plaintext) => plaintext,
+            Err(_)        => { return Err(Error::WrongPassphrase); }
+        };
+
+        // The plaintext is an ordinary credential file
+        let inner: CredentialFile = match serde_json::from_slice(&plaintext) {
+            Ok(inner) => inner,
+            Err(err)  => { return Err(Error::IllegalFormat{ err }); }
+        };
+        if inner.version != CREDENTIAL_FILE_VERSION { return Err(Error::UnsupportedVersion{ version: inner.version }); }
+        let mut cred: Self = Self::from_credential_file(inner)?;
+        cred.encrypted = true;
+        Ok(cred)
+    }
+
+    /// Builds a Credential from a parsed credential file.
+    /// 
+    /// **Arguments**
+    ///  * `file`: The parsed (and decrypted) credential file.
+    /// 
+    /// **Returns**  
+    /// The new Credential instance on success, or else an Error.
+    fn from_credential_file(file: CredentialFile) -> Result<Self, Error> {
+        let username: String = normalize_username(file.username);
+        verify_username(&username)?;
+        let created: Option<OffsetDateTime> = match file.created {
+            Some(raw) => match OffsetDateTime::parse(&raw, &Rfc3339) {
+                Ok(created) => Some(created),
+                Err(err)    => { return Err(Error::IllegalCreated{ raw, err }); }
+            },
+            None => None,
+        };
+
+        // Done
+        Ok(Self{
+            username,
+            password  : file.password,
+            created,
+            host      : file.host,
+            encrypted : false,
+        })
+    }
+
+    /// Binds the Credential to the given host.
+    /// 
+    /// **Generic types**
+    ///  * `S`: The String-like type of the host.
+    /// 
+    /// **Arguments**
+    ///  * `host`: The host (e.g., the address of a Todo server) that this credential is meant for.
+    /// 
+    /// **Returns**  
+    /// The same Credential, but now bound to the given host.
+    #[inline]
+    pub fn with_host<S: Into<String>>(mut self, host: S) -> Self {
+        self.host = Some(host.into());
+        self
+    }
+
 
 
     /// Writes the credential to a credential string in a file.
     /// 
+    /// On unix, the file is made readable and writable for its owner only.
+    /// 
     /// **Generic types**
     ///  * `P`: The Path-like type of the path that is passed to the function.
     /// 
@@ -302,21 +1111,54 @@ impl Credential {
     /// 
     /// **Returns**  
     /// Nothing on success, or else an Error.
+    #[inline]
     pub fn serialize_to_file<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
-        // Convert the path-like to a path
-        let path: &Path = path.as_ref();
+        Self::write_file(path.as_ref(), &self.serialize())
+    }
 
-        // Serialize ourselves first
-        let to_write = self.serialize();
+    /// Writes the credential to a file, encrypted with the given passphrase.
+    /// 
+    /// On unix, the file is made readable and writable for its owner only.
+    /// 
+    /// **Generic types**
+    ///  * `P`: The Path-like type of the path that is passed to the function.
+    /// 
+    /// **Arguments**
+    ///  * `path`: A path-like that refers to the location where we should write it to.
+    ///  * `passphrase`: The passphrase to encrypt the credential with.
+    /// 
+    /// **Returns**  
+    /// Nothing on success, or else an Error.
+    pub fn serialize_to_file_encrypted<P: AsRef<Path>>(&self, path: P, passphrase: &str) -> Result<(), Error> {
+        let to_write: String = self.serialize_encrypted(passphrase)?;
+        Self::write_file(path.as_ref(), &to_write)
+    }
 
-        // Try to open the file
-        let mut handle = match File::create(path) {
+    /// Writes a serialized credential to a file that only its owner may read.
+    /// 
+    /// **Arguments**
+    ///  * `path`: The location where we should write it to.
+    ///  * `to_write`: The serialized credential.
+    /// 
+    /// **Returns**  
+    /// Nothing on success, or else an Error.
+    fn write_file(path: &Path, to_write: &str) -> Result<(), Error> {
+        // Try to open the file; the mode only applies to new files, so we also fix it for existing ones before writing anything
+        let mut options = OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        options.mode(0o600);
+        let mut handle = match options.open(path) {
             Ok(handle) => handle,
             Err(err)   => { return Err(Error::FileOpenError{ path: path.to_path_buf(), err }); }
         };
+        #[cfg(unix)]
+        if let Err(err) = handle.set_permissions(std::fs::Permissions::from_mode(0o600)) {
+            return Err(Error::FileWriteError{ path: path.to_path_buf(), err });
+        }
 
         // Try to write it all
-        if let Err(err) = write!(handle, "{}", to_write) {
+        if let Err(err) = writeln!(handle, "{}", to_write) {
             return Err(Error::FileWriteError{ path: path.to_path_buf(), err });
         }
 
@@ -324,18 +1166,64 @@ impl Credential {
         Ok(())
     }
 
-    /// Serializes the credential file to a string.
+    /// Serializes the credential file to a string in the versioned format.
+    /// 
+    /// If the credential has no creation time yet, the current time is used.
     /// 
     /// **Returns**  
-    /// The serialized credential file as a String on success, or else an Error.
-    #[inline]
+    /// The serialized credential file as a String.
     pub fn serialize(&self) -> String {
-        format!("{}+{}", self.username, self.password)
+        let created: OffsetDateTime = self.created.unwrap_or_else(OffsetDateTime::now_utc);
+        serde_json::to_string(&CredentialFile {
+            version  : CREDENTIAL_FILE_VERSION,
+            username : self.username.clone(),
+            password : self.password.clone(),
+            created  : Some(created.format(&Rfc3339).expect("Could not format credential creation time to ISO/RFC3339; this should never happen!")),
+            host     : self.host.clone(),
+        }).expect("Could not serialize credential file; this should never happen!")
+    }
+
+    /// Serializes the credential file to a string in the versioned format, encrypted with the given passphrase.
+    /// 
+    /// The key is derived from the passphrase with Argon2id and a random salt, and the credential is encrypted with XChaCha20-Poly1305.
+    /// 
+    /// **Arguments**
+    ///  * `passphrase`: The passphrase to encrypt the credential with.
+    /// 
+    /// **Returns**  
+    /// The serialized, encrypted credential file as a String on success, or else an Error.
+    pub fn serialize_encrypted(&self, passphrase: &str) -> Result<String, Error> {
+        // Derive a key with a fresh salt
+        let mut salt = [0; ENCRYPTION_SALT_LENGTH];
+        OsRng.fill_bytes(&mut salt);
+        let kdf = KdfParams {
+            algorithm : ENCRYPTION_KDF.into(),
+            m_cost    : ENCRYPTION_M_COST,
+            t_cost    : ENCRYPTION_T_COST,
+            p_cost    : ENCRYPTION_P_COST,
+            salt      : base64::encode(salt),
+        };
+        let key: [u8; 32] = derive_key(passphrase, &kdf)?;
+
+        // Encrypt the unencrypted form with a fresh nonce
+        let mut nonce = [0; ENCRYPTION_NONCE_LENGTH];
+        OsRng.fill_bytes(&mut nonce);
+        let cipher = XChaCha20Poly1305::new_from_slice(&key).expect("Could not create cipher from 256-bit key; this should never happen!");
+        let ciphertext: Vec<u8> = cipher.encrypt(XNonce::from_slice(&nonce), self.serialize().as_bytes()).expect("Could not encrypt credential file; this should never happen!");
+
+        // Wrap it in the envelope
+        Ok(serde_json::to_string(&EncryptedCredentialFile {
+            version    : CREDENTIAL_FILE_VERSION,
+            kdf,
+            cipher     : ENCRYPTION_CIPHER.into(),
+            nonce      : base64::encode(nonce),
+            ciphertext : base64::encode(ciphertext),
+        }).expect("Could not serialize encrypted credential file; this should never happen!"))
     }
 
 
 
-    /// Compares this Credential to the given username + password.
+    /// Compares this Credential to the given username + password, assuming it was hashed without a pepper.
     /// 
     /// **Generic types**
     ///  * `S`: The String-like type of the username that is passed to the function.
@@ -347,24 +1235,42 @@ impl Credential {
     /// 
     /// **Returns**  
     /// Whether or not the passwords match, or else an Error if some error occurred while hashing.
+    #[inline]
     pub fn verify<S: Into<String>, B: AsRef<[u8]>>(&self, username: S, password: B) -> Result<bool, Error> {
-        // Convert String-like into String
-        let username = username.into();
-        // Convert bytes-like into bytes
-        let password = password.as_ref();
-
-        // Make sure the username makes sense
-        if self.username != username { return Ok(false); }
+        self.verify_with(username, password, &HashPolicy::default())
+    }
 
-        // Re-hash the internal password
-        let hash = match PasswordHash::new(&self.password) {
-            Ok(hash) => hash,
-            Err(err) => { return Err(Error::IllegalHash{ err }); }
-        };
+    /// Compares this Credential to the given username + password, using the pepper of the given HashPolicy if the hash needs it.
+    /// 
+    /// **Generic types**
+    ///  * `S`: The String-like type of the username that is passed to the function.
+    ///  * `B`: The Bytes-like type of the password that is passed to the function.
+    /// 
+    /// **Arguments**
+    ///  * `username`: The username of the user to whom the given password belongs.
+    ///  * `password`: The plain-text password we want to match against this one.
+    ///  * `policy`: The HashPolicy that has the pepper (if any).
+    /// 
+    /// **Returns**  
+    /// Whether or not the passwords match, or else an Error if some error occurred while hashing.
+    pub fn verify_with<S: Into<String>, B: AsRef<[u8]>>(&self, username: S, password: B, policy: &HashPolicy) -> Result<bool, Error> {
+        // Make sure the username makes sense; like the store, we do not care about case
+        if username_key(&self.username) != username_key(username.into()) { return Ok(false); }
 
         // Verify the hash and return the result!
-        let argon2 = Argon2::default();
-        Ok(argon2.verify_password(password, &hash).is_ok())
+        policy.verify(&self.password, password)
+    }
+
+    /// Checks whether the internal password hash is weaker than the given HashPolicy.
+    /// 
+    /// **Arguments**
+    ///  * `policy`: The HashPolicy to compare with.
+    /// 
+    /// **Returns**  
+    /// Whether or not the password should be rehashed, or else an Error if the internal hash is invalid.
+    #[inline]
+    pub fn needs_rehash(&self, policy: &HashPolicy) -> Result<bool, Error> {
+        policy.needs_rehash(&self.password)
     }
 
 
@@ -376,4 +1282,16 @@ impl Credential {
     /// Returns the internal password.
     #[inline]
     pub fn pass(&self) -> &str { &self.password }
+
+    /// Returns when the credential was first written to a file, if it was read from one that records it.
+    #[inline]
+    pub fn created(&self) -> Option<OffsetDateTime> { self.created }
+
+    /// Returns the host this credential is bound to, if any.
+    #[inline]
+    pub fn host(&self) -> Option<&str> { self.host.as_deref() }
+
+    /// Returns whether the credential was read from an encrypted file.
+    #[inline]
+    pub fn encrypted(&self) -> bool { self.encrypted }
 }
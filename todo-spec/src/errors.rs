@@ -4,7 +4,7 @@
  * Created:
  *   18 Mar 2022, 16:04:08
  * Last edited:
- *   19 Mar 2022, 20:34:30
+ *   18 Oct 2026, 07:11:43
  * Auto updated?
  *   Yes
  *
@@ -16,6 +16,9 @@ use std::error::Error;
 use std::fmt::{Display, Formatter, Result as FResult};
 use std::path::PathBuf;
 
+use crate::credentials::{CREDENTIAL_FILE_VERSION, MAX_DISPLAY_NAME_LENGTH, MAX_USERNAME_LENGTH};
+use crate::password::Violation;
+
 
 /***** ERRORS *****/
 /// Errors that occur while working with credentials.
@@ -23,10 +26,18 @@ use std::path::PathBuf;
 pub enum CredentialError {
     /// The given username was not valid.
     InvalidUsername{ username: String },
+    /// The given username mixes scripts in a way that makes it look like another name.
+    ConfusableUsername{ username: String },
+    /// The given display name was not valid.
+    InvalidDisplayName{ name: String },
     /// Could not hash the given password.
     PasswordHashError{ err: argon2::password_hash::Error },
     /// Could not re-hash the given password string
     IllegalHash{ err: argon2::password_hash::Error },
+    /// The given hashing parameters are not valid
+    IllegalHashParams{ err: argon2::Error },
+    /// The given password hash was peppered with a pepper we do not know
+    UnknownPepper{ keyid: String },
 
     /// Could not open the given file
     FileOpenError{ path: PathBuf, err: std::io::Error },
@@ -34,25 +45,211 @@ pub enum CredentialError {
     FileReadError{ path: PathBuf, err: std::io::Error },
     /// Could not write to the given file
     FileWriteError{ path: PathBuf, err: std::io::Error },
+    /// The given credential file may be read by others than its owner
+    InsecurePermissions{ path: PathBuf, mode: u32 },
 
     /// Didn't find the split '+' in the username/character pair.
     MissingSeparator,
+    /// The versioned credential file was not valid
+    IllegalFormat{ err: serde_json::Error },
+    /// The versioned credential file is of a version we do not know
+    UnsupportedVersion{ version: u32 },
+    /// The creation time in the credential file was not valid
+    IllegalCreated{ raw: String, err: time::error::Parse },
+
+    /// The credential file is encrypted, but no passphrase was given
+    MissingPassphrase,
+    /// The credential file could not be decrypted with the given passphrase
+    WrongPassphrase,
+    /// The credential file is encrypted with a cipher we do not know
+    UnsupportedCipher{ cipher: String },
+    /// The key of the credential file is derived with a function we do not know
+    UnsupportedKdf{ kdf: String },
+    /// Some binary field of the encrypted credential file was not valid base64
+    IllegalEncoding{ what: &'static str, err: base64::DecodeError },
+    /// The nonce of the encrypted credential file has the wrong length
+    IllegalNonce,
+    /// Could not derive the key of the encrypted credential file
+    KeyDerivationError{ err: argon2::Error },
 }
 
 impl Display for CredentialError {
     fn fmt(&self, f: &mut Formatter<'_>) -> FResult {
         match self {
-            CredentialError::InvalidUsername{ username } => write!(f, "Given username '{}' is invalid; it may only contain alphanumerical characters and underscores (_) and dashes(-)", username),
-            CredentialError::PasswordHashError{ err }    => write!(f, "Could not hash password: {}", err),
-            CredentialError::IllegalHash{ err }          => write!(f, "Internal password 'is not a valid hash: {}", err),
+            CredentialError::InvalidUsername{ username }    => write!(f, "Given username '{}' is invalid; it must be between 1 and {} characters long, and may only contain letters, digits, underscores (_) and dashes (-)", username, MAX_USERNAME_LENGTH),
+            CredentialError::ConfusableUsername{ username } => write!(f, "Given username '{}' is invalid; it mixes scripts in a way that makes it easy to confuse with another name", username),
+            CredentialError::InvalidDisplayName{ name }     => write!(f, "Given display name '{}' is invalid; it must be between 1 and {} characters long, may not start or end with whitespace and may not contain control characters", name, MAX_DISPLAY_NAME_LENGTH),
+            CredentialError::PasswordHashError{ err }       => write!(f, "Could not hash password: {}", err),
+            CredentialError::IllegalHash{ err }             => write!(f, "Internal password 'is not a valid hash: {}", err),
+            CredentialError::IllegalHashParams{ err }       => write!(f, "Invalid password hashing parameters: {}", err),
+            CredentialError::UnknownPepper{ keyid }         => write!(f, "Password hash was peppered with unknown pepper '{}'", keyid),
 
-            CredentialError::FileOpenError{ path, err }  => write!(f, "Could not open file '{}': {}", path.display(), err),
-            CredentialError::FileReadError{ path, err }  => write!(f, "Could not read from file '{}': {}", path.display(), err),
-            CredentialError::FileWriteError{ path, err } => write!(f, "Could not write to file '{}': {}", path.display(), err),
+            CredentialError::FileOpenError{ path, err }        => write!(f, "Could not open file '{}': {}", path.display(), err),
+            CredentialError::FileReadError{ path, err }        => write!(f, "Could not read from file '{}': {}", path.display(), err),
+            CredentialError::FileWriteError{ path, err }       => write!(f, "Could not write to file '{}': {}", path.display(), err),
+            CredentialError::InsecurePermissions{ path, mode } => write!(f, "Credential file '{}' may be read by other users (mode {:03o}); run 'chmod 600 {}' to fix this", path.display(), mode, path.display()),
 
-            CredentialError::MissingSeparator => write!(f, "Missing username/password separator '+' in serialized credential"),
+            CredentialError::MissingSeparator              => write!(f, "Missing username/password separator '+' in serialized credential"),
+            CredentialError::IllegalFormat{ err }          => write!(f, "Could not parse credential file: {}", err),
+            CredentialError::UnsupportedVersion{ version } => write!(f, "Unsupported credential file version {} (expected {})", version, CREDENTIAL_FILE_VERSION),
+            CredentialError::IllegalCreated{ raw, err }    => write!(f, "Invalid creation time '{}' in credential file: {}", raw, err),
+
+            CredentialError::MissingPassphrase             => write!(f, "Credential file is encrypted, but no passphrase was given"),
+            CredentialError::WrongPassphrase               => write!(f, "Could not decrypt credential file; is the passphrase correct?"),
+            CredentialError::UnsupportedCipher{ cipher }   => write!(f, "Unsupported cipher '{}' in encrypted credential file", cipher),
+            CredentialError::UnsupportedKdf{ kdf }         => write!(f, "Unsupported key derivation function '{}' in encrypted credential file", kdf),
+            CredentialError::IllegalEncoding{ what, err }  => write!(f, "Could not decode {} of encrypted credential file as base64: {}", what, err),
+            CredentialError::IllegalNonce                  => write!(f, "Nonce of encrypted credential file has the wrong length"),
+            CredentialError::KeyDerivationError{ err }     => write!(f, "Could not derive key of encrypted credential file: {}", err),
         }
     }
 }
 
 impl Error for CredentialError {}
+
+
+
+/// Errors that occur while signing or verifying JWT's.
+#[derive(Debug)]
+pub enum JwtError {
+    /// Could not read the given key file
+    FileReadError{ path: PathBuf, err: std::io::Error },
+    /// Could not create the Hmac key from the given secret
+    HmacKeyError{ err: hmac::digest::InvalidLength },
+    /// The given PEM file does not contain a key we support
+    UnsupportedPem,
+    /// The given RSA key is too short to be used safely
+    WeakRsaKey{ bits: usize },
+    /// The given JWK is not a key we can use
+    IllegalJwk{ kid: String, reason: &'static str },
+    /// The given algorithm is not one we support
+    UnknownAlgorithm{ raw: String },
+    /// The given role is not one we know
+    UnknownRole{ raw: String },
+
+    /// The given key only has a public part, and thus cannot sign
+    NotASigningKey{ kid: String },
+    /// Could not sign the given claims
+    SignError{ err: rsa::signature::Error },
+
+    /// The given token does not consist of a header, payload and signature
+    MalformedToken,
+    /// Could not decode a part of the given token as base64
+    Base64DecodeError{ what: &'static str, err: base64::DecodeError },
+    /// Could not decode a part of the given token as JSON
+    JsonDecodeError{ what: &'static str, err: serde_json::Error },
+    /// The token was signed with a key we do not know
+    UnknownKey{ kid: Option<String> },
+    /// The signature of the token does not match
+    InvalidSignature,
+
+    /// The token does not have the given claim
+    MissingClaim{ name: &'static str },
+    /// The given claim has an illegal value
+    IllegalClaim{ name: &'static str, raw: String },
+    /// The token has expired
+    Expired{ exp: String },
+}
+
+impl Display for JwtError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FResult {
+        match self {
+            JwtError::FileReadError{ path, err }    => write!(f, "Could not read key file '{}': {}", path.display(), err),
+            JwtError::HmacKeyError{ err }           => write!(f, "Could not create Hmac key: {}", err),
+            JwtError::UnsupportedPem                => write!(f, "PEM file does not contain an Ed25519 or RSA key"),
+            JwtError::WeakRsaKey{ bits }            => write!(f, "RSA key of {} bits is too short; use at least 2048 bits", bits),
+            JwtError::IllegalJwk{ kid, reason }     => write!(f, "Illegal JWK '{}': {}", kid, reason),
+            JwtError::UnknownAlgorithm{ raw }       => write!(f, "Unknown JWT algorithm '{}'", raw),
+            JwtError::UnknownRole{ raw }            => write!(f, "Unknown role '{}'", raw),
+
+            JwtError::NotASigningKey{ kid } => write!(f, "Key '{}' is a public key and cannot sign JWT's", kid),
+            JwtError::SignError{ err }      => write!(f, "Could not sign JWT: {}", err),
+
+            JwtError::MalformedToken                => write!(f, "JWT does not consist of a header, payload and signature"),
+            JwtError::Base64DecodeError{ what, err } => write!(f, "Could not decode JWT {} as base64: {}", what, err),
+            JwtError::JsonDecodeError{ what, err }   => write!(f, "Could not decode JWT {} as JSON: {}", what, err),
+            JwtError::UnknownKey{ kid }              => match kid {
+                Some(kid) => write!(f, "JWT is signed with unknown key '{}'", kid),
+                None      => write!(f, "JWT is signed with an unknown key"),
+            },
+            JwtError::InvalidSignature => write!(f, "JWT has an invalid signature"),
+
+            JwtError::MissingClaim{ name }      => write!(f, "JWT is missing claim '{}'", name),
+            JwtError::IllegalClaim{ name, raw } => write!(f, "JWT has illegal value '{}' for claim '{}'", raw, name),
+            JwtError::Expired{ exp }            => write!(f, "JWT has expired at {}", exp),
+        }
+    }
+}
+
+impl Error for JwtError {}
+
+
+
+/// Errors that occur while working with TOTP secrets.
+#[derive(Debug)]
+pub enum TotpError {
+    /// The given secret is not valid base32
+    IllegalSecret{ raw: String },
+    /// The given secret is too short to be safe
+    ShortSecret{ bytes: usize },
+}
+
+impl Display for TotpError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FResult {
+        match self {
+            TotpError::IllegalSecret{ raw } => write!(f, "TOTP secret '{}' is not valid base32", raw),
+            TotpError::ShortSecret{ bytes } => write!(f, "TOTP secret of {} bytes is too short; use at least 16 bytes", bytes),
+        }
+    }
+}
+
+impl Error for TotpError {}
+
+
+
+/// Errors that occur while parsing the values of the API, such as audit event kinds and scopes.
+#[derive(Debug)]
+pub enum AuditError {
+    /// The given authentication event type is not one we know
+    UnknownEventKind{ raw: String },
+    /// The given scope is not one we know
+    UnknownScope{ raw: String },
+}
+
+impl Display for AuditError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FResult {
+        match self {
+            AuditError::UnknownEventKind{ raw } => write!(f, "Unknown authentication event type '{}'", raw),
+            AuditError::UnknownScope{ raw }     => write!(f, "Unknown scope '{}'", raw),
+        }
+    }
+}
+
+impl Error for AuditError {}
+
+
+
+/// Errors that occur while checking passwords against a policy.
+#[derive(Debug)]
+pub enum PasswordError {
+    /// The password does not meet one or more rules of the policy
+    Rejected{ violations: Vec<Violation> },
+
+    /// The given directory with breached passwords does not exist
+    BreachedDirError{ path: PathBuf },
+    /// Could not read the given file with breached passwords
+    BreachedReadError{ path: PathBuf, err: std::io::Error },
+}
+
+impl Display for PasswordError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FResult {
+        match self {
+            PasswordError::Rejected{ violations } => write!(f, "Password {}", violations.iter().map(|violation| violation.to_string()).collect::<Vec<String>>().join(", and ")),
+
+            PasswordError::BreachedDirError{ path }       => write!(f, "Breached passwords directory '{}' does not exist", path.display()),
+            PasswordError::BreachedReadError{ path, err } => write!(f, "Could not read breached passwords file '{}': {}", path.display(), err),
+        }
+    }
+}
+
+impl Error for PasswordError {}
@@ -4,7 +4,7 @@
  * Created:
  *   17 Mar 2022, 18:34:58
  * Last edited:
- *   19 Mar 2022, 12:13:14
+ *   18 Oct 2026, 05:57:19
  * Auto updated?
  *   Yes
  *
@@ -18,5 +18,11 @@ pub mod errors;
 
 /// Defines the credential structs and logic.
 pub mod credentials;
+/// Defines the policy that new passwords have to meet
+pub mod password;
 /// Defines the API structs
 pub mod api;
+/// Defines the JWT's and how to verify them
+pub mod jwt;
+/// Defines time-based one-time passwords (TOTP) for second factors
+pub mod totp;
@@ -0,0 +1,324 @@
+/* PASSWORD.rs
+ *   by Lut99
+ *
+ * Created:
+ *   18 Oct 2026, 05:49:22
+ * Last edited:
+ *   18 Oct 2026, 05:57:19
+ * Auto updated?
+ *   Yes
+ *
+ * Description:
+ *   Defines the policy that new passwords have to meet, such as a
+ *   minimum length and strength.
+ *
+ *   Optionally, passwords are also looked up in a local list of
+ *   breached passwords. This list is laid out like the k-anonymity
+ *   ranges of Have I Been Pwned: a directory with one file per first
+ *   five hexadecimal characters of the (uppercase) SHA-1 hash, named
+ *   `<PREFIX>.txt`, where every line is `<SUFFIX>:<COUNT>`.
+**/
+
+use std::fmt::{Display, Formatter, Result as FResult};
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+
+pub use crate::errors::PasswordError as Error;
+
+
+/***** UNIT TESTS *****/
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns the violations of the given password under the given policy, or panics if it could not be checked.
+    fn violations(policy: &PasswordPolicy, username: &str, password: &str) -> Vec<Violation> {
+        match policy.check(username, password) {
+            Ok(())                             => vec![],
+            Err(Error::Rejected{ violations }) => violations,
+            Err(err)                           => { panic!("Could not check password: {}", err); }
+        }
+    }
+
+    #[test]
+    fn test_strength() {
+        // Repeats and sequences don't add much
+        assert!(strength("") == 0.0);
+        assert!(strength("aaaaaaaaaaaa") < strength("a1"));
+        assert!(strength("abcdefghijkl") < strength("a1"));
+        assert!(strength("123456789") < 10.0);
+
+        // Larger alphabets and longer passwords do
+        assert!(strength("correcthorse") < strength("correcthorsebattery"));
+        assert!(strength("correcthorse") < strength("correct_horse"));
+        assert!(strength("this_is_secret!") >= PasswordPolicy::default().min_strength as f64);
+    }
+
+    #[test]
+    fn test_policy() {
+        let policy = PasswordPolicy::default();
+        assert_eq!(violations(&policy, "john", "this_is_secret!"), vec![]);
+
+        // Every rule that fails is reported
+        assert_eq!(violations(&policy, "john", ""), vec![ Violation::TooShort{ min_length: 8 }, Violation::TooFewClasses{ min_classes: 1 }, Violation::TooWeak{ min_strength: 36 } ]);
+        assert_eq!(violations(&policy, "john", "aaaaaaaaaa"), vec![ Violation::TooWeak{ min_strength: 36 } ]);
+        assert_eq!(violations(&policy, "john", "JOHN_is_great"), vec![ Violation::ContainsUsername ]);
+        assert_eq!(violations(&PasswordPolicy{ forbid_username: false, ..PasswordPolicy::default() }, "john", "JOHN_is_great"), vec![]);
+        assert_eq!(violations(&PasswordPolicy{ min_classes: 3, ..PasswordPolicy::default() }, "john", "this_is_secret!"), vec![ Violation::TooFewClasses{ min_classes: 3 } ]);
+        assert_eq!(violations(&PasswordPolicy{ max_length: 10, ..PasswordPolicy::default() }, "john", "this_is_secret!"), vec![ Violation::TooLong{ max_length: 10 } ]);
+
+        // Violations serialize with the name of the rule
+        assert_eq!(serde_json::to_string(&Violation::TooShort{ min_length: 8 }).unwrap(), "{\"rule\":\"too_short\",\"min_length\":8}");
+        assert_eq!(serde_json::to_string(&Violation::ContainsUsername).unwrap(), "{\"rule\":\"contains_username\"}");
+    }
+
+    #[test]
+    fn test_breached() {
+        // SHA-1 of 'password' is 5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD8, of 'this_is_secret!' A74F5063E065DD23941D347631FD2BE1816F080D (which we add as padding)
+        let dir: PathBuf = std::env::temp_dir().join(format!("todo-spec-breached-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("5BAA6.txt"), "003D68EB55068C33ACE09247EE4C639306B:3\r\n1e4c9b93f3f0682250b6cf8331b7ee68fd8:42\r\n").unwrap();
+        fs::write(dir.join("A74F5.txt"), "063E065DD23941D347631FD2BE1816F080D:0\n").unwrap();
+
+        let breached = BreachedPasswords::new(&dir).unwrap();
+        assert_eq!(breached.count("password").unwrap(), 42);
+        assert_eq!(breached.count("this_is_secret!").unwrap(), 0);
+
+        let policy = PasswordPolicy{ breached: Some(breached), ..PasswordPolicy::default() };
+        assert_eq!(violations(&policy, "john", "password"), vec![ Violation::TooWeak{ min_strength: 36 }, Violation::Breached{ count: 42 } ]);
+        assert_eq!(violations(&policy, "john", "this_is_secret!"), vec![]);
+
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(matches!(BreachedPasswords::new(&dir), Err(Error::BreachedDirError{ .. })));
+    }
+}
+
+
+
+
+
+/***** CONSTANTS *****/
+/// The number of hexadecimal characters of the hash that name the file it is in.
+const PREFIX_LENGTH: usize = 5;
+/// The minimum length of a username before we refuse passwords that contain it; shorter ones appear in passwords by accident.
+const MIN_USERNAME_MATCH: usize = 3;
+/// The number of characters that each class of characters adds to the pool, in the order of `class()`.
+const POOL_SIZES: [u32; 5] = [ 26, 26, 10, 33, 100 ];
+
+
+
+
+
+/***** HELPER FUNCTIONS *****/
+/// Returns the class of the given character, as an index in `POOL_SIZES`.
+/// 
+/// The classes are lowercase letters, uppercase letters, digits, (ASCII) symbols and everything else, respectively.
+fn class(c: char) -> usize {
+    if c.is_ascii_lowercase() { 0 }
+    else if c.is_ascii_uppercase() { 1 }
+    else if c.is_ascii_digit() { 2 }
+    else if c.is_ascii() { 3 }
+    else { 4 }
+}
+
+/// Returns which classes of characters (see `class()`) appear in the given password.
+fn classes(password: &str) -> [bool; 5] {
+    let mut res: [bool; 5] = [ false; 5 ];
+    for c in password.chars() { res[class(c)] = true; }
+    res
+}
+
+
+
+
+
+/***** LIBRARY FUNCTIONS *****/
+/// Estimates the strength of the given password, in bits.
+/// 
+/// This is a rough estimate: every character counts as a pick from the classes of characters that appear in the password, except for characters that repeat or continue the previous one (as in 'aaa' or 'abc'), which count for nothing.
+/// 
+/// **Arguments**
+///  * `password`: The password to estimate the strength of.
+/// 
+/// **Returns**  
+/// The estimated number of bits it takes to guess the password.
+pub fn strength(password: &str) -> f64 {
+    let pool: u32 = POOL_SIZES.iter().zip(classes(password)).filter(|(_, present)| *present).map(|(size, _)| size).sum();
+    if pool == 0 { return 0.0; }
+
+    // Count the characters that aren't predictable from the one before
+    let mut effective: u32 = 0;
+    let mut prev: Option<char> = None;
+    for c in password.chars() {
+        let predictable: bool = match prev {
+            Some(prev) => (c as i64 - prev as i64).abs() <= 1,
+            None       => false,
+        };
+        if !predictable { effective += 1; }
+        prev = Some(c);
+    }
+    effective as f64 * (pool as f64).log2()
+}
+
+
+
+
+
+/***** LIBRARY STRUCTS *****/
+/// Defines a rule of the PasswordPolicy that a password does not meet.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(tag = "rule", rename_all = "snake_case")]
+pub enum Violation {
+    /// The password has fewer characters than allowed.
+    TooShort{ min_length: usize },
+    /// The password has more characters than allowed.
+    TooLong{ max_length: usize },
+    /// The password uses too few classes of characters (lowercase, uppercase, digits, symbols and others).
+    TooFewClasses{ min_classes: usize },
+    /// The estimated strength of the password (in bits) is too low.
+    TooWeak{ min_strength: u32 },
+    /// The password contains the username.
+    ContainsUsername,
+    /// The password appears in the list of breached passwords.
+    Breached{ count: u64 },
+}
+
+impl Display for Violation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FResult {
+        match self {
+            Violation::TooShort{ min_length }       => write!(f, "must be at least {} characters long", min_length),
+            Violation::TooLong{ max_length }        => write!(f, "must be at most {} characters long", max_length),
+            Violation::TooFewClasses{ min_classes } => write!(f, "must use at least {} of lowercase letters, uppercase letters, digits, symbols and other characters", min_classes),
+            Violation::TooWeak{ min_strength }      => write!(f, "is too easy to guess (needs an estimated strength of at least {} bits)", min_strength),
+            Violation::ContainsUsername             => write!(f, "must not contain the username"),
+            Violation::Breached{ count }            => write!(f, "has appeared {} time(s) in known data breaches", count),
+        }
+    }
+}
+
+
+
+/// Defines a local list of breached passwords, laid out as k-anonymity ranges of SHA-1 hashes.
+#[derive(Clone, Debug)]
+pub struct BreachedPasswords {
+    /// The directory with the range files.
+    dir : PathBuf,
+}
+
+impl BreachedPasswords {
+    /// Constructor for the BreachedPasswords.
+    /// 
+    /// **Arguments**
+    ///  * `dir`: The directory with the range files (`<PREFIX>.txt`).
+    /// 
+    /// **Returns**  
+    /// The new BreachedPasswords on success, or an Error if the directory does not exist.
+    pub fn new<P: Into<PathBuf>>(dir: P) -> Result<Self, Error> {
+        let dir: PathBuf = dir.into();
+        if !dir.is_dir() { return Err(Error::BreachedDirError{ path: dir }); }
+        Ok(Self { dir })
+    }
+
+
+
+    /// Looks up how often the given password appears in the list.
+    /// 
+    /// A range file that does not exist counts as empty, so a partial list can be used.
+    /// 
+    /// **Arguments**
+    ///  * `password`: The password to look up.
+    /// 
+    /// **Returns**  
+    /// The number of times that the password has been seen in breaches (0 if never), or an Error if its range file could not be read.
+    pub fn count(&self, password: &str) -> Result<u64, Error> {
+        let hash: String = Sha1::digest(password.as_bytes()).iter().map(|b| format!("{:02X}", b)).collect();
+        let (prefix, suffix) = hash.split_at(PREFIX_LENGTH);
+
+        // Read the range that the hash is in
+        let path: PathBuf = self.dir.join(format!("{}.txt", prefix));
+        let data: String = match fs::read_to_string(&path) {
+            Ok(data) => data,
+            Err(err) if err.kind() == ErrorKind::NotFound => { return Ok(0); },
+            Err(err) => { return Err(Error::BreachedReadError{ path, err }); }
+        };
+
+        // Find the suffix in it; padding entries have a count of 0
+        for line in data.lines() {
+            let (hash, count): (&str, &str) = line.trim().split_once(':').unwrap_or((line.trim(), "1"));
+            if hash.eq_ignore_ascii_case(suffix) { return Ok(count.trim().parse().unwrap_or(1)); }
+        }
+        Ok(0)
+    }
+
+    /// Returns the directory with the range files.
+    #[inline]
+    pub fn dir(&self) -> &Path { &self.dir }
+}
+
+
+
+/// Defines the rules that new passwords have to meet.
+#[derive(Clone, Debug)]
+pub struct PasswordPolicy {
+    /// The minimum number of characters.
+    pub min_length      : usize,
+    /// The maximum number of characters.
+    pub max_length      : usize,
+    /// The minimum number of classes of characters (lowercase, uppercase, digits, symbols and others) to use.
+    pub min_classes     : usize,
+    /// The minimum estimated strength, in bits (see `strength()`).
+    pub min_strength    : u32,
+    /// Whether to refuse passwords that contain the username.
+    pub forbid_username : bool,
+    /// The list of breached passwords to refuse, if any.
+    pub breached        : Option<BreachedPasswords>,
+}
+
+impl PasswordPolicy {
+    /// Checks the given password against the policy.
+    /// 
+    /// **Arguments**
+    ///  * `username`: The name of the user whose password it is.
+    ///  * `password`: The plain-text password to check.
+    /// 
+    /// **Returns**  
+    /// Nothing if the password meets the policy, or an `Error::Rejected` listing every rule it does not meet. If the list of breached passwords could not be read, returns that Error instead.
+    pub fn check(&self, username: &str, password: &str) -> Result<(), Error> {
+        let mut violations: Vec<Violation> = vec![];
+
+        let length: usize = password.chars().count();
+        if length < self.min_length { violations.push(Violation::TooShort{ min_length: self.min_length }); }
+        if length > self.max_length { violations.push(Violation::TooLong{ max_length: self.max_length }); }
+        if classes(password).iter().filter(|present| **present).count() < self.min_classes { violations.push(Violation::TooFewClasses{ min_classes: self.min_classes }); }
+        if strength(password) < self.min_strength as f64 { violations.push(Violation::TooWeak{ min_strength: self.min_strength }); }
+        if self.forbid_username && username.chars().count() >= MIN_USERNAME_MATCH && password.to_lowercase().contains(&username.to_lowercase()) {
+            violations.push(Violation::ContainsUsername);
+        }
+        if let Some(breached) = &self.breached {
+            let count: u64 = breached.count(password)?;
+            if count > 0 { violations.push(Violation::Breached{ count }); }
+        }
+
+        // Done
+        if violations.is_empty() { Ok(()) } else { Err(Error::Rejected{ violations }) }
+    }
+}
+
+impl Default for PasswordPolicy {
+    /// Returns a policy in the spirit of NIST SP 800-63B: at least eight characters and a rough check against trivial passwords, without composition rules.
+    #[inline]
+    fn default() -> Self {
+        Self {
+            min_length      : 8,
+            max_length      : 1024,
+            min_classes     : 1,
+            min_strength    : 36,
+            forbid_username : true,
+            breached        : None,
+        }
+    }
+}
@@ -0,0 +1,280 @@
+/* TOTP.rs
+ *   by Lut99
+ *
+ * Created:
+ *   18 Oct 2026, 03:50:48
+ * Last edited:
+ *   18 Oct 2026, 03:59:58
+ * Auto updated?
+ *   Yes
+ *
+ * Description:
+ *   Implements time-based one-time passwords (RFC 6238), as generated
+ *   by most authenticator apps. We use the parameters that every app
+ *   supports: HMAC-SHA1, six digits and a period of thirty seconds.
+**/
+
+use std::fmt::{Debug, Formatter, Result as FResult};
+
+use hmac::{Hmac, Mac};
+use rand_core::{OsRng, RngCore};
+use sha1::Sha1;
+use time::OffsetDateTime;
+
+pub use crate::errors::TotpError as Error;
+
+
+/***** UNIT TESTS *****/
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_base32() {
+        // Test vectors from RFC 4648
+        assert_eq!(base32_encode(b""), "");
+        assert_eq!(base32_encode(b"f"), "MY");
+        assert_eq!(base32_encode(b"foob"), "MZXW6YQ");
+        assert_eq!(base32_encode(b"foobar"), "MZXW6YTBOI");
+        assert_eq!(base32_decode("MZXW6YTBOI").unwrap(), b"foobar");
+        assert_eq!(base32_decode("mzxw 6yq=").unwrap(), b"foob");
+        assert!(base32_decode("MZXW1").is_none());
+    }
+
+    #[test]
+    fn test_codes() {
+        // Test vectors from RFC 6238 (SHA1, truncated to six digits)
+        let totp = Totp::from_base32(&base32_encode(b"12345678901234567890")).unwrap();
+        assert_eq!(totp.code(59 / PERIOD), "287082");
+        assert_eq!(totp.code(1111111109 / PERIOD), "081804");
+        assert_eq!(totp.code(1234567890 / PERIOD), "005924");
+        assert_eq!(totp.code(2000000000 / PERIOD), "279037");
+    }
+
+    #[test]
+    fn test_verify() {
+        let totp = Totp::generate();
+        let now = OffsetDateTime::from_unix_timestamp(1234567890).unwrap();
+        let step = Totp::step(now);
+
+        // The current code and those of the neighbouring steps are accepted
+        assert_eq!(totp.verify(&totp.code(step), now, 1), Some(step));
+        assert_eq!(totp.verify(&totp.code(step - 1), now, 1), Some(step - 1));
+        assert_eq!(totp.verify(&totp.code(step + 1), now, 1), Some(step + 1));
+        assert_eq!(totp.verify(&totp.code(step + 2), now, 1), None);
+        assert_eq!(totp.verify(&totp.code(step - 1), now, 0), None);
+        assert_eq!(totp.verify("12345", now, 1), None);
+        assert_eq!(totp.verify("abcdef", now, 1), None);
+    }
+
+    #[test]
+    fn test_uri() {
+        let totp = Totp::from_base32("JBSWY3DPEHPK3PXPJBSWY3DPEHPK3PXP").unwrap();
+        assert_eq!(totp.uri("todo rust", "john"), "otpauth://totp/todo%20rust:john?secret=JBSWY3DPEHPK3PXPJBSWY3DPEHPK3PXP&issuer=todo%20rust&algorithm=SHA1&digits=6&period=30");
+        assert!(matches!(Totp::from_base32("JBSWY3DP"), Err(Error::ShortSecret{ .. })));
+        assert!(matches!(Totp::from_base32("not base32!"), Err(Error::IllegalSecret{ .. })));
+    }
+}
+
+
+
+
+
+/***** CONSTANTS *****/
+/// The number of digits in a code.
+pub const DIGITS: usize = 6;
+/// The number of seconds that a code is valid.
+pub const PERIOD: u64 = 30;
+
+/// The number of bytes in newly generated secrets (as recommended by RFC 4226).
+const SECRET_SIZE: usize = 20;
+/// The minimum number of bytes in a secret (as required by RFC 4226).
+const MIN_SECRET_SIZE: usize = 16;
+/// The alphabet used for base32 (RFC 4648).
+const BASE32_ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+
+
+
+
+/***** HELPER FUNCTIONS *****/
+/// Percent-encodes the given string for use in an URI.
+fn percent_encode(value: &str) -> String {
+    let mut res = String::with_capacity(value.len());
+    for b in value.bytes() {
+        if b.is_ascii_alphanumeric() || b"-._~".contains(&b) {
+            res.push(b as char);
+        } else {
+            res.push_str(&format!("%{:02X}", b));
+        }
+    }
+    res
+}
+
+
+
+
+
+/***** LIBRARY FUNCTIONS *****/
+/// Encodes the given bytes as (unpadded) base32, which is how authenticator apps expect secrets.
+/// 
+/// **Arguments**
+///  * `data`: The bytes to encode.
+/// 
+/// **Returns**  
+/// The base32-encoded string.
+pub fn base32_encode(data: &[u8]) -> String {
+    let mut res = String::with_capacity((data.len() * 8).div_ceil(5));
+    let mut buffer: u32 = 0;
+    let mut bits: u32 = 0;
+    for b in data {
+        buffer = (buffer << 8) | *b as u32;
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            res.push(BASE32_ALPHABET[((buffer >> bits) & 0x1F) as usize] as char);
+        }
+    }
+    if bits > 0 { res.push(BASE32_ALPHABET[((buffer << (5 - bits)) & 0x1F) as usize] as char); }
+    res
+}
+
+/// Decodes the given base32 string. Case, whitespace and padding are ignored.
+/// 
+/// **Arguments**
+///  * `data`: The string to decode.
+/// 
+/// **Returns**  
+/// The decoded bytes, or None if the string is not valid base32.
+pub fn base32_decode(data: &str) -> Option<Vec<u8>> {
+    let mut res = Vec::with_capacity(data.len() * 5 / 8);
+    let mut buffer: u32 = 0;
+    let mut bits: u32 = 0;
+    for c in data.bytes().filter(|c| !c.is_ascii_whitespace() && *c != b'=') {
+        let value = BASE32_ALPHABET.iter().position(|a| *a == c.to_ascii_uppercase())?;
+        buffer = (buffer << 5) | value as u32;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            res.push((buffer >> bits) as u8);
+        }
+    }
+    Some(res)
+}
+
+
+
+
+
+/***** LIBRARY STRUCTS *****/
+/// Defines a TOTP generator for one account.
+#[derive(Clone)]
+pub struct Totp {
+    /// The secret shared with the authenticator app
+    secret : Vec<u8>,
+}
+
+impl Totp {
+    /// Constructor for the Totp that generates a new, random secret.
+    /// 
+    /// **Returns**  
+    /// A new Totp instance.
+    pub fn generate() -> Self {
+        let mut secret = vec![ 0; SECRET_SIZE ];
+        OsRng.fill_bytes(&mut secret);
+        Self{ secret }
+    }
+
+    /// Constructor for the Totp that takes an existing, base32-encoded secret.
+    /// 
+    /// **Arguments**
+    ///  * `secret`: The base32-encoded secret.
+    /// 
+    /// **Returns**  
+    /// The new Totp instance on success, or an Error if the secret is invalid.
+    pub fn from_base32(secret: &str) -> Result<Self, Error> {
+        let bytes: Vec<u8> = match base32_decode(secret) {
+            Some(bytes) => bytes,
+            None        => { return Err(Error::IllegalSecret{ raw: secret.to_string() }); }
+        };
+        if bytes.len() < MIN_SECRET_SIZE { return Err(Error::ShortSecret{ bytes: bytes.len() }); }
+        Ok(Self{ secret: bytes })
+    }
+
+
+
+    /// Computes the time step for the given moment.
+    /// 
+    /// **Arguments**
+    ///  * `time`: The moment to compute the time step of.
+    /// 
+    /// **Returns**  
+    /// The number of periods that have passed since the UNIX epoch.
+    #[inline]
+    pub fn step(time: OffsetDateTime) -> u64 {
+        time.unix_timestamp().max(0) as u64 / PERIOD
+    }
+
+    /// Computes the code for the given time step (i.e., the HOTP value from RFC 4226).
+    /// 
+    /// **Arguments**
+    ///  * `step`: The time step to compute the code for.
+    /// 
+    /// **Returns**  
+    /// The code, as a zero-padded string of `DIGITS` digits.
+    pub fn code(&self, step: u64) -> String {
+        let mut mac = Hmac::<Sha1>::new_from_slice(&self.secret).expect("HMAC accepts keys of any size; this should never happen!");
+        mac.update(&step.to_be_bytes());
+        let hash = mac.finalize().into_bytes();
+
+        // Dynamic truncation
+        let offset: usize = (hash[hash.len() - 1] & 0x0F) as usize;
+        let value: u32 = u32::from_be_bytes([ hash[offset] & 0x7F, hash[offset + 1], hash[offset + 2], hash[offset + 3] ]);
+        format!("{:0width$}", value % 10u32.pow(DIGITS as u32), width = DIGITS)
+    }
+
+    /// Checks the given code against the codes around the given moment.
+    /// 
+    /// Note that it is up to the caller to remember the returned step, and to refuse codes for that step (or earlier ones) afterwards. Otherwise, codes could be replayed.
+    /// 
+    /// **Arguments**
+    ///  * `code`: The code given by the user.
+    ///  * `time`: The current moment.
+    ///  * `skew`: The number of steps before and after the current one that we also accept, to allow for clock drift.
+    /// 
+    /// **Returns**  
+    /// The time step that matched the code, or None if it matched none of them.
+    pub fn verify(&self, code: &str, time: OffsetDateTime, skew: u64) -> Option<u64> {
+        if code.len() != DIGITS || !code.bytes().all(|c| c.is_ascii_digit()) { return None; }
+        let step = Self::step(time);
+        (step.saturating_sub(skew)..=step.saturating_add(skew)).find(|step| {
+            // Compare in constant time
+            self.code(*step).bytes().zip(code.bytes()).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0
+        })
+    }
+
+
+
+    /// Returns the secret as base32, so it can be stored or typed into an authenticator app.
+    #[inline]
+    pub fn secret(&self) -> String { base32_encode(&self.secret) }
+
+    /// Returns the 'otpauth://' URI that authenticator apps understand (usually shown as a QR code).
+    /// 
+    /// **Arguments**
+    ///  * `issuer`: The name of the service, as shown in the app.
+    ///  * `account`: The name of the account, as shown in the app.
+    /// 
+    /// **Returns**  
+    /// The URI with the secret and our parameters.
+    pub fn uri(&self, issuer: &str, account: &str) -> String {
+        format!("otpauth://totp/{}:{}?secret={}&issuer={}&algorithm=SHA1&digits={}&period={}", percent_encode(issuer), percent_encode(account), self.secret(), percent_encode(issuer), DIGITS, PERIOD)
+    }
+}
+
+impl Debug for Totp {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FResult {
+        // Never show the secret itself
+        f.debug_struct("Totp").finish_non_exhaustive()
+    }
+}